@@ -0,0 +1,159 @@
+// Purpose: the fixed acpi description table. It's where the power management
+// registers (sleep / soft-off), the reset register, the sci, the rtc's century
+// register & the pointer to the dsdt live. Older (shorter) fadts simply lack
+// the later fields; those read as zero.
+
+use crate::acpi::*;
+
+// field offsets
+const FADT_FIRMWARE_CTRL: usize = 36;
+const FADT_DSDT: usize = 40;
+const FADT_SCI_INT: usize = 46;
+const FADT_SMI_CMD: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+const FADT_ACPI_DISABLE: usize = 53;
+const FADT_PM1A_EVT_BLK: usize = 56;
+const FADT_PM1B_EVT_BLK: usize = 60;
+const FADT_PM1A_CNT_BLK: usize = 64;
+const FADT_PM1B_CNT_BLK: usize = 68;
+const FADT_PM_TMR_BLK: usize = 76;
+const FADT_PM1_EVT_LEN: usize = 88;
+const FADT_PM1_CNT_LEN: usize = 89;
+const FADT_PM_TMR_LEN: usize = 91;
+const FADT_CENTURY: usize = 108;
+const FADT_IAPC_BOOT_ARCH: usize = 109;
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REG: usize = 116;
+const FADT_RESET_VALUE: usize = 128;
+const FADT_ARM_BOOT_ARCH: usize = 129;
+const FADT_X_FIRMWARE_CTRL: usize = 132;
+const FADT_X_DSDT: usize = 140;
+const FADT_X_PM1A_EVT_BLK: usize = 148;
+const FADT_X_PM1B_EVT_BLK: usize = 160;
+const FADT_X_PM1A_CNT_BLK: usize = 172;
+const FADT_X_PM1B_CNT_BLK: usize = 184;
+const FADT_X_PM_TMR_BLK: usize = 208;
+const FADT_SLEEP_CONTROL_REG: usize = 244;
+const FADT_SLEEP_STATUS_REG: usize = 256;
+
+// iapc_boot_arch
+pub const FADT_BOOT_ARCH_LEGACY_DEVICES: u16 = 1 << 0;
+pub const FADT_BOOT_ARCH_8042: u16 = 1 << 1;
+pub const FADT_BOOT_ARCH_NO_VGA: u16 = 1 << 2;
+pub const FADT_BOOT_ARCH_NO_CMOS_RTC: u16 = 1 << 5;
+
+// flags
+pub const FADT_FLAG_TMR_VAL_EXT: u32 = 1 << 8;
+pub const FADT_FLAG_RESET_REG_SUP: u32 = 1 << 10;
+pub const FADT_FLAG_HW_REDUCED_ACPI: u32 = 1 << 20;
+
+// arm_boot_arch
+pub const FADT_ARM_BOOT_ARCH_PSCI_COMPLIANT: u16 = 1 << 0;
+pub const FADT_ARM_BOOT_ARCH_PSCI_USE_HVC: u16 = 1 << 1;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Fadt {
+    pub revision: u8,
+    pub firmware_ctrl: u64,
+    pub dsdt: u64,
+    pub sci_interrupt: u16,
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event_block: GenericAddress,
+    pub pm1b_event_block: GenericAddress,
+    pub pm1a_control_block: GenericAddress,
+    pub pm1b_control_block: GenericAddress,
+    pub pm_timer_block: GenericAddress,
+    pub century_reg: u8,
+    pub iapc_boot_arch: u16,
+    pub arm_boot_arch: u16,
+    pub flags: u32,
+    pub reset_reg: GenericAddress,
+    pub reset_value: u8,
+    pub sleep_control_reg: GenericAddress,
+    pub sleep_status_reg: GenericAddress,
+}
+
+impl Fadt {
+    pub fn parse(table: &AcpiTable) -> Fadt {
+        let b = table.bytes();
+
+        // the 32 bit blocks are i/o ports; the extended ones win when present
+        let block = |legacy: usize, extended: usize, len: u8| {
+            let x = GenericAddress::parse(b, extended);
+
+            if x.is_present() {
+                x
+            } else {
+                GenericAddress {
+                    space_id: ACPI_GAS_SYSTEM_IO,
+                    bit_width: len.saturating_mul(8),
+                    bit_offset: 0,
+                    access_size: 0,
+                    address: read_u32(b, legacy) as u64,
+                }
+            }
+        };
+
+        let pm1_evt_len = read_u8(b, FADT_PM1_EVT_LEN);
+        let pm1_cnt_len = read_u8(b, FADT_PM1_CNT_LEN);
+
+        let x_dsdt = read_u64(b, FADT_X_DSDT);
+        let x_firmware_ctrl = read_u64(b, FADT_X_FIRMWARE_CTRL);
+
+        Fadt {
+            revision: table.revision,
+            firmware_ctrl: if x_firmware_ctrl != ZERO_U64 { x_firmware_ctrl } else { read_u32(b, FADT_FIRMWARE_CTRL) as u64 },
+            dsdt: if x_dsdt != ZERO_U64 { x_dsdt } else { read_u32(b, FADT_DSDT) as u64 },
+            sci_interrupt: read_u16(b, FADT_SCI_INT),
+            smi_command_port: read_u32(b, FADT_SMI_CMD),
+            acpi_enable: read_u8(b, FADT_ACPI_ENABLE),
+            acpi_disable: read_u8(b, FADT_ACPI_DISABLE),
+            pm1a_event_block: block(FADT_PM1A_EVT_BLK, FADT_X_PM1A_EVT_BLK, pm1_evt_len),
+            pm1b_event_block: block(FADT_PM1B_EVT_BLK, FADT_X_PM1B_EVT_BLK, pm1_evt_len),
+            pm1a_control_block: block(FADT_PM1A_CNT_BLK, FADT_X_PM1A_CNT_BLK, pm1_cnt_len),
+            pm1b_control_block: block(FADT_PM1B_CNT_BLK, FADT_X_PM1B_CNT_BLK, pm1_cnt_len),
+            pm_timer_block: block(FADT_PM_TMR_BLK, FADT_X_PM_TMR_BLK, read_u8(b, FADT_PM_TMR_LEN)),
+            century_reg: read_u8(b, FADT_CENTURY),
+            iapc_boot_arch: read_u16(b, FADT_IAPC_BOOT_ARCH),
+            arm_boot_arch: read_u16(b, FADT_ARM_BOOT_ARCH),
+            flags: read_u32(b, FADT_FLAGS),
+            reset_reg: GenericAddress::parse(b, FADT_RESET_REG),
+            reset_value: read_u8(b, FADT_RESET_VALUE),
+            sleep_control_reg: GenericAddress::parse(b, FADT_SLEEP_CONTROL_REG),
+            sleep_status_reg: GenericAddress::parse(b, FADT_SLEEP_STATUS_REG),
+        }
+    }
+
+    pub fn dsdt_address(&self) -> u64 {
+        self.dsdt
+    }
+
+    // the reset register is only meaningful if the firmware says so
+    pub fn reset_supported(&self) -> bool {
+        self.flags & FADT_FLAG_RESET_REG_SUP != 0 && self.reset_reg.is_present()
+    }
+
+    // hardware-reduced platforms have no pm1 blocks, sci, etc.
+    pub fn hardware_reduced(&self) -> bool {
+        self.flags & FADT_FLAG_HW_REDUCED_ACPI != 0
+    }
+
+    // revision 1 fadts predate the flag, and were all pc-at compatible
+    pub fn has_8042(&self) -> bool {
+        self.revision < 2 || self.iapc_boot_arch & FADT_BOOT_ARCH_8042 != 0
+    }
+
+    pub fn has_cmos_rtc(&self) -> bool {
+        self.iapc_boot_arch & FADT_BOOT_ARCH_NO_CMOS_RTC == 0
+    }
+
+    // whether psci is there & whether it's reached via hvc (else smc)
+    pub fn psci(&self) -> Option<bool> {
+        match self.arm_boot_arch & FADT_ARM_BOOT_ARCH_PSCI_COMPLIANT {
+            0 => None,
+            _ => Some(self.arm_boot_arch & FADT_ARM_BOOT_ARCH_PSCI_USE_HVC != 0),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+// Purpose: the hpet description table; where the event timer block lives.
+
+use crate::acpi::*;
+
+const HPET_TABLE_EVENT_TIMER_BLOCK_ID: usize = 36;
+const HPET_TABLE_BASE_ADDRESS: usize = 40;
+const HPET_TABLE_NUMBER: usize = 52;
+const HPET_TABLE_MIN_TICK: usize = 53;
+
+#[derive(Debug, Copy, Clone)]
+pub struct HpetTable {
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    pub min_tick: u16,
+}
+
+impl HpetTable {
+    pub fn parse(table: &AcpiTable) -> HpetTable {
+        let b = table.bytes();
+
+        HpetTable {
+            event_timer_block_id: read_u32(b, HPET_TABLE_EVENT_TIMER_BLOCK_ID),
+            base_address: GenericAddress::parse(b, HPET_TABLE_BASE_ADDRESS),
+            hpet_number: read_u8(b, HPET_TABLE_NUMBER),
+            min_tick: read_u16(b, HPET_TABLE_MIN_TICK),
+        }
+    }
+
+    // the register block, if it's in memory space (it always should be)
+    pub fn base(&self) -> Option<PhysAddr> {
+        match self.base_address.space_id {
+            ACPI_GAS_SYSTEM_MEMORY if self.base_address.is_present() => Some(PhysAddr(self.base_address.address as usize)),
+            _ => None,
+        }
+    }
+
+    // the number of comparators, from the block id
+    pub fn comparator_count(&self) -> u8 {
+        (((self.event_timer_block_id >> 8) & 0x1F) + 1) as u8
+    }
+}
@@ -0,0 +1,254 @@
+// Purpose: the multiple apic description table. On x86 it lists the local
+// apics (i.e. the cpus), the i/o apics & how the isa irqs are wired to gsis;
+// on aarch64 it describes the gic the same way.
+
+use crate::acpi::*;
+
+const MADT_LOCAL_APIC_ADDRESS: usize = 36;
+const MADT_FLAGS: usize = 40;
+const MADT_ENTRIES: usize = 44;
+
+// flags: the legacy pics are present (& need masking)
+pub const MADT_FLAG_PCAT_COMPAT: u32 = 1 << 0;
+
+// entry types
+const MADT_TYPE_LOCAL_APIC: u8 = 0x0;
+const MADT_TYPE_IO_APIC: u8 = 0x1;
+const MADT_TYPE_INTERRUPT_SOURCE_OVERRIDE: u8 = 0x2;
+const MADT_TYPE_NMI_SOURCE: u8 = 0x3;
+const MADT_TYPE_LOCAL_APIC_NMI: u8 = 0x4;
+const MADT_TYPE_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 0x5;
+const MADT_TYPE_LOCAL_X2APIC: u8 = 0x9;
+const MADT_TYPE_GICC: u8 = 0xB;
+const MADT_TYPE_GICD: u8 = 0xC;
+const MADT_TYPE_GIC_MSI_FRAME: u8 = 0xD;
+const MADT_TYPE_GICR: u8 = 0xE;
+
+// local apic / x2apic / gicc flags
+pub const MADT_CPU_ENABLED: u32 = 1 << 0;
+pub const MADT_CPU_ONLINE_CAPABLE: u32 = 1 << 1;
+
+// mps inti flags (interrupt source overrides & nmis)
+pub const MADT_INTI_POLARITY_MASK: u16 = 0b11;
+pub const MADT_INTI_POLARITY_ACTIVE_HIGH: u16 = 0b01;
+pub const MADT_INTI_POLARITY_ACTIVE_LOW: u16 = 0b11;
+pub const MADT_INTI_TRIGGER_MASK: u16 = 0b11 << 2;
+pub const MADT_INTI_TRIGGER_EDGE: u16 = 0b01 << 2;
+pub const MADT_INTI_TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+#[derive(Debug, Copy, Clone)]
+pub enum MadtEntry {
+    LocalApic { processor_uid: u8, apic_id: u8, flags: u32 },
+    IoApic { id: u8, address: u32, gsi_base: u32 },
+    InterruptSourceOverride { bus: u8, source: u8, gsi: u32, flags: u16 },
+    NmiSource { flags: u16, gsi: u32 },
+    LocalApicNmi { processor_uid: u8, flags: u16, lint: u8 },
+    LocalApicAddressOverride { address: u64 },
+    LocalX2Apic { x2apic_id: u32, flags: u32, processor_uid: u32 },
+    Gicc { cpu_interface: u32, processor_uid: u32, flags: u32, base: u64, gicr_base: u64, mpidr: u64 },
+    Gicd { gic_id: u32, base: u64, version: u8 },
+    GicMsiFrame { id: u32, base: u64, flags: u32, spi_count: u16, spi_base: u16 },
+    Gicr { base: u64, length: u32 },
+    Unknown { entry_type: u8, length: u8 },
+}
+
+impl MadtEntry {
+    fn parse(b: &[u8], offset: usize) -> MadtEntry {
+        let entry_type = read_u8(b, offset);
+        let length = read_u8(b, offset + 1);
+
+        match entry_type {
+            MADT_TYPE_LOCAL_APIC => MadtEntry::LocalApic {
+                processor_uid: read_u8(b, offset + 2),
+                apic_id: read_u8(b, offset + 3),
+                flags: read_u32(b, offset + 4),
+            },
+            MADT_TYPE_IO_APIC => MadtEntry::IoApic {
+                id: read_u8(b, offset + 2),
+                address: read_u32(b, offset + 4),
+                gsi_base: read_u32(b, offset + 8),
+            },
+            MADT_TYPE_INTERRUPT_SOURCE_OVERRIDE => MadtEntry::InterruptSourceOverride {
+                bus: read_u8(b, offset + 2),
+                source: read_u8(b, offset + 3),
+                gsi: read_u32(b, offset + 4),
+                flags: read_u16(b, offset + 8),
+            },
+            MADT_TYPE_NMI_SOURCE => MadtEntry::NmiSource {
+                flags: read_u16(b, offset + 2),
+                gsi: read_u32(b, offset + 4),
+            },
+            MADT_TYPE_LOCAL_APIC_NMI => MadtEntry::LocalApicNmi {
+                processor_uid: read_u8(b, offset + 2),
+                flags: read_u16(b, offset + 3),
+                lint: read_u8(b, offset + 5),
+            },
+            MADT_TYPE_LOCAL_APIC_ADDRESS_OVERRIDE => MadtEntry::LocalApicAddressOverride {
+                address: read_u64(b, offset + 4),
+            },
+            MADT_TYPE_LOCAL_X2APIC => MadtEntry::LocalX2Apic {
+                x2apic_id: read_u32(b, offset + 4),
+                flags: read_u32(b, offset + 8),
+                processor_uid: read_u32(b, offset + 12),
+            },
+            MADT_TYPE_GICC => MadtEntry::Gicc {
+                cpu_interface: read_u32(b, offset + 4),
+                processor_uid: read_u32(b, offset + 8),
+                flags: read_u32(b, offset + 12),
+                base: read_u64(b, offset + 32),
+                gicr_base: read_u64(b, offset + 60),
+                mpidr: read_u64(b, offset + 68),
+            },
+            MADT_TYPE_GICD => MadtEntry::Gicd {
+                gic_id: read_u32(b, offset + 4),
+                base: read_u64(b, offset + 8),
+                version: read_u8(b, offset + 20),
+            },
+            MADT_TYPE_GIC_MSI_FRAME => MadtEntry::GicMsiFrame {
+                id: read_u32(b, offset + 4),
+                base: read_u64(b, offset + 8),
+                flags: read_u32(b, offset + 16),
+                spi_count: read_u16(b, offset + 20),
+                spi_base: read_u16(b, offset + 22),
+            },
+            MADT_TYPE_GICR => MadtEntry::Gicr {
+                base: read_u64(b, offset + 4),
+                length: read_u32(b, offset + 12),
+            },
+            _ => MadtEntry::Unknown { entry_type, length },
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Madt {
+    table: AcpiTable,
+    pub local_apic_address: u32,
+    pub flags: u32,
+}
+
+impl Madt {
+    pub fn parse(table: &AcpiTable) -> Madt {
+        let b = table.bytes();
+
+        Madt {
+            table: *table,
+            local_apic_address: read_u32(b, MADT_LOCAL_APIC_ADDRESS),
+            flags: read_u32(b, MADT_FLAGS),
+        }
+    }
+
+    // the interrupt controller structures, in table order; a truncated
+    // entry ends the walk
+    pub fn entries(&self) -> impl Iterator<Item = MadtEntry> {
+        let b = self.table.bytes();
+        let end = self.table.length;
+        let mut offset = MADT_ENTRIES;
+
+        core::iter::from_fn(move || {
+            if offset + 2 > end {
+                return None;
+            }
+
+            let length = read_u8(b, offset + 1) as usize;
+
+            if length < 2 || offset + length > end {
+                return None;
+            }
+
+            let entry = MadtEntry::parse(b, offset);
+            offset += length;
+
+            Some(entry)
+        })
+    }
+
+    pub fn has_legacy_pics(&self) -> bool {
+        self.flags & MADT_FLAG_PCAT_COMPAT != 0
+    }
+
+    // the local apic's physical address, honoring any 64 bit override
+    pub fn local_apic_base(&self) -> PhysAddr {
+        for e in self.entries() {
+            if let MadtEntry::LocalApicAddressOverride { address } = e {
+                return PhysAddr(address as usize);
+            }
+        }
+
+        PhysAddr(self.local_apic_address as usize)
+    }
+
+    // the index'th i/o apic as (base, gsi base)
+    pub fn io_apic(&self, index: usize) -> Option<(PhysAddr, u32)> {
+        self.entries()
+            .filter_map(|e| match e {
+                MadtEntry::IoApic { address, gsi_base, .. } => Some((PhysAddr(address as usize), gsi_base)),
+                _ => None,
+            })
+            .nth(index)
+    }
+
+    // the gsi an isa irq is actually wired to, & its mps inti flags (0 means
+    // the bus default: edge triggered, active high). isa irqs that aren't
+    // overridden are identity mapped.
+    pub fn isa_irq_to_gsi(&self, irq: u8) -> (u32, u16) {
+        for e in self.entries() {
+            if let MadtEntry::InterruptSourceOverride { bus: 0, source, gsi, flags } = e {
+                if source == irq {
+                    return (gsi, flags);
+                }
+            }
+        }
+
+        (irq as u32, 0)
+    }
+
+    // the number of usable cpus (enabled or able to be brought online)
+    pub fn cpu_count(&self) -> usize {
+        self.entries()
+            .filter(|e| match *e {
+                MadtEntry::LocalApic { flags, .. }
+                | MadtEntry::LocalX2Apic { flags, .. }
+                | MadtEntry::Gicc { flags, .. } => flags & (MADT_CPU_ENABLED | MADT_CPU_ONLINE_CAPABLE) != 0,
+                _ => false,
+            })
+            .count()
+    }
+
+    // the gic distributor, as (base, version); version 0 means "go look"
+    pub fn gic_distributor(&self) -> Option<(PhysAddr, u8)> {
+        self.entries().find_map(|e| match e {
+            MadtEntry::Gicd { base, version, .. } => Some((PhysAddr(base as usize), version)),
+            _ => None,
+        })
+    }
+
+    // the gicv2 cpu interface base (the boot cpu's; they're all the same)
+    pub fn gic_cpu_interface(&self) -> Option<PhysAddr> {
+        self.entries().find_map(|e| match e {
+            MadtEntry::Gicc { base, .. } if base != ZERO_U64 => Some(PhysAddr(base as usize)),
+            _ => None,
+        })
+    }
+
+    // the gicv3 redistributor region; either listed outright, or per cpu
+    pub fn gic_redistributor(&self) -> Option<PhysAddr> {
+        self.entries()
+            .find_map(|e| match e {
+                MadtEntry::Gicr { base, .. } => Some(PhysAddr(base as usize)),
+                _ => None,
+            })
+            .or_else(|| self.entries().find_map(|e| match e {
+                MadtEntry::Gicc { gicr_base, .. } if gicr_base != ZERO_U64 => Some(PhysAddr(gicr_base as usize)),
+                _ => None,
+            }))
+    }
+
+    // the first gicv2m msi frame
+    pub fn gic_msi_frame(&self) -> Option<PhysAddr> {
+        self.entries().find_map(|e| match e {
+            MadtEntry::GicMsiFrame { base, .. } => Some(PhysAddr(base as usize)),
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,68 @@
+// Purpose: the pci express memory mapped configuration table; one ecam window
+// per pci segment group (& bus range).
+
+use crate::acpi::*;
+
+const MCFG_ENTRIES: usize = 44;
+const MCFG_ENTRY_LENGTH: usize = 16;
+
+#[derive(Debug, Copy, Clone)]
+pub struct McfgEntry {
+    pub base: PhysAddr,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl McfgEntry {
+    // the config space of segment:bus:device.function; each function gets 4k
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<PhysAddr> {
+        if bus < self.start_bus || bus > self.end_bus || device > 31 || function > 7 {
+            return None;
+        }
+
+        let offset = ((bus - self.start_bus) as usize) << 20 | (device as usize) << 15 | (function as usize) << 12;
+
+        Some(PhysAddr(self.base.as_usize() + offset))
+    }
+
+    // the size of the whole window
+    pub fn size(&self) -> usize {
+        ((self.end_bus as usize) - (self.start_bus as usize) + 1) << 20
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Mcfg {
+    table: AcpiTable,
+}
+
+impl Mcfg {
+    pub fn parse(table: &AcpiTable) -> Mcfg {
+        Mcfg { table: *table }
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.table.length.saturating_sub(MCFG_ENTRIES) / MCFG_ENTRY_LENGTH
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = McfgEntry> {
+        let b = self.table.bytes();
+
+        (0..self.entry_count()).map(move |i| {
+            let offset = MCFG_ENTRIES + i * MCFG_ENTRY_LENGTH;
+
+            McfgEntry {
+                base: PhysAddr(read_u64(b, offset) as usize),
+                segment: read_u16(b, offset + 8),
+                start_bus: read_u8(b, offset + 10),
+                end_bus: read_u8(b, offset + 11),
+            }
+        })
+    }
+
+    // the window covering segment:bus, if any
+    pub fn find(&self, segment: u16, bus: u8) -> Option<McfgEntry> {
+        self.entries().find(|e| e.segment == segment && bus >= e.start_bus && bus <= e.end_bus)
+    }
+}
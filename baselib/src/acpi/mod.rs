@@ -0,0 +1,542 @@
+// Purpose: acpi table discovery. The boot path hands us the rsdp (uefi finds it
+// in the configuration table, before boot services go away); from there we walk
+// the xsdt (or the rsdt on acpi 1.0 firmware), checksum every table, and keep a
+// registry of where they all live. The typed views for the tables we actually
+// use are in the submodules. All of this reads physical memory through the
+// identity map, so it works the same before & after the paging switch.
+
+// Submodule(s)
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod power;
+
+// Rust Items
+use core::sync::atomic::{AtomicBool, Ordering};
+// Internal Items
+use crate::common::base::*;
+use crate::permissions::Owner;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::asm::*;
+
+pub use fadt::Fadt;
+pub use hpet::HpetTable;
+pub use madt::Madt;
+pub use mcfg::Mcfg;
+pub use power::*;
+
+// table signatures
+pub const ACPI_SIG_RSDT: [u8; 4] = *b"RSDT";
+pub const ACPI_SIG_XSDT: [u8; 4] = *b"XSDT";
+pub const ACPI_SIG_FADT: [u8; 4] = *b"FACP";
+pub const ACPI_SIG_DSDT: [u8; 4] = *b"DSDT";
+pub const ACPI_SIG_SSDT: [u8; 4] = *b"SSDT";
+pub const ACPI_SIG_MADT: [u8; 4] = *b"APIC";
+pub const ACPI_SIG_HPET: [u8; 4] = *b"HPET";
+pub const ACPI_SIG_MCFG: [u8; 4] = *b"MCFG";
+
+const ACPI_RSDP_SIGNATURE: [u8; 8] = *b"RSD PTR ";
+// the acpi 1.0 rsdp is 20 bytes; 2.0+ adds the length, the xsdt & a second checksum
+const ACPI_RSDP_V1_LENGTH: usize = 20;
+const ACPI_RSDP_V2_LENGTH: usize = 36;
+
+// every sdt starts with the same 36 byte header
+pub const ACPI_SDT_HEADER_LENGTH: usize = 36;
+
+// how many tables we'll keep track of; real firmware has a few dozen at most
+const ACPI_MAX_TABLES: usize = 64;
+
+// nobody's tables are this big; anything larger is a bogus header
+const ACPI_MAX_TABLE_LENGTH: usize = 0x0100_0000;
+
+//-----------------------------------------------------------------------------------
+
+// unaligned little endian reads out of table memory; acpi packs everything
+#[inline(always)]
+pub(crate) fn read_u8(bytes: &[u8], offset: usize) -> u8 {
+    bytes.get(offset).copied().unwrap_or(0)
+}
+
+#[inline(always)]
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    match bytes.get(offset..offset + 2) {
+        Some(b) => u16::from_le_bytes([b[0], b[1]]),
+        None => 0,
+    }
+}
+
+#[inline(always)]
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        None => 0,
+    }
+}
+
+#[inline(always)]
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    (read_u32(bytes, offset) as u64) | ((read_u32(bytes, offset + 4) as u64) << 32)
+}
+
+// all the bytes of a structure have to sum to zero (mod 256)
+fn acpi_checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+// a raw view of length bytes of physical memory
+fn acpi_phys_bytes(phys: PhysAddr, length: usize) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(phys.as_usize() as *const u8, length) }
+}
+
+//-----------------------------------------------------------------------------------
+
+// the generic address structure; how acpi describes a register that could be
+// in memory, i/o port space, pci config space, etc.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub space_id: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+pub const ACPI_GAS_LENGTH: usize = 12;
+pub const ACPI_GAS_SYSTEM_MEMORY: u8 = 0;
+pub const ACPI_GAS_SYSTEM_IO: u8 = 1;
+pub const ACPI_GAS_PCI_CONFIG: u8 = 2;
+
+impl GenericAddress {
+    pub(crate) fn parse(bytes: &[u8], offset: usize) -> GenericAddress {
+        GenericAddress {
+            space_id: read_u8(bytes, offset),
+            bit_width: read_u8(bytes, offset + 1),
+            bit_offset: read_u8(bytes, offset + 2),
+            access_size: read_u8(bytes, offset + 3),
+            address: read_u64(bytes, offset + 4),
+        }
+    }
+
+    // an all-zero gas means the register isn't there
+    pub fn is_present(&self) -> bool {
+        self.address != ZERO_U64
+    }
+
+    // the access width in bits; the access size wins if it's given, else
+    // the register's own width
+    fn width(&self) -> u8 {
+        match self.access_size {
+            1 => 8,
+            2 => 16,
+            3 => 32,
+            4 => 64,
+            _ => match self.bit_width {
+                0..=8 => 8,
+                9..=16 => 16,
+                17..=32 => 32,
+                _ => 64,
+            },
+        }
+    }
+
+    // reads the register; None if it isn't there or lives somewhere we can't reach.
+    // system memory registers have to be mapped already.
+    pub fn read(&self) -> Option<u64> {
+        if !self.is_present() {
+            return None;
+        }
+
+        let value = match self.space_id {
+            ACPI_GAS_SYSTEM_MEMORY => unsafe {
+                match self.width() {
+                    8 => core::ptr::read_volatile(self.address as usize as *const u8) as u64,
+                    16 => core::ptr::read_volatile(self.address as usize as *const u16) as u64,
+                    32 => core::ptr::read_volatile(self.address as usize as *const u32) as u64,
+                    _ => core::ptr::read_volatile(self.address as usize as *const u64),
+                }
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            ACPI_GAS_SYSTEM_IO => match self.width() {
+                8 => x86_inport8(self.address as u16) as u64,
+                16 => x86_inport16(self.address as u16) as u64,
+                _ => x86_inport32(self.address as u16) as u64,
+            },
+            _ => return None,
+        };
+
+        Some(value >> self.bit_offset)
+    }
+
+    // writes the register; false if it isn't there or lives somewhere we can't reach
+    pub fn write(&self, value: u64) -> bool {
+        if !self.is_present() {
+            return false;
+        }
+
+        let value = value << self.bit_offset;
+
+        match self.space_id {
+            ACPI_GAS_SYSTEM_MEMORY => unsafe {
+                match self.width() {
+                    8 => core::ptr::write_volatile(self.address as usize as *mut u8, value as u8),
+                    16 => core::ptr::write_volatile(self.address as usize as *mut u16, value as u16),
+                    32 => core::ptr::write_volatile(self.address as usize as *mut u32, value as u32),
+                    _ => core::ptr::write_volatile(self.address as usize as *mut u64, value),
+                }
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            ACPI_GAS_SYSTEM_IO => match self.width() {
+                8 => x86_outport8(self.address as u16, value as u8),
+                16 => x86_outport16(self.address as u16, value as u16),
+                _ => x86_outport32(self.address as u16, value as u32),
+            },
+            // bus 0; device in bits 32-47, function in 16-31, register in 0-15.
+            // only byte writes, which is all the reset register ever needs
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            ACPI_GAS_PCI_CONFIG if self.width() == 8 => {
+                let device = ((self.address >> 32) & 0x1F) as u32;
+                let function = ((self.address >> 16) & 0x7) as u32;
+                let register = (self.address & 0xFF) as u32;
+
+                x86_outport32(ACPI_PCI_CONFIG_ADDRESS, (1 << 31) | (device << 11) | (function << 8) | (register & 0xFC));
+                x86_outport8(ACPI_PCI_CONFIG_DATA + (register & 0x3) as u16, value as u8);
+            },
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+// the legacy pci configuration mechanism
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const ACPI_PCI_CONFIG_ADDRESS: u16 = 0xCF8;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const ACPI_PCI_CONFIG_DATA: u16 = 0xCFC;
+
+//-----------------------------------------------------------------------------------
+
+// one checksum-validated table we've found
+#[derive(Debug, Copy, Clone)]
+pub struct AcpiTable {
+    pub signature: [u8; 4],
+    pub phys: PhysAddr,
+    pub length: usize,
+    pub revision: u8,
+}
+
+impl AcpiTable {
+    const fn empty() -> AcpiTable {
+        AcpiTable {
+            signature: [0; 4],
+            phys: PhysAddr(0),
+            length: 0,
+            revision: 0,
+        }
+    }
+
+    // the whole table, header included
+    pub fn bytes(&self) -> &'static [u8] {
+        acpi_phys_bytes(self.phys, self.length)
+    }
+
+    pub fn signature_str(&self) -> &str {
+        core::str::from_utf8(&self.signature).unwrap_or("????")
+    }
+
+    // who built the table; qemu's say "BOCHS "
+    pub fn oem_id(&self) -> [u8; 6] {
+        let b = self.bytes();
+        [b[10], b[11], b[12], b[13], b[14], b[15]]
+    }
+
+    // validates the header & checksum of the table at phys
+    fn probe(phys: PhysAddr) -> Option<AcpiTable> {
+        if phys.is_null() {
+            return None;
+        }
+
+        let header = acpi_phys_bytes(phys, ACPI_SDT_HEADER_LENGTH);
+        let length = read_u32(header, 4) as usize;
+
+        if length < ACPI_SDT_HEADER_LENGTH || length > ACPI_MAX_TABLE_LENGTH {
+            return None;
+        }
+
+        let table = AcpiTable {
+            signature: [header[0], header[1], header[2], header[3]],
+            phys,
+            length,
+            revision: read_u8(header, 8),
+        };
+
+        if !acpi_checksum_ok(table.bytes()) {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("acpi: {} @ 0x{:0x} fails its checksum; ignoring it", table.signature_str(), phys);
+            return None;
+        }
+
+        Some(table)
+    }
+}
+
+struct AcpiRegistry {
+    rsdp: PhysAddr,
+    rsdp_length: usize,
+    revision: u8,
+    tables: [AcpiTable; ACPI_MAX_TABLES],
+    table_count: usize,
+}
+
+impl AcpiRegistry {
+    fn add(&mut self, table: AcpiTable) {
+        if self.table_count >= ACPI_MAX_TABLES {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("acpi: table registry full; dropping {}", table.signature_str());
+            return;
+        }
+
+        // firmware has been known to list the same table twice
+        if self.tables[..self.table_count].iter().any(|t| t.phys == table.phys) {
+            return;
+        }
+
+        self.tables[self.table_count] = table;
+        self.table_count += 1;
+    }
+}
+
+// where the boot path found the rsdp
+static mut ACPI_RSDP_ADDR: Option<PhysAddr> = None;
+
+static mut ACPI_REGISTRY: Option<AcpiRegistry> = None;
+
+fn acpi_registry() -> Option<&'static AcpiRegistry> {
+    unsafe { (*core::ptr::addr_of!(ACPI_REGISTRY)).as_ref() }
+}
+
+// records the rsdp's physical address; the boot path calls this with whatever
+// the firmware / bootloader told it, before acpi_init()
+pub fn acpi_set_rsdp(rsdp: PhysAddr) {
+    unsafe {
+        ACPI_RSDP_ADDR = Some(rsdp);
+    }
+}
+
+// validates the rsdp, walks the xsdt / rsdt and registers every table that
+// passes its checksum (plus the dsdt, which only the fadt points to). returns
+// false if there's no rsdp or it's bogus.
+pub fn acpi_init() -> bool {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return false;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let rsdp = match unsafe { *core::ptr::addr_of!(ACPI_RSDP_ADDR) } {
+        Some(rsdp) if !rsdp.is_null() => rsdp,
+        _ => {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("acpi_init() -> no rsdp; no acpi");
+            return false;
+        },
+    };
+
+    // the v1 part has to check out regardless of revision
+    let rsdp_v1 = acpi_phys_bytes(rsdp, ACPI_RSDP_V1_LENGTH);
+
+    if rsdp_v1[..8] != ACPI_RSDP_SIGNATURE || !acpi_checksum_ok(rsdp_v1) {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("acpi_init() -> bad rsdp @ 0x{:0x}", rsdp);
+        return false;
+    }
+
+    let revision = read_u8(rsdp_v1, 15);
+    let rsdt_addr = read_u32(rsdp_v1, 16) as usize;
+    let mut rsdp_length = ACPI_RSDP_V1_LENGTH;
+    let mut xsdt_addr = ZERO_USIZE;
+
+    // 2.0+: the extended checksum covers the whole thing
+    if revision >= 2 {
+        let length = read_u32(acpi_phys_bytes(rsdp, ACPI_RSDP_V2_LENGTH), 20) as usize;
+
+        if length >= ACPI_RSDP_V2_LENGTH && acpi_checksum_ok(acpi_phys_bytes(rsdp, length)) {
+            rsdp_length = length;
+            xsdt_addr = read_u64(acpi_phys_bytes(rsdp, ACPI_RSDP_V2_LENGTH), 24) as usize;
+        } else {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("acpi_init() -> rsdp extended checksum failed; falling back to the rsdt");
+        }
+    }
+
+    let mut registry = AcpiRegistry {
+        rsdp,
+        rsdp_length,
+        revision,
+        tables: [AcpiTable::empty(); ACPI_MAX_TABLES],
+        table_count: 0,
+    };
+
+    // the xsdt's entries are 64 bits wide, the rsdt's 32; prefer the xsdt
+    let root = match AcpiTable::probe(PhysAddr(xsdt_addr)) {
+        Some(xsdt) if xsdt.signature == ACPI_SIG_XSDT => Some((xsdt, 8)),
+        _ => match AcpiTable::probe(PhysAddr(rsdt_addr)) {
+            Some(rsdt) if rsdt.signature == ACPI_SIG_RSDT => Some((rsdt, 4)),
+            _ => None,
+        },
+    };
+
+    let (root, entry_size) = match root {
+        Some(root) => root,
+        None => {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("acpi_init() -> no valid xsdt / rsdt");
+            return false;
+        },
+    };
+
+    registry.add(root);
+
+    let root_bytes = root.bytes();
+    let mut offset = ACPI_SDT_HEADER_LENGTH;
+
+    while offset + entry_size <= root.length {
+        let table_addr = match entry_size {
+            8 => read_u64(root_bytes, offset) as usize,
+            _ => read_u32(root_bytes, offset) as usize,
+        };
+
+        offset += entry_size;
+
+        if let Some(table) = AcpiTable::probe(PhysAddr(table_addr)) {
+            registry.add(table);
+        }
+    }
+
+    // the dsdt isn't listed in the root table; the fadt points at it
+    let fadt = registry.tables[..registry.table_count]
+        .iter()
+        .find(|t| t.signature == ACPI_SIG_FADT)
+        .copied();
+
+    if let Some(fadt) = fadt {
+        if let Some(dsdt) = AcpiTable::probe(PhysAddr(Fadt::parse(&fadt).dsdt_address() as usize)) {
+            if dsdt.signature == ACPI_SIG_DSDT {
+                registry.add(dsdt);
+            }
+        }
+    }
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    {
+        serial_println!("acpi_init() -> rsdp @ 0x{:0x}, revision {}, {} tables via {}",
+            rsdp, revision, registry.table_count, root.signature_str());
+
+        for t in registry.tables[..registry.table_count].iter() {
+            serial_println!("  {} @ 0x{:08x}, {} bytes, revision {}", t.signature_str(), t.phys, t.length, t.revision);
+        }
+    }
+
+    unsafe {
+        ACPI_REGISTRY = Some(registry);
+    }
+
+    true
+}
+
+// the acpi revision the rsdp claims; 0 is acpi 1.0
+pub fn acpi_revision() -> Option<u8> {
+    acpi_registry().map(|r| r.revision)
+}
+
+// every table we found, in the order the firmware listed them
+pub fn acpi_tables() -> &'static [AcpiTable] {
+    match acpi_registry() {
+        Some(r) => &r.tables[..r.table_count],
+        None => &[],
+    }
+}
+
+// the index'th table with the given signature (ssdts, for one, come in multiples)
+pub fn acpi_find_table(signature: &[u8; 4], index: usize) -> Option<AcpiTable> {
+    acpi_tables().iter().filter(|t| t.signature == *signature).nth(index).copied()
+}
+
+pub fn acpi_madt() -> Option<Madt> {
+    acpi_find_table(&ACPI_SIG_MADT, 0).map(|t| Madt::parse(&t))
+}
+
+pub fn acpi_fadt() -> Option<Fadt> {
+    acpi_find_table(&ACPI_SIG_FADT, 0).map(|t| Fadt::parse(&t))
+}
+
+pub fn acpi_hpet() -> Option<HpetTable> {
+    acpi_find_table(&ACPI_SIG_HPET, 0).map(|t| HpetTable::parse(&t))
+}
+
+pub fn acpi_mcfg() -> Option<Mcfg> {
+    acpi_find_table(&ACPI_SIG_MCFG, 0).map(|t| Mcfg::parse(&t))
+}
+
+// calls f(base, size) for every page-aligned region holding the rsdp or a table
+pub fn acpi_for_each_region<F: FnMut(PhysAddr, usize)>(mut f: F) {
+    let registry = match acpi_registry() {
+        Some(r) => r,
+        None => return,
+    };
+
+    let mut region = |phys: PhysAddr, length: usize| {
+        let base = align_down(phys.as_usize(), MEMORY_DEFAULT_PAGE_USIZE);
+        let end = align_up(phys.as_usize() + length, MEMORY_DEFAULT_PAGE_USIZE);
+        f(PhysAddr(base), end - base);
+    };
+
+    region(registry.rsdp, registry.rsdp_length);
+
+    for t in registry.tables[..registry.table_count].iter() {
+        region(t.phys, t.length);
+    }
+}
+
+// firmware doesn't always put the tables in acpi reclaim / nvs memory; when
+// they're sitting in memory the frame allocator was handed as free, take it
+// back out so nobody scribbles over them. the frame allocator has to be up.
+pub fn acpi_reserve_regions() {
+    // the frame allocator's idea of what's free has to be exact for this
+    frame_cache_drain_all();
+
+    acpi_for_each_region(|base, size| {
+        let mut frame_alloc_lock = iron().unwrap().frame_alloc_internal_04.lock_rw_spin();
+        let frame_alloc = frame_alloc_lock.as_mut().unwrap().as_mut().unwrap();
+
+        // take each run of free pages in one go
+        let mut page = base.as_usize();
+        let end = base.as_usize() + size;
+
+        while page < end {
+            if frame_alloc.frame_owner(PhysAddr(page)) != Some(Owner::Nobody) {
+                page += MEMORY_DEFAULT_PAGE_USIZE;
+                continue;
+            }
+
+            let run_start = page;
+
+            while page < end && frame_alloc.frame_owner(PhysAddr(page)) == Some(Owner::Nobody) {
+                page += MEMORY_DEFAULT_PAGE_USIZE;
+            }
+
+            if frame_alloc.reserve_frame_fixed(PhysAddr(run_start), page - run_start, Owner::Firmware).is_none() {
+                panic!("acpi: failed to reserve table memory @ 0x{:08x}, size {}", run_start, page - run_start);
+            }
+
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("acpi: reserved 0x{:08x}, {} bytes of conventional memory", run_start, page - run_start);
+        }
+    });
+}
@@ -0,0 +1,187 @@
+// Purpose: the acpi side of powering off & resetting. Entering a sleep state
+// takes the SLP_TYP values from the \_Sx package in the dsdt (or an ssdt);
+// we don't have an aml interpreter, but the package is always a plain list of
+// integer constants, so finding the name & decoding what follows it does.
+
+use crate::acpi::*;
+
+// the soft-off sleep state
+pub const ACPI_S5: u8 = 5;
+
+// pm1 control register bits
+const PM1_CNT_SCI_EN: u64 = 1 << 0;
+const PM1_CNT_SLP_TYP_SHIFT: u64 = 10;
+const PM1_CNT_SLP_TYP_MASK: u64 = 0b111 << PM1_CNT_SLP_TYP_SHIFT;
+const PM1_CNT_SLP_EN: u64 = 1 << 13;
+
+// the hardware-reduced sleep control register
+const SLEEP_CONTROL_SLP_TYP_SHIFT: u64 = 2;
+const SLEEP_CONTROL_SLP_EN: u64 = 1 << 5;
+
+// how long we'll wait on the firmware to hand us SCI_EN after the smi
+const ACPI_ENABLE_SPINS: usize = 1_000_000;
+
+// the aml we need to get through a \_Sx package
+const AML_ZERO_OP: u8 = 0x00;
+const AML_ONE_OP: u8 = 0x01;
+const AML_NAME_OP: u8 = 0x08;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_WORD_PREFIX: u8 = 0x0B;
+const AML_DWORD_PREFIX: u8 = 0x0C;
+const AML_QWORD_PREFIX: u8 = 0x0E;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_ONES_OP: u8 = 0xFF;
+const AML_ROOT_CHAR: u8 = b'\\';
+
+// an integer constant at *offset, moving past it
+fn aml_integer(b: &[u8], offset: &mut usize) -> Option<u64> {
+    let op = *b.get(*offset)?;
+    *offset += 1;
+
+    let (value, size) = match op {
+        AML_ZERO_OP => (ZERO_U64, 0),
+        AML_ONE_OP => (1, 0),
+        AML_ONES_OP => (u64::MAX, 0),
+        AML_BYTE_PREFIX => (read_u8(b, *offset) as u64, 1),
+        AML_WORD_PREFIX => (read_u16(b, *offset) as u64, 2),
+        AML_DWORD_PREFIX => (read_u32(b, *offset) as u64, 4),
+        AML_QWORD_PREFIX => (read_u64(b, *offset), 8),
+        _ => return None,
+    };
+
+    if *offset + size > b.len() {
+        return None;
+    }
+
+    *offset += size;
+
+    Some(value)
+}
+
+// looks for Name(\_Sx, Package() { SLP_TYPa, SLP_TYPb, ... }) in one table
+fn aml_find_sleep_package(b: &[u8], name: &[u8; 4]) -> Option<(u8, u8)> {
+    let mut i = ACPI_SDT_HEADER_LENGTH;
+
+    while i + 4 <= b.len() {
+        if b[i..i + 4] != *name {
+            i += 1;
+            continue;
+        }
+
+        // has to be a definition (NameOp, maybe rooted), not a reference
+        let defined = match (i.checked_sub(1).map(|j| b[j]), i.checked_sub(2).map(|j| b[j])) {
+            (Some(AML_NAME_OP), _) => true,
+            (Some(AML_ROOT_CHAR), Some(AML_NAME_OP)) => true,
+            _ => false,
+        };
+
+        let mut offset = i + 4;
+        i += 1;
+
+        if !defined || b.get(offset) != Some(&AML_PACKAGE_OP) {
+            continue;
+        }
+
+        // PkgLength: the top two bits of the lead byte count the bytes after it
+        offset += 1;
+        let pkg_length_bytes = (read_u8(b, offset) >> 6) as usize;
+        offset += 1 + pkg_length_bytes;
+
+        // NumElements
+        offset += 1;
+
+        let slp_typ_a = aml_integer(b, &mut offset)?;
+        let slp_typ_b = aml_integer(b, &mut offset).unwrap_or(ZERO_U64);
+
+        return Some((slp_typ_a as u8, slp_typ_b as u8));
+    }
+
+    None
+}
+
+// the (SLP_TYPa, SLP_TYPb) pair for sleep state sx, from the dsdt or an ssdt
+pub fn acpi_sleep_type(state: u8) -> Option<(u8, u8)> {
+    let name = [b'_', b'S', b'0' + state, b'_'];
+
+    acpi_tables()
+        .iter()
+        .filter(|t| t.signature == ACPI_SIG_DSDT || t.signature == ACPI_SIG_SSDT)
+        .find_map(|t| aml_find_sleep_package(t.bytes(), &name))
+}
+
+// firmware that boots in legacy mode wants asking (via the smi port) before
+// it'll let go of the pm registers
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn acpi_enable(fadt: &Fadt) {
+    let sci_enabled = || fadt.pm1a_control_block.read().map_or(false, |v| v & PM1_CNT_SCI_EN != 0);
+
+    if sci_enabled() || fadt.smi_command_port == ZERO_U32 || fadt.acpi_enable == 0 {
+        return;
+    }
+
+    crate::arch::x86::asm::x86_outport8(fadt.smi_command_port as u16, fadt.acpi_enable);
+
+    for _ in 0..ACPI_ENABLE_SPINS {
+        if sci_enabled() {
+            return;
+        }
+
+        core::hint::spin_loop();
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn acpi_enable(_fadt: &Fadt) {}
+
+// puts the machine into sleep state sx (only S5 is of any use without a way
+// back); returns false if acpi can't. on success the caller should give the
+// hardware a moment before trying something else.
+pub fn acpi_enter_sleep_state(state: u8) -> bool {
+    let fadt = match acpi_fadt() {
+        Some(fadt) => fadt,
+        None => return false,
+    };
+
+    let (slp_typ_a, slp_typ_b) = match acpi_sleep_type(state) {
+        Some(slp_typ) => slp_typ,
+        None => {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("acpi: no \\_S{} package; can't enter S{}", state, state);
+            return false;
+        },
+    };
+
+    if fadt.hardware_reduced() {
+        return fadt.sleep_control_reg.write(((slp_typ_a as u64) << SLEEP_CONTROL_SLP_TYP_SHIFT) | SLEEP_CONTROL_SLP_EN);
+    }
+
+    if !fadt.pm1a_control_block.is_present() {
+        return false;
+    }
+
+    acpi_enable(&fadt);
+
+    let mut entered = false;
+
+    for (block, slp_typ) in [(&fadt.pm1a_control_block, slp_typ_a), (&fadt.pm1b_control_block, slp_typ_b)] {
+        let value = match block.read() {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let value = (value & !PM1_CNT_SLP_TYP_MASK) | ((slp_typ as u64) << PM1_CNT_SLP_TYP_SHIFT) | PM1_CNT_SLP_EN;
+
+        entered |= block.write(value);
+    }
+
+    entered
+}
+
+// resets the machine via the fadt's reset register; returns false if there
+// isn't one. like the above, give it a moment.
+pub fn acpi_reset() -> bool {
+    match acpi_fadt() {
+        Some(fadt) if fadt.reset_supported() => fadt.reset_reg.write(fadt.reset_value as u64),
+        _ => false,
+    }
+}
@@ -0,0 +1,343 @@
+use core::arch::asm;
+
+//==========================================================
+// VOID aarch_nop()
+//==========================================================
+#[inline(always)]
+pub fn aarch_noop() {
+    unsafe {
+        asm!("nop", 
+        options(nostack, nomem));
+    }
+}
+
+//==========================================================
+// UINTN aarch_popcount(usize value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_popcount(value: usize) -> usize {
+    let mut popcount: usize;
+
+    unsafe {
+        asm!("cnt {0:w}, {1:x}", 
+        in(reg) value,
+        lateout(reg) popcount,
+        options(nostack, nomem));
+    }
+    popcount
+}
+
+//==========================================================
+// VOID aarch_write_vbar_el1(UINTN vector_table)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_vbar_el1(vector_table: usize) {
+    unsafe {
+        asm!("msr vbar_el1, {0}",
+        "isb",
+        in(reg) vector_table,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// UINTN aarch_read_vbar_el1()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_vbar_el1() -> usize {
+    let vbar: usize;
+
+    unsafe {
+        asm!("mrs {0}, vbar_el1",
+        out(reg) vbar,
+        options(nostack, nomem));
+    }
+    vbar
+}
+
+//==========================================================
+// UINTN aarch_read_current_el()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_current_el() -> usize {
+    let current_el: usize;
+
+    unsafe {
+        asm!("mrs {0}, CurrentEL",
+        out(reg) current_el,
+        options(nostack, nomem));
+    }
+    (current_el >> 2) & 0x3
+}
+
+//==========================================================
+// VOID aarch_enable_interrupts()
+//==========================================================
+#[inline(always)]
+pub fn aarch_enable_interrupts() {
+    unsafe {
+        asm!("msr daifclr, #2",
+        options(nostack, nomem));
+    }
+}
+
+//==========================================================
+// VOID aarch_disable_interrupts()
+//==========================================================
+#[inline(always)]
+pub fn aarch_disable_interrupts() {
+    unsafe {
+        asm!("msr daifset, #2",
+        options(nostack, nomem));
+    }
+}
+
+//==========================================================
+// UINTN aarch_read_daif()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_daif() -> usize {
+    let daif: usize;
+
+    unsafe {
+        asm!("mrs {0}, daif",
+        out(reg) daif,
+        options(nostack, nomem));
+    }
+    daif
+}
+
+//==========================================================
+// UINTN aarch_read_mpidr_el1()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_mpidr_el1() -> usize {
+    let value: usize;
+
+    unsafe {
+        asm!("mrs {0}, mpidr_el1",
+        out(reg) value,
+        options(nostack, nomem));
+    }
+    value
+}
+
+//==========================================================
+// UINTN aarch_read_id_aa64pfr0_el1()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_id_aa64pfr0_el1() -> usize {
+    let value: usize;
+
+    unsafe {
+        asm!("mrs {0}, id_aa64pfr0_el1",
+        out(reg) value,
+        options(nostack, nomem));
+    }
+    value
+}
+
+//==========================================================
+// UINTN aarch_read_icc_sre_el1()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_icc_sre_el1() -> usize {
+    let value: usize;
+
+    unsafe {
+        asm!("mrs {0}, S3_0_C12_C12_5",
+        out(reg) value,
+        options(nostack, nomem));
+    }
+    value
+}
+
+//==========================================================
+// UINTN aarch_read_icc_iar1_el1()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_icc_iar1_el1() -> usize {
+    let value: usize;
+
+    unsafe {
+        asm!("mrs {0}, S3_0_C12_C12_0",
+        out(reg) value,
+        options(nostack, nomem));
+    }
+    value
+}
+
+//==========================================================
+// UINTN aarch_read_cntfrq_el0()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_cntfrq_el0() -> usize {
+    let value: usize;
+
+    unsafe {
+        asm!("mrs {0}, cntfrq_el0",
+        out(reg) value,
+        options(nostack, nomem));
+    }
+    value
+}
+
+//==========================================================
+// UINTN aarch_read_cntvct_el0()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_cntvct_el0() -> usize {
+    let value: usize;
+
+    unsafe {
+        asm!("mrs {0}, cntvct_el0",
+        out(reg) value,
+        options(nostack, nomem));
+    }
+    value
+}
+
+//==========================================================
+// UINTN aarch_read_cntpct_el0()
+//==========================================================
+#[inline(always)]
+pub fn aarch_read_cntpct_el0() -> usize {
+    let value: usize;
+
+    unsafe {
+        asm!("mrs {0}, cntpct_el0",
+        out(reg) value,
+        options(nostack, nomem));
+    }
+    value
+}
+
+//==========================================================
+// VOID aarch_write_icc_sre_el1(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_icc_sre_el1(value: usize) {
+    unsafe {
+        asm!("msr S3_0_C12_C12_5, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_icc_pmr_el1(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_icc_pmr_el1(value: usize) {
+    unsafe {
+        asm!("msr S3_0_C4_C6_0, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_icc_bpr1_el1(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_icc_bpr1_el1(value: usize) {
+    unsafe {
+        asm!("msr S3_0_C12_C12_3, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_icc_igrpen1_el1(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_icc_igrpen1_el1(value: usize) {
+    unsafe {
+        asm!("msr S3_0_C12_C12_7, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_icc_eoir1_el1(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_icc_eoir1_el1(value: usize) {
+    unsafe {
+        asm!("msr S3_0_C12_C12_1, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_icc_sgi1r_el1(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_icc_sgi1r_el1(value: usize) {
+    unsafe {
+        asm!("msr S3_0_C12_C11_5, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_cntv_tval_el0(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_cntv_tval_el0(value: usize) {
+    unsafe {
+        asm!("msr cntv_tval_el0, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_cntv_ctl_el0(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_cntv_ctl_el0(value: usize) {
+    unsafe {
+        asm!("msr cntv_ctl_el0, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_cntp_tval_el0(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_cntp_tval_el0(value: usize) {
+    unsafe {
+        asm!("msr cntp_tval_el0, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
+
+//==========================================================
+// VOID aarch_write_cntp_ctl_el0(UINTN value)
+//==========================================================
+#[inline(always)]
+pub fn aarch_write_cntp_ctl_el0(value: usize) {
+    unsafe {
+        asm!("msr cntp_ctl_el0, {0}",
+        "isb",
+        in(reg) value,
+        options(nostack));
+    }
+}
@@ -0,0 +1,410 @@
+#![cfg(target_arch = "aarch64")]
+
+// Purpose: the el1 exception vector table (vbar_el1). Every entry saves the
+// full register frame & calls into aa64_exception_dispatch(); synchronous
+// exceptions get their esr_el1 / far_el1 decoded & reported the same way
+// the x86 side reports its exceptions, irqs go to whichever interrupt
+// controller driver hooked them.
+
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::common::base::*;
+use crate::arch::aa64::asm::*;
+use crate::status::KernelServiceStatus;
+
+// exception classes (esr_el1.ec)
+pub const AA64_EC_UNKNOWN: u32 = 0x00;
+pub const AA64_EC_WFX: u32 = 0x01;
+pub const AA64_EC_SIMD_FP: u32 = 0x07;
+pub const AA64_EC_ILLEGAL_STATE: u32 = 0x0E;
+pub const AA64_EC_SVC64: u32 = 0x15;
+pub const AA64_EC_HVC64: u32 = 0x16;
+pub const AA64_EC_SMC64: u32 = 0x17;
+pub const AA64_EC_SYSREG: u32 = 0x18;
+pub const AA64_EC_INST_ABORT_LOWER: u32 = 0x20;
+pub const AA64_EC_INST_ABORT_SAME: u32 = 0x21;
+pub const AA64_EC_PC_ALIGNMENT: u32 = 0x22;
+pub const AA64_EC_DATA_ABORT_LOWER: u32 = 0x24;
+pub const AA64_EC_DATA_ABORT_SAME: u32 = 0x25;
+pub const AA64_EC_SP_ALIGNMENT: u32 = 0x26;
+pub const AA64_EC_FP64: u32 = 0x2C;
+pub const AA64_EC_SERROR: u32 = 0x2F;
+pub const AA64_EC_BREAKPOINT_LOWER: u32 = 0x30;
+pub const AA64_EC_BREAKPOINT_SAME: u32 = 0x31;
+pub const AA64_EC_STEP_LOWER: u32 = 0x32;
+pub const AA64_EC_STEP_SAME: u32 = 0x33;
+pub const AA64_EC_WATCHPOINT_LOWER: u32 = 0x34;
+pub const AA64_EC_WATCHPOINT_SAME: u32 = 0x35;
+pub const AA64_EC_BRK64: u32 = 0x3C;
+
+// abort iss bits
+const AA64_ISS_ABORT_WNR: u32 = 1 << 6;
+const AA64_ISS_ABORT_S1PTW: u32 = 1 << 7;
+const AA64_ISS_ABORT_CM: u32 = 1 << 8;
+const AA64_ISS_ABORT_EA: u32 = 1 << 9;
+const AA64_ISS_ABORT_FNV: u32 = 1 << 10;
+const AA64_ISS_ABORT_FSC_MASK: u32 = 0x3F;
+
+// the size of an instruction, for stepping over brk
+const AA64_INSTRUCTION_SIZE: u64 = 4;
+
+// the 16 vectors, in table order
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExceptionKind {
+    Sync,
+    Irq,
+    Fiq,
+    SError,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExceptionOrigin {
+    CurrentElSp0,
+    CurrentElSpx,
+    LowerElAarch64,
+    LowerElAarch32,
+}
+
+// the register frame the vector entries build on the stack; the layout is
+// shared with the assembly below, so don't reorder it
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ExceptionFrame {
+    pub x: [u64; 31],
+    pub sp_el0: u64,
+    pub elr: u64,
+    pub spsr: u64,
+    pub esr: u64,
+    pub far: u64,
+}
+
+pub const AA64_EXCEPTION_FRAME_SIZE: usize = core::mem::size_of::<ExceptionFrame>();
+
+// the vector entries hardcode the frame size (& sp has to stay 16 byte aligned)
+const _: () = assert!(AA64_EXCEPTION_FRAME_SIZE == 288);
+
+// a decoded esr_el1
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExceptionSyndrome {
+    pub ec: u32,
+    pub il: bool,
+    pub iss: u32,
+}
+
+impl ExceptionSyndrome {
+    pub fn from_esr(esr: u64) -> Self {
+        ExceptionSyndrome {
+            ec: ((esr >> 26) & 0x3F) as u32,
+            il: (esr >> 25) & 1 == 1,
+            iss: (esr & 0x01FF_FFFF) as u32,
+        }
+    }
+
+    pub fn is_abort(&self) -> bool {
+        matches!(self.ec, AA64_EC_INST_ABORT_LOWER | AA64_EC_INST_ABORT_SAME |
+                          AA64_EC_DATA_ABORT_LOWER | AA64_EC_DATA_ABORT_SAME)
+    }
+
+    pub fn is_data_abort(&self) -> bool {
+        self.ec == AA64_EC_DATA_ABORT_LOWER || self.ec == AA64_EC_DATA_ABORT_SAME
+    }
+
+    // the fault status code of an abort
+    pub fn fault_status(&self) -> u32 {
+        self.iss & AA64_ISS_ABORT_FSC_MASK
+    }
+
+    // far_el1 only holds the faulting address when this is clear
+    pub fn far_valid(&self) -> bool {
+        self.iss & AA64_ISS_ABORT_FNV == 0
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.is_data_abort() && self.iss & AA64_ISS_ABORT_WNR != 0
+    }
+}
+
+pub fn aa64_exception_class_name(ec: u32) -> &'static str {
+    match ec {
+        AA64_EC_UNKNOWN => "Unknown Reason",
+        AA64_EC_WFX => "Trapped WFI/WFE",
+        AA64_EC_SIMD_FP => "SIMD/FP Access Trap",
+        AA64_EC_ILLEGAL_STATE => "Illegal Execution State",
+        AA64_EC_SVC64 => "SVC",
+        AA64_EC_HVC64 => "HVC",
+        AA64_EC_SMC64 => "SMC",
+        AA64_EC_SYSREG => "Trapped MSR/MRS/System Instruction",
+        AA64_EC_INST_ABORT_LOWER => "Instruction Abort (Lower EL)",
+        AA64_EC_INST_ABORT_SAME => "Instruction Abort",
+        AA64_EC_PC_ALIGNMENT => "PC Alignment Fault",
+        AA64_EC_DATA_ABORT_LOWER => "Data Abort (Lower EL)",
+        AA64_EC_DATA_ABORT_SAME => "Data Abort",
+        AA64_EC_SP_ALIGNMENT => "SP Alignment Fault",
+        AA64_EC_FP64 => "Floating-Point Exception",
+        AA64_EC_SERROR => "SError",
+        AA64_EC_BREAKPOINT_LOWER | AA64_EC_BREAKPOINT_SAME => "Breakpoint",
+        AA64_EC_STEP_LOWER | AA64_EC_STEP_SAME => "Software Step",
+        AA64_EC_WATCHPOINT_LOWER | AA64_EC_WATCHPOINT_SAME => "Watchpoint",
+        AA64_EC_BRK64 => "BRK Instruction",
+        _ => "Reserved",
+    }
+}
+
+pub fn aa64_fault_status_name(fsc: u32) -> &'static str {
+    match fsc {
+        0x00..=0x03 => "address size fault",
+        0x04..=0x07 => "translation fault",
+        0x08..=0x0B => "access flag fault",
+        0x0C..=0x0F => "permission fault",
+        0x10 => "synchronous external abort",
+        0x11 => "synchronous tag check fault",
+        0x14..=0x17 => "synchronous external abort on table walk",
+        0x18 => "synchronous parity/ecc error",
+        0x1C..=0x1F => "synchronous parity/ecc error on table walk",
+        0x21 => "alignment fault",
+        0x30 => "tlb conflict abort",
+        0x31 => "unsupported atomic hardware update",
+        _ => "reserved fault status",
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// the vector table; each entry is 0x80 bytes, which is too small for the whole
+// save, so the entries just make room, stash x0/x1, and branch to the common path
+global_asm!(
+    ".macro aa64_exception_entry kind",
+    ".balign 0x80",
+    "sub sp, sp, #288",
+    "stp x0, x1, [sp, #0]",
+    "mov x0, #\\kind",
+    "b aa64_exception_common",
+    ".endm",
+    "",
+    ".pushsection .text.aa64_vectors, \"ax\"",
+    ".balign 0x800",
+    ".global aa64_exception_vector_table",
+    "aa64_exception_vector_table:",
+    // current el, sp_el0
+    "aa64_exception_entry 0",
+    "aa64_exception_entry 1",
+    "aa64_exception_entry 2",
+    "aa64_exception_entry 3",
+    // current el, sp_elx
+    "aa64_exception_entry 4",
+    "aa64_exception_entry 5",
+    "aa64_exception_entry 6",
+    "aa64_exception_entry 7",
+    // lower el, aarch64
+    "aa64_exception_entry 8",
+    "aa64_exception_entry 9",
+    "aa64_exception_entry 10",
+    "aa64_exception_entry 11",
+    // lower el, aarch32
+    "aa64_exception_entry 12",
+    "aa64_exception_entry 13",
+    "aa64_exception_entry 14",
+    "aa64_exception_entry 15",
+    "",
+    "aa64_exception_common:",
+    "stp x2, x3, [sp, #16]",
+    "stp x4, x5, [sp, #32]",
+    "stp x6, x7, [sp, #48]",
+    "stp x8, x9, [sp, #64]",
+    "stp x10, x11, [sp, #80]",
+    "stp x12, x13, [sp, #96]",
+    "stp x14, x15, [sp, #112]",
+    "stp x16, x17, [sp, #128]",
+    "stp x18, x19, [sp, #144]",
+    "stp x20, x21, [sp, #160]",
+    "stp x22, x23, [sp, #176]",
+    "stp x24, x25, [sp, #192]",
+    "stp x26, x27, [sp, #208]",
+    "stp x28, x29, [sp, #224]",
+    "mrs x2, sp_el0",
+    "stp x30, x2, [sp, #240]",
+    "mrs x2, elr_el1",
+    "mrs x3, spsr_el1",
+    "stp x2, x3, [sp, #256]",
+    "mrs x2, esr_el1",
+    "mrs x3, far_el1",
+    "stp x2, x3, [sp, #272]",
+    "mov x1, x0",
+    "mov x0, sp",
+    "bl aa64_exception_dispatch",
+    // the dispatcher may have moved elr (e.g. to step over a brk)
+    "ldp x2, x3, [sp, #256]",
+    "msr elr_el1, x2",
+    "msr spsr_el1, x3",
+    "ldp x30, x2, [sp, #240]",
+    "msr sp_el0, x2",
+    "ldp x0, x1, [sp, #0]",
+    "ldp x2, x3, [sp, #16]",
+    "ldp x4, x5, [sp, #32]",
+    "ldp x6, x7, [sp, #48]",
+    "ldp x8, x9, [sp, #64]",
+    "ldp x10, x11, [sp, #80]",
+    "ldp x12, x13, [sp, #96]",
+    "ldp x14, x15, [sp, #112]",
+    "ldp x16, x17, [sp, #128]",
+    "ldp x18, x19, [sp, #144]",
+    "ldp x20, x21, [sp, #160]",
+    "ldp x22, x23, [sp, #176]",
+    "ldp x24, x25, [sp, #192]",
+    "ldp x26, x27, [sp, #208]",
+    "ldp x28, x29, [sp, #224]",
+    "add sp, sp, #288",
+    "eret",
+    ".popsection",
+);
+
+extern "C" {
+    static aa64_exception_vector_table: u8;
+}
+
+//-----------------------------------------------------------------------------------
+
+// irqs belong to the interrupt controller driver; it hooks them here. kept
+// as a raw fn pointer (0 = none) so the vector path never takes a lock
+pub type IrqHandler = fn(&mut ExceptionFrame);
+
+static IRQ_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+// installs (or with None, removes) the irq handler; returns the previous one
+pub fn set_irq_handler(handler: Option<IrqHandler>) -> Option<IrqHandler> {
+    let new_raw = match handler {
+        Some(f) => f as usize,
+        None => ZERO_USIZE,
+    };
+
+    match IRQ_HANDLER.swap(new_raw, Ordering::SeqCst) {
+        ZERO_USIZE => None,
+        old_raw => Some(unsafe { core::mem::transmute::<usize, IrqHandler>(old_raw) }),
+    }
+}
+
+fn irq_handler() -> Option<IrqHandler> {
+    match IRQ_HANDLER.load(Ordering::SeqCst) {
+        ZERO_USIZE => None,
+        raw => Some(unsafe { core::mem::transmute::<usize, IrqHandler>(raw) }),
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+fn aa64_exception_report(kind: ExceptionKind, origin: ExceptionOrigin, frame: &ExceptionFrame) {
+    let syndrome = ExceptionSyndrome::from_esr(frame.esr);
+
+    serial_println!(
+        "exception: {:?} from {:?}, ec 0x{:02x} ({}), il: {}, iss: 0x{:07x}",
+        kind,
+        origin,
+        syndrome.ec,
+        aa64_exception_class_name(syndrome.ec),
+        if syndrome.il { 32 } else { 16 },
+        syndrome.iss
+    );
+
+    if kind == ExceptionKind::Sync && syndrome.is_abort() {
+        let fsc = syndrome.fault_status();
+
+        let access = if !syndrome.is_data_abort() { "instruction fetch" } else if syndrome.is_write() { "write" } else { "read" };
+
+        if syndrome.far_valid() {
+            serial_println!("  {} on {} @ 0x{:016x}", aa64_fault_status_name(fsc), access, frame.far);
+        } else {
+            serial_println!("  {} on {} @ unknown address (far not valid)", aa64_fault_status_name(fsc), access);
+        }
+
+        if syndrome.iss & AA64_ISS_ABORT_S1PTW != 0 {
+            serial_println!("  fault during a stage 1 translation table walk");
+        }
+
+        if syndrome.iss & (AA64_ISS_ABORT_CM | AA64_ISS_ABORT_EA) != 0 {
+            serial_println!("  cache maintenance / external abort");
+        }
+
+        // translation, access flag & permission faults carry the table level
+        if (0x04..=0x0F).contains(&fsc) {
+            serial_println!("  fault at translation level {}", fsc & 0x3);
+        }
+    }
+
+    serial_println!("  elr: 0x{:016x}  spsr: 0x{:016x}  far: 0x{:016x}", frame.elr, frame.spsr, frame.far);
+    serial_println!("  sp_el0: 0x{:016x}  lr: 0x{:016x}", frame.sp_el0, frame.x[30]);
+
+    for i in (0..30).step_by(2) {
+        serial_println!("  x{:<2}: 0x{:016x}  x{:<2}: 0x{:016x}", i, frame.x[i], i + 1, frame.x[i + 1]);
+    }
+}
+
+#[no_mangle]
+extern "C" fn aa64_exception_dispatch(frame: &mut ExceptionFrame, raw_kind: u64) {
+    let origin = match raw_kind >> 2 {
+        0 => ExceptionOrigin::CurrentElSp0,
+        1 => ExceptionOrigin::CurrentElSpx,
+        2 => ExceptionOrigin::LowerElAarch64,
+        _ => ExceptionOrigin::LowerElAarch32,
+    };
+
+    let kind = match raw_kind & 0x3 {
+        0 => ExceptionKind::Sync,
+        1 => ExceptionKind::Irq,
+        2 => ExceptionKind::Fiq,
+        _ => ExceptionKind::SError,
+    };
+
+    if kind == ExceptionKind::Irq {
+        if let Some(handler) = irq_handler() {
+            handler(frame);
+            return;
+        }
+    }
+
+    aa64_exception_report(kind, origin, frame);
+
+    // brk & the debug exceptions are benign; step over a brk so we don't loop on it
+    if kind == ExceptionKind::Sync {
+        match ExceptionSyndrome::from_esr(frame.esr).ec {
+            AA64_EC_BRK64 => {
+                frame.elr += AA64_INSTRUCTION_SIZE;
+                return;
+            },
+            AA64_EC_BREAKPOINT_SAME | AA64_EC_STEP_SAME | AA64_EC_WATCHPOINT_SAME => return,
+            _ => {},
+        }
+    }
+
+    panic!("unhandled {:?} exception from {:?} @ 0x{:016x}", kind, origin, frame.elr);
+}
+
+//-----------------------------------------------------------------------------------
+
+// points vbar_el1 at our vector table
+pub fn aa64_exceptions_init() {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let current_el = aarch_read_current_el();
+
+    if current_el != 1 {
+        panic!("aa64_exceptions_init() -> running at el{}, expected el1", current_el);
+    }
+
+    let table = unsafe { core::ptr::addr_of!(aa64_exception_vector_table) as usize };
+    aarch_write_vbar_el1(table);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("aa64_exceptions_init() -> vbar_el1 = 0x{:016x}", aarch_read_vbar_el1());
+
+    iron().unwrap().status.lock_rw_spin().as_mut().unwrap().internal_exceptions = KernelServiceStatus::SysOnly;
+}
@@ -0,0 +1,470 @@
+#![cfg(target_arch = "aarch64")]
+
+// Purpose: the arm generic interrupt controller. We figure out whether we're
+// looking at a GICv2 (mmio cpu interface) or a GICv3 (redistributors + system
+// register cpu interface), bring up the distributor & this cpu's interface, and
+// hook the irq vector so acknowledged interrupts land in crate::interrupts.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::common::base::*;
+use crate::arch::aa64::asm::*;
+use crate::arch::aa64::exception::*;
+use crate::interrupts::{interrupt_dispatch, InterruptVector};
+use crate::status::KernelServiceStatus;
+
+// where qemu's virt machine puts things, until we can ask the firmware tables
+pub const AA64_GIC_QEMU_VIRT_DIST_BASE: usize = 0x0800_0000;
+pub const AA64_GIC_QEMU_VIRT_CPU_BASE: usize = 0x0801_0000;
+pub const AA64_GIC_QEMU_VIRT_V2M_BASE: usize = 0x0802_0000;
+pub const AA64_GIC_QEMU_VIRT_REDIST_BASE: usize = 0x080A_0000;
+
+// interrupt id ranges
+pub const AA64_GIC_SGI_COUNT: u32 = 16;
+pub const AA64_GIC_PPI_FIRST: u32 = 16;
+pub const AA64_GIC_SPI_FIRST: u32 = 32;
+pub const AA64_GIC_MAX_INTID: u32 = 1019;
+pub const AA64_GIC_SPURIOUS_INTID: u32 = 1023;
+
+// distributor registers
+const GICD_CTLR: usize = 0x0000;
+const GICD_TYPER: usize = 0x0004;
+const GICD_SETSPI_NSR: usize = 0x0040;
+const GICD_IGROUPR: usize = 0x0080;
+const GICD_ISENABLER: usize = 0x0100;
+const GICD_ICENABLER: usize = 0x0180;
+const GICD_ICPENDR: usize = 0x0280;
+const GICD_IPRIORITYR: usize = 0x0400;
+const GICD_ITARGETSR: usize = 0x0800;
+const GICD_ICFGR: usize = 0x0C00;
+const GICD_SGIR: usize = 0x0F00;
+const GICD_IROUTER: usize = 0x6100;
+const GICD_PIDR2: usize = 0xFFE8;
+
+const GICD_CTLR_ENABLE_GRP0: u32 = 1 << 0;
+const GICD_CTLR_ENABLE_GRP1: u32 = 1 << 1;
+const GICD_CTLR_ARE_NS: u32 = 1 << 4;
+const GICD_CTLR_RWP: u32 = 1 << 31;
+const GICD_TYPER_MBIS: u32 = 1 << 16;
+
+// gicv2 cpu interface registers
+const GICC_CTLR: usize = 0x0000;
+const GICC_PMR: usize = 0x0004;
+const GICC_BPR: usize = 0x0008;
+const GICC_IAR: usize = 0x000C;
+const GICC_EOIR: usize = 0x0010;
+
+// gicv2m msi frame
+const GICV2M_MSI_TYPER: usize = 0x0008;
+const GICV2M_MSI_SETSPI_NS: usize = 0x0040;
+
+// gicv3 redistributor; each cpu has an rd frame followed by an sgi frame
+const GICR_FRAME_SIZE: usize = 0x2_0000;
+const GICR_SGI_OFFSET: usize = 0x1_0000;
+const GICR_WAKER: usize = 0x0014;
+const GICR_TYPER: usize = 0x0008;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+const GICR_TYPER_LAST: u64 = 1 << 4;
+
+// icc_sre_el1.sre
+const ICC_SRE_ENABLE: usize = 1 << 0;
+
+// let everything through the priority mask
+const AA64_GIC_PRIORITY_MASK_ALL: u32 = 0xFF;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GicVersion {
+    V2,
+    V3,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Gic {
+    version: GicVersion,
+    dist_base: usize,
+    // the gicv2 cpu interface, or this cpu's gicv3 redistributor frame
+    cpu_base: usize,
+    // the gicv2m msi frame, if there is one
+    v2m_base: Option<usize>,
+    line_count: u32,
+}
+
+//-----------------------------------------------------------------------------------
+
+#[inline(always)]
+fn mmio_read32(addr: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+#[inline(always)]
+fn mmio_write32(addr: usize, value: u32) {
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) }
+}
+
+#[inline(always)]
+fn mmio_read64(addr: usize) -> u64 {
+    unsafe { core::ptr::read_volatile(addr as *const u64) }
+}
+
+#[inline(always)]
+fn mmio_write64(addr: usize, value: u64) {
+    unsafe { core::ptr::write_volatile(addr as *mut u64, value) }
+}
+
+#[inline(always)]
+fn mmio_write8(addr: usize, value: u8) {
+    unsafe { core::ptr::write_volatile(addr as *mut u8, value) }
+}
+
+// this cpu's affinity in the mpidr_el1 layout (aff3 in bits 32-39)
+fn current_affinity() -> u64 {
+    (aarch_read_mpidr_el1() as u64) & 0x0000_00FF_00FF_FFFF
+}
+
+//-----------------------------------------------------------------------------------
+
+impl Gic {
+    // works out which gic sits at dist_base; the cpu interface base is
+    // only needed for a v2, the redistributor base only for a v3
+    pub fn detect(dist_base: usize, gicv2_cpu_base: usize, gicv3_redist_base: usize, v2m_base: Option<usize>) -> Option<Gic> {
+        let arch_rev = (mmio_read32(dist_base + GICD_PIDR2) >> 4) & 0xF;
+
+        // id_aa64pfr0_el1.gic says whether the system register interface exists
+        let has_sysregs = (aarch_read_id_aa64pfr0_el1() >> 24) & 0xF != 0;
+
+        let version = match arch_rev {
+            1 | 2 => GicVersion::V2,
+            3 | 4 if has_sysregs => GicVersion::V3,
+            _ => {
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("Gic::detect() -> unrecognized gic architecture revision {}", arch_rev);
+                return None;
+            },
+        };
+
+        let line_count = ((mmio_read32(dist_base + GICD_TYPER) & 0x1F) + 1) * 32;
+
+        let cpu_base = match version {
+            GicVersion::V2 => gicv2_cpu_base,
+            GicVersion::V3 => Gic::find_redistributor(gicv3_redist_base)?,
+        };
+
+        Some(Gic {
+            version,
+            dist_base,
+            cpu_base,
+            v2m_base: if version == GicVersion::V2 { v2m_base } else { None },
+            line_count: line_count.min(AA64_GIC_MAX_INTID + 1),
+        })
+    }
+
+    // walks the redistributor frames looking for the one whose affinity matches ours
+    fn find_redistributor(redist_base: usize) -> Option<usize> {
+        let affinity = current_affinity();
+        let wanted = (affinity & 0xFF_FFFF) | ((affinity >> 32) << 24);
+        let mut frame = redist_base;
+
+        loop {
+            let typer = mmio_read64(frame + GICR_TYPER);
+
+            if typer >> 32 == wanted {
+                return Some(frame);
+            }
+
+            if typer & GICR_TYPER_LAST != 0 {
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("Gic::find_redistributor() -> no redistributor for affinity 0x{:0x}", wanted);
+                return None;
+            }
+
+            frame += GICR_FRAME_SIZE;
+        }
+    }
+
+    pub fn version(&self) -> GicVersion {
+        self.version
+    }
+
+    pub fn line_count(&self) -> u32 {
+        self.line_count
+    }
+
+    fn wait_for_rwp(&self) {
+        if self.version == GicVersion::V3 {
+            while mmio_read32(self.dist_base + GICD_CTLR) & GICD_CTLR_RWP != 0 {
+                aarch_noop();
+            }
+        }
+    }
+
+    // the per-interrupt banks for sgis & ppis live in the redistributor on a v3
+    fn banked_base(&self, intid: u32) -> usize {
+        if self.version == GicVersion::V3 && intid < AA64_GIC_SPI_FIRST {
+            self.cpu_base + GICR_SGI_OFFSET
+        } else {
+            self.dist_base
+        }
+    }
+
+    // global distributor setup: everything disabled, group 1, lowest priority
+    pub fn init_distributor(&self) {
+        mmio_write32(self.dist_base + GICD_CTLR, 0);
+        self.wait_for_rwp();
+
+        for i in (AA64_GIC_SPI_FIRST..self.line_count).step_by(32) {
+            let reg = (i / 32) as usize * 4;
+            mmio_write32(self.dist_base + GICD_ICENABLER + reg, u32::MAX);
+            mmio_write32(self.dist_base + GICD_ICPENDR + reg, u32::MAX);
+            mmio_write32(self.dist_base + GICD_IGROUPR + reg, u32::MAX);
+        }
+
+        for i in AA64_GIC_SPI_FIRST..self.line_count {
+            mmio_write8(self.dist_base + GICD_IPRIORITYR + i as usize, 0xA0);
+        }
+
+        self.wait_for_rwp();
+
+        match self.version {
+            GicVersion::V2 => mmio_write32(self.dist_base + GICD_CTLR, GICD_CTLR_ENABLE_GRP0 | GICD_CTLR_ENABLE_GRP1),
+            GicVersion::V3 => mmio_write32(self.dist_base + GICD_CTLR, GICD_CTLR_ARE_NS | GICD_CTLR_ENABLE_GRP1),
+        }
+
+        self.wait_for_rwp();
+    }
+
+    // per-cpu setup: wake the redistributor (v3) & turn on the cpu interface
+    pub fn init_cpu_interface(&self) {
+        match self.version {
+            GicVersion::V2 => {
+                mmio_write32(self.dist_base + GICD_ICENABLER, u32::MAX);
+                mmio_write32(self.cpu_base + GICC_PMR, AA64_GIC_PRIORITY_MASK_ALL);
+                mmio_write32(self.cpu_base + GICC_BPR, 0);
+                mmio_write32(self.cpu_base + GICC_CTLR, 1);
+            },
+            GicVersion::V3 => {
+                let waker = mmio_read32(self.cpu_base + GICR_WAKER);
+                mmio_write32(self.cpu_base + GICR_WAKER, waker & !GICR_WAKER_PROCESSOR_SLEEP);
+
+                while mmio_read32(self.cpu_base + GICR_WAKER) & GICR_WAKER_CHILDREN_ASLEEP != 0 {
+                    aarch_noop();
+                }
+
+                let sgi_base = self.cpu_base + GICR_SGI_OFFSET;
+                mmio_write32(sgi_base + GICD_ICENABLER, u32::MAX);
+                mmio_write32(sgi_base + GICD_IGROUPR, u32::MAX);
+
+                aarch_write_icc_sre_el1(aarch_read_icc_sre_el1() | ICC_SRE_ENABLE);
+                aarch_write_icc_pmr_el1(AA64_GIC_PRIORITY_MASK_ALL as usize);
+                aarch_write_icc_bpr1_el1(0);
+                aarch_write_icc_igrpen1_el1(1);
+            },
+        }
+    }
+
+    pub fn set_priority(&self, intid: u32, priority: u8) {
+        mmio_write8(self.banked_base(intid) + GICD_IPRIORITYR + intid as usize, priority);
+    }
+
+    // true = edge triggered, false = level; sgis are always edge
+    pub fn set_edge_triggered(&self, intid: u32, edge: bool) {
+        if intid < AA64_GIC_SGI_COUNT {
+            return;
+        }
+
+        let reg = self.banked_base(intid) + GICD_ICFGR + (intid / 16) as usize * 4;
+        let shift = (intid % 16) * 2 + 1;
+        let value = mmio_read32(reg);
+
+        mmio_write32(reg, if edge { value | (1 << shift) } else { value & !(1 << shift) });
+    }
+
+    // routes an spi to this cpu
+    pub fn route_to_current_cpu(&self, intid: u32) {
+        if intid < AA64_GIC_SPI_FIRST {
+            return;
+        }
+
+        match self.version {
+            GicVersion::V2 => {
+                // the itargetsr bytes for the private ids read back as this cpu's mask
+                let mask = mmio_read32(self.dist_base + GICD_ITARGETSR) as u8;
+                mmio_write8(self.dist_base + GICD_ITARGETSR + intid as usize, if mask == 0 { 1 } else { mask });
+            },
+            GicVersion::V3 => {
+                mmio_write64(self.dist_base + GICD_IROUTER + (intid - AA64_GIC_SPI_FIRST) as usize * 8, current_affinity());
+            },
+        }
+    }
+
+    pub fn enable(&self, intid: u32) {
+        let base = self.banked_base(intid);
+        mmio_write32(base + GICD_ISENABLER + (intid / 32) as usize * 4, 1 << (intid % 32));
+        self.wait_for_rwp();
+    }
+
+    pub fn disable(&self, intid: u32) {
+        let base = self.banked_base(intid);
+        mmio_write32(base + GICD_ICENABLER + (intid / 32) as usize * 4, 1 << (intid % 32));
+        self.wait_for_rwp();
+    }
+
+    // acknowledges the highest priority pending interrupt; returns its raw iar
+    // value (the id is in the low bits), AA64_GIC_SPURIOUS_INTID if none
+    #[inline(always)]
+    pub fn acknowledge(&self) -> u32 {
+        match self.version {
+            GicVersion::V2 => mmio_read32(self.cpu_base + GICC_IAR),
+            GicVersion::V3 => aarch_read_icc_iar1_el1() as u32,
+        }
+    }
+
+    #[inline(always)]
+    pub fn eoi(&self, iar: u32) {
+        match self.version {
+            GicVersion::V2 => mmio_write32(self.cpu_base + GICC_EOIR, iar),
+            GicVersion::V3 => aarch_write_icc_eoir1_el1(iar as usize),
+        }
+    }
+
+    // sends sgi intid to the cpu with the given affinity (v3) / cpu interface number (v2)
+    pub fn send_sgi(&self, intid: u32, target: u64) {
+        if intid >= AA64_GIC_SGI_COUNT {
+            return;
+        }
+
+        match self.version {
+            GicVersion::V2 => {
+                let target_list = 1u32 << (target & 0x7);
+                mmio_write32(self.dist_base + GICD_SGIR, (target_list << 16) | intid);
+            },
+            GicVersion::V3 => {
+                // target list is a bitmap of aff0 within the aff3.aff2.aff1 cluster
+                let aff0 = target & 0xF;
+                let aff1 = (target >> 8) & 0xFF;
+                let aff2 = (target >> 16) & 0xFF;
+                let aff3 = (target >> 32) & 0xFF;
+
+                let sgi1r = (1u64 << aff0) | (aff1 << 16) | ((intid as u64) << 24) | (aff2 << 32) | (aff3 << 48);
+                aarch_write_icc_sgi1r_el1(sgi1r as usize);
+            },
+        }
+    }
+
+    // sends sgi intid to every cpu but this one
+    pub fn send_sgi_all_but_self(&self, intid: u32) {
+        if intid >= AA64_GIC_SGI_COUNT {
+            return;
+        }
+
+        match self.version {
+            GicVersion::V2 => mmio_write32(self.dist_base + GICD_SGIR, (1 << 24) | intid),
+            GicVersion::V3 => aarch_write_icc_sgi1r_el1(((1u64 << 40) | ((intid as u64) << 24)) as usize),
+        }
+    }
+
+    // the spis a device may target with an msi write, if any
+    pub fn msi_range(&self) -> Option<(u32, u32)> {
+        match self.version {
+            GicVersion::V2 => {
+                let typer = mmio_read32(self.v2m_base? + GICV2M_MSI_TYPER);
+                let first = (typer >> 16) & 0x3FF;
+                let count = typer & 0x3FF;
+
+                if count == 0 { None } else { Some((first, first + count - 1)) }
+            },
+            GicVersion::V3 => {
+                if mmio_read32(self.dist_base + GICD_TYPER) & GICD_TYPER_MBIS != 0 {
+                    Some((AA64_GIC_SPI_FIRST, self.line_count - 1))
+                } else {
+                    None
+                }
+            },
+        }
+    }
+
+    // the doorbell a device writes an spi number to
+    pub fn msi_doorbell(&self) -> Option<usize> {
+        match self.version {
+            GicVersion::V2 => Some(self.v2m_base? + GICV2M_MSI_SETSPI_NS),
+            GicVersion::V3 => Some(self.dist_base + GICD_SETSPI_NSR),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// written once during bringup, read from interrupt context afterwards
+static mut KERNEL_GIC: Option<Gic> = None;
+
+pub fn kernel_gic() -> Option<&'static Gic> {
+    unsafe { (*core::ptr::addr_of!(KERNEL_GIC)).as_ref() }
+}
+
+// the raw iar of the interrupt being handled; on a v2 the eoi has to carry
+// the source cpu bits for sgis, which the dispatcher doesn't know about
+static GIC_IN_SERVICE_IAR: AtomicU32 = AtomicU32::new(AA64_GIC_SPURIOUS_INTID);
+
+// signals end of interrupt for intid
+pub fn aa64_gic_eoi(intid: u32) {
+    if let Some(gic) = kernel_gic() {
+        let iar = GIC_IN_SERVICE_IAR.load(Ordering::Relaxed);
+        gic.eoi(if iar & 0x3FF == intid { iar } else { intid });
+    }
+}
+
+// the irq vector's entry into the gic: acknowledge, dispatch, and let the
+// dispatcher eoi through the irq backend
+fn aa64_gic_irq(_frame: &mut ExceptionFrame) {
+    let gic = match kernel_gic() {
+        Some(gic) => gic,
+        None => return,
+    };
+
+    let iar = gic.acknowledge();
+    let intid = iar & 0x3FF;
+
+    if intid > AA64_GIC_MAX_INTID {
+        return;
+    }
+
+    GIC_IN_SERVICE_IAR.store(iar, Ordering::Relaxed);
+    interrupt_dispatch(intid as InterruptVector);
+}
+
+// finds & initializes the gic, and takes over the irq vector. every interrupt
+// stays disabled until somebody registers for it. returns false if there's no
+// gic we know how to drive.
+pub fn aa64_gic_init(dist_base: usize, gicv2_cpu_base: usize, gicv3_redist_base: usize, v2m_base: Option<usize>) -> bool {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return false;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let gic = match Gic::detect(dist_base, gicv2_cpu_base, gicv3_redist_base, v2m_base) {
+        Some(gic) => gic,
+        None => return false,
+    };
+
+    gic.init_distributor();
+    gic.init_cpu_interface();
+
+    unsafe {
+        KERNEL_GIC = Some(gic);
+    }
+
+    set_irq_handler(Some(aa64_gic_irq));
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("aa64_gic_init() -> {:?} @ 0x{:08x}, {} interrupt lines, cpu interface @ 0x{:08x}", gic.version, gic.dist_base, gic.line_count, gic.cpu_base);
+
+    iron().unwrap().status.lock_rw_spin().as_mut().unwrap().external_interrupts = KernelServiceStatus::SysOnly;
+
+    true
+}
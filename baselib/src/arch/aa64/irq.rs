@@ -0,0 +1,97 @@
+#![cfg(target_arch = "aarch64")]
+
+// Purpose: the aarch64 (gic) backend for the driver interrupt api in
+// crate::interrupts. Vectors are gic interrupt ids; drivers get the spis,
+// the sgis & ppis stay with the kernel. Unlike the apic, the gic takes a
+// priority per interrupt, so the priority a driver registers with is
+// programmed straight into the distributor.
+
+use crate::common::base::*;
+use crate::arch::aa64::asm::*;
+use crate::arch::aa64::gic::*;
+use crate::interrupts::{InterruptVector, MsiMessage};
+
+// the number of vectors the dispatcher has to track
+pub const ARCH_INTERRUPT_VECTOR_COUNT: usize = (AA64_GIC_MAX_INTID + 1) as usize;
+
+// daif.i
+const AA64_DAIF_IRQ_MASKED: usize = 1 << 7;
+
+//-----------------------------------------------------------------------------------
+
+// true if drivers may register on this vector
+pub fn arch_interrupt_vector_is_dynamic(vector: InterruptVector) -> bool {
+    match kernel_gic() {
+        Some(gic) => vector >= AA64_GIC_SPI_FIRST && vector < gic.line_count(),
+        None => false,
+    }
+}
+
+// the vectors allocate_vector() picks from; on the gic that's whatever spis msis
+// can reach, independent of priority (which is set per interrupt at install time)
+pub fn arch_interrupt_priority_range(_priority: Priority) -> (InterruptVector, InterruptVector) {
+    match kernel_gic().and_then(|gic| gic.msi_range()) {
+        Some((first, last)) => (first, last),
+        // an empty range
+        None => (1, 0),
+    }
+}
+
+// lower values are more urgent on the gic
+fn aa64_gic_priority(priority: Priority) -> u8 {
+    match priority {
+        Priority::System => 0x20,
+        Priority::Highest => 0x40,
+        Priority::High => 0x60,
+        Priority::Normal => 0x80,
+        Priority::Low => 0xA0,
+        Priority::Anonymous => 0xC0,
+        Priority::Lowest => 0xE0,
+    }
+}
+
+// sets the interrupt's priority, routes it here & enables it
+pub fn arch_interrupt_install(vector: InterruptVector, priority: Priority) {
+    if let Some(gic) = kernel_gic() {
+        gic.set_priority(vector, aa64_gic_priority(priority));
+        gic.route_to_current_cpu(vector);
+        gic.enable(vector);
+    }
+}
+
+pub fn arch_interrupt_remove(vector: InterruptVector) {
+    if let Some(gic) = kernel_gic() {
+        gic.disable(vector);
+    }
+}
+
+#[inline(always)]
+pub fn arch_interrupt_eoi(vector: InterruptVector) {
+    aa64_gic_eoi(vector);
+}
+
+// masks irqs on this cpu; returns whether they were unmasked
+#[inline(always)]
+pub fn arch_interrupt_save_disable() -> bool {
+    let enabled = aarch_read_daif() & AA64_DAIF_IRQ_MASKED == 0;
+    aarch_disable_interrupts();
+    enabled
+}
+
+#[inline(always)]
+pub fn arch_interrupt_restore(enabled: bool) {
+    if enabled {
+        aarch_enable_interrupts();
+    }
+}
+
+// msis on the gic are writes of the spi number to the doorbell; they
+// always land on whichever cpu the spi is routed to
+pub fn arch_msi_message(vector: InterruptVector, _target: u32) -> Option<MsiMessage> {
+    let doorbell = kernel_gic()?.msi_doorbell()?;
+
+    Some(MsiMessage {
+        address: doorbell as u64,
+        data: vector,
+    })
+}
@@ -0,0 +1,61 @@
+#![cfg(target_arch = "aarch64")]
+
+// Purpose: the aarch64 backend for crate::power. Arm platforms power off &
+// reset through psci (whose conduit, hvc or smc, the fadt names), and qemu
+// can be left via semihosting.
+
+use core::arch::asm;
+
+use crate::arch::aa64::asm::*;
+use crate::acpi::acpi_fadt;
+
+// psci 0.2+ function ids
+const PSCI_SYSTEM_OFF: u32 = 0x8400_0008;
+const PSCI_SYSTEM_RESET: u32 = 0x8400_0009;
+
+fn psci_call(function: u32) -> bool {
+    let use_hvc = match acpi_fadt().and_then(|fadt| fadt.psci()) {
+        Some(use_hvc) => use_hvc,
+        None => return false,
+    };
+
+    // SYSTEM_OFF & SYSTEM_RESET don't come back if they work
+    unsafe {
+        if use_hvc {
+            asm!("hvc #0", inout("x0") function as usize => _, options(nomem, nostack));
+        } else {
+            asm!("smc #0", inout("x0") function as usize => _, options(nomem, nostack));
+        }
+    }
+
+    true
+}
+
+pub fn arch_power_off() -> bool {
+    psci_call(PSCI_SYSTEM_OFF)
+}
+
+pub fn arch_reset() -> bool {
+    psci_call(PSCI_SYSTEM_RESET)
+}
+
+// no way to force it; just stop
+pub fn arch_reset_last_resort() -> ! {
+    crate::cpu::wait_forever()
+}
+
+// no cpuid to ask; the firmware tables have to give it away
+pub fn arch_hypervisor_is_qemu() -> bool {
+    false
+}
+
+// leaves via semihosting; qemu has to be run with -semihosting
+pub fn arch_qemu_exit(code: u32) -> ! {
+    use qemu_exit::QEMUExit;
+
+    qemu_exit::AArch64::new().exit(code)
+}
+
+pub fn arch_power_disable_interrupts() {
+    aarch_disable_interrupts();
+}
@@ -0,0 +1,155 @@
+#![cfg(target_arch = "aarch64")]
+
+// Purpose: the aarch64 backend for crate::timer, on the arm generic timer.
+// The counter is cntvct / cntpct at cntfrq; the tick is the matching el1
+// timer (cntv / cntp) reloaded from its own ppi. Unlike x86, the frequency
+// is architecturally published, so there's nothing to calibrate.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::common::base::*;
+use crate::arch::aa64::asm::*;
+use crate::interrupts::{register_system_handler, InterruptResult, InterruptVector};
+use crate::timer::timer_tick;
+
+// the el1 timers' ppis
+pub const AA64_TIMER_VIRTUAL_INTID: u32 = 27;
+pub const AA64_TIMER_PHYSICAL_INTID: u32 = 30;
+
+// cntv_ctl / cntp_ctl bits
+const AA64_TIMER_CTL_ENABLE: usize = 1 << 0;
+const AA64_TIMER_CTL_IMASK: usize = 1 << 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenericTimer {
+    // cntv; what a guest normally gets
+    Virtual,
+    // cntp; the el1 physical timer
+    Physical,
+}
+
+// false = virtual, true = physical
+static USE_PHYSICAL: AtomicBool = AtomicBool::new(false);
+// counter ticks per timer tick; 0 = not running
+static RELOAD: AtomicU64 = AtomicU64::new(0);
+static TICK_HZ: AtomicU64 = AtomicU64::new(0);
+
+fn generic_timer() -> GenericTimer {
+    if USE_PHYSICAL.load(Ordering::Relaxed) { GenericTimer::Physical } else { GenericTimer::Virtual }
+}
+
+fn write_tval(value: u64) {
+    match generic_timer() {
+        GenericTimer::Virtual => aarch_write_cntv_tval_el0(value as usize),
+        GenericTimer::Physical => aarch_write_cntp_tval_el0(value as usize),
+    }
+}
+
+fn write_ctl(value: usize) {
+    match generic_timer() {
+        GenericTimer::Virtual => aarch_write_cntv_ctl_el0(value),
+        GenericTimer::Physical => aarch_write_cntp_ctl_el0(value),
+    }
+}
+
+// the tick: rearm for the next period, then count it
+fn aa64_timer_irq(_vector: InterruptVector, _context: usize) -> InterruptResult {
+    match RELOAD.load(Ordering::Relaxed) {
+        ZERO_U64 => write_ctl(AA64_TIMER_CTL_IMASK),
+        reload => {
+            write_tval(reload);
+            timer_tick();
+        },
+    }
+
+    InterruptResult::Handled
+}
+
+//-----------------------------------------------------------------------------------
+
+// picks the el1 timer to tick with & hooks its ppi; the gic must be up
+pub fn aa64_timer_init(timer: GenericTimer) -> bool {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return false;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    USE_PHYSICAL.store(timer == GenericTimer::Physical, Ordering::SeqCst);
+    write_ctl(AA64_TIMER_CTL_IMASK);
+
+    let intid = match timer {
+        GenericTimer::Virtual => AA64_TIMER_VIRTUAL_INTID,
+        GenericTimer::Physical => AA64_TIMER_PHYSICAL_INTID,
+    };
+
+    if register_system_handler(intid, Priority::System, aa64_timer_irq, ZERO_USIZE).is_none() {
+        return false;
+    }
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("aa64_timer_init() -> {:?} generic timer on ppi {}, counter @ {} hz", timer, intid, arch_timer_counter_hz());
+
+    true
+}
+
+//-----------------------------------------------------------------------------------
+
+pub fn arch_timer_tick_hz() -> u64 {
+    TICK_HZ.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+pub fn arch_timer_counter() -> u64 {
+    match generic_timer() {
+        GenericTimer::Virtual => aarch_read_cntvct_el0() as u64,
+        GenericTimer::Physical => aarch_read_cntpct_el0() as u64,
+    }
+}
+
+pub fn arch_timer_counter_hz() -> u64 {
+    (aarch_read_cntfrq_el0() & 0xFFFF_FFFF) as u64
+}
+
+// the system counter is architecturally constant rate
+pub fn arch_timer_counter_invariant() -> bool {
+    true
+}
+
+// so there's never a need for anything else
+pub fn arch_timer_fallback_counter() -> u64 {
+    ZERO_U64
+}
+
+pub fn arch_timer_fallback_counter_hz() -> u64 {
+    ZERO_U64
+}
+
+pub fn arch_timer_start_periodic(hz: u64) -> bool {
+    let reload = arch_timer_counter_hz() / hz;
+
+    // tval is a signed 32 bit down counter
+    if reload == ZERO_U64 || reload > i32::MAX as u64 {
+        return false;
+    }
+
+    RELOAD.store(reload, Ordering::SeqCst);
+    TICK_HZ.store(hz, Ordering::SeqCst);
+
+    write_tval(reload);
+    write_ctl(AA64_TIMER_CTL_ENABLE);
+
+    true
+}
+
+pub fn arch_timer_stop() {
+    RELOAD.store(ZERO_U64, Ordering::SeqCst);
+    TICK_HZ.store(ZERO_U64, Ordering::SeqCst);
+
+    write_ctl(AA64_TIMER_CTL_IMASK);
+}
@@ -0,0 +1,397 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: local apic (xAPIC via mmio, x2APIC via msrs) & i/o apic drivers.
+// The local apic gives us eoi, the spurious vector, an error vector and the
+// per-cpu timer; the i/o apic routes external (gsi) interrupts to vectors.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::arch::x86::cpu::*;
+use crate::arch::x86::interrupts::*;
+use crate::arch::x86::pic::*;
+use crate::status::KernelServiceStatus;
+use crate::timer::timer_tick;
+
+// IA32_APIC_BASE msr
+pub const X86_MSR_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_GLOBAL_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+// x2APIC registers live at msr 0x800 + (mmio offset >> 4)
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+// local apic register offsets (xAPIC mmio)
+pub const LAPIC_REG_ID: usize = 0x020;
+pub const LAPIC_REG_VERSION: usize = 0x030;
+pub const LAPIC_REG_TPR: usize = 0x080;
+pub const LAPIC_REG_EOI: usize = 0x0B0;
+pub const LAPIC_REG_SVR: usize = 0x0F0;
+pub const LAPIC_REG_ESR: usize = 0x280;
+pub const LAPIC_REG_ICR_LOW: usize = 0x300;
+pub const LAPIC_REG_ICR_HIGH: usize = 0x310;
+pub const LAPIC_REG_LVT_TIMER: usize = 0x320;
+pub const LAPIC_REG_LVT_LINT0: usize = 0x350;
+pub const LAPIC_REG_LVT_LINT1: usize = 0x360;
+pub const LAPIC_REG_LVT_ERROR: usize = 0x370;
+pub const LAPIC_REG_TIMER_INITIAL: usize = 0x380;
+pub const LAPIC_REG_TIMER_CURRENT: usize = 0x390;
+pub const LAPIC_REG_TIMER_DIVIDE: usize = 0x3E0;
+
+// svr: software enable
+const LAPIC_SVR_ENABLE: u32 = 1 << 8;
+// lvt bits
+pub const LAPIC_LVT_MASKED: u32 = 1 << 16;
+pub const LAPIC_LVT_TIMER_PERIODIC: u32 = 1 << 17;
+const LAPIC_LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+
+// timer divide configuration values
+pub const LAPIC_TIMER_DIVIDE_1: u32 = 0b1011;
+pub const LAPIC_TIMER_DIVIDE_16: u32 = 0b0011;
+
+// the vectors the apics own; the legacy pics are parked on 0x20-0x2F
+pub const X86_APIC_TIMER_VECTOR: u8 = 0x30;
+pub const X86_APIC_ERROR_VECTOR: u8 = 0xFE;
+pub const X86_APIC_SPURIOUS_VECTOR: u8 = 0xFF;
+
+// until the timer is calibrated, just tick at something sane-ish
+pub const X86_APIC_TIMER_DEFAULT_COUNT: u32 = 0x0100_0000;
+
+// the i/o apic, unless the firmware tables say otherwise
+pub const X86_IOAPIC_DEFAULT_BASE: usize = 0xFEC0_0000;
+
+const IOAPIC_REG_SELECT: usize = 0x00;
+const IOAPIC_REG_WINDOW: usize = 0x10;
+const IOAPIC_REG_ID: u32 = 0x00;
+const IOAPIC_REG_VERSION: u32 = 0x01;
+const IOAPIC_REG_REDIR_BASE: u32 = 0x10;
+
+// redirection entry bits
+pub const IOAPIC_REDIR_DEST_LOGICAL: u64 = 1 << 11;
+pub const IOAPIC_REDIR_ACTIVE_LOW: u64 = 1 << 13;
+pub const IOAPIC_REDIR_LEVEL_TRIGGERED: u64 = 1 << 15;
+pub const IOAPIC_REDIR_MASKED: u64 = 1 << 16;
+
+//-----------------------------------------------------------------------------------
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LocalApicMode {
+    XApic(PhysAddr),
+    X2Apic,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LocalApic {
+    mode: LocalApicMode,
+}
+
+impl LocalApic {
+    pub const fn new(mode: LocalApicMode) -> Self {
+        LocalApic { mode }
+    }
+
+    pub fn mode(&self) -> LocalApicMode {
+        self.mode
+    }
+
+    pub fn read(&self, reg: usize) -> u32 {
+        match self.mode {
+            LocalApicMode::XApic(base) => unsafe {
+                core::ptr::read_volatile((base.as_usize() + reg) as *const u32)
+            },
+            LocalApicMode::X2Apic => x86_read_msr(X2APIC_MSR_BASE + (reg >> 4) as u32) as u32,
+        }
+    }
+
+    pub fn write(&self, reg: usize, value: u32) {
+        match self.mode {
+            LocalApicMode::XApic(base) => unsafe {
+                core::ptr::write_volatile((base.as_usize() + reg) as *mut u32, value)
+            },
+            LocalApicMode::X2Apic => x86_write_msr(X2APIC_MSR_BASE + (reg >> 4) as u32, value as u64),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self.mode {
+            LocalApicMode::XApic(_) => self.read(LAPIC_REG_ID) >> 24,
+            LocalApicMode::X2Apic => self.read(LAPIC_REG_ID),
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.read(LAPIC_REG_VERSION) & 0xFF
+    }
+
+    pub fn max_lvt(&self) -> u32 {
+        (self.read(LAPIC_REG_VERSION) >> 16) & 0xFF
+    }
+
+    // signal end of interrupt for the in-service vector
+    #[inline(always)]
+    pub fn eoi(&self) {
+        self.write(LAPIC_REG_EOI, 0);
+    }
+
+    // software enables the apic & sets the spurious vector
+    pub fn enable(&self, spurious_vector: u8) {
+        self.write(LAPIC_REG_SVR, LAPIC_SVR_ENABLE | spurious_vector as u32);
+    }
+
+    // accept all priorities
+    pub fn set_task_priority(&self, priority: u8) {
+        self.write(LAPIC_REG_TPR, priority as u32);
+    }
+
+    // reads (and clears) the error status register
+    pub fn error_status(&self) -> u32 {
+        // the esr has to be written before it's read
+        self.write(LAPIC_REG_ESR, 0);
+        self.read(LAPIC_REG_ESR)
+    }
+
+    pub fn start_timer_periodic(&self, vector: u8, initial_count: u32, divide: u32) {
+        self.write(LAPIC_REG_TIMER_DIVIDE, divide);
+        self.write(LAPIC_REG_LVT_TIMER, LAPIC_LVT_TIMER_PERIODIC | vector as u32);
+        self.write(LAPIC_REG_TIMER_INITIAL, initial_count);
+    }
+
+    pub fn start_timer_oneshot(&self, vector: u8, initial_count: u32, divide: u32) {
+        self.write(LAPIC_REG_TIMER_DIVIDE, divide);
+        self.write(LAPIC_REG_LVT_TIMER, vector as u32);
+        self.write(LAPIC_REG_TIMER_INITIAL, initial_count);
+    }
+
+    pub fn stop_timer(&self) {
+        self.write(LAPIC_REG_LVT_TIMER, LAPIC_LVT_MASKED);
+        self.write(LAPIC_REG_TIMER_INITIAL, 0);
+    }
+
+    pub fn timer_current_count(&self) -> u32 {
+        self.read(LAPIC_REG_TIMER_CURRENT)
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+#[derive(Debug, Copy, Clone)]
+pub struct IoApic {
+    base: PhysAddr,
+    gsi_base: u32,
+}
+
+impl IoApic {
+    pub const fn new(base: PhysAddr, gsi_base: u32) -> Self {
+        IoApic { base, gsi_base }
+    }
+
+    pub fn base(&self) -> PhysAddr {
+        self.base
+    }
+
+    pub fn gsi_base(&self) -> u32 {
+        self.gsi_base
+    }
+
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            core::ptr::write_volatile((self.base.as_usize() + IOAPIC_REG_SELECT) as *mut u32, reg);
+            core::ptr::read_volatile((self.base.as_usize() + IOAPIC_REG_WINDOW) as *const u32)
+        }
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            core::ptr::write_volatile((self.base.as_usize() + IOAPIC_REG_SELECT) as *mut u32, reg);
+            core::ptr::write_volatile((self.base.as_usize() + IOAPIC_REG_WINDOW) as *mut u32, value);
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        ((self.read(IOAPIC_REG_ID) >> 24) & 0x0F) as u8
+    }
+
+    // the number of redirection entries (i.e. input pins)
+    pub fn redirection_count(&self) -> u32 {
+        ((self.read(IOAPIC_REG_VERSION) >> 16) & 0xFF) + 1
+    }
+
+    // true if this i/o apic handles the given gsi
+    pub fn handles_gsi(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.redirection_count()
+    }
+
+    pub fn read_redirection(&self, pin: u32) -> u64 {
+        let low = self.read(IOAPIC_REG_REDIR_BASE + pin * 2) as u64;
+        let high = self.read(IOAPIC_REG_REDIR_BASE + pin * 2 + 1) as u64;
+        (high << 32) | low
+    }
+
+    pub fn write_redirection(&self, pin: u32, entry: u64) {
+        // keep the pin masked while the halves disagree
+        self.write(IOAPIC_REG_REDIR_BASE + pin * 2, IOAPIC_REDIR_MASKED as u32);
+        self.write(IOAPIC_REG_REDIR_BASE + pin * 2 + 1, (entry >> 32) as u32);
+        self.write(IOAPIC_REG_REDIR_BASE + pin * 2, entry as u32);
+    }
+
+    // routes pin to vector on the apic with dest_apic_id (fixed delivery, physical
+    // destination); flags are IOAPIC_REDIR_* polarity / trigger / mask bits
+    pub fn set_redirection(&self, pin: u32, vector: u8, dest_apic_id: u8, flags: u64) {
+        let entry = (vector as u64) | flags | ((dest_apic_id as u64) << 56);
+        self.write_redirection(pin, entry);
+    }
+
+    pub fn mask(&self, pin: u32) {
+        let entry = self.read_redirection(pin);
+        self.write_redirection(pin, entry | IOAPIC_REDIR_MASKED);
+    }
+
+    pub fn unmask(&self, pin: u32) {
+        let entry = self.read_redirection(pin);
+        self.write_redirection(pin, entry & !IOAPIC_REDIR_MASKED);
+    }
+
+    pub fn mask_all(&self) {
+        for pin in 0..self.redirection_count() {
+            self.write_redirection(pin, IOAPIC_REDIR_MASKED);
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// the bsp's local apic & the (first) i/o apic; written once during
+// bringup, read from interrupt context afterwards
+static mut LOCAL_APIC: Option<LocalApic> = None;
+static mut IO_APIC: Option<IoApic> = None;
+
+pub fn local_apic() -> Option<&'static LocalApic> {
+    unsafe { (*core::ptr::addr_of!(LOCAL_APIC)).as_ref() }
+}
+
+pub fn io_apic() -> Option<&'static IoApic> {
+    unsafe { (*core::ptr::addr_of!(IO_APIC)).as_ref() }
+}
+
+// the physical base of the local apic's mmio window
+pub fn x86_lapic_base() -> PhysAddr {
+    PhysAddr((x86_read_msr(X86_MSR_APIC_BASE) & APIC_BASE_ADDR_MASK) as usize)
+}
+
+//-----------------------------------------------------------------------------------
+
+extern "x86-interrupt" fn x86_apic_timer_handler(_frame: ExceptionStackFrame) {
+    timer_tick();
+
+    if let Some(lapic) = local_apic() {
+        lapic.eoi();
+    }
+}
+
+extern "x86-interrupt" fn x86_apic_error_handler(_frame: ExceptionStackFrame) {
+    if let Some(lapic) = local_apic() {
+        serial_println!("local apic error: esr = 0x{:0x}", lapic.error_status());
+        lapic.eoi();
+    }
+}
+
+// spurious interrupts are never in service, so no eoi
+extern "x86-interrupt" fn x86_apic_spurious_handler(_frame: ExceptionStackFrame) {}
+
+// whatever the masked legacy pics still manage to raise; they're not
+// the local apic's to acknowledge either
+extern "x86-interrupt" fn x86_pic_spurious_handler(_frame: ExceptionStackFrame) {}
+
+//-----------------------------------------------------------------------------------
+
+// brings up the bsp's local apic (x2APIC if available), parks & masks the legacy
+// pics, and masks every i/o apic input. the lapic & i/o apic mmio windows must
+// already be mapped (uncached). returns false if there's no apic at all.
+pub fn x86_apic_init(ioapic_base: PhysAddr, ioapic_gsi_base: u32) -> bool {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return false;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let cpu = Cpu::detect();
+
+    if !cpu.info.features.feat_apic() {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("x86_apic_init() -> no local apic present");
+        return false;
+    }
+
+    // get the legacy pics out of the way first
+    x86_pic_disable();
+
+    // globally enable the local apic, in x2APIC mode if we can
+    let mut apic_base_msr = x86_read_msr(X86_MSR_APIC_BASE) | APIC_BASE_GLOBAL_ENABLE;
+
+    let mode = if cpu.info.features.feat_x2apic() {
+        apic_base_msr |= APIC_BASE_X2APIC_ENABLE;
+        LocalApicMode::X2Apic
+    } else {
+        LocalApicMode::XApic(PhysAddr((apic_base_msr & APIC_BASE_ADDR_MASK) as usize))
+    };
+
+    x86_write_msr(X86_MSR_APIC_BASE, apic_base_msr);
+
+    let lapic = LocalApic::new(mode);
+    let ioapic = IoApic::new(ioapic_base, ioapic_gsi_base);
+
+    unsafe {
+        LOCAL_APIC = Some(lapic);
+        IO_APIC = Some(ioapic);
+    }
+
+    // wire up the gates we own
+    let idt = kernel_idt();
+    let cs = x86_read_cs();
+
+    for vector in X86_PIC1_VECTOR_BASE..X86_PIC2_VECTOR_BASE + 8 {
+        idt.set_handler(vector, x86_pic_spurious_handler as usize, cs, IDT_GATE_INTERRUPT);
+    }
+
+    idt.set_handler(X86_APIC_TIMER_VECTOR, x86_apic_timer_handler as usize, cs, IDT_GATE_INTERRUPT);
+    idt.set_handler(X86_APIC_ERROR_VECTOR, x86_apic_error_handler as usize, cs, IDT_GATE_INTERRUPT);
+    idt.set_handler(X86_APIC_SPURIOUS_VECTOR, x86_apic_spurious_handler as usize, cs, IDT_GATE_INTERRUPT);
+
+    // the local vector table: lint0 stays masked (that was the pic), lint1 is nmi
+    lapic.write(LAPIC_REG_LVT_LINT0, LAPIC_LVT_MASKED);
+    lapic.write(LAPIC_REG_LVT_LINT1, LAPIC_LVT_DELIVERY_NMI);
+    lapic.write(LAPIC_REG_LVT_ERROR, X86_APIC_ERROR_VECTOR as u32);
+    lapic.error_status();
+
+    lapic.set_task_priority(0);
+    lapic.enable(X86_APIC_SPURIOUS_VECTOR);
+
+    // nothing gets through the i/o apic until a driver asks for it
+    ioapic.mask_all();
+
+    // the timer stays off until crate::timer starts it
+    lapic.stop_timer();
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!(
+        "x86_apic_init() -> local apic id {} ({:?}, version 0x{:0x}, {} lvt entries); i/o apic id {} @ 0x{:08x} with {} inputs",
+        lapic.id(),
+        lapic.mode(),
+        lapic.version(),
+        lapic.max_lvt() + 1,
+        ioapic.id(),
+        ioapic.base(),
+        ioapic.redirection_count()
+    );
+
+    iron().unwrap().status.lock_rw_spin().as_mut().unwrap().external_interrupts = KernelServiceStatus::SysOnly;
+
+    true
+}
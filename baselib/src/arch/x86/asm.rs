@@ -129,7 +129,7 @@ pub fn x86_inport32(port: u16) -> u32 {
 #[inline(always)]
 pub fn x86_enable_interrupts() {
     unsafe {
-        asm!("cli", options(nostack, nomem),);
+        asm!("sti", options(nostack, nomem),);
     }
 }
 
@@ -139,8 +139,28 @@ pub fn x86_enable_interrupts() {
 #[inline(always)]
 pub fn x86_disable_interrupts() {
     unsafe {
-        asm!("sti", options(nostack, nomem),);
+        asm!("cli", options(nostack, nomem),);
+    }
+}
+
+//==========================================================
+// UINTN x86_read_flags()
+//==========================================================
+#[inline(always)]
+pub fn x86_read_flags() -> usize {
+    let flags: usize;
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        asm!("pushfq", "pop {}", out(reg) flags, options(nomem, preserves_flags));
     }
+
+    #[cfg(target_arch = "x86")]
+    unsafe {
+        asm!("pushfd", "pop {}", out(reg) flags, options(nomem, preserves_flags));
+    }
+
+    flags
 }
 
 //==========================================================
@@ -281,6 +301,104 @@ pub fn x86_read_cr2() -> usize {
     out
 }
 
+//==========================================================
+// usize x86_read_cr0()
+//==========================================================
+#[inline(always)]
+pub fn x86_read_cr0() -> usize {
+    let mut out: usize;
+
+    unsafe {
+        asm!(
+            "mov {0}, cr0",
+            lateout(reg) out,
+            options(nostack, nomem),
+        );
+    }
+    out
+}
+
+//==========================================================
+// VOID x86_write_cr0(usize new_cr0)
+//==========================================================
+#[inline(always)]
+pub fn x86_write_cr0(cr0: usize) {
+    unsafe {
+        asm!(
+            "mov cr0, {0}",
+            in(reg) cr0,
+            options(nostack),
+        );
+    }
+}
+
+//==========================================================
+// usize x86_read_cr4()
+//==========================================================
+#[inline(always)]
+pub fn x86_read_cr4() -> usize {
+    let mut out: usize;
+
+    unsafe {
+        asm!(
+            "mov {0}, cr4",
+            lateout(reg) out,
+            options(nostack, nomem),
+        );
+    }
+    out
+}
+
+//==========================================================
+// VOID x86_write_cr4(usize new_cr4)
+//==========================================================
+#[inline(always)]
+pub fn x86_write_cr4(cr4: usize) {
+    unsafe {
+        asm!(
+            "mov cr4, {0}",
+            in(reg) cr4,
+            options(nostack),
+        );
+    }
+}
+
+//==========================================================
+// UINT64 x86_read_msr(UINT32 msr)
+//==========================================================
+#[inline(always)]
+pub fn x86_read_msr(msr: u32) -> u64 {
+    let eax: u32;
+    let edx: u32;
+
+    unsafe {
+        asm!(
+            "rdmsr",
+            in("ecx") msr,
+            lateout("eax") eax,
+            lateout("edx") edx,
+            options(nostack, nomem),
+        );
+    }
+    (edx as u64) << 32 | eax as u64
+}
+
+//==========================================================
+// VOID x86_write_msr(UINT32 msr, UINT64 value)
+//==========================================================
+#[inline(always)]
+pub fn x86_write_msr(msr: u32, value: u64) {
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack),
+        );
+    }
+}
+
 //==========================================================
 // UINT16 x86_read_cs()
 //==========================================================
@@ -383,6 +501,53 @@ pub fn x86_read_gs() -> u16 {
     out
 }
 
+//==========================================================
+// VOID x86_reload_segments (UINT16 code_sel, UINT16 data_sel)
+//==========================================================
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn x86_reload_segments(code_sel: u16, data_sel: u16) {
+    unsafe {
+        asm!(
+            "push {0}",
+            "lea {2}, [rip + 2f]",
+            "push {2}",
+            "retfq",
+            "2:",
+            "mov ds, {1:x}",
+            "mov es, {1:x}",
+            "mov fs, {1:x}",
+            "mov gs, {1:x}",
+            "mov ss, {1:x}",
+            in(reg) code_sel as usize,
+            in(reg) data_sel as usize,
+            out(reg) _,
+        );
+    }
+}
+
+#[cfg(target_arch = "x86")]
+#[inline(always)]
+pub fn x86_reload_segments(code_sel: u16, data_sel: u16) {
+    unsafe {
+        asm!(
+            "push {0}",
+            "lea {2}, [2f]",
+            "push {2}",
+            "retf",
+            "2:",
+            "mov ds, {1:x}",
+            "mov es, {1:x}",
+            "mov fs, {1:x}",
+            "mov gs, {1:x}",
+            "mov ss, {1:x}",
+            in(reg) code_sel as usize,
+            in(reg) data_sel as usize,
+            out(reg) _,
+        );
+    }
+}
+
 //==========================================================
 // VOID x86_load_tr (UINT16 tr_gdt_descr_index)
 //==========================================================
@@ -934,3 +1099,38 @@ pub fn x86_halt() {
         }
     }
 }
+
+//==========================================================
+// VOID x86_switch_stack_and_jump(usize StackTop, VOID (*Entry)(VOID))
+// never returns; the old stack is abandoned
+//==========================================================
+#[cfg(target_arch = "x86_64")]
+pub fn x86_switch_stack_and_jump(stack_top: usize, entry: extern "C" fn() -> !) -> ! {
+    unsafe {
+        asm!(
+            "mov rsp, {0}",
+            "sub rsp, 32",      // shadow space for the ms abi
+            "xor rbp, rbp",
+            "call {1}",
+            "ud2",
+            in(reg) stack_top,
+            in(reg) entry,
+            options(noreturn),
+        );
+    }
+}
+
+#[cfg(target_arch = "x86")]
+pub fn x86_switch_stack_and_jump(stack_top: usize, entry: extern "C" fn() -> !) -> ! {
+    unsafe {
+        asm!(
+            "mov esp, {0}",
+            "xor ebp, ebp",
+            "call {1}",
+            "ud2",
+            in(reg) stack_top,
+            in(reg) entry,
+            options(noreturn),
+        );
+    }
+}
@@ -116,6 +116,44 @@ pub struct CacheDescriptor {
     pub sectored: CacheSectored,
 }
 
+// Deterministic cache parameters CPUID(4); used when CPUID(2)
+// hands back the 0xFF "go look at leaf 4" descriptor. these don't
+// squeeze into the fixed size / associativity enums above, so the
+// raw geometry is kept instead
+#[allow(dead_code)] // TODO: Remove when in use
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheParameters {
+    pub level: CacheLevel,
+    pub type_of_cache: CacheType,
+    pub size: usize,
+    pub ways: u32,
+    pub partitions: u32,
+    pub line_size: u32,
+    pub sets: u32,
+    pub fully_associative: bool,
+    pub self_initializing: bool,
+    pub inclusive: bool,
+    pub max_sharing_threads: u32,
+}
+
+impl CacheParameters {
+    pub const fn empty() -> Self {
+        CacheParameters {
+            level: CacheLevel::Unknown,
+            type_of_cache: CacheType::Unknown,
+            size: 0,
+            ways: 0,
+            partitions: 0,
+            line_size: 0,
+            sets: 0,
+            fully_associative: false,
+            self_initializing: false,
+            inclusive: false,
+            max_sharing_threads: 0,
+        }
+    }
+}
+
 // Legacy configs CPUID(2)
 pub const CACHE_CONFIGS: [CacheDescriptor; 0x100] = [
     CacheDescriptor {
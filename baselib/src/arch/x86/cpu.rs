@@ -14,6 +14,49 @@ pub fn wait_forever() -> ! {
     }
 }
 
+// control register & msr bits we need during bringup
+pub const X86_CR0_WP: usize = 1 << 16;
+pub const X86_MSR_EFER: u32 = 0xC000_0080;
+pub const X86_EFER_NXE: u64 = 1 << 11;
+
+// cpuid 0x80000001 edx bit 20 advertises the no-execute bit
+const X86_CPUID_EXT_FEATURES: u32 = 0x8000_0001;
+const X86_CPUID_EXT_NX_BIT: usize = 20;
+
+// returns true if the processor supports the NX (XD) page bit
+pub fn x86_nx_supported() -> bool {
+    let max_ext = x86_cpuid(X86_CPUID_EXT_MAX).eax;
+    if max_ext < X86_CPUID_EXT_FEATURES {
+        return false;
+    }
+
+    u32bit::is_bit_set(x86_cpuid(X86_CPUID_EXT_FEATURES).edx, X86_CPUID_EXT_NX_BIT)
+}
+
+// turns on EFER.NXE so the NX bit in our page tables is honored
+// (and not treated as a reserved bit, which would fault).
+// returns false if the processor doesn't support it.
+pub fn x86_enable_nx() -> bool {
+    if !x86_nx_supported() {
+        return false;
+    }
+
+    let efer = x86_read_msr(X86_MSR_EFER);
+    if efer & X86_EFER_NXE == 0 {
+        x86_write_msr(X86_MSR_EFER, efer | X86_EFER_NXE);
+    }
+
+    true
+}
+
+// turns on CR0.WP so that read-only pages are enforced in ring 0 too
+pub fn x86_enable_write_protect() {
+    let cr0 = x86_read_cr0();
+    if cr0 & X86_CR0_WP == 0 {
+        x86_write_cr0(cr0 | X86_CR0_WP);
+    }
+}
+
 #[bitfield(u64)]
 #[derive(PartialEq, Eq)]
 pub struct CpuFeatures {
@@ -259,10 +302,36 @@ pub struct CpuFeaturesExt {
     reserved: u16,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuVendor {
+    Unknown,
+    Intel,
+    Amd,
+}
+
+// CPUID(2) can hand back at most 15 descriptors (3 in eax, 4 each in ebx/ecx/edx)
+pub const CPU_CACHE_DESCRIPTOR_MAX: usize = 15;
+// CPUID(4) subleaves we keep; real parts stop at 4 or 5
+pub const CPU_CACHE_PARAMETERS_MAX: usize = 8;
+
+const X86_CPUID_VENDOR_INTEL: &[u8; 12] = b"GenuineIntel";
+const X86_CPUID_VENDOR_AMD: &[u8; 12] = b"AuthenticAMD";
+
+pub const X86_CPUID_EXT_MAX: u32 = 0x8000_0000;
+const X86_CPUID_BRAND_FIRST: u32 = 0x8000_0002;
+const X86_CPUID_BRAND_LAST: u32 = 0x8000_0004;
+
+// CPUID(2) descriptors that aren't caches at all, but flags
+const X86_CPUID2_USE_LEAF4_TLB: u8 = 0xFE;
+const X86_CPUID2_USE_LEAF4_CACHE: u8 = 0xFF;
+
 #[derive(Debug)]
 pub struct CpuInfo {
     pub vendor_id: [u8; 12],
+    pub vendor: CpuVendor,
+    pub brand_string: [u8; 48],
     pub max_cpuid_level: u32,
+    pub max_extended_cpuid_level: u32,
     pub extended_model: u8,
     pub extended_family: u8,
     pub processor_type: u8,
@@ -273,14 +342,70 @@ pub struct CpuInfo {
     pub clflush_chunk_count: u8,
     pub cpu_count: u16,
     pub default_apic_id: u8,
-    pub cache_descriptors: [CacheDescriptor; 15],
+    pub cache_descriptors: [CacheDescriptor; CPU_CACHE_DESCRIPTOR_MAX],
     pub cache_descriptor_count: u8,
+    pub cache_parameters: [CacheParameters; CPU_CACHE_PARAMETERS_MAX],
+    pub cache_parameter_count: u8,
     pub mode4_cache_info: bool,
     pub mode4_tlb_info: bool,
     pub features: CpuFeatures,
     pub features_ext: CpuFeaturesExt,
 }
 
+impl CpuInfo {
+    // the vendor id as a string, i.e. "GenuineIntel"
+    pub fn vendor_str(&self) -> &str {
+        core::str::from_utf8(&self.vendor_id).unwrap_or("")
+    }
+
+    // the brand string, minus the padding intel likes to put on either end
+    pub fn brand_str(&self) -> &str {
+        let len = self.brand_string.iter().position(|&b| b == 0).unwrap_or(self.brand_string.len());
+
+        core::str::from_utf8(&self.brand_string[..len])
+            .unwrap_or("")
+            .trim()
+    }
+
+    // the family as the sdm / apm say to display it; the extended
+    // family only counts once the base family is maxed out
+    pub fn family(&self) -> u32 {
+        if self.processor_family == 0x0F {
+            self.processor_family as u32 + self.extended_family as u32
+        } else {
+            self.processor_family as u32
+        }
+    }
+
+    // likewise for the model; intel also extends family 6
+    pub fn model(&self) -> u32 {
+        let extend = match self.vendor {
+            CpuVendor::Intel => self.processor_family == 0x06 || self.processor_family == 0x0F,
+            _ => self.processor_family == 0x0F,
+        };
+
+        if extend {
+            ((self.extended_model as u32) << 4) | self.processor_model as u32
+        } else {
+            self.processor_model as u32
+        }
+    }
+
+    pub fn stepping(&self) -> u32 {
+        self.processor_stepping as u32
+    }
+
+    // the valid CPUID(2) descriptors
+    pub fn cache_descriptors(&self) -> &[CacheDescriptor] {
+        &self.cache_descriptors[..self.cache_descriptor_count as usize]
+    }
+
+    // the CPUID(4) caches, if we had to go there
+    pub fn cache_parameters(&self) -> &[CacheParameters] {
+        &self.cache_parameters[..self.cache_parameter_count as usize]
+    }
+}
+
 #[allow(dead_code)] // TODO: Remove when in use
 #[derive(Debug)]
 pub struct Cpu {
@@ -292,10 +417,14 @@ pub struct Cpu {
 
 #[allow(dead_code)] // TODO: Remove when in use
 impl Cpu {
-    pub fn new() -> Cpu {
+    // identifies the cpu we're running on via cpuid
+    pub fn detect() -> Cpu {
         let info = CpuInfo {
             vendor_id: [0; 12],
+            vendor: CpuVendor::Unknown,
+            brand_string: [0; 48],
             max_cpuid_level: 0,
+            max_extended_cpuid_level: 0,
             extended_model: 0,
             extended_family: 0,
             processor_type: 0,
@@ -306,136 +435,14 @@ impl Cpu {
             clflush_chunk_count: 0,
             cpu_count: 0,
             default_apic_id: 0,
-            cache_descriptors: [CacheDescriptor {
-                level: CacheLevel::Unknown,
-                type_of_cache: CacheType::Unknown,
-                size: CacheSize::Unknown,
-                associativity: CacheAssociativity::Unknown,
-                layout: CacheLayoutType::Unknown,
-                count: 0,
-                ecc: false,
-                sectored: CacheSectored::No,
-            }; 15],
+            cache_descriptors: [CACHE_CONFIGS[0]; CPU_CACHE_DESCRIPTOR_MAX],
             cache_descriptor_count: 0,
+            cache_parameters: [CacheParameters::empty(); CPU_CACHE_PARAMETERS_MAX],
+            cache_parameter_count: 0,
             mode4_cache_info: false,
             mode4_tlb_info: false,
-            features: CpuFeatures::new()
-                .with_feat_hypervisor_present(false)
-                .with_feat_rdrand(false)
-                .with_feat_f16c(false)
-                .with_feat_avx(false)
-                .with_feat_osxsave(false)
-                .with_feat_xsave(false)
-                .with_feat_aes(false)
-                .with_feat_tsc_deadline(false)
-                .with_feat_popcnt(false)
-                .with_feat_movbe(false)
-                .with_feat_x2apic(false)
-                .with_feat_sse42(false)
-                .with_feat_sse41(false)
-                .with_feat_dca(false)
-                .with_feat_pcid(false)
-                .with_feat_pdcm(false)
-                .with_feat_etprd(false)
-                .with_feat_cx16(false)
-                .with_feat_fma(false)
-                .with_feat_sdbg(false)
-                .with_feat_cid(false)
-                .with_feat_ssse3(false)
-                .with_feat_tm2(false)
-                .with_feat_est(false)
-                .with_feat_smx(false)
-                .with_feat_vmx(false)
-                .with_feat_dscpl(false)
-                .with_feat_monitor(false)
-                .with_feat_dtes64(false)
-                .with_feat_pclmul(false)
-                .with_feat_sse3(false)
-                .with_feat_pbe(false)
-                .with_feat_tm1(false)
-                .with_feat_hyperthreading(false)
-                .with_feat_selfsnoop(false)
-                .with_feat_sse2(false)
-                .with_feat_sse(false)
-                .with_feat_fxsr(false)
-                .with_feat_mmx(false)
-                .with_feat_acpi_therm(false)
-                .with_feat_dtes(false)
-                .with_feat_clfl(false)
-                .with_feat_psn(false)
-                .with_feat_pse36(false)
-                .with_feat_pat(false)
-                .with_feat_cmov(false)
-                .with_feat_mca(false)
-                .with_feat_pge(false)
-                .with_feat_mtrr(false)
-                .with_feat_sysenter(false)
-                .with_feat_apic(false)
-                .with_feat_cx8(false)
-                .with_feat_mce(false)
-                .with_feat_pae(false)
-                .with_feat_msr(false)
-                .with_feat_tsc(false)
-                .with_feat_pse(false)
-                .with_feat_de(false)
-                .with_feat_vme(false)
-                .with_feat_fpu(false),
-            features_ext: CpuFeaturesExt::new()
-                .with_feat_avx512vl(false)
-                .with_feat_avx512bw(false)
-                .with_feat_sha(false)
-                .with_feat_avx512cd(false)
-                .with_feat_avx512er(false)
-                .with_feat_avx512pf(false)
-                .with_feat_processor_trace(false)
-                .with_feat_clwb(false)
-                .with_feat_clflushopt(false)
-                .with_feat_pcommit(false)
-                .with_feat_avx512ifma(false)
-                .with_feat_smap(false)
-                .with_feat_adx(false)
-                .with_feat_rdseed(false)
-                .with_feat_avx512dq(false)
-                .with_feat_avx512f(false)
-                .with_feat_pqe(false)
-                .with_feat_mpx(false)
-                .with_feat_fpcsds(false)
-                .with_feat_pqm(false)
-                .with_feat_rtm(false)
-                .with_feat_invpcid(false)
-                .with_feat_erms(false)
-                .with_feat_bmi2(false)
-                .with_feat_smep(false)
-                .with_feat_fpdp(false)
-                .with_feat_avx2(false)
-                .with_feat_hle(false)
-                .with_feat_bmi1(false)
-                .with_feat_sgx(false)
-                .with_feat_tsc_adjust(false)
-                .with_feat_fsgsbase(false)
-                .with_feat_sgx_lc(false)
-                .with_feat_rdpid(false)
-                .with_feat_va57(false)
-                .with_feat_avx512vp_dq(false)
-                .with_feat_tme(false)
-                .with_feat_avx512bitalg(false)
-                .with_feat_avx512vnni(false)
-                .with_feat_vpcl(false)
-                .with_feat_vaes(false)
-                .with_feat_gfni(false)
-                .with_feat_cet(false)
-                .with_feat_avx512vbmi2(false)
-                .with_feat_ospke(false)
-                .with_feat_pku(false)
-                .with_feat_umip(false)
-                .with_feat_avx512vbmi(false)
-                .with_feat_prefetchwt1(false)
-                .with_feat_arch_cap_msr(false)
-                .with_feat_stibp(false)
-                .with_feat_ibrs_mbpb(false)
-                .with_feat_pconfig(false)
-                .with_feat_avx512qfma(false)
-                .with_feat_avx512qvnniw(false),
+            features: CpuFeatures::new(),
+            features_ext: CpuFeaturesExt::new(),
         };
 
         let mut cpu = Cpu {
@@ -451,26 +458,23 @@ impl Cpu {
         // Figure out how far we can go
         cpu.info.max_cpuid_level = regs.eax;
 
-        // Go
-        cpu.info.vendor_id[0] = ((regs.ebx & BYTE3_U32) >> 24) as u8;
-        cpu.info.vendor_id[1] = ((regs.ebx & BYTE2_U32) >> 16) as u8;
-        cpu.info.vendor_id[2] = ((regs.ebx & BYTE1_U32) >> 8) as u8;
-        cpu.info.vendor_id[3] = (regs.ebx & BYTE0_U32) as u8;
-        cpu.info.vendor_id[4] = ((regs.edx & BYTE3_U32) >> 24) as u8;
-        cpu.info.vendor_id[5] = ((regs.edx & BYTE2_U32) >> 16) as u8;
-        cpu.info.vendor_id[6] = ((regs.edx & BYTE1_U32) >> 8) as u8;
-        cpu.info.vendor_id[7] = (regs.edx & BYTE0_U32) as u8;
-        cpu.info.vendor_id[8] = ((regs.ecx & BYTE3_U32) >> 24) as u8;
-        cpu.info.vendor_id[9] = ((regs.ecx & BYTE2_U32) >> 16) as u8;
-        cpu.info.vendor_id[10] = ((regs.ecx & BYTE1_U32) >> 8) as u8;
-        cpu.info.vendor_id[11] = (regs.ecx & BYTE0_U32) as u8;
+        // Go; the vendor string is ebx, edx, ecx, low byte first
+        cpu.info.vendor_id[0..4].copy_from_slice(&regs.ebx.to_le_bytes());
+        cpu.info.vendor_id[4..8].copy_from_slice(&regs.edx.to_le_bytes());
+        cpu.info.vendor_id[8..12].copy_from_slice(&regs.ecx.to_le_bytes());
+
+        cpu.info.vendor = match &cpu.info.vendor_id {
+            X86_CPUID_VENDOR_INTEL => CpuVendor::Intel,
+            X86_CPUID_VENDOR_AMD => CpuVendor::Amd,
+            _ => CpuVendor::Unknown,
+        };
 
         // CPUID / EAX == 1
         if cpu.info.max_cpuid_level >= 1 {
             let regs = x86_cpuid(1);
 
-            cpu.info.extended_family = ((regs.eax & BYTE3_U32) >> 20) as u8;
-            cpu.info.extended_model = ((regs.eax & BYTE2_U32) >> 16) as u8;
+            cpu.info.extended_family = ((regs.eax >> 20) & 0xFF) as u8;
+            cpu.info.extended_model = ((regs.eax >> 16) & 0x0F) as u8;
             cpu.info.processor_type = ((regs.eax & 0x3000) >> 12) as u8;
             cpu.info.processor_family = ((regs.eax & 0xF00) >> 8) as u8;
             cpu.info.processor_model = ((regs.eax & 0xF0) >> 4) as u8;
@@ -479,7 +483,6 @@ impl Cpu {
             cpu.info.clflush_chunk_count = ((regs.ebx & BYTE1_U32) >> 8) as u8;
             cpu.info.cpu_count = ((regs.ebx & BYTE2_U32) >> 16) as u16;
             cpu.info.default_apic_id = ((regs.ebx & BYTE3_U32) >> 24) as u8;
-
             // Individual feature flags (ECX)
             cpu.info
                 .features
@@ -666,211 +669,85 @@ impl Cpu {
         }
 
         // CPUID / EAX == 2
-        if cpu.info.max_cpuid_level >= 2 {
+        // the descriptors are intel's; amd hands back all zeros here
+        if cpu.info.vendor == CpuVendor::Intel && cpu.info.max_cpuid_level >= 2 {
             let regs = x86_cpuid(2);
-            let mut i = 0;
 
-            debug_assert!(regs.eax & BYTE0_U32 == 0x01);
-
-            // clear bit 31 indicates this register has
-            // valid descriptors
-            // EAX has bytes 1-3 (byte 0 is always 01H)
-            if u32bit::is_bit_clear(regs.eax, 31) {
-                if regs.eax & BYTE1_U32 != 0 {
-                    let val = (regs.eax & BYTE1_U32 >> 8) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.eax & BYTE2_U32 != 0 {
-                    let val = (regs.eax & BYTE2_U32 >> 16) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
+            // the low byte of eax is the number of times to run
+            // CPUID(2); it's been 01H on everything for a long time,
+            // & a zero count means there are no descriptors at all
+            let has_descriptors = regs.eax & BYTE0_U32 != 0x00;
+
+            // a clear bit 31 says the register holds valid
+            // descriptors; eax only has bytes 1-3, since byte 0
+            // is the count above. some may contain null entries
+            for (reg, first_byte) in [(regs.eax, 1), (regs.ebx, 0), (regs.ecx, 0), (regs.edx, 0)] {
+                if !has_descriptors || u32bit::is_bit_set(reg, 31) {
+                    continue;
                 }
-                if regs.eax & BYTE3_U32 != 0 {
-                    let val = (regs.eax & BYTE3_U32 >> 24) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
 
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
+                for byte in first_byte..4 {
+                    let val = ((reg >> (byte * 8)) & BYTE0_U32) as u8;
+
+                    match val {
+                        0x00 => continue,
+                        X86_CPUID2_USE_LEAF4_TLB => cpu.info.mode4_tlb_info = true,
+                        X86_CPUID2_USE_LEAF4_CACHE => cpu.info.mode4_cache_info = true,
+                        _ => {
+                            let i = cpu.info.cache_descriptor_count as usize;
+                            cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val as usize];
+                            cpu.info.cache_descriptor_count += 1;
+                        },
                     }
                 }
             }
+        }
 
-            // EBX, ECX & EDX all have all 4 bytes if bit 31 is clear
-            // some may contain null entries however
-            if u32bit::is_bit_clear(regs.ebx, 31) {
-                if regs.ebx & BYTE0_U32 != 0 {
-                    let val = (regs.ebx & BYTE0_U32) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.ebx & BYTE1_U32 != 0 {
-                    let val = (regs.ebx & BYTE1_U32 >> 8) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.ebx & BYTE2_U32 != 0 {
-                    let val = (regs.ebx & BYTE2_U32 >> 16) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.ebx & BYTE3_U32 != 0 {
-                    let val = (regs.ebx & BYTE3_U32 >> 24) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-            }
-
-            if u32bit::is_bit_clear(regs.ecx, 31) {
-                if regs.ecx & BYTE0_U32 != 0 {
-                    let val = (regs.ecx & BYTE0_U32) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.ecx & BYTE1_U32 != 0 {
-                    let val = (regs.ecx & BYTE1_U32 >> 8) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.ecx & BYTE2_U32 != 0 {
-                    let val = (regs.ecx & BYTE2_U32 >> 16) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.ecx & BYTE3_U32 != 0 {
-                    let val = (regs.ecx & BYTE3_U32 >> 24) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-            }
-
-            if u32bit::is_bit_clear(regs.edx, 31) {
-                if regs.edx & BYTE0_U32 != 0 {
-                    let val = (regs.edx & BYTE0_U32) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.edx & BYTE1_U32 != 0 {
-                    let val = (regs.edx & BYTE1_U32 >> 8) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.edx & BYTE2_U32 != 0 {
-                    let val = (regs.edx & BYTE2_U32 >> 16) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
-                if regs.edx & BYTE3_U32 != 0 {
-                    let val = (regs.edx & BYTE3_U32 >> 24) as usize;
-                    cpu.info.cache_descriptors[i] = CACHE_CONFIGS[val];
-                    i += 1;
-
-                    if val == 0xFE {
-                        cpu.info.mode4_tlb_info = true;
-                    }
-                    if val == 0xFF {
-                        cpu.info.mode4_cache_info = true;
-                    }
-                }
+        // CPUID / EAX == 4
+        // only asked for when CPUID(2) told us to; each subleaf
+        // describes one cache until we get a null type back
+        if cpu.info.mode4_cache_info && cpu.info.max_cpuid_level >= 4 {
+            for subleaf in 0..CPU_CACHE_PARAMETERS_MAX as u32 {
+                let regs = x86_cpuid_ext(4, subleaf);
+
+                let type_of_cache = match regs.eax & 0x1F {
+                    0 => break,
+                    1 => CacheType::Data,
+                    2 => CacheType::Code,
+                    3 => CacheType::CodeAndData,
+                    _ => CacheType::Unknown,
+                };
+
+                let level = match (regs.eax >> 5) & 0x07 {
+                    1 => CacheLevel::L1,
+                    2 => CacheLevel::L2,
+                    3 => CacheLevel::L3,
+                    4 => CacheLevel::L4,
+                    _ => CacheLevel::Unknown,
+                };
+
+                // everything in ebx & ecx is encoded minus one
+                let line_size = (regs.ebx & 0xFFF) + 1;
+                let partitions = ((regs.ebx >> 12) & 0x3FF) + 1;
+                let ways = ((regs.ebx >> 22) & 0x3FF) + 1;
+                let sets = regs.ecx + 1;
+
+                cpu.info.cache_parameters[subleaf as usize] = CacheParameters {
+                    level,
+                    type_of_cache,
+                    size: ways as usize * partitions as usize * line_size as usize * sets as usize,
+                    ways,
+                    partitions,
+                    line_size,
+                    sets,
+                    fully_associative: u32bit::is_bit_set(regs.eax, 9),
+                    self_initializing: u32bit::is_bit_set(regs.eax, 8),
+                    inclusive: u32bit::is_bit_set(regs.edx, 1),
+                    max_sharing_threads: ((regs.eax >> 14) & 0xFFF) + 1,
+                };
+                cpu.info.cache_parameter_count += 1;
             }
-            cpu.info.cache_descriptor_count = i as u8;
         }
-
         // CPUID / EAX == 7
         if cpu.info.max_cpuid_level >= 7 {
             let regs = x86_cpuid_ext(7, 0);
@@ -1026,24 +903,49 @@ impl Cpu {
                 .set_feat_prefetchwt1(u32bit::is_bit_set(regs.ecx, 0));
             cpu.info
                 .features_ext
-                .set_feat_arch_cap_msr(u32bit::is_bit_set(regs.ecx, 29));
+                .set_feat_arch_cap_msr(u32bit::is_bit_set(regs.edx, 29));
             cpu.info
                 .features_ext
-                .set_feat_stibp(u32bit::is_bit_set(regs.ecx, 27));
+                .set_feat_stibp(u32bit::is_bit_set(regs.edx, 27));
             cpu.info
                 .features_ext
-                .set_feat_ibrs_mbpb(u32bit::is_bit_set(regs.ecx, 26));
+                .set_feat_ibrs_mbpb(u32bit::is_bit_set(regs.edx, 26));
             cpu.info
                 .features_ext
-                .set_feat_pconfig(u32bit::is_bit_set(regs.ecx, 18));
+                .set_feat_pconfig(u32bit::is_bit_set(regs.edx, 18));
             cpu.info
                 .features_ext
-                .set_feat_avx512qfma(u32bit::is_bit_set(regs.ecx, 3));
+                .set_feat_avx512qfma(u32bit::is_bit_set(regs.edx, 3));
             cpu.info
                 .features_ext
-                .set_feat_avx512qvnniw(u32bit::is_bit_set(regs.ecx, 2));
+                .set_feat_avx512qvnniw(u32bit::is_bit_set(regs.edx, 2));
         }
 
+        // CPUID / EAX == 80000002H - 80000004H
+        cpu.info.max_extended_cpuid_level = x86_cpuid(X86_CPUID_EXT_MAX).eax;
+
+        if cpu.info.max_extended_cpuid_level >= X86_CPUID_BRAND_LAST {
+            for (i, leaf) in (X86_CPUID_BRAND_FIRST..=X86_CPUID_BRAND_LAST).enumerate() {
+                let regs = x86_cpuid(leaf);
+                let chunk = &mut cpu.info.brand_string[i * 16..(i + 1) * 16];
+
+                chunk[0..4].copy_from_slice(&regs.eax.to_le_bytes());
+                chunk[4..8].copy_from_slice(&regs.ebx.to_le_bytes());
+                chunk[8..12].copy_from_slice(&regs.ecx.to_le_bytes());
+                chunk[12..16].copy_from_slice(&regs.edx.to_le_bytes());
+            }
+        }
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!(
+            "Cpu::detect() -> {} ({}) family 0x{:0x} model 0x{:0x} stepping {}",
+            cpu.info.brand_str(),
+            cpu.info.vendor_str(),
+            cpu.info.family(),
+            cpu.info.model(),
+            cpu.info.stepping()
+        );
+
         cpu
     }
 }
@@ -0,0 +1,250 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the kernel's own global descriptor table & task state segment.
+// Once boot services are gone we stop relying on the firmware's gdt; on
+// x86_64 the tss also carries the interrupt stack table, which gives
+// #DF, NMI & #MC known-good stacks to run on no matter what state
+// the interrupted stack is in.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::arch::x86::interrupts::*;
+
+// selectors (index << 3 | rpl); user data precedes user code
+// so that sysret can derive both from a single base
+pub const X86_GDT_KERNEL_CODE_SEL: u16 = 0x08;
+pub const X86_GDT_KERNEL_DATA_SEL: u16 = 0x10;
+pub const X86_GDT_USER_DATA_SEL: u16 = 0x18 | 3;
+pub const X86_GDT_USER_CODE_SEL: u16 = 0x20 | 3;
+pub const X86_GDT_TSS_SEL: u16 = 0x28;
+
+// segment descriptors: flat 4GB, present; code is long mode on x86_64
+#[cfg(target_arch = "x86_64")]
+const X86_GDT_KERNEL_CODE: u64 = 0x00AF_9A00_0000_FFFF;
+#[cfg(target_arch = "x86_64")]
+const X86_GDT_USER_CODE: u64 = 0x00AF_FA00_0000_FFFF;
+#[cfg(target_arch = "x86")]
+const X86_GDT_KERNEL_CODE: u64 = 0x00CF_9A00_0000_FFFF;
+#[cfg(target_arch = "x86")]
+const X86_GDT_USER_CODE: u64 = 0x00CF_FA00_0000_FFFF;
+const X86_GDT_KERNEL_DATA: u64 = 0x00CF_9200_0000_FFFF;
+const X86_GDT_USER_DATA: u64 = 0x00CF_F200_0000_FFFF;
+
+// present, dpl 0, available tss
+const X86_TSS_DESCR_TYPE: u64 = 0x89;
+
+// the 64-bit tss descriptor takes two slots
+#[cfg(target_arch = "x86_64")]
+pub const X86_GDT_ENTRY_COUNT: usize = 7;
+#[cfg(target_arch = "x86")]
+pub const X86_GDT_ENTRY_COUNT: usize = 6;
+
+// interrupt stack table slots (1-based; 0 means "don't switch")
+pub const X86_IST_DOUBLE_FAULT: u8 = 1;
+pub const X86_IST_NMI: u8 = 2;
+pub const X86_IST_MACHINE_CHECK: u8 = 3;
+pub const X86_IST_STACK_COUNT: usize = 3;
+
+//-----------------------------------------------------------------------------------
+
+#[cfg(target_arch = "x86_64")]
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct Tss {
+    reserved0: u32,
+    pub rsp: [u64; 3],
+    reserved1: u64,
+    pub ist: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    pub iomap_base: u16,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Tss {
+    pub const fn new() -> Self {
+        Tss {
+            reserved0: 0,
+            rsp: [0; 3],
+            reserved1: 0,
+            ist: [0; 7],
+            reserved2: 0,
+            reserved3: 0,
+            // no i/o permission bitmap
+            iomap_base: core::mem::size_of::<Tss>() as u16,
+        }
+    }
+}
+
+#[cfg(target_arch = "x86")]
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct Tss {
+    pub prev_task_link: u32,
+    pub esp0: u32,
+    pub ss0: u32,
+    pub esp1: u32,
+    pub ss1: u32,
+    pub esp2: u32,
+    pub ss2: u32,
+    pub cr3: u32,
+    pub eip: u32,
+    pub eflags: u32,
+    pub gp_regs: [u32; 8],
+    pub seg_regs: [u32; 6],
+    pub ldt: u32,
+    pub trap: u16,
+    pub iomap_base: u16,
+}
+
+#[cfg(target_arch = "x86")]
+impl Tss {
+    pub const fn new() -> Self {
+        Tss {
+            prev_task_link: 0,
+            esp0: 0,
+            ss0: X86_GDT_KERNEL_DATA_SEL as u32,
+            esp1: 0,
+            ss1: 0,
+            esp2: 0,
+            ss2: 0,
+            cr3: 0,
+            eip: 0,
+            eflags: 0,
+            gp_regs: [0; 8],
+            seg_regs: [0; 6],
+            ldt: 0,
+            trap: 0,
+            iomap_base: core::mem::size_of::<Tss>() as u16,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+pub struct Gdt {
+    pub entries: [u64; X86_GDT_ENTRY_COUNT],
+}
+
+impl Gdt {
+    pub const fn new() -> Self {
+        Gdt {
+            entries: [0; X86_GDT_ENTRY_COUNT],
+        }
+    }
+
+    // writes the tss descriptor for the tss at base
+    fn set_tss(&mut self, base: usize) {
+        let limit = (core::mem::size_of::<Tss>() - 1) as u64;
+        let b = base as u64;
+
+        let low = (limit & 0xFFFF)
+            | ((b & 0xFF_FFFF) << 16)
+            | (X86_TSS_DESCR_TYPE << 40)
+            | (((limit >> 16) & 0xF) << 48)
+            | (((b >> 24) & 0xFF) << 56);
+
+        let idx = (X86_GDT_TSS_SEL >> 3) as usize;
+        self.entries[idx] = low;
+
+        // the upper half of the base lives in the next slot
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.entries[idx + 1] = b >> 32;
+        }
+    }
+
+    // loads this table into the gdtr; the table must live forever
+    pub fn load(&'static self) {
+        let gdtr = DescriptorTablePtr {
+            limit: (core::mem::size_of::<Gdt>() - 1) as u16,
+            base: self as *const Gdt as usize,
+        };
+
+        x86_write_gdtr(&gdtr);
+    }
+}
+
+// the kernel's gdt & tss; like the idt, they live in the image's
+// data section and are only written during bringup
+static mut KERNEL_GDT: Gdt = Gdt::new();
+static mut KERNEL_TSS: Tss = Tss::new();
+
+// returns a mutable reference to the kernel's tss
+pub fn kernel_tss() -> &'static mut Tss {
+    unsafe { &mut *core::ptr::addr_of_mut!(KERNEL_TSS) }
+}
+
+//-----------------------------------------------------------------------------------
+
+// builds & loads the kernel's gdt & tss, reloads every segment register,
+// and re-points the idt gates at the new code segment. kernel_stack_top is
+// the stack we come back to from ring 3; ist_stack_tops are the tops of the
+// #DF, NMI & #MC stacks (in that order), and are ignored on x86.
+pub fn x86_gdt_init(kernel_stack_top: PhysAddr, ist_stack_tops: &[PhysAddr; X86_IST_STACK_COUNT]) {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // fill out the tss
+    let tss = kernel_tss();
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        tss.rsp[0] = kernel_stack_top.as_usize() as u64;
+        tss.ist[(X86_IST_DOUBLE_FAULT - 1) as usize] = ist_stack_tops[0].as_usize() as u64;
+        tss.ist[(X86_IST_NMI - 1) as usize] = ist_stack_tops[1].as_usize() as u64;
+        tss.ist[(X86_IST_MACHINE_CHECK - 1) as usize] = ist_stack_tops[2].as_usize() as u64;
+    }
+
+    #[cfg(target_arch = "x86")]
+    {
+        tss.esp0 = kernel_stack_top.as_usize() as u32;
+        let _ = ist_stack_tops;
+    }
+
+    // fill out the gdt
+    let gdt = unsafe { &mut *core::ptr::addr_of_mut!(KERNEL_GDT) };
+
+    gdt.entries[0] = 0;
+    gdt.entries[(X86_GDT_KERNEL_CODE_SEL >> 3) as usize] = X86_GDT_KERNEL_CODE;
+    gdt.entries[(X86_GDT_KERNEL_DATA_SEL >> 3) as usize] = X86_GDT_KERNEL_DATA;
+    gdt.entries[(X86_GDT_USER_DATA_SEL >> 3) as usize] = X86_GDT_USER_DATA;
+    gdt.entries[(X86_GDT_USER_CODE_SEL >> 3) as usize] = X86_GDT_USER_CODE;
+    gdt.set_tss(tss as *const Tss as usize);
+
+    // the idt gates still point at the firmware's code segment, and
+    // the critical exceptions get their own stacks
+    let idt = kernel_idt();
+
+    for vector in 0..IDT_ENTRY_COUNT {
+        if idt.entries[vector].is_present() {
+            idt.entries[vector].set_selector(X86_GDT_KERNEL_CODE_SEL);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        idt.entries[X86_VEC_DOUBLE_FAULT as usize].set_ist(X86_IST_DOUBLE_FAULT);
+        idt.entries[X86_VEC_NMI as usize].set_ist(X86_IST_NMI);
+        idt.entries[X86_VEC_MACHINE_CHECK as usize].set_ist(X86_IST_MACHINE_CHECK);
+    }
+
+    // switch over; nothing may fault between the lgdt & the segment reload
+    x86_disable_interrupts();
+
+    gdt.load();
+    x86_reload_segments(X86_GDT_KERNEL_CODE_SEL, X86_GDT_KERNEL_DATA_SEL);
+    x86_load_tr(X86_GDT_TSS_SEL);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("x86_gdt_init() -> gdt loaded @ 0x{:08x}, tss @ 0x{:08x}, cs = 0x{:0x}", gdt as *const Gdt as usize, tss as *const Tss as usize, x86_read_cs());
+}
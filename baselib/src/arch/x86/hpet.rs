@@ -0,0 +1,121 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: just enough of the hpet to use its main counter as a clock: a
+// calibration reference for the tsc / local apic timer, and the clock of
+// last resort when the tsc can't be trusted. None of the comparators are used.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::common::base::*;
+
+// register offsets
+const HPET_REG_CAPABILITIES: usize = 0x000;
+const HPET_REG_CONFIG: usize = 0x010;
+const HPET_REG_MAIN_COUNTER: usize = 0x0F0;
+
+// capabilities: the counter period (femtoseconds) lives in the high dword
+const HPET_CAP_COUNTER_64BIT: u64 = 1 << 13;
+const HPET_CAP_PERIOD_SHIFT: u64 = 32;
+// the spec caps the period at 100ns
+const HPET_MAX_PERIOD_FS: u64 = 100_000_000;
+
+// config: main counter runs
+const HPET_CONFIG_ENABLE: u64 = 1 << 0;
+
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Hpet {
+    base: PhysAddr,
+    period_fs: u64,
+    counter_64bit: bool,
+}
+
+impl Hpet {
+    fn read(&self, reg: usize) -> u64 {
+        unsafe { core::ptr::read_volatile((self.base.as_usize() + reg) as *const u64) }
+    }
+
+    fn write(&self, reg: usize, value: u64) {
+        unsafe { core::ptr::write_volatile((self.base.as_usize() + reg) as *mut u64, value) }
+    }
+
+    pub fn base(&self) -> PhysAddr {
+        self.base
+    }
+
+    // femtoseconds per counter tick
+    pub fn period_fs(&self) -> u64 {
+        self.period_fs
+    }
+
+    pub fn hz(&self) -> u64 {
+        FEMTOSECONDS_PER_SECOND / self.period_fs
+    }
+
+    // a 32 bit main counter wraps every ~5 minutes at the usual 14.3MHz
+    pub fn counter_64bit(&self) -> bool {
+        self.counter_64bit
+    }
+
+    #[inline(always)]
+    pub fn counter(&self) -> u64 {
+        self.read(HPET_REG_MAIN_COUNTER)
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+static mut KERNEL_HPET: Option<Hpet> = None;
+
+pub fn hpet() -> Option<&'static Hpet> {
+    unsafe { (*core::ptr::addr_of!(KERNEL_HPET)).as_ref() }
+}
+
+// sanity checks the hpet at base (which must already be mapped uncached)
+// & starts its main counter. returns false if there's nothing sane there.
+pub fn x86_hpet_init(base: PhysAddr) -> bool {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return false;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let mut hpet = Hpet {
+        base,
+        period_fs: 0,
+        counter_64bit: false,
+    };
+
+    let caps = hpet.read(HPET_REG_CAPABILITIES);
+    hpet.period_fs = caps >> HPET_CAP_PERIOD_SHIFT;
+    hpet.counter_64bit = caps & HPET_CAP_COUNTER_64BIT != 0;
+
+    if hpet.period_fs == ZERO_U64 || hpet.period_fs > HPET_MAX_PERIOD_FS {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("x86_hpet_init() -> bogus counter period {} fs @ 0x{:0x}", hpet.period_fs, base);
+        return false;
+    }
+
+    let config = hpet.read(HPET_REG_CONFIG);
+    hpet.write(HPET_REG_CONFIG, config | HPET_CONFIG_ENABLE);
+
+    unsafe {
+        KERNEL_HPET = Some(hpet);
+    }
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!(
+        "x86_hpet_init() -> hpet @ 0x{:0x}, {} hz, {} bit main counter",
+        base,
+        hpet.hz(),
+        if hpet.counter_64bit { 64 } else { 32 }
+    );
+
+    true
+}
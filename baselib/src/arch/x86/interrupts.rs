@@ -0,0 +1,387 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the kernel's interrupt descriptor table & the handlers
+// for the 32 architecturally defined exceptions
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::arch::x86::page_fault::x86_handle_page_fault;
+use crate::status::KernelServiceStatus;
+
+// the number of gates in the idt
+pub const IDT_ENTRY_COUNT: usize = 256;
+
+// the number of architecturally defined exception vectors
+pub const X86_EXCEPTION_COUNT: usize = 32;
+
+// present, dpl 0, interrupt gate (interrupts disabled on entry)
+pub const IDT_GATE_INTERRUPT: u8 = 0x8E;
+// present, dpl 0, trap gate (interrupts left as they were)
+pub const IDT_GATE_TRAP: u8 = 0x8F;
+
+// exception vectors
+pub const X86_VEC_DIVIDE_ERROR: u8 = 0;
+pub const X86_VEC_DEBUG: u8 = 1;
+pub const X86_VEC_NMI: u8 = 2;
+pub const X86_VEC_BREAKPOINT: u8 = 3;
+pub const X86_VEC_OVERFLOW: u8 = 4;
+pub const X86_VEC_BOUND_RANGE: u8 = 5;
+pub const X86_VEC_INVALID_OPCODE: u8 = 6;
+pub const X86_VEC_DEVICE_NOT_AVAILABLE: u8 = 7;
+pub const X86_VEC_DOUBLE_FAULT: u8 = 8;
+pub const X86_VEC_COPROCESSOR_SEGMENT_OVERRUN: u8 = 9;
+pub const X86_VEC_INVALID_TSS: u8 = 10;
+pub const X86_VEC_SEGMENT_NOT_PRESENT: u8 = 11;
+pub const X86_VEC_STACK_SEGMENT_FAULT: u8 = 12;
+pub const X86_VEC_GENERAL_PROTECTION: u8 = 13;
+pub const X86_VEC_PAGE_FAULT: u8 = 14;
+pub const X86_VEC_X87_FLOATING_POINT: u8 = 16;
+pub const X86_VEC_ALIGNMENT_CHECK: u8 = 17;
+pub const X86_VEC_MACHINE_CHECK: u8 = 18;
+pub const X86_VEC_SIMD_FLOATING_POINT: u8 = 19;
+pub const X86_VEC_VIRTUALIZATION: u8 = 20;
+pub const X86_VEC_CONTROL_PROTECTION: u8 = 21;
+pub const X86_VEC_HYPERVISOR_INJECTION: u8 = 28;
+pub const X86_VEC_VMM_COMMUNICATION: u8 = 29;
+pub const X86_VEC_SECURITY: u8 = 30;
+
+const X86_EXCEPTION_NAMES: [&str; X86_EXCEPTION_COUNT] = [
+    "divide error (#DE)",
+    "debug (#DB)",
+    "non-maskable interrupt (NMI)",
+    "breakpoint (#BP)",
+    "overflow (#OF)",
+    "bound range exceeded (#BR)",
+    "invalid opcode (#UD)",
+    "device not available (#NM)",
+    "double fault (#DF)",
+    "coprocessor segment overrun",
+    "invalid tss (#TS)",
+    "segment not present (#NP)",
+    "stack-segment fault (#SS)",
+    "general protection (#GP)",
+    "page fault (#PF)",
+    "reserved (15)",
+    "x87 floating-point (#MF)",
+    "alignment check (#AC)",
+    "machine check (#MC)",
+    "simd floating-point (#XM)",
+    "virtualization (#VE)",
+    "control protection (#CP)",
+    "reserved (22)",
+    "reserved (23)",
+    "reserved (24)",
+    "reserved (25)",
+    "reserved (26)",
+    "reserved (27)",
+    "hypervisor injection (#HV)",
+    "vmm communication (#VC)",
+    "security (#SX)",
+    "reserved (31)",
+];
+
+// returns the human readable name of an exception vector
+pub fn x86_exception_name(vector: u8) -> &'static str {
+    if (vector as usize) < X86_EXCEPTION_COUNT {
+        X86_EXCEPTION_NAMES[vector as usize]
+    } else {
+        "external interrupt"
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// the frame the processor pushes on entry; on x86 the stack pointer &
+// stack segment are only pushed on a privilege level change
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ExceptionStackFrame {
+    pub instruction_pointer: usize,
+    pub code_segment: usize,
+    pub cpu_flags: usize,
+    pub stack_pointer: usize,
+    pub stack_segment: usize,
+}
+
+// a single idt gate descriptor
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct IdtEntry {
+    offset_low: u16,
+    selector: u16,
+    ist: u8,
+    type_attr: u8,
+    offset_mid: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+#[cfg(target_arch = "x86")]
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct IdtEntry {
+    offset_low: u16,
+    selector: u16,
+    reserved: u8,
+    type_attr: u8,
+    offset_high: u16,
+}
+
+impl IdtEntry {
+    #[cfg(target_arch = "x86_64")]
+    pub const fn missing() -> Self {
+        IdtEntry {
+            offset_low: 0,
+            selector: 0,
+            ist: 0,
+            type_attr: 0,
+            offset_mid: 0,
+            offset_high: 0,
+            reserved: 0,
+        }
+    }
+
+    #[cfg(target_arch = "x86")]
+    pub const fn missing() -> Self {
+        IdtEntry {
+            offset_low: 0,
+            selector: 0,
+            reserved: 0,
+            type_attr: 0,
+            offset_high: 0,
+        }
+    }
+
+    // point the gate at handler, entering through the given code segment
+    #[cfg(target_arch = "x86_64")]
+    pub fn set(&mut self, handler: usize, selector: u16, type_attr: u8) {
+        self.offset_low = (handler & 0xFFFF) as u16;
+        self.offset_mid = ((handler >> 16) & 0xFFFF) as u16;
+        self.offset_high = ((handler >> 32) & 0xFFFF_FFFF) as u32;
+        self.selector = selector;
+        self.type_attr = type_attr;
+        self.reserved = 0;
+    }
+
+    #[cfg(target_arch = "x86")]
+    pub fn set(&mut self, handler: usize, selector: u16, type_attr: u8) {
+        self.offset_low = (handler & 0xFFFF) as u16;
+        self.offset_high = ((handler >> 16) & 0xFFFF) as u16;
+        self.selector = selector;
+        self.type_attr = type_attr;
+        self.reserved = 0;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn handler(&self) -> usize {
+        (self.offset_low as usize) | ((self.offset_mid as usize) << 16) | ((self.offset_high as usize) << 32)
+    }
+
+    #[cfg(target_arch = "x86")]
+    pub fn handler(&self) -> usize {
+        (self.offset_low as usize) | ((self.offset_high as usize) << 16)
+    }
+
+    // selects the interrupt stack table slot to switch to on entry (0 = none)
+    #[cfg(target_arch = "x86_64")]
+    pub fn set_ist(&mut self, ist: u8) {
+        self.ist = ist & 0x7;
+    }
+
+    pub fn selector(&self) -> u16 {
+        self.selector
+    }
+
+    pub fn set_selector(&mut self, selector: u16) {
+        self.selector = selector;
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.type_attr & 0x80 != 0
+    }
+}
+
+#[repr(C, align(16))]
+pub struct Idt {
+    pub entries: [IdtEntry; IDT_ENTRY_COUNT],
+}
+
+impl Idt {
+    pub const fn new() -> Self {
+        Idt {
+            entries: [IdtEntry::missing(); IDT_ENTRY_COUNT],
+        }
+    }
+
+    pub fn set_handler(&mut self, vector: u8, handler: usize, selector: u16, type_attr: u8) {
+        self.entries[vector as usize].set(handler, selector, type_attr);
+    }
+
+    // loads this table into the idtr; the table must live forever
+    pub fn load(&'static self) {
+        let idtr = DescriptorTablePtr {
+            limit: (core::mem::size_of::<Idt>() - 1) as u16,
+            base: self as *const Idt as usize,
+        };
+
+        x86_write_idtr(&idtr);
+    }
+}
+
+// the kernel's idt; it lives in the image's data section and is only
+// written during bringup & when drivers (un)hook vectors, with interrupts off
+static mut KERNEL_IDT: Idt = Idt::new();
+
+// returns a mutable reference to the kernel's idt
+pub fn kernel_idt() -> &'static mut Idt {
+    unsafe { &mut *core::ptr::addr_of_mut!(KERNEL_IDT) }
+}
+
+//-----------------------------------------------------------------------------------
+
+// exception entry points; the x86-interrupt abi takes care of saving
+// the scratch registers & the iret, we just hand off to the dispatcher
+
+macro_rules! x86_exception_handler {
+    ($name:ident, $vector:expr) => {
+        extern "x86-interrupt" fn $name(frame: ExceptionStackFrame) {
+            x86_exception_dispatch($vector, None, &frame);
+        }
+    };
+}
+
+macro_rules! x86_exception_handler_with_error {
+    ($name:ident, $vector:expr) => {
+        extern "x86-interrupt" fn $name(frame: ExceptionStackFrame, error_code: usize) {
+            x86_exception_dispatch($vector, Some(error_code), &frame);
+        }
+    };
+}
+
+x86_exception_handler!(x86_exc_00, 0);
+x86_exception_handler!(x86_exc_01, 1);
+x86_exception_handler!(x86_exc_02, 2);
+x86_exception_handler!(x86_exc_03, 3);
+x86_exception_handler!(x86_exc_04, 4);
+x86_exception_handler!(x86_exc_05, 5);
+x86_exception_handler!(x86_exc_06, 6);
+x86_exception_handler!(x86_exc_07, 7);
+x86_exception_handler_with_error!(x86_exc_08, 8);
+x86_exception_handler!(x86_exc_09, 9);
+x86_exception_handler_with_error!(x86_exc_10, 10);
+x86_exception_handler_with_error!(x86_exc_11, 11);
+x86_exception_handler_with_error!(x86_exc_12, 12);
+x86_exception_handler_with_error!(x86_exc_13, 13);
+x86_exception_handler_with_error!(x86_exc_14, 14);
+x86_exception_handler!(x86_exc_15, 15);
+x86_exception_handler!(x86_exc_16, 16);
+x86_exception_handler_with_error!(x86_exc_17, 17);
+x86_exception_handler!(x86_exc_18, 18);
+x86_exception_handler!(x86_exc_19, 19);
+x86_exception_handler!(x86_exc_20, 20);
+x86_exception_handler_with_error!(x86_exc_21, 21);
+x86_exception_handler!(x86_exc_22, 22);
+x86_exception_handler!(x86_exc_23, 23);
+x86_exception_handler!(x86_exc_24, 24);
+x86_exception_handler!(x86_exc_25, 25);
+x86_exception_handler!(x86_exc_26, 26);
+x86_exception_handler!(x86_exc_27, 27);
+x86_exception_handler!(x86_exc_28, 28);
+x86_exception_handler_with_error!(x86_exc_29, 29);
+x86_exception_handler_with_error!(x86_exc_30, 30);
+x86_exception_handler!(x86_exc_31, 31);
+
+// returns the entry point for exception vector
+fn x86_exception_entry(vector: usize) -> usize {
+    let handlers: [usize; X86_EXCEPTION_COUNT] = [
+        x86_exc_00 as usize, x86_exc_01 as usize, x86_exc_02 as usize, x86_exc_03 as usize,
+        x86_exc_04 as usize, x86_exc_05 as usize, x86_exc_06 as usize, x86_exc_07 as usize,
+        x86_exc_08 as usize, x86_exc_09 as usize, x86_exc_10 as usize, x86_exc_11 as usize,
+        x86_exc_12 as usize, x86_exc_13 as usize, x86_exc_14 as usize, x86_exc_15 as usize,
+        x86_exc_16 as usize, x86_exc_17 as usize, x86_exc_18 as usize, x86_exc_19 as usize,
+        x86_exc_20 as usize, x86_exc_21 as usize, x86_exc_22 as usize, x86_exc_23 as usize,
+        x86_exc_24 as usize, x86_exc_25 as usize, x86_exc_26 as usize, x86_exc_27 as usize,
+        x86_exc_28 as usize, x86_exc_29 as usize, x86_exc_30 as usize, x86_exc_31 as usize,
+    ];
+
+    handlers[vector]
+}
+
+// common exception path: report what happened over serial, then either
+// resume (for the benign traps) or stop the world
+fn x86_exception_dispatch(vector: u8, error_code: Option<usize>, frame: &ExceptionStackFrame) {
+    // page faults get a shot at being resolved before we call them fatal
+    if vector == X86_VEC_PAGE_FAULT && x86_handle_page_fault(error_code.unwrap_or(ZERO_USIZE), frame) {
+        return;
+    }
+
+    serial_println!("exception: vector {} -> {}", vector, x86_exception_name(vector));
+
+    match error_code {
+        Some(code) => serial_println!("  error code: 0x{:0x}", code),
+        None => serial_println!("  error code: none"),
+    }
+
+    serial_println!(
+        "  ip: 0x{:0x}  cs: 0x{:0x}  flags: 0x{:0x}",
+        frame.instruction_pointer,
+        frame.code_segment,
+        frame.cpu_flags
+    );
+
+    // only pushed unconditionally in long mode
+    #[cfg(target_arch = "x86_64")]
+    serial_println!(
+        "  sp: 0x{:0x}  ss: 0x{:0x}",
+        frame.stack_pointer,
+        frame.stack_segment
+    );
+
+    match vector {
+        // traps we can safely return from
+        X86_VEC_DEBUG | X86_VEC_BREAKPOINT | X86_VEC_OVERFLOW => {}
+        _ => panic!("unhandled {} @ 0x{:0x}", x86_exception_name(vector), frame.instruction_pointer),
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// populates the kernel idt with the exception handlers & loads it;
+// the gates use whatever code segment we're currently running on
+pub fn x86_interrupts_init() {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let cs = x86_read_cs();
+    let idt = kernel_idt();
+
+    for vector in 0..X86_EXCEPTION_COUNT {
+        // the benign traps go through trap gates, everything else
+        // comes in with interrupts off
+        let gate = match vector as u8 {
+            X86_VEC_DEBUG | X86_VEC_BREAKPOINT | X86_VEC_OVERFLOW => IDT_GATE_TRAP,
+            _ => IDT_GATE_INTERRUPT,
+        };
+
+        idt.set_handler(vector as u8, x86_exception_entry(vector), cs, gate);
+    }
+
+    idt.load();
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("x86_interrupts_init() -> idt loaded @ 0x{:08x}, cs = 0x{:0x}", idt as *const Idt as usize, cs);
+
+    // we can now take (and report) exceptions
+    iron().unwrap().status.lock_rw_spin().as_mut().unwrap().internal_exceptions = KernelServiceStatus::SysOnly;
+}
@@ -0,0 +1,142 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the x86 (apic) backend for the driver interrupt api in
+// crate::interrupts. Device vectors live in 0x40-0xEF; each one gets its
+// own idt stub (the x86-interrupt abi doesn't tell us which vector fired),
+// and every stub funnels into the arch-neutral dispatcher.
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::arch::x86::apic::*;
+use crate::arch::x86::interrupts::*;
+use crate::interrupts::{interrupt_dispatch, InterruptVector, MsiMessage};
+
+// the number of vectors the dispatcher has to track
+pub const ARCH_INTERRUPT_VECTOR_COUNT: usize = IDT_ENTRY_COUNT;
+
+// the vectors handed out to drivers
+pub const X86_IRQ_VECTOR_FIRST: u8 = 0x40;
+pub const X86_IRQ_VECTOR_LAST: u8 = 0xEF;
+
+// msi address: fixed destination in the local apic's window
+const X86_MSI_ADDRESS_BASE: u64 = 0xFEE0_0000;
+const X86_MSI_DEST_ID_SHIFT: u64 = 12;
+
+const X86_RFLAGS_IF: usize = 1 << 9;
+
+//-----------------------------------------------------------------------------------
+
+// one monomorphized stub per device vector
+extern "x86-interrupt" fn x86_irq_stub<const VECTOR: u8>(_frame: ExceptionStackFrame) {
+    interrupt_dispatch(VECTOR as InterruptVector);
+}
+
+macro_rules! x86_irq_stub_table {
+    ($($vector:literal),* $(,)?) => {
+        [$(x86_irq_stub::<$vector> as usize),*]
+    };
+}
+
+fn x86_irq_stub_addr(vector: u8) -> usize {
+    let stubs: [usize; (X86_IRQ_VECTOR_LAST - X86_IRQ_VECTOR_FIRST) as usize + 1] = x86_irq_stub_table!(
+    0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+    0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F,
+    0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57,
+    0x58, 0x59, 0x5A, 0x5B, 0x5C, 0x5D, 0x5E, 0x5F,
+    0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67,
+    0x68, 0x69, 0x6A, 0x6B, 0x6C, 0x6D, 0x6E, 0x6F,
+    0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77,
+    0x78, 0x79, 0x7A, 0x7B, 0x7C, 0x7D, 0x7E, 0x7F,
+    0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x8B, 0x8C, 0x8D, 0x8E, 0x8F,
+    0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,
+    0x98, 0x99, 0x9A, 0x9B, 0x9C, 0x9D, 0x9E, 0x9F,
+    0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xAB, 0xAC, 0xAD, 0xAE, 0xAF,
+    0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7,
+    0xB8, 0xB9, 0xBA, 0xBB, 0xBC, 0xBD, 0xBE, 0xBF,
+    0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7,
+    0xC8, 0xC9, 0xCA, 0xCB, 0xCC, 0xCD, 0xCE, 0xCF,
+    0xD0, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7,
+    0xD8, 0xD9, 0xDA, 0xDB, 0xDC, 0xDD, 0xDE, 0xDF,
+    0xE0, 0xE1, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7,
+    0xE8, 0xE9, 0xEA, 0xEB, 0xEC, 0xED, 0xEE, 0xEF,
+    );
+
+    stubs[(vector - X86_IRQ_VECTOR_FIRST) as usize]
+}
+
+//-----------------------------------------------------------------------------------
+
+// true if drivers may register on this vector
+pub fn arch_interrupt_vector_is_dynamic(vector: InterruptVector) -> bool {
+    vector >= X86_IRQ_VECTOR_FIRST as InterruptVector && vector <= X86_IRQ_VECTOR_LAST as InterruptVector
+}
+
+// the vectors allocate_vector() picks from for a given priority. the local apic
+// prioritizes by vector class (vector >> 4), so higher priorities get higher classes
+pub fn arch_interrupt_priority_range(priority: Priority) -> (InterruptVector, InterruptVector) {
+    let (first, last): (u8, u8) = match priority {
+        Priority::Lowest => (0x40, 0x4F),
+        Priority::Anonymous => (0x50, 0x5F),
+        Priority::Low => (0x60, 0x7F),
+        Priority::Normal => (0x80, 0xAF),
+        Priority::High => (0xB0, 0xCF),
+        Priority::Highest => (0xD0, 0xDF),
+        Priority::System => (0xE0, X86_IRQ_VECTOR_LAST),
+    };
+
+    (first as InterruptVector, last as InterruptVector)
+}
+
+// points the vector's gate at its stub; the priority is implied by the vector on x86.
+// the kernel's own sources (exceptions, the apic timer) keep their dedicated gates
+pub fn arch_interrupt_install(vector: InterruptVector, _priority: Priority) {
+    if !arch_interrupt_vector_is_dynamic(vector) {
+        return;
+    }
+
+    let vector = vector as u8;
+
+    kernel_idt().set_handler(vector, x86_irq_stub_addr(vector), x86_read_cs(), IDT_GATE_INTERRUPT);
+}
+
+// takes the vector's gate away again
+pub fn arch_interrupt_remove(vector: InterruptVector) {
+    if !arch_interrupt_vector_is_dynamic(vector) {
+        return;
+    }
+
+    kernel_idt().entries[vector as usize] = IdtEntry::missing();
+}
+
+#[inline(always)]
+pub fn arch_interrupt_eoi(_vector: InterruptVector) {
+    if let Some(lapic) = local_apic() {
+        lapic.eoi();
+    }
+}
+
+// disables interrupts on this cpu; returns whether they were enabled
+#[inline(always)]
+pub fn arch_interrupt_save_disable() -> bool {
+    let enabled = x86_read_flags() & X86_RFLAGS_IF != 0;
+    x86_disable_interrupts();
+    enabled
+}
+
+#[inline(always)]
+pub fn arch_interrupt_restore(enabled: bool) {
+    if enabled {
+        x86_enable_interrupts();
+    }
+}
+
+// the message a device writes to raise vector on the cpu whose apic id is
+// target (fixed delivery, physical destination, edge triggered)
+pub fn arch_msi_message(vector: InterruptVector, target: u32) -> Option<MsiMessage> {
+    Some(MsiMessage {
+        address: X86_MSI_ADDRESS_BASE | (((target & 0xFF) as u64) << X86_MSI_DEST_ID_SHIFT),
+        data: vector & 0xFF,
+    })
+}
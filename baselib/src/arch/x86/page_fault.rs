@@ -0,0 +1,238 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: page fault decoding. On #PF we capture everything we know about
+// the fault (cr2, the decoded error code, the pte chain in the active
+// address space, and what the frame allocator & page info structs say
+// about the backing frame) into a PageFaultRecord, then offer it to a
+// pluggable resolver. Demand paging, copy-on-write & friends can be
+// layered on by installing a resolver; the handler itself doesn't change.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use bitfield_struct::*;
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::arch::x86::interrupts::ExceptionStackFrame;
+
+// the #PF error code pushed by the processor
+#[bitfield(u32)]
+#[derive(PartialEq, Eq)]
+pub struct PageFaultErrorCode {
+    // 0 = non-present page, 1 = protection violation
+    #[bits(1)]
+    pub present: bool,
+    // 0 = read, 1 = write
+    #[bits(1)]
+    pub write: bool,
+    // 0 = supervisor, 1 = user mode access
+    #[bits(1)]
+    pub user: bool,
+    // a reserved bit was set in a paging structure entry
+    #[bits(1)]
+    pub reserved_bit: bool,
+    // the access was an instruction fetch
+    #[bits(1)]
+    pub instruction_fetch: bool,
+    // protection key violation
+    #[bits(1)]
+    pub protection_key: bool,
+    // shadow stack access
+    #[bits(1)]
+    pub shadow_stack: bool,
+    #[bits(8)]
+    reserved0: u8,
+    // sgx access control violation
+    #[bits(1)]
+    pub sgx: bool,
+    #[bits(16)]
+    reserved1: u16,
+}
+
+// everything we could find out about a page fault
+#[derive(Debug, Copy, Clone)]
+pub struct PageFaultRecord {
+    pub fault_addr: VirtAddr,
+    pub error_code: PageFaultErrorCode,
+    pub instruction_pointer: usize,
+    pub cr3: PhysAddr,
+    // whether the fault happened in the kernel's base address space
+    pub is_kernel_vas: bool,
+    // the owner of the faulting address space, if we know it
+    pub vas_owner: Option<Owner>,
+    // the raw paging structure entries visited, top level first
+    pub pte_chain: [Pte; PAGING_LEVELS],
+    pub pte_levels: usize,
+    // the physical frame the leaf entry points at (present or not)
+    pub phys_frame: Option<PhysAddr>,
+    // what the page info structs & the frame allocator say about that frame;
+    // None if unknown or if the structures were locked when we faulted
+    pub page_status: Option<pages::PageStatus>,
+    pub frame_owner: Option<Owner>,
+}
+
+// what a resolver made of a fault
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageFaultResolution {
+    // the mapping was fixed up; retry the faulting instruction
+    Resolved,
+    // not ours to fix; the fault is fatal
+    Unhandled,
+}
+
+pub type PageFaultResolver = fn(&PageFaultRecord) -> PageFaultResolution;
+
+// the installed resolver as a raw fn pointer (0 = none); kept
+// lock free so the handler can never deadlock on it
+static PAGE_FAULT_RESOLVER: AtomicUsize = AtomicUsize::new(0);
+
+// installs (or with None, removes) the page fault resolver;
+// returns the previously installed one
+pub fn set_page_fault_resolver(resolver: Option<PageFaultResolver>) -> Option<PageFaultResolver> {
+    let new_raw = match resolver {
+        Some(f) => f as usize,
+        None => ZERO_USIZE,
+    };
+
+    match PAGE_FAULT_RESOLVER.swap(new_raw, Ordering::SeqCst) {
+        ZERO_USIZE => None,
+        old_raw => Some(unsafe { core::mem::transmute::<usize, PageFaultResolver>(old_raw) }),
+    }
+}
+
+fn page_fault_resolver() -> Option<PageFaultResolver> {
+    match PAGE_FAULT_RESOLVER.load(Ordering::SeqCst) {
+        ZERO_USIZE => None,
+        raw => Some(unsafe { core::mem::transmute::<usize, PageFaultResolver>(raw) }),
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+impl PageFaultRecord {
+    // builds the record for the fault currently being handled. we can get here
+    // with any lock in the system held, so we only ever try the locks once
+    pub fn capture(error_code: usize, frame: &ExceptionStackFrame) -> Self {
+        let cr3 = PhysAddr(x86_read_raw_cr3() & !PAGING_PCID_CR3_MASK);
+        let fault_addr = x86_read_cr2().as_virt();
+
+        let mut record = PageFaultRecord {
+            fault_addr,
+            error_code: PageFaultErrorCode::from(error_code as u32),
+            instruction_pointer: frame.instruction_pointer,
+            cr3,
+            is_kernel_vas: false,
+            vas_owner: None,
+            pte_chain: [ZERO_USIZE.as_phys(); PAGING_LEVELS],
+            pte_levels: ZERO_USIZE,
+            phys_frame: None,
+            page_status: None,
+            frame_owner: None,
+        };
+
+        // walk the table the processor was actually using; everything is identity
+        // mapped, so we can get at the paging structures through their physical addresses
+        let base_page_table = raw::abracadabra_static_ref_mut::<BasePageTable>(cr3.align_canon_default(), false);
+        let (pte_chain, pte_levels) = base_page_table.walk_entries(fault_addr);
+        record.pte_chain = pte_chain;
+        record.pte_levels = pte_levels;
+
+        // only a leaf (or large page) entry carries a frame address
+        let leaf = pte_chain[pte_levels - 1];
+        let reached_frame = pte_levels == PAGING_LEVELS ||
+                            ubit::is_bit_set(leaf.as_usize(), PAGING_IS_PAGE_FRAME_BIT);
+
+        if reached_frame && leaf.align_canon_default() != ZERO_USIZE.as_phys() {
+            record.phys_frame = Some(base_page_table.virt_to_phys(fault_addr));
+        }
+
+        // no genesis frame yet, so nothing else to consult
+        let neb = match iron() {
+            Some(neb) => neb,
+            None => return record,
+        };
+
+        // is this the kernel's address space?
+        if let Some(mut vas_lock) = neb.base_vas_07.try_lock_rw_immediate() {
+            if let Some(Some(vas)) = vas_lock.as_mut() {
+                if vas.cr3.align_canon_default() == cr3.align_canon_default() {
+                    record.is_kernel_vas = true;
+                    record.vas_owner = Some(vas.owner);
+                }
+            }
+        }
+
+        let phys_frame = match record.phys_frame {
+            Some(p) => p,
+            None => return record,
+        };
+
+        // page status
+        if let Some(mut page_info_lock) = neb.page_info_structs_01.try_lock_rw_immediate() {
+            if let Some(Some(page_info_structs)) = page_info_lock.as_mut() {
+                let page_idx = pages::addr_to_page_index(phys_frame);
+
+                if page_idx < page_info_structs.len() {
+                    record.page_status = Some(page_info_structs[page_idx].status);
+                }
+            }
+        }
+
+        // frame owner
+        if let Some(mut frame_alloc_lock) = neb.frame_alloc_internal_04.try_lock_rw_immediate() {
+            if let Some(Some(frame_alloc)) = frame_alloc_lock.as_mut() {
+                record.frame_owner = frame_alloc.frame_owner(phys_frame);
+            }
+        }
+
+        record
+    }
+
+    // dumps the record to the serial port
+    pub fn report(&self) {
+        let e = self.error_code;
+
+        serial_println!("page fault @ 0x{:0x} (cr2), ip: 0x{:0x}", self.fault_addr, self.instruction_pointer);
+        serial_println!(
+            "  {} {} from {} mode{}{}{}",
+            if e.present() { "protection violation on" } else { "non-present page on" },
+            if e.instruction_fetch() { "instruction fetch" } else if e.write() { "write" } else { "read" },
+            if e.user() { "user" } else { "supervisor" },
+            if e.reserved_bit() { ", reserved bit set" } else { "" },
+            if e.protection_key() { ", protection key" } else { "" },
+            if e.shadow_stack() { ", shadow stack" } else { "" },
+        );
+        serial_println!(
+            "  cr3: 0x{:0x} ({}), vas owner: {:?}",
+            self.cr3,
+            if self.is_kernel_vas { "kernel vas" } else { "unknown vas" },
+            self.vas_owner
+        );
+
+        for level in 0..self.pte_levels {
+            serial_println!("  level {} entry: 0x{:016x}", PAGING_LEVELS - level, self.pte_chain[level].as_usize());
+        }
+
+        match self.phys_frame {
+            Some(p) => serial_println!("  frame: 0x{:0x}, status: {:?}, owner: {:?}", p, self.page_status, self.frame_owner),
+            None => serial_println!("  frame: none"),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// the #PF path proper: capture, offer to the resolver, and report if nobody
+// could fix it up. returns true if the faulting instruction can be retried.
+pub fn x86_handle_page_fault(error_code: usize, frame: &ExceptionStackFrame) -> bool {
+    let record = PageFaultRecord::capture(error_code, frame);
+
+    if let Some(resolver) = page_fault_resolver() {
+        if resolver(&record) == PageFaultResolution::Resolved {
+            return true;
+        }
+    }
+
+    record.report();
+    false
+}
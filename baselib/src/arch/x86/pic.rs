@@ -0,0 +1,56 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the legacy 8259 pic pair. We don't use it, but it has to be
+// moved out of the way & silenced before the apics take over, otherwise a
+// stray irq 0-7 arrives on an exception vector.
+
+use crate::arch::x86::asm::*;
+
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+
+// icw1: edge triggered, cascade, icw4 follows
+const PIC_ICW1_INIT: u8 = 0x11;
+// icw4: 8086 mode
+const PIC_ICW4_8086: u8 = 0x01;
+
+// where the pics get parked; spurious irqs (7 & 15) from a masked
+// pic still show up on these vectors
+pub const X86_PIC1_VECTOR_BASE: u8 = 0x20;
+pub const X86_PIC2_VECTOR_BASE: u8 = 0x28;
+
+// a write to an unused port gives the pics time to settle between icws
+#[inline(always)]
+fn pic_io_wait() {
+    x86_outport8(0x80, 0);
+}
+
+// remaps both pics above the exception vectors & masks every line
+pub fn x86_pic_disable() {
+    x86_outport8(PIC1_COMMAND, PIC_ICW1_INIT);
+    pic_io_wait();
+    x86_outport8(PIC2_COMMAND, PIC_ICW1_INIT);
+    pic_io_wait();
+
+    x86_outport8(PIC1_DATA, X86_PIC1_VECTOR_BASE);
+    pic_io_wait();
+    x86_outport8(PIC2_DATA, X86_PIC2_VECTOR_BASE);
+    pic_io_wait();
+
+    // the slave hangs off the master's irq 2
+    x86_outport8(PIC1_DATA, 0x04);
+    pic_io_wait();
+    x86_outport8(PIC2_DATA, 0x02);
+    pic_io_wait();
+
+    x86_outport8(PIC1_DATA, PIC_ICW4_8086);
+    pic_io_wait();
+    x86_outport8(PIC2_DATA, PIC_ICW4_8086);
+    pic_io_wait();
+
+    // mask everything
+    x86_outport8(PIC1_DATA, 0xFF);
+    x86_outport8(PIC2_DATA, 0xFF);
+}
@@ -0,0 +1,59 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the legacy 8254 pit. Nothing ticks off it; channel 2 is only
+// used as a known-good stopwatch to calibrate the tsc & the local apic
+// timer against, since it can be polled without taking an interrupt.
+
+use crate::arch::x86::asm::*;
+
+// the pit's input clock
+pub const X86_PIT_HZ: u64 = 1_193_182;
+
+const PIT_CHANNEL2_DATA: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+
+// nmi status & control; bit 0 gates channel 2, bit 1 routes it to the
+// speaker (which we want off), bit 5 reflects channel 2's output
+const PIT_GATE_PORT: u16 = 0x61;
+const PIT_GATE_CHANNEL2: u8 = 1 << 0;
+const PIT_GATE_SPEAKER: u8 = 1 << 1;
+const PIT_GATE_OUT2: u8 = 1 << 5;
+
+// channel 2, lobyte/hibyte, mode 0 (interrupt on terminal count), binary
+const PIT_CMD_CHANNEL2_ONESHOT: u8 = 0b1011_0000;
+
+// the longest window a 16 bit count can cover, ~54.9ms
+pub const X86_PIT_MAX_WINDOW_US: u64 = (0xFFFF * 1_000_000) / X86_PIT_HZ;
+
+// busy waits on channel 2 for window_us microseconds (capped at
+// X86_PIT_MAX_WINDOW_US), calling begin() right as the count starts &
+// returning whatever end() returns the moment it runs out
+pub fn x86_pit_stopwatch<B, E, R>(window_us: u64, begin: B, end: E) -> R
+where
+    B: FnOnce(),
+    E: FnOnce() -> R,
+{
+    let count = ((X86_PIT_HZ * window_us.min(X86_PIT_MAX_WINDOW_US)) / 1_000_000).max(1);
+
+    // gate low & speaker off while we load the count
+    let gate = x86_inport8(PIT_GATE_PORT) & !(PIT_GATE_CHANNEL2 | PIT_GATE_SPEAKER);
+    x86_outport8(PIT_GATE_PORT, gate);
+
+    x86_outport8(PIT_COMMAND, PIT_CMD_CHANNEL2_ONESHOT);
+    x86_outport8(PIT_CHANNEL2_DATA, (count & 0xFF) as u8);
+    x86_outport8(PIT_CHANNEL2_DATA, ((count >> 8) & 0xFF) as u8);
+
+    // raising the gate starts the count; out2 goes high at zero
+    x86_outport8(PIT_GATE_PORT, gate | PIT_GATE_CHANNEL2);
+    begin();
+
+    while x86_inport8(PIT_GATE_PORT) & PIT_GATE_OUT2 == 0 {
+        core::hint::spin_loop();
+    }
+
+    let result = end();
+
+    x86_outport8(PIT_GATE_PORT, gate);
+
+    result
+}
@@ -0,0 +1,96 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the x86 backend for crate::power. Powering off is all acpi (see
+// crate::acpi::power); what's here are the older ways of resetting a pc,
+// for when the fadt's reset register isn't there or doesn't take, and the
+// isa-debug-exit port qemu offers for getting out of a test run.
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::acpi::acpi_fadt;
+
+// the 8042 keyboard controller; status bit 1 means its input buffer is full,
+// & command 0xFE pulses the cpu reset line
+const I8042_STATUS_PORT: u16 = 0x64;
+const I8042_COMMAND_PORT: u16 = 0x64;
+const I8042_STATUS_INPUT_FULL: u8 = 1 << 1;
+const I8042_CMD_PULSE_RESET: u8 = 0xFE;
+const I8042_SPINS: usize = 100_000;
+
+// where qemu's isa-debug-exit device usually sits (-device isa-debug-exit,iobase=0xf4,iosize=0x04);
+// qemu exits with (code << 1) | 1
+pub const X86_QEMU_EXIT_PORT: u16 = 0xF4;
+const X86_QEMU_EXIT_SUCCESS: u32 = 1;
+
+// cpuid's hypervisor leaf; tcg is qemu without kvm
+const X86_CPUID_HYPERVISOR_LEAF: u32 = 0x4000_0000;
+const X86_CPUID_FEATURE_HYPERVISOR_BIT: usize = 31;
+const X86_HYPERVISOR_TCG: [u8; 12] = *b"TCGTCGTCGTCG";
+
+// nothing extra to power off with on x86
+pub fn arch_power_off() -> bool {
+    false
+}
+
+// pulses the reset line through the keyboard controller, if the fadt says
+// there is one (firmware that predates the flag is assumed to have it)
+pub fn arch_reset() -> bool {
+    if !acpi_fadt().map_or(true, |fadt| fadt.has_8042()) {
+        return false;
+    }
+
+    for _ in 0..I8042_SPINS {
+        if x86_inport8(I8042_STATUS_PORT) & I8042_STATUS_INPUT_FULL == 0 {
+            break;
+        }
+
+        core::hint::spin_loop();
+    }
+
+    x86_outport8(I8042_COMMAND_PORT, I8042_CMD_PULSE_RESET);
+
+    true
+}
+
+// the reset that can't fail: with an empty idt, any exception is a triple fault
+pub fn arch_reset_last_resort() -> ! {
+    x86_disable_interrupts();
+
+    let empty_idt = DescriptorTablePtr { limit: 0, base: 0 };
+    x86_write_idtr(&empty_idt);
+
+    unsafe {
+        core::arch::asm!("int3", options(nomem, nostack));
+    }
+
+    loop {
+        x86_halt();
+    }
+}
+
+// tcg says so outright; under kvm it's the cpu the firmware tables give away
+pub fn arch_hypervisor_is_qemu() -> bool {
+    if !u32bit::is_bit_set(x86_cpuid(1).ecx, X86_CPUID_FEATURE_HYPERVISOR_BIT) {
+        return false;
+    }
+
+    let regs = x86_cpuid(X86_CPUID_HYPERVISOR_LEAF);
+    let mut vendor = [0u8; 12];
+
+    vendor[0..4].copy_from_slice(&regs.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&regs.ecx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&regs.edx.to_le_bytes());
+
+    vendor == X86_HYPERVISOR_TCG
+}
+
+// leaves via isa-debug-exit; if the device isn't there, this halts forever
+pub fn arch_qemu_exit(code: u32) -> ! {
+    use qemu_exit::QEMUExit;
+
+    qemu_exit::X86::new(X86_QEMU_EXIT_PORT, X86_QEMU_EXIT_SUCCESS).exit(code)
+}
+
+pub fn arch_power_disable_interrupts() {
+    x86_disable_interrupts();
+}
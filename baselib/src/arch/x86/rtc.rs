@@ -0,0 +1,209 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the pc-at cmos real time clock, for when the firmware's clock
+// isn't there to ask (non-uefi boots). The rtc keeps whatever format the
+// firmware left it in (bcd or binary, 12 or 24 hour), so everything gets
+// normalized on the way in & out. We take it to be running on utc.
+
+use spin::Mutex;
+
+use crate::arch::x86::asm::*;
+use crate::clock::DateTime;
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+// time & date registers
+const RTC_REG_SECONDS: u8 = 0x00;
+const RTC_REG_MINUTES: u8 = 0x02;
+const RTC_REG_HOURS: u8 = 0x04;
+const RTC_REG_DAY: u8 = 0x07;
+const RTC_REG_MONTH: u8 = 0x08;
+const RTC_REG_YEAR: u8 = 0x09;
+const RTC_REG_STATUS_A: u8 = 0x0A;
+const RTC_REG_STATUS_B: u8 = 0x0B;
+
+// where the century usually lives; the fadt names the real one
+pub const X86_RTC_DEFAULT_CENTURY_REG: u8 = 0x32;
+
+// status a: an update is in progress, don't trust the registers
+const RTC_STATUS_A_UPDATING: u8 = 1 << 7;
+// status b: freeze updates / 24 hour mode / binary (not bcd)
+const RTC_STATUS_B_SET: u8 = 1 << 7;
+const RTC_STATUS_B_24HOUR: u8 = 1 << 1;
+const RTC_STATUS_B_BINARY: u8 = 1 << 2;
+
+// the pm flag on the hours register in 12 hour mode
+const RTC_HOURS_PM: u8 = 1 << 7;
+
+// how many times we'll reread before giving up on a stable value
+const RTC_READ_ATTEMPTS: usize = 8;
+
+// the index register is shared, so whole accesses are serialized; the
+// century register index is 0 when there isn't one
+struct Rtc {
+    century_reg: u8,
+}
+
+static RTC: Mutex<Rtc> = Mutex::new(Rtc { century_reg: X86_RTC_DEFAULT_CENTURY_REG });
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct RtcRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+fn cmos_read(reg: u8) -> u8 {
+    x86_outport8(CMOS_INDEX, reg);
+    x86_inport8(CMOS_DATA)
+}
+
+fn cmos_write(reg: u8, value: u8) {
+    x86_outport8(CMOS_INDEX, reg);
+    x86_outport8(CMOS_DATA, value);
+}
+
+fn bcd_to_binary(value: u8) -> u8 {
+    (value & 0x0F) + (value >> 4) * 10
+}
+
+fn binary_to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+impl Rtc {
+    // one pass over the registers, once no update is in progress
+    fn read_registers(&self) -> RtcRegisters {
+        while cmos_read(RTC_REG_STATUS_A) & RTC_STATUS_A_UPDATING != 0 {
+            core::hint::spin_loop();
+        }
+
+        RtcRegisters {
+            seconds: cmos_read(RTC_REG_SECONDS),
+            minutes: cmos_read(RTC_REG_MINUTES),
+            hours: cmos_read(RTC_REG_HOURS),
+            day: cmos_read(RTC_REG_DAY),
+            month: cmos_read(RTC_REG_MONTH),
+            year: cmos_read(RTC_REG_YEAR),
+            century: if self.century_reg != 0 { cmos_read(self.century_reg) } else { 0 },
+        }
+    }
+
+    fn read(&self) -> Option<DateTime> {
+        // an update can still sneak in between the status check & the reads,
+        // so keep going until two passes agree
+        let mut regs = self.read_registers();
+        let mut stable = false;
+
+        for _ in 0..RTC_READ_ATTEMPTS {
+            let again = self.read_registers();
+
+            if again == regs {
+                stable = true;
+                break;
+            }
+
+            regs = again;
+        }
+
+        if !stable {
+            return None;
+        }
+
+        let status_b = cmos_read(RTC_REG_STATUS_B);
+        let binary = status_b & RTC_STATUS_B_BINARY != 0;
+        let convert = |v: u8| if binary { v } else { bcd_to_binary(v) };
+
+        // in 12 hour mode the pm flag rides on top of the (possibly bcd) hour,
+        // and 12am / 12pm are 0 / 12
+        let hours = if status_b & RTC_STATUS_B_24HOUR != 0 {
+            convert(regs.hours)
+        } else {
+            let pm = regs.hours & RTC_HOURS_PM != 0;
+            (convert(regs.hours & !RTC_HOURS_PM) % 12) + if pm { 12 } else { 0 }
+        };
+
+        let year = convert(regs.year) as u16;
+        let century = convert(regs.century) as u16;
+
+        // no (sane) century register; assume 1970 - 2069
+        let year = match century {
+            19..=99 => century * 100 + year,
+            _ if year < 70 => 2000 + year,
+            _ => 1900 + year,
+        };
+
+        let dt = DateTime {
+            year,
+            month: convert(regs.month),
+            day: convert(regs.day),
+            hour: hours,
+            minute: convert(regs.minutes),
+            second: convert(regs.seconds),
+            nanosecond: 0,
+        };
+
+        if dt.is_valid() { Some(dt) } else { None }
+    }
+
+    fn write(&self, dt: &DateTime) -> bool {
+        if !dt.is_valid() {
+            return false;
+        }
+
+        let status_b = cmos_read(RTC_REG_STATUS_B);
+        let binary = status_b & RTC_STATUS_B_BINARY != 0;
+        let convert = |v: u8| if binary { v } else { binary_to_bcd(v) };
+
+        let hours = if status_b & RTC_STATUS_B_24HOUR != 0 {
+            convert(dt.hour)
+        } else {
+            let hour12 = match dt.hour % 12 {
+                0 => 12,
+                h => h,
+            };
+
+            convert(hour12) | if dt.hour >= 12 { RTC_HOURS_PM } else { 0 }
+        };
+
+        // hold off updates while the registers are inconsistent
+        cmos_write(RTC_REG_STATUS_B, status_b | RTC_STATUS_B_SET);
+
+        cmos_write(RTC_REG_SECONDS, convert(dt.second));
+        cmos_write(RTC_REG_MINUTES, convert(dt.minute));
+        cmos_write(RTC_REG_HOURS, hours);
+        cmos_write(RTC_REG_DAY, convert(dt.day));
+        cmos_write(RTC_REG_MONTH, convert(dt.month));
+        cmos_write(RTC_REG_YEAR, convert((dt.year % 100) as u8));
+
+        if self.century_reg != 0 {
+            cmos_write(self.century_reg, convert((dt.year / 100) as u8));
+        }
+
+        cmos_write(RTC_REG_STATUS_B, status_b & !RTC_STATUS_B_SET);
+
+        true
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// points the driver at the century register the fadt names; 0 = there isn't one
+pub fn x86_rtc_set_century_register(reg: u8) {
+    RTC.lock().century_reg = reg;
+}
+
+// the rtc's current date & time; None if it won't hold still or reads as garbage
+pub fn x86_rtc_read() -> Option<DateTime> {
+    RTC.lock().read()
+}
+
+// sets the rtc, keeping the format it's already in
+pub fn x86_rtc_write(dt: &DateTime) -> bool {
+    RTC.lock().write(dt)
+}
@@ -0,0 +1,96 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: the x86 backend for crate::timer. The tick is the local apic timer,
+// the counter is the tsc. Neither frequency is architecturally known, so both
+// read as 0 until tsc.rs calibrates them & tells us. The hpet, if there is one,
+// stands in as the clock's counter when the tsc isn't invariant.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::arch::x86::apic::*;
+use crate::arch::x86::hpet::*;
+use crate::arch::x86::tsc::*;
+
+// the local apic timer's input clock (after LAPIC_TIMER_DIVIDE_16), the tsc
+// frequency & the tick rate we're running at; 0 = unknown
+static LAPIC_TIMER_HZ: AtomicU64 = AtomicU64::new(0);
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+static TICK_HZ: AtomicU64 = AtomicU64::new(0);
+
+// records the calibrated local apic timer frequency (at divide 16)
+pub fn x86_set_lapic_timer_hz(hz: u64) {
+    LAPIC_TIMER_HZ.store(hz, Ordering::SeqCst);
+}
+
+// records the calibrated tsc frequency
+pub fn x86_set_tsc_hz(hz: u64) {
+    TSC_HZ.store(hz, Ordering::SeqCst);
+}
+
+//-----------------------------------------------------------------------------------
+
+pub fn arch_timer_tick_hz() -> u64 {
+    TICK_HZ.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+pub fn arch_timer_counter() -> u64 {
+    x86_read_tsc()
+}
+
+pub fn arch_timer_counter_hz() -> u64 {
+    TSC_HZ.load(Ordering::Relaxed)
+}
+
+// only an invariant tsc keeps ticking at TSC_HZ through power state changes
+pub fn arch_timer_counter_invariant() -> bool {
+    x86_tsc_invariant()
+}
+
+// the hpet main counter, when the tsc won't do. a 32 bit counter wraps too
+// quickly to be left alone, so we only offer a 64 bit one.
+pub fn arch_timer_fallback_counter() -> u64 {
+    match hpet() {
+        Some(hpet) => hpet.counter(),
+        None => ZERO_U64,
+    }
+}
+
+pub fn arch_timer_fallback_counter_hz() -> u64 {
+    match hpet() {
+        Some(hpet) if hpet.counter_64bit() => hpet.hz(),
+        _ => ZERO_U64,
+    }
+}
+
+// without a calibrated apic timer we can't hit hz, so the timer ticks at
+// X86_APIC_TIMER_DEFAULT_COUNT instead & we say so
+pub fn arch_timer_start_periodic(hz: u64) -> bool {
+    let lapic = match local_apic() {
+        Some(lapic) => lapic,
+        None => return false,
+    };
+
+    let lapic_hz = LAPIC_TIMER_HZ.load(Ordering::SeqCst);
+
+    if lapic_hz == ZERO_U64 || lapic_hz / hz == ZERO_U64 || lapic_hz / hz > u32::MAX as u64 {
+        lapic.start_timer_periodic(X86_APIC_TIMER_VECTOR, X86_APIC_TIMER_DEFAULT_COUNT, LAPIC_TIMER_DIVIDE_16);
+        TICK_HZ.store(ZERO_U64, Ordering::SeqCst);
+        return false;
+    }
+
+    lapic.start_timer_periodic(X86_APIC_TIMER_VECTOR, (lapic_hz / hz) as u32, LAPIC_TIMER_DIVIDE_16);
+    TICK_HZ.store(hz, Ordering::SeqCst);
+
+    true
+}
+
+pub fn arch_timer_stop() {
+    if let Some(lapic) = local_apic() {
+        lapic.stop_timer();
+    }
+
+    TICK_HZ.store(ZERO_U64, Ordering::SeqCst);
+}
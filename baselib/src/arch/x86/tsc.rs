@@ -0,0 +1,270 @@
+#![cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+
+// Purpose: works out how fast the tsc & the local apic timer run. Newer parts
+// simply tell us (cpuid 15H / 16H); everything else gets timed against the
+// hpet if we have one, the pit if we don't. Both results are handed to the
+// timer backend via x86_set_tsc_hz() / x86_set_lapic_timer_hz().
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::common::base::*;
+use crate::arch::x86::asm::*;
+use crate::arch::x86::cpu::X86_CPUID_EXT_MAX;
+use crate::arch::x86::apic::*;
+use crate::arch::x86::hpet::*;
+use crate::arch::x86::pit::*;
+use crate::arch::x86::timer::*;
+
+// tsc / core crystal clock ratio & the crystal frequency
+const X86_CPUID_TSC_LEAF: u32 = 0x15;
+// processor base / max / bus frequencies in MHz
+const X86_CPUID_FREQ_LEAF: u32 = 0x16;
+// advanced power management; edx bit 8 is the invariant tsc
+const X86_CPUID_APM_LEAF: u32 = 0x8000_0007;
+const X86_CPUID_APM_INVARIANT_TSC_BIT: usize = 8;
+
+// how long each measurement runs, and how many we take (keeping the fastest,
+// i.e. the one least disturbed by smis / the hypervisor)
+const TSC_CALIBRATION_WINDOW_US: u64 = 10_000;
+const TSC_CALIBRATION_ROUNDS: usize = 3;
+
+// the local apic timer is run at divide 16, to match timer.rs
+const TSC_LAPIC_DIVIDE: u64 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TscSource {
+    // not calibrated
+    Unknown,
+    // cpuid 15H (& maybe 16H) spelled it out
+    Cpuid,
+    // timed against the hpet main counter
+    Hpet,
+    // timed against pit channel 2
+    Pit,
+    // nothing to time against; cpuid 16H's base frequency is a best guess
+    CpuidBaseFrequency,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TscCalibration {
+    pub tsc_hz: u64,
+    pub lapic_timer_hz: u64,
+    pub source: TscSource,
+    pub invariant: bool,
+}
+
+static TSC_INVARIANT: AtomicBool = AtomicBool::new(false);
+
+// true if the tsc runs at a constant rate across p-, c- & t-states, and so
+// is usable as a clock
+pub fn x86_tsc_invariant() -> bool {
+    TSC_INVARIANT.load(Ordering::Relaxed)
+}
+
+fn x86_tsc_detect_invariant() -> bool {
+    if x86_cpuid(X86_CPUID_EXT_MAX).eax < X86_CPUID_APM_LEAF {
+        return false;
+    }
+
+    u32bit::is_bit_set(x86_cpuid(X86_CPUID_APM_LEAF).edx, X86_CPUID_APM_INVARIANT_TSC_BIT)
+}
+
+// cpuid 15H: tsc = crystal * ebx / eax. the crystal frequency (ecx) is
+// allowed to be 0, in which case 16H's base frequency stands in for the tsc.
+// returns (tsc hz, crystal hz); crystal is 0 if not enumerated.
+fn x86_tsc_hz_from_cpuid() -> Option<(u64, u64)> {
+    let max_level = x86_cpuid(0).eax;
+
+    if max_level < X86_CPUID_TSC_LEAF {
+        return None;
+    }
+
+    let regs = x86_cpuid(X86_CPUID_TSC_LEAF);
+
+    if regs.eax == ZERO_U32 || regs.ebx == ZERO_U32 {
+        return None;
+    }
+
+    let crystal_hz = regs.ecx as u64;
+
+    if crystal_hz != ZERO_U64 {
+        return Some((crystal_hz * regs.ebx as u64 / regs.eax as u64, crystal_hz));
+    }
+
+    x86_tsc_base_hz_from_cpuid().map(|hz| (hz, ZERO_U64))
+}
+
+// cpuid 16H eax: the base frequency in MHz
+fn x86_tsc_base_hz_from_cpuid() -> Option<u64> {
+    if x86_cpuid(0).eax < X86_CPUID_FREQ_LEAF {
+        return None;
+    }
+
+    match (x86_cpuid(X86_CPUID_FREQ_LEAF).eax & 0xFFFF) as u64 {
+        ZERO_U64 => None,
+        mhz => Some(mhz * 1_000_000),
+    }
+}
+
+// one timed window: (tsc ticks, lapic timer ticks, window length in ns).
+// the lapic timer runs one-shot & masked from u32::MAX, so it can't fire.
+fn x86_tsc_measure(hpet: Option<&Hpet>) -> Option<(u64, u64, u64)> {
+    let lapic = local_apic();
+
+    let lapic_start = || {
+        if let Some(lapic) = lapic {
+            lapic.write(LAPIC_REG_TIMER_DIVIDE, LAPIC_TIMER_DIVIDE_16);
+            lapic.write(LAPIC_REG_LVT_TIMER, LAPIC_LVT_MASKED);
+            lapic.write(LAPIC_REG_TIMER_INITIAL, u32::MAX);
+        }
+    };
+
+    let lapic_elapsed = || match lapic {
+        Some(lapic) => (u32::MAX - lapic.timer_current_count()) as u64,
+        None => ZERO_U64,
+    };
+
+    let result = match hpet {
+        Some(hpet) => {
+            let window = (TSC_CALIBRATION_WINDOW_US * 1_000_000_000) / hpet.period_fs();
+
+            lapic_start();
+            let hpet_start = hpet.counter();
+            let tsc_start = x86_read_tsc();
+
+            let mut hpet_now = hpet_start;
+            while hpet_now.wrapping_sub(hpet_start) < window {
+                core::hint::spin_loop();
+                hpet_now = hpet.counter();
+            }
+
+            let tsc_end = x86_read_tsc();
+            let lapic_ticks = lapic_elapsed();
+            let ns = (hpet_now.wrapping_sub(hpet_start) as u128 * hpet.period_fs() as u128 / 1_000_000) as u64;
+
+            (tsc_end.wrapping_sub(tsc_start), lapic_ticks, ns)
+        },
+        None => {
+            let mut tsc_start = ZERO_U64;
+
+            let (tsc_end, lapic_ticks) = x86_pit_stopwatch(
+                TSC_CALIBRATION_WINDOW_US,
+                || {
+                    lapic_start();
+                    tsc_start = x86_read_tsc();
+                },
+                || (x86_read_tsc(), lapic_elapsed()),
+            );
+
+            (tsc_end.wrapping_sub(tsc_start), lapic_ticks, TSC_CALIBRATION_WINDOW_US * 1_000)
+        },
+    };
+
+    if let Some(lapic) = lapic {
+        lapic.stop_timer();
+    }
+
+    match result {
+        (ZERO_U64, _, _) | (_, _, ZERO_U64) => None,
+        result => Some(result),
+    }
+}
+
+// best of TSC_CALIBRATION_ROUNDS windows, as (tsc hz, lapic timer hz)
+fn x86_tsc_measure_hz(hpet: Option<&Hpet>) -> Option<(u64, u64)> {
+    let mut best: Option<(u64, u64, u64)> = None;
+
+    for _ in 0..TSC_CALIBRATION_ROUNDS {
+        let sample = match x86_tsc_measure(hpet) {
+            Some(sample) => sample,
+            None => continue,
+        };
+
+        // an interruption only ever makes a window look longer in tsc ticks
+        // than it was on the reference clock, so the smallest ratio wins
+        best = match best {
+            Some(b) if (b.0 as u128 * sample.2 as u128) <= (sample.0 as u128 * b.2 as u128) => Some(b),
+            _ => Some(sample),
+        };
+    }
+
+    let (tsc_ticks, lapic_ticks, ns) = best?;
+
+    Some((
+        (tsc_ticks as u128 * 1_000_000_000 / ns as u128) as u64,
+        (lapic_ticks as u128 * 1_000_000_000 / ns as u128) as u64,
+    ))
+}
+
+//-----------------------------------------------------------------------------------
+
+// calibrates the tsc & the local apic timer & tells the timer backend. the local
+// apic has to be up for the latter; if the hpet is, it's used instead of the pit.
+// interrupts must be off, since we're busy waiting on the reference clock.
+pub fn x86_tsc_calibrate() -> Option<TscCalibration> {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return None;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let invariant = x86_tsc_detect_invariant();
+    TSC_INVARIANT.store(invariant, Ordering::SeqCst);
+
+    let hpet = hpet();
+    let measured = x86_tsc_measure_hz(hpet);
+    let measured_source = if hpet.is_some() { TscSource::Hpet } else { TscSource::Pit };
+
+    let mut calibration = TscCalibration {
+        tsc_hz: ZERO_U64,
+        lapic_timer_hz: ZERO_U64,
+        source: TscSource::Unknown,
+        invariant,
+    };
+
+    // what cpuid says beats what we measure; the lapic timer always has to
+    // be measured, unless it's known to run off the crystal
+    match (x86_tsc_hz_from_cpuid(), measured) {
+        (Some((tsc_hz, crystal_hz)), measured) => {
+            calibration.tsc_hz = tsc_hz;
+            calibration.source = TscSource::Cpuid;
+            calibration.lapic_timer_hz = match measured {
+                Some((_, lapic_hz)) if lapic_hz != ZERO_U64 => lapic_hz,
+                _ => crystal_hz / TSC_LAPIC_DIVIDE,
+            };
+        },
+        (None, Some((tsc_hz, lapic_hz))) => {
+            calibration.tsc_hz = tsc_hz;
+            calibration.lapic_timer_hz = lapic_hz;
+            calibration.source = measured_source;
+        },
+        (None, None) => {
+            if let Some(base_hz) = x86_tsc_base_hz_from_cpuid() {
+                calibration.tsc_hz = base_hz;
+                calibration.source = TscSource::CpuidBaseFrequency;
+            }
+        },
+    }
+
+    x86_set_tsc_hz(calibration.tsc_hz);
+    x86_set_lapic_timer_hz(calibration.lapic_timer_hz);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!(
+        "x86_tsc_calibrate() -> tsc @ {} hz ({:?}, {}invariant), lapic timer @ {} hz",
+        calibration.tsc_hz,
+        calibration.source,
+        if invariant { "" } else { "not " },
+        calibration.lapic_timer_hz
+    );
+
+    match calibration.tsc_hz {
+        ZERO_U64 => None,
+        _ => Some(calibration),
+    }
+}
@@ -0,0 +1,311 @@
+// Purpose: the handoff from the boot method specific pre-init code to
+// kernel_init(). Each xxx_pre_init() translates whatever its loader / firmware
+// gave it into a BootInfo: a memory map in the kernel's own terms, the command
+// line, the framebuffer, where the acpi rsdp / device tree are, and any entropy
+// it could get hold of. kernel_init() consumes only this.
+
+// Internal
+use crate::common::base::*;
+
+// how much entropy a boot method can hand over
+pub const BOOT_INFO_ENTROPY_BYTES: usize = 64;
+
+// the scratch pages ahead of the staged memory map: allocated frames & the rng
+pub const BOOT_INFO_SCRATCH_FIXED_PAGES: usize = 2;
+
+// the memory regions one page of the staged map holds
+pub const BOOT_INFO_REGIONS_PER_PAGE: usize = MEMORY_DEFAULT_PAGE_USIZE / core::mem::size_of::<MemRegion>();
+
+// what a region of physical memory is, as far as the kernel cares
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemRegionType {
+    // not described / not ours, ever
+    Reserved = 0,
+    // free for the taking
+    Usable,
+    // acpi tables; ours once we're done with them
+    AcpiReclaimable,
+    // acpi non-volatile storage; the firmware's
+    AcpiNvs,
+    // bad ram
+    Unusable,
+    // device memory; must be mapped uncached
+    Mmio,
+    // firmware runtime code & data (uefi runtime services); must stay mapped
+    FirmwareCode,
+    FirmwareData,
+    // the firmware's boot time code & data (uefi boot services)
+    BootServices,
+    // what the loader (or the pre-init code, as the loader) allocated,
+    // including the loaded kernel image
+    Loader,
+    // the kernel image, when the boot method can tell it apart from the above
+    KernelImage,
+}
+
+// a run of physical pages of one type
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MemRegion {
+    pub base: PhysAddr,
+    pub page_count: usize,
+    pub ty: MemRegionType,
+}
+
+impl MemRegion {
+    pub fn size(&self) -> usize {
+        pages::pages_to_bytes(self.page_count, MEMORY_DEFAULT_PAGE_SIZE_ENUM)
+    }
+
+    pub fn end(&self) -> usize {
+        self.base.as_usize() + self.size()
+    }
+}
+
+// how the framebuffer's pixels are laid out
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelLayout {
+    // 32 bit pixels, red in the low byte
+    Rgb,
+    // 32 bit pixels, blue in the low byte
+    Bgr,
+    // anything else (bitmasks, palettes, text mode)
+    Other,
+}
+
+// a linear framebuffer the loader / firmware set up for us
+#[derive(Copy, Clone, Debug)]
+pub struct BootFramebuffer {
+    pub address: PhysAddr,
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+    // bytes per scan line
+    pub pitch: u32,
+    pub bpp: u8,
+    pub layout: PixelLayout,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BootInfo {
+    // the scratch pages: allocated frames, the rng, the memory map
+    pub scratch_base: PhysAddr,
+    region_pages: usize,
+    region_count: usize,
+    pub kernel_image_base: PhysAddr,
+    pub kernel_image_size: usize,
+    pub cmdline: Option<&'static str>,
+    pub framebuffer: Option<BootFramebuffer>,
+    pub rsdp: Option<PhysAddr>,
+    pub dtb: Option<PhysAddr>,
+    pub entropy: Option<[u8; BOOT_INFO_ENTROPY_BYTES]>,
+}
+
+impl BootInfo {
+    // the pages needed to stage a memory map of up to region_count regions, plus
+    // a spare slot for the one region limit_usable() may split off at mem=
+    pub fn region_pages_for(region_count: usize) -> usize {
+        usize::max(1, pages::bytes_to_pages((region_count + 1) * core::mem::size_of::<MemRegion>(), MEMORY_DEFAULT_PAGE_SIZE_ENUM))
+    }
+
+    // the scratch pages needed, all told, for a memory map of up to region_count regions
+    pub fn scratch_pages_for(region_count: usize) -> usize {
+        BOOT_INFO_SCRATCH_FIXED_PAGES + Self::region_pages_for(region_count)
+    }
+
+    // starts a BootInfo with its scratch pages at scratch_base (room for up to
+    // region_count memory regions): zeroes them and records them in the
+    // allocated frame array (the first scratch page); the staged memory map is
+    // one entry there, however many pages it spans
+    pub fn new(scratch_base: PhysAddr, region_count: usize) -> BootInfo {
+        let region_pages = Self::region_pages_for(region_count);
+
+        raw::memset_aligned(
+            scratch_base,
+            pages::pages_to_bytes(BOOT_INFO_SCRATCH_FIXED_PAGES + region_pages, MEMORY_DEFAULT_PAGE_SIZE_ENUM),
+            BytePattern::ZeroZero.as_usize_pattern());
+
+        let allocated_frame_array: &mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES] = unsafe { core::mem::transmute::<PhysAddr, &mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES]>(scratch_base) };
+
+        for (i, frame) in allocated_frame_array.iter_mut().take(BOOT_INFO_SCRATCH_FIXED_PAGES + 1).enumerate() {
+            frame.base_addr = PhysAddr(scratch_base.as_usize() + pages::pages_to_bytes(i, MEMORY_DEFAULT_PAGE_SIZE_ENUM));
+            frame.size = if i < BOOT_INFO_SCRATCH_FIXED_PAGES {
+                MEMORY_DEFAULT_PAGE_USIZE
+            } else {
+                pages::pages_to_bytes(region_pages, MEMORY_DEFAULT_PAGE_SIZE_ENUM)
+            };
+        }
+
+        BootInfo {
+            scratch_base,
+            region_pages,
+            region_count: ZERO_USIZE,
+            kernel_image_base: PhysAddr(ZERO_USIZE),
+            kernel_image_size: ZERO_USIZE,
+            cmdline: None,
+            framebuffer: None,
+            rsdp: None,
+            dtb: None,
+            entropy: None,
+        }
+    }
+
+    // the second scratch page
+    pub fn rng_base(&self) -> PhysAddr {
+        PhysAddr(self.scratch_base.as_usize() + MEMORY_DEFAULT_PAGE_USIZE)
+    }
+
+    // the third scratch page on, where the memory map is staged
+    pub fn regions_base(&self) -> PhysAddr {
+        PhysAddr(self.scratch_base.as_usize() + pages::pages_to_bytes(BOOT_INFO_SCRATCH_FIXED_PAGES, MEMORY_DEFAULT_PAGE_SIZE_ENUM))
+    }
+
+    // the most regions the staged map holds
+    pub fn region_capacity(&self) -> usize {
+        self.region_pages * BOOT_INFO_REGIONS_PER_PAGE
+    }
+
+    fn region_slots(&self) -> &'static mut [MemRegion] {
+        unsafe { core::slice::from_raw_parts_mut(self.regions_base().as_usize() as *mut MemRegion, self.region_capacity()) }
+    }
+
+    pub fn regions(&self) -> &'static [MemRegion] {
+        &self.region_slots()[..self.region_count]
+    }
+
+    // appends [base, base + page_count pages) to the memory map, folding it into
+    // the previous region if it picks up where that one (of the same type) left
+    // off; empty regions are dropped. a map that doesn't fit is fatal: the
+    // pre-init code sizes the staging area from the map it was given, so this
+    // means the map changed (or lied) in between.
+    pub fn push_region(&mut self, base: PhysAddr, page_count: usize, ty: MemRegionType) {
+        if page_count == ZERO_USIZE {
+            return;
+        }
+
+        let slots = self.region_slots();
+
+        if let Some(last) = self.region_count.checked_sub(1).map(|i| &mut slots[i]) {
+            if last.ty == ty && last.end() == base.as_usize() {
+                last.page_count += page_count;
+                return;
+            }
+        }
+
+        if self.region_count >= slots.len() {
+            panic!(
+                "BootInfo::push_region() -> memory map overflows its staging area: {} regions in {} page(s); region @ 0x{:08x} ({} pages, {:?}) doesn't fit",
+                slots.len(),
+                self.region_pages,
+                base,
+                page_count,
+                ty);
+        }
+
+        slots[self.region_count] = MemRegion { base, page_count, ty };
+        self.region_count += 1;
+    }
+
+    // kernel_init() expects the map in address order; not every loader obliges.
+    // neighbours of the same type that only meet once sorted are merged.
+    pub fn sort_regions(&mut self) {
+        let slots = &mut self.region_slots()[..self.region_count];
+        slots.sort_unstable_by_key(|r| r.base.as_usize());
+
+        let mut merged = ZERO_USIZE;
+
+        for i in 0..slots.len() {
+            if merged > 0 && slots[merged - 1].ty == slots[i].ty && slots[merged - 1].end() == slots[i].base.as_usize() {
+                slots[merged - 1].page_count += slots[i].page_count;
+            } else {
+                slots[merged] = slots[i];
+                merged += 1;
+            }
+        }
+
+        self.region_count = merged;
+    }
+
+    // turns the usable memory at & above limit into reserved memory (mem= on
+    // the command line); a region straddling it is split, and its upper half
+    // goes in the spare slot region_pages_for() left for it (regions never
+    // overlap, so at most one straddles)
+    pub fn limit_usable(&mut self, limit: usize) {
+        let limit = align_down(limit, MEMORY_DEFAULT_PAGE_USIZE);
+
+        for i in 0..self.region_count {
+            let r = self.region_slots()[i];
+
+            if r.ty != MemRegionType::Usable || r.end() <= limit {
+                continue;
+            }
+
+            if r.base.as_usize() >= limit {
+                self.region_slots()[i].ty = MemRegionType::Reserved;
+                continue;
+            }
+
+            let kept = pages::bytes_to_pages(limit - r.base.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+            self.region_slots()[i].page_count = kept;
+            self.push_region(PhysAddr(limit), r.page_count - kept, MemRegionType::Reserved);
+        }
+
+        self.sort_regions();
+    }
+
+    // the number of free pages
+    pub fn usable_page_count(&self) -> usize {
+        self.regions().iter()
+            .filter(|r| r.ty == MemRegionType::Usable)
+            .map(|r| r.page_count)
+            .sum()
+    }
+
+    // the end of the last free region
+    pub fn phys_boundary(&self) -> PhysAddr {
+        PhysAddr(self.regions().iter()
+            .filter(|r| r.ty == MemRegionType::Usable)
+            .map(|r| r.end())
+            .max()
+            .unwrap_or(ZERO_USIZE))
+    }
+}
+
+// the handoff, as kernel_init() received it
+static mut BOOT_INFO: Option<BootInfo> = None;
+
+// returns the BootInfo kernel_init() was handed, once it has been
+pub fn boot_info() -> Option<&'static BootInfo> {
+    unsafe { (*core::ptr::addr_of!(BOOT_INFO)).as_ref() }
+}
+
+// keeps a copy of the handoff for later; only the first call sticks
+pub(crate) fn boot_info_store(handoff: &BootInfo) -> &'static BootInfo {
+    unsafe {
+        if (*core::ptr::addr_of!(BOOT_INFO)).is_none() {
+            BOOT_INFO = Some(*handoff);
+        }
+    }
+
+    boot_info().unwrap()
+}
+
+// applies a mem= limit to the stored copy's memory map
+pub(crate) fn boot_info_limit_usable(limit: usize) {
+    unsafe {
+        if let Some(stored) = (*core::ptr::addr_of_mut!(BOOT_INFO)).as_mut() {
+            stored.limit_usable(limit);
+        }
+    }
+}
+
+// the command line lives in the loader's memory; once that's been reclaimed,
+// it's gone
+pub(crate) fn boot_info_forget_cmdline() {
+    unsafe {
+        if let Some(stored) = (*core::ptr::addr_of_mut!(BOOT_INFO)).as_mut() {
+            stored.cmdline = None;
+        }
+    }
+}
@@ -0,0 +1,264 @@
+// Purpose: the allocator kernel_init() uses before there is a frame allocator.
+// It hands out runs of usable memory from the staged memory map, steering
+// clear of everything already recorded in the allocated frame array (the
+// first scratch page), and records what it hands out there as well. Placement
+// is either the lowest address that fits or, given an rng, a random pick among
+// every aligned address that fits. Either way, nothing fitting is reported
+// rather than searched for forever.
+
+// Internal
+use crate::common::base::*;
+use crate::bringup::bootinfo::*;
+
+// nothing below this is handed out (real mode ivt, bda, ebda & friends)
+pub const EARLY_ALLOC_FLOOR: usize = USIZE_512K;
+
+pub struct EarlyRegionAllocator {
+    regions: &'static [MemRegion],
+    allocated: &'static mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES],
+}
+
+impl EarlyRegionAllocator {
+    // an allocator over the usable regions in the boot info's map, keeping
+    // track in its allocated frame array
+    pub fn new(boot_info: &BootInfo) -> EarlyRegionAllocator {
+        EarlyRegionAllocator {
+            regions: boot_info.regions(),
+            allocated: unsafe { core::mem::transmute::<PhysAddr, &mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES]>(boot_info.scratch_base) },
+        }
+    }
+
+    // allocates size bytes (rounded up to whole pages) aligned to align (a power
+    // of two, at least a page) below limit; randomly placed among all the spots
+    // that fit if an rng is given, else at the lowest one. returns None if nothing
+    // fits or the allocated frame array is full.
+    pub fn alloc(&mut self, size: usize, align: usize, limit: usize, rng: Option<&mut Isaac64Rng>) -> Option<PhysAddr> {
+        let size = align_up(size, MEMORY_DEFAULT_PAGE_USIZE);
+        let align = usize::max(align, MEMORY_DEFAULT_PAGE_USIZE);
+
+        if size == ZERO_USIZE || !align.is_power_of_two() {
+            return None;
+        }
+
+        let slot = self.allocated.iter().position(|b| b.size == ZERO_USIZE)?;
+
+        let fit_count = self.fits(size, align, limit).map(|(_, count)| count).sum::<usize>();
+
+        if fit_count == ZERO_USIZE {
+            return None;
+        }
+
+        // rand_usize(max) is inclusive of max (& 0 means unbounded)
+        let mut pick = match rng {
+            Some(rng) if fit_count > 1 => rng.rand_usize(fit_count - 1),
+            _ => ZERO_USIZE,
+        };
+
+        let mut base = ZERO_USIZE;
+
+        for (first, count) in self.fits(size, align, limit) {
+            if pick < count {
+                base = first + pick * align;
+                break;
+            }
+
+            pick -= count;
+        }
+
+        self.allocated[slot].base_addr = PhysAddr(base);
+        self.allocated[slot].size = size;
+
+        Some(PhysAddr(base))
+    }
+
+    // everything handed out so far, the scratch pages included
+    pub fn allocated(&self) -> impl Iterator<Item = &MemBlock<PhysAddr>> + '_ {
+        self.allocated.iter().take_while(|b| b.size != ZERO_USIZE)
+    }
+
+    // the free gaps (not yet allocated) in usable memory between the floor &
+    // limit, as the first aligned address in each that fits size bytes & how
+    // many aligned addresses there fit it
+    fn fits(&self, size: usize, align: usize, limit: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.regions.iter()
+            .filter(|r| r.ty == MemRegionType::Usable)
+            .flat_map(move |r| {
+                let lo = usize::max(r.base.as_usize(), EARLY_ALLOC_FLOOR);
+                let hi = usize::min(r.end(), limit);
+                self.gaps(lo, hi)
+            })
+            .filter_map(move |(lo, hi)| {
+                let first = align_up(lo, align);
+
+                if first >= hi || hi - first < size {
+                    return None;
+                }
+
+                Some((first, (hi - size - first) / align + 1))
+            })
+    }
+
+    // the parts of [lo, hi) no allocated block overlaps, in address order
+    fn gaps(&self, lo: usize, hi: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut cursor = lo;
+
+        core::iter::from_fn(move || {
+            while cursor < hi {
+                // the lowest allocated block still in the way
+                let next = self.allocated()
+                    .filter(|b| b.base_addr.as_usize() < hi && b.base_addr.as_usize() + b.size > cursor)
+                    .min_by_key(|b| b.base_addr.as_usize());
+
+                let (gap_end, resume) = match next {
+                    Some(b) => (usize::max(b.base_addr.as_usize(), cursor), b.base_addr.as_usize() + b.size),
+                    None => (hi, hi),
+                };
+
+                let gap = (cursor, gap_end);
+                cursor = resume;
+
+                if gap.0 < gap.1 {
+                    return Some(gap);
+                }
+            }
+
+            None
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_BLOCK: MemBlock<PhysAddr> = MemBlock { base_addr: PhysAddr(ZERO_USIZE), size: ZERO_USIZE };
+
+    static mut ALLOCATED: [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES] = [NO_BLOCK; MAX_PREBOOT_PAGES];
+
+    // the rng wants a page aligned page for its buffers
+    #[repr(C, align(4096))]
+    struct RngPage([u64; MEMORY_DEFAULT_PAGE_USIZE / 8]);
+
+    static mut RNG_PAGE: RngPage = RngPage([ZERO_U64; MEMORY_DEFAULT_PAGE_USIZE / 8]);
+
+    const fn region(base: usize, size: usize, ty: MemRegionType) -> MemRegion {
+        MemRegion { base: PhysAddr(base), page_count: size / MEMORY_DEFAULT_PAGE_USIZE, ty }
+    }
+
+    // an allocator over regions with ALLOCATED emptied out, standing in for
+    // the scratch page BootInfo::new() would have set up
+    fn allocator(regions: &'static [MemRegion]) -> EarlyRegionAllocator {
+        let allocated = unsafe { &mut *core::ptr::addr_of_mut!(ALLOCATED) };
+        allocated.iter_mut().for_each(|b| *b = NO_BLOCK);
+
+        EarlyRegionAllocator { regions, allocated }
+    }
+
+    fn rng(seed: u64) -> Isaac64Rng<'static> {
+        let mut rng = Isaac64Rng::new_with_fixed_buf(PhysAddr(unsafe { core::ptr::addr_of!(RNG_PAGE) } as usize)).unwrap();
+        rng.reseed_via_u64_val(seed);
+        rng
+    }
+
+    #[test_case]
+    fn early_alloc_skips_overlapping_block() {
+        static REGIONS: [MemRegion; 1] = [region(USIZE_512K, USIZE_64K, MemRegionType::Usable)];
+        let mut early = allocator(&REGIONS);
+
+        // a block hanging off the front of the region, & one in the middle
+        early.allocated[0] = MemBlock { base_addr: PhysAddr(USIZE_512K - USIZE_4K), size: USIZE_8K };
+        early.allocated[1] = MemBlock { base_addr: PhysAddr(USIZE_512K + USIZE_16K), size: USIZE_4K };
+
+        assert_eq!(early.alloc(USIZE_4K, USIZE_4K, usize::MAX, None), Some(PhysAddr(USIZE_512K + USIZE_4K)));
+        // 16k doesn't fit in what's left before the middle block
+        assert_eq!(early.alloc(USIZE_16K, USIZE_4K, usize::MAX, None), Some(PhysAddr(USIZE_512K + USIZE_16K + USIZE_4K)));
+        assert_eq!(early.alloc(USIZE_8K, USIZE_4K, usize::MAX, None), Some(PhysAddr(USIZE_512K + USIZE_8K)));
+        assert_eq!(early.allocated().count(), 5);
+    }
+
+    #[test_case]
+    fn early_alloc_stays_above_floor() {
+        static REGIONS: [MemRegion; 2] = [
+            region(ZERO_USIZE, USIZE_256K, MemRegionType::Usable),
+            region(EARLY_ALLOC_FLOOR - USIZE_64K, USIZE_64K + USIZE_16K, MemRegionType::Usable),
+        ];
+        let mut early = allocator(&REGIONS);
+
+        // only the 16k of the second region above the floor is up for grabs
+        assert_eq!(early.alloc(USIZE_4K, USIZE_4K, usize::MAX, None), Some(PhysAddr(EARLY_ALLOC_FLOOR)));
+        assert_eq!(early.alloc(USIZE_16K, USIZE_4K, usize::MAX, None), None);
+        assert_eq!(early.alloc(USIZE_8K, USIZE_8K, usize::MAX, None), Some(PhysAddr(EARLY_ALLOC_FLOOR + USIZE_8K)));
+        assert_eq!(early.alloc(USIZE_4K, USIZE_4K, usize::MAX, None), Some(PhysAddr(EARLY_ALLOC_FLOOR + USIZE_4K)));
+        assert_eq!(early.alloc(USIZE_4K, USIZE_4K, usize::MAX, None), None);
+    }
+
+    #[test_case]
+    fn early_alloc_counts_aligned_fits() {
+        static REGIONS: [MemRegion; 2] = [
+            region(USIZE_512K, USIZE_64K, MemRegionType::Usable),
+            region(USIZE_1M, USIZE_64K, MemRegionType::Reserved),
+        ];
+        let early = allocator(&REGIONS);
+
+        assert_eq!(early.fits(USIZE_16K, USIZE_16K, usize::MAX).map(|(_, count)| count).sum::<usize>(), 4);
+        assert_eq!(early.fits(USIZE_4K, USIZE_4K, usize::MAX).map(|(_, count)| count).sum::<usize>(), 16);
+        // the limit cuts the region short
+        assert_eq!(early.fits(USIZE_16K, USIZE_16K, USIZE_512K + USIZE_32K).map(|(_, count)| count).sum::<usize>(), 2);
+
+        // a 4k block @ +16k leaves [+0, +16k) & [+20k, +64k): one 16k aligned fit
+        // in the first, two (+32k, +48k) in the second
+        early.allocated[0] = MemBlock { base_addr: PhysAddr(USIZE_512K + USIZE_16K), size: USIZE_4K };
+
+        let mut fits = early.fits(USIZE_16K, USIZE_16K, usize::MAX);
+        assert_eq!(fits.next(), Some((USIZE_512K, 1)));
+        assert_eq!(fits.next(), Some((USIZE_512K + USIZE_32K, 2)));
+        assert_eq!(fits.next(), None);
+    }
+
+    #[test_case]
+    fn early_alloc_random_pick_covers_every_fit() {
+        static REGIONS: [MemRegion; 2] = [
+            region(USIZE_512K, USIZE_8K, MemRegionType::Usable),
+            region(USIZE_1M, USIZE_4K, MemRegionType::Usable),
+        ];
+        let mut early = allocator(&REGIONS);
+        let mut rng = rng(0x6e65_6275_6c61_6521);
+
+        // three spots fit a page; over enough picks each comes up, the last one too
+        let mut seen = [false; 3];
+
+        for _ in 0..64 {
+            let base = early.alloc(USIZE_4K, USIZE_4K, usize::MAX, Some(&mut rng)).unwrap().as_usize();
+
+            match base {
+                b if b == USIZE_512K => seen[0] = true,
+                b if b == USIZE_512K + USIZE_4K => seen[1] = true,
+                b if b == USIZE_1M => seen[2] = true,
+                _ => panic!("early alloc picked 0x{:08x}, which doesn't fit", base),
+            }
+
+            // give it back for the next round
+            early.allocated[0] = NO_BLOCK;
+        }
+
+        assert_eq!(seen, [true; 3]);
+    }
+
+    #[test_case]
+    fn early_alloc_full_array() {
+        static REGIONS: [MemRegion; 1] = [region(USIZE_1M, USIZE_1M, MemRegionType::Usable)];
+        let mut early = allocator(&REGIONS);
+
+        // every slot taken by blocks well out of the way
+        for (i, b) in early.allocated.iter_mut().enumerate() {
+            *b = MemBlock { base_addr: PhysAddr(USIZE_4M + i * USIZE_4K), size: USIZE_4K };
+        }
+
+        assert_eq!(early.alloc(USIZE_4K, USIZE_4K, usize::MAX, None), None);
+
+        // one free slot is all it takes
+        early.allocated[MAX_PREBOOT_PAGES - 1] = NO_BLOCK;
+        assert_eq!(early.alloc(USIZE_4K, USIZE_4K, usize::MAX, None), Some(PhysAddr(USIZE_1M)));
+        assert_eq!(early.alloc(USIZE_4K, USIZE_4K, usize::MAX, None), None);
+    }
+}
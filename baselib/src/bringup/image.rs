@@ -0,0 +1,193 @@
+// Purpose: minimal PE/COFF parsing of the loaded kernel image.
+// During bringup we need to know which pages of our own image are code
+// and which are data so that the kernel address space can be mapped
+// W^X (code read-only & executable, data writeable & non-executable).
+
+// Internal Items
+use crate::common::base::*;
+
+// dos header
+const PE_DOS_MAGIC: u16 = 0x5A4D; // "MZ"
+const PE_DOS_LFANEW_OFFSET: usize = 0x3C;
+
+// nt headers
+const PE_NT_SIGNATURE: u32 = 0x0000_4550; // "PE\0\0"
+const PE_COFF_HEADER_OFFSET: usize = 4;
+const PE_COFF_HEADER_SIZE: usize = 20;
+const PE_COFF_NUMBER_OF_SECTIONS_OFFSET: usize = 2;
+const PE_COFF_SIZE_OF_OPT_HEADER_OFFSET: usize = 16;
+
+// the size of headers field sits at the same offset for pe32 and pe32+
+const PE_OPT_SIZE_OF_HEADERS_OFFSET: usize = 60;
+// as does size of image
+const PE_OPT_SIZE_OF_IMAGE_OFFSET: usize = 56;
+
+// section headers
+const PE_SECTION_HEADER_SIZE: usize = 40;
+const PE_SECTION_VIRTUAL_SIZE_OFFSET: usize = 8;
+const PE_SECTION_VIRTUAL_ADDRESS_OFFSET: usize = 12;
+const PE_SECTION_CHARACTERISTICS_OFFSET: usize = 36;
+
+// section characteristics
+pub const PE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub const PE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+// no-execute is only available to us on x86_64
+#[cfg(target_arch = "x86_64")]
+const IMAGE_PAGE_NX: usize = PAGING_NX;
+#[cfg(not(target_arch = "x86_64"))]
+const IMAGE_PAGE_NX: usize = ZERO_USIZE;
+
+// a loaded (i.e. already relocated & laid out by the firmware) pe image
+#[derive(Debug, Copy, Clone)]
+pub struct PeImage {
+    base: PhysAddr,
+    size: usize,
+    header_size: usize,
+    section_table: usize,
+    section_count: usize,
+}
+
+impl PeImage {
+    // validates the headers of the image at base and returns a
+    // PeImage describing it, or None if it doesn't look like a pe image
+    pub fn parse(base: PhysAddr, size: usize) -> Option<PeImage> {
+        if base.is_null() || size < PE_DOS_LFANEW_OFFSET + 4 {
+            return None;
+        }
+
+        if Self::read_u16(base, ZERO_USIZE) != PE_DOS_MAGIC {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("PeImage::parse() -> no dos header @ 0x{:08x}", base);
+            return None;
+        }
+
+        let nt_offset = Self::read_u32(base, PE_DOS_LFANEW_OFFSET) as usize;
+        if nt_offset + PE_COFF_HEADER_OFFSET + PE_COFF_HEADER_SIZE > size {
+            return None;
+        }
+
+        if Self::read_u32(base, nt_offset) != PE_NT_SIGNATURE {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("PeImage::parse() -> bad nt signature @ 0x{:08x}", base.as_usize() + nt_offset);
+            return None;
+        }
+
+        let coff_offset = nt_offset + PE_COFF_HEADER_OFFSET;
+        let section_count = Self::read_u16(base, coff_offset + PE_COFF_NUMBER_OF_SECTIONS_OFFSET) as usize;
+        let opt_header_size = Self::read_u16(base, coff_offset + PE_COFF_SIZE_OF_OPT_HEADER_OFFSET) as usize;
+
+        let opt_offset = coff_offset + PE_COFF_HEADER_SIZE;
+        if opt_header_size < PE_OPT_SIZE_OF_HEADERS_OFFSET + 4 {
+            return None;
+        }
+
+        let header_size = Self::read_u32(base, opt_offset + PE_OPT_SIZE_OF_HEADERS_OFFSET) as usize;
+        let section_table = opt_offset + opt_header_size;
+
+        // the section table has to live inside the image
+        if section_table + section_count * PE_SECTION_HEADER_SIZE > size {
+            return None;
+        }
+
+        Some(PeImage {
+            base,
+            size,
+            header_size,
+            section_table,
+            section_count,
+        })
+    }
+
+    // the image's size in memory according to its own headers, for loaders
+    // that don't tell us (i.e. anything but uefi)
+    pub fn size_of_image(base: PhysAddr) -> Option<usize> {
+        if base.is_null() || Self::read_u16(base, ZERO_USIZE) != PE_DOS_MAGIC {
+            return None;
+        }
+
+        let nt_offset = Self::read_u32(base, PE_DOS_LFANEW_OFFSET) as usize;
+        if Self::read_u32(base, nt_offset) != PE_NT_SIGNATURE {
+            return None;
+        }
+
+        let opt_offset = nt_offset + PE_COFF_HEADER_OFFSET + PE_COFF_HEADER_SIZE;
+
+        Some(Self::read_u32(base, opt_offset + PE_OPT_SIZE_OF_IMAGE_OFFSET) as usize)
+    }
+
+    pub fn base(&self) -> PhysAddr {
+        self.base
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn header_size(&self) -> usize {
+        self.header_size
+    }
+
+    pub fn section_count(&self) -> usize {
+        self.section_count
+    }
+
+    // returns (virtual address offset, virtual size, characteristics) for section idx
+    pub fn section(&self, idx: usize) -> Option<(usize, usize, u32)> {
+        if idx >= self.section_count {
+            return None;
+        }
+
+        let hdr = self.section_table + idx * PE_SECTION_HEADER_SIZE;
+
+        Some((
+            Self::read_u32(self.base, hdr + PE_SECTION_VIRTUAL_ADDRESS_OFFSET) as usize,
+            Self::read_u32(self.base, hdr + PE_SECTION_VIRTUAL_SIZE_OFFSET) as usize,
+            Self::read_u32(self.base, hdr + PE_SECTION_CHARACTERISTICS_OFFSET),
+        ))
+    }
+
+    // the paging flags for the image page at the given offset from the image base;
+    // everything starts out read-only & non-executable (headers, gaps between sections)
+    // and each section overlapping the page adds its own permissions
+    pub fn page_flags(&self, page_offset: usize) -> usize {
+        let page_start = align_down(page_offset, MEMORY_DEFAULT_PAGE_USIZE);
+        let page_end = page_start + MEMORY_DEFAULT_PAGE_USIZE;
+
+        let mut flags = PAGING_PRESENT | IMAGE_PAGE_NX;
+
+        for i in 0..self.section_count {
+            let (sect_start, sect_size, characteristics) = self.section(i).unwrap();
+            let sect_end = sect_start + align_up(sect_size, MEMORY_DEFAULT_PAGE_USIZE);
+
+            if sect_size == ZERO_USIZE || sect_end <= page_start || sect_start >= page_end {
+                continue;
+            }
+
+            if characteristics & PE_SCN_MEM_WRITE != 0 {
+                flags |= PAGING_WRITEABLE;
+            }
+
+            if characteristics & PE_SCN_MEM_EXECUTE != 0 {
+                flags &= !IMAGE_PAGE_NX;
+            }
+        }
+
+        // sections sharing a page can leave us with a page that is both;
+        // we let it through, but it breaks W^X so make some noise
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        if flags & PAGING_WRITEABLE != 0 && IMAGE_PAGE_NX != ZERO_USIZE && flags & IMAGE_PAGE_NX == 0 {
+            serial_println!("PeImage::page_flags() -> warning: image page @ +0x{:0x} is both writeable and executable", page_start);
+        }
+
+        flags
+    }
+
+    fn read_u16(base: PhysAddr, offset: usize) -> u16 {
+        unsafe { core::ptr::read_unaligned((base.as_usize() + offset) as *const u16) }
+    }
+
+    fn read_u32(base: PhysAddr, offset: usize) -> u32 {
+        unsafe { core::ptr::read_unaligned((base.as_usize() + offset) as *const u32) }
+    }
+}
@@ -5,36 +5,74 @@
 // 2. The kernel is given control by the bootloader / firmware / hypervisor:
 //    a. For uefi, the kernel is given a pointer to the boot services table
 //       and control is passed to uefi_start() in iron/src/main.rs.
-//    b. For multiboot, the kernel is given a pointer to the multiboot info struct
-//       and control is passed to multiboot_start() in iron/src/main.rs.
+//    b. For multiboot2 (x86_64 only), the loader jumps to the 32 bit trampoline in
+//       iron/src/multiboot.rs, which enters long mode and passes control (and a
+//       pointer to the multiboot2 info struct) to multiboot_start() in iron/src/main.rs.
 // 3. The pre-init functions, which will vary by arch/platform/boot method, shall be
 //    called xxx_pre_init() and shall be located in baselib/src/bringup/xxx.rs; as
 //    an example, the uefi pre-init function is uefi_pre_init() and it is located in
 //    baselib/src/bringup/uefi.rs.
 //    a. The pre-init functions shall parse the boot information provided by the 
-//       bootloader / firmware / hypervisor and shall add the available memory
-//       frames to the frame allocator.
-//    b. The pre-init functions shall then call the kernel_init() function, which
-//       will perform the remaining initialization that is common to all archs/platforms.
+//       bootloader / firmware / hypervisor into a BootInfo (bringup/bootinfo.rs):
+//       the memory map in the kernel's own region types, the command line, the
+//       framebuffer, the acpi rsdp / device tree pointers and any entropy.
+//    b. The pre-init functions shall then call the kernel_init() function with it,
+//       which will perform the remaining initialization that is common to all
+//       archs/platforms. kernel_init() consumes nothing but the BootInfo.
 // 4. kernel_init() -> upon completion of kernel environment setup, including creation
 //    of a new kernel stack, shall call kernel_main() in iron/src/main.rs using a kernel
 //    configured address space and the newly created stack.
 // --------------------------------------------------------------------------------------
 
 // Submodule(s)
+pub mod bootinfo;
+pub mod early_alloc;
+pub mod image;
+pub mod multiboot;
 pub mod uefi;
 
 // Rust Items
 use core::sync::atomic::{AtomicBool, Ordering};
-// External Items
-use ::uefi::table::boot::*;
 // Internal Items
 use crate::common::base::*;
 use crate::structures::bitmap::*;
 use crate::bringup::uefi::*;
+use crate::bringup::bootinfo::*;
+use crate::bringup::early_alloc::*;
+use crate::bringup::image::*;
+use crate::status::KernelServiceStatus;
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
 use crate::cpu::*;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use crate::arch::x86::random::*;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::asm::{x86_read_raw_cr3, x86_switch_stack_and_jump, x86_invalidate_page};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::cpu::x86_enable_write_protect;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::interrupts::x86_interrupts_init;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::gdt::{x86_gdt_init, X86_IST_STACK_COUNT};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::apic::{x86_apic_init, x86_lapic_base, X86_IOAPIC_DEFAULT_BASE};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::tsc::x86_tsc_calibrate;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::hpet::x86_hpet_init;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::rtc::x86_rtc_set_century_register;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::x86::cpu::x86_enable_nx;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aa64::exception::aa64_exceptions_init;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aa64::gic::*;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aa64::timer::{aa64_timer_init, GenericTimer};
+use crate::timer::*;
+use crate::clock::*;
+use crate::acpi::*;
+use crate::params::*;
 
 // these constants should hold true for all uefi architectures
 pub const MEMORY_DEFAULT_PAGE_USIZE: usize = USIZE_4K;
@@ -52,10 +90,6 @@ pub const MEMORY_DEFAULT_SHIFT: usize = UFACTOR_OF_4K;
 pub const PAGE_TABLE_ENTRY_UBYTES: usize = core::mem::size_of::<usize>();
 pub const PAGE_TABLE_MAX_ENTRIES: usize = MEMORY_DEFAULT_PAGE_USIZE / PAGE_TABLE_ENTRY_UBYTES;
 
-// how often the frame allocator will coalesce free regions
-// in terms of de-allocations
-pub const FRAME_ALLOCATOR_COALESCE_THRESHOLD_DEALLOC: usize = 100;
-
 // kernel boot methods
 #[repr(usize)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -131,10 +165,29 @@ pub fn base_nebulae_genesis_frame(new_genesis_frame_base: Option<PhysAddr>) -> O
 }
 
 // kernel init
-pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base_addr: PhysAddr, _mmap_entry_count: usize) {
+pub fn kernel_init(boot_info: &BootInfo) {
     
     // signal that we have the memory map in hand
     memory_map_fuse(false);
+
+    // hang on to the handoff; everything below works from the stored copy
+    boot_info_store(boot_info);
+
+    // the command line's settings; anything it doesn't mention keeps its default
+    let params = kernel_params_init(boot_info.cmdline);
+
+    // mem= cuts the usable memory short before anything is sized off of it
+    if let Some(mem_limit) = params.mem_limit {
+        boot_info_limit_usable(mem_limit);
+    }
+
+    let boot_info = crate::bringup::bootinfo::boot_info().unwrap();
+
+    let conv_page_count = boot_info.usable_page_count();
+    let phys_boundary = boot_info.phys_boundary();
+    let kernel_image_base = boot_info.kernel_image_base;
+    let kernel_image_size = boot_info.kernel_image_size;
+    let regions = boot_info.regions();
     
     //-----------------------------------------------------------------------------------
     
@@ -144,7 +197,7 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     serial_print!("nebulae::uefi_init() -> seeding rng...");
 
     // use the 2nd scratch page for the rng
-    let rng_base = scratch_base_addr.clone().inner_inc_by_page_size(MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+    let rng_base = boot_info.rng_base();
     let rng_result = Isaac64Rng::new_with_fixed_buf(rng_base);
 
     // panic if we can't instantiate the rng
@@ -156,11 +209,25 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
 
     // seed the rng with 512 random u32s
     let mut rng_seed: [u32; 512] = [ZERO_U32; 512];
-    let fill_result = unsafe { rdseed_slice::<u32>(&mut rng_seed) };
 
-    // panic if we can't fill the rngseed with random data
-    if !fill_result {
-        panic!("nebulae::uefi_init() -> failed to fill rng seed with random data");
+    if let Some(seed) = params.rng_seed {
+        // rngseed= on the command line: the same seed, the same boot
+        kernel_expand_seed(seed, &mut rng_seed);
+    } else {
+        let fill_result = unsafe { rdseed_slice::<u32>(&mut rng_seed) };
+
+        // panic if we can't fill the rngseed with random data, unless the
+        // boot method brought some along
+        if !fill_result && boot_info.entropy.is_none() {
+            panic!("nebulae::uefi_init() -> failed to fill rng seed with random data");
+        }
+
+        // mix in whatever entropy the boot method brought along
+        if let Some(entropy) = boot_info.entropy {
+            for (i, chunk) in entropy.chunks_exact(4).enumerate() {
+                rng_seed[i] ^= u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            }
+        }
     }
 
     // seed the rng
@@ -175,32 +242,15 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     // genesis frame -> nebulae struct
 
     
-    // obtain a mutable reference to the 3rd scratch page
-    // as an array of MemoryDescriptor structs
-    const MEM_DESCR_PER_PAGE: usize = MEMORY_DEFAULT_PAGE_USIZE / core::mem::size_of::<MemoryDescriptor>();
-    let mm_scratch: &mut [MemoryDescriptor; MEM_DESCR_PER_PAGE] =
-        unsafe { 
-            core::mem::transmute::<PhysAddr, &mut [MemoryDescriptor; MEM_DESCR_PER_PAGE]>(rng_base.clone().inner_inc_by_page_size(MEMORY_DEFAULT_PAGE_SIZE_ENUM))
-        };    
-    
-    let mut new_nebulae_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys();
-    let mut is_new_base_free = false;
-
-    while !is_new_base_free {
-        for e in mm_scratch.iter_mut() {
-            if new_nebulae_base.as_usize() >= e.phys_start.as_usize() &&
-               new_nebulae_base.as_usize() < e.phys_start.as_usize() + (e.page_count.as_usize() * MEMORY_DEFAULT_PAGE_USIZE) {
-
-                if e.ty != MemoryType::CONVENTIONAL {
-                    break;
-                }
-
-                is_new_base_free = true;
-                break;
-            }
-        }
-        if !is_new_base_free { new_nebulae_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys(); }
-    }
+    // everything from here until the frame allocator is up is placed by the
+    // early allocator: randomly, below 2g, among the usable memory it fits in
+    let mut early_alloc = EarlyRegionAllocator::new(boot_info);
+
+    let new_nebulae_base = early_alloc
+        .alloc(MEMORY_DEFAULT_PAGE_USIZE, MEMORY_DEFAULT_PAGE_USIZE, USIZE_2G, Some(&mut rng))
+        .unwrap_or_else(|| {
+            panic!("nebulae::kernel_init() -> no usable memory below 0x{:08x} for the genesis frame", USIZE_2G);
+        });
 
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
     serial_println!("genesis frame location determined: 0x{:08x}.", new_nebulae_base.as_usize());
@@ -208,16 +258,11 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     // zero the new frame
     raw::memset_aligned(new_nebulae_base, MEMORY_DEFAULT_PAGE_USIZE, BytePattern::ZeroZero.as_usize_pattern());
 
-    // mark it allocated in our scratch page array
-    let allocated_frame_array: &mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES] = unsafe { core::mem::transmute::<PhysAddr, &mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES]>(scratch_base_addr) };
-    allocated_frame_array[3].base_addr = new_nebulae_base;
-    allocated_frame_array[3].size = MEMORY_DEFAULT_PAGE_USIZE;
-
     // instantiate the base nebulae struct @ the genesis frame base address
     {
         // instantiate the nebulae struct @ the new genesis frame base address
         let new_neb_id = rng.rand_usize(ZERO_USIZE);
-        Nebulae::new_at_phys_fixed(new_nebulae_base, nebulae, new_neb_id, conv_page_count, pages::bytes_to_pages(phys_boundary.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM), phys_boundary, rng_base.clone().inner_inc_by_page_size(MEMORY_DEFAULT_PAGE_SIZE_ENUM));
+        Nebulae::new_at_phys_fixed(new_nebulae_base, nebulae, new_neb_id, conv_page_count, pages::bytes_to_pages(phys_boundary.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM), phys_boundary, boot_info.regions_base());
     
         // sanity check
         let neb = raw::abracadabra::<Nebulae>(new_nebulae_base, false);
@@ -260,7 +305,7 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
 
     // mem region descriptor structs to cover conv_page_count nodes (again, most degraded case)
     let node_storage_pages_reqd = pages::bytes_to_pages(
-        phys_range_page_count * core::mem::size_of::<FrameDescr>(),
+        phys_range_page_count * KernelFrameAllocator::FRAME_NODE_SIZE,
         MEMORY_DEFAULT_PAGE_SIZE_ENUM);
     let node_storage_bytes_reqd = pages::pages_to_bytes(node_storage_pages_reqd, MEMORY_DEFAULT_PAGE_SIZE_ENUM);
 
@@ -274,54 +319,14 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     serial_println!("genesis::kernel_init() -> allocating memory for physical frame allocator bitmap");
 
     // Allocate memory for the bitmap
-    let mut new_bitmap_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys();
-    let mut is_new_bitmap_base_free = false;
-    
-    while !is_new_bitmap_base_free {
-        let mut frame_idx = ZERO_USIZE;
-        let mut is_new_bitmap_base_allocated = false;
-
-        while allocated_frame_array[frame_idx].size != ZERO_USIZE {
-            if new_bitmap_base.as_usize() >= allocated_frame_array[frame_idx].base_addr.as_usize() &&
-               new_bitmap_base.as_usize() < allocated_frame_array[frame_idx].base_addr.as_usize() + allocated_frame_array[frame_idx].size {
-                
-                    is_new_bitmap_base_allocated = true;
-                    break;
-            } else {
-                frame_idx += 1;
-            }
-        }
-
-        if !is_new_bitmap_base_allocated {
-            for e in mm_scratch.iter_mut() {
-                if new_bitmap_base.as_usize() >= e.phys_start.as_usize() &&
-                   new_bitmap_base.as_usize() < e.phys_start.as_usize() + pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM) {
-
-                    // found a match but it's not conventional memory
-                    if e.ty != MemoryType::CONVENTIONAL {
-                        break;
-                    }
-
-                    // make sure we are still within the frame size tolerances
-                    let total_frame_size = pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-                    if new_bitmap_base.as_usize() + bitmap_bytes_reqd > e.phys_start.as_usize() + total_frame_size {
-                        break;
-                    }
-
-                    is_new_bitmap_base_free = true;
-                    break;
-                }
-            }
-        }
-        if !is_new_bitmap_base_free { new_bitmap_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys(); }
-    }
+    let new_bitmap_base = early_alloc
+        .alloc(bitmap_bytes_reqd, MEMORY_DEFAULT_PAGE_USIZE, USIZE_2G, Some(&mut rng))
+        .unwrap_or_else(|| {
+            panic!("nebulae::kernel_init() -> no usable memory below 0x{:08x} for the physical frame allocator bitmap ({} bytes)", USIZE_2G, bitmap_bytes_reqd);
+        });
     
     // prepare the memory for bitmap usage (all ones)
     raw::memset_aligned(new_bitmap_base, bitmap_bytes_reqd, BytePattern::FF.as_usize_pattern());
-
-    // mark the bitmap pages as allocated in our scratch array
-    allocated_frame_array[4].base_addr = new_bitmap_base;
-    allocated_frame_array[4].size = bitmap_bytes_reqd;
     
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
     serial_println!("genesis::kernel_init() -> physical frame allocator bitmap allocated: {} page(s) @ 0x{:08x}", bitmap_pages_reqd, new_bitmap_base.as_usize());
@@ -330,55 +335,15 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     serial_println!("genesis::kernel_init() -> allocating memory for physical frame allocator node storage");
 
     // Allocate memory for node storage
-    let mut new_node_storage_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys();
-    let mut is_new_node_storage_base_free = false;
-    
-    while !is_new_node_storage_base_free {
-        let mut frame_idx = ZERO_USIZE;
-        let mut is_new_node_storage_base_allocated = false;
-
-        while allocated_frame_array[frame_idx].size != ZERO_USIZE {
-            if new_node_storage_base.as_usize() >= allocated_frame_array[frame_idx].base_addr.as_usize() &&
-               new_node_storage_base.as_usize() < allocated_frame_array[frame_idx].base_addr.as_usize() + allocated_frame_array[frame_idx].size {
-                
-                    is_new_node_storage_base_allocated = true;
-                    break;
-            } else {
-                frame_idx += 1;
-            }
-        }
-
-        if !is_new_node_storage_base_allocated {
-            for e in mm_scratch.iter_mut() {
-                if new_node_storage_base.as_usize() >= e.phys_start.as_usize() &&
-                   new_node_storage_base.as_usize() < e.phys_start.as_usize() + pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM) {
-
-                    // found a match but it's not conventional memory
-                    if e.ty != MemoryType::CONVENTIONAL {
-                        break;
-                    }
-
-                    // make sure we are still within the frame size tolerances
-                    let total_frame_size = pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-                    if new_node_storage_base.as_usize() + node_storage_bytes_reqd > e.phys_start.as_usize() + total_frame_size {
-                        break;
-                    }
-
-                    is_new_node_storage_base_free = true;
-                    break;
-                }
-            }
-        }
-        if !is_new_node_storage_base_free { new_node_storage_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys(); }
-    }
+    let new_node_storage_base = early_alloc
+        .alloc(node_storage_bytes_reqd, MEMORY_DEFAULT_PAGE_USIZE, USIZE_2G, Some(&mut rng))
+        .unwrap_or_else(|| {
+            panic!("nebulae::kernel_init() -> no usable memory below 0x{:08x} for the physical frame allocator node storage ({} bytes)", USIZE_2G, node_storage_bytes_reqd);
+        });
 
     // zero the memory for node storage
     raw::memset_aligned(new_node_storage_base, node_storage_bytes_reqd, BytePattern::ZeroZero.as_usize_pattern());
 
-    // mark the node storage as allocated in our scratch array
-    allocated_frame_array[5].base_addr = new_node_storage_base;
-    allocated_frame_array[5].size = node_storage_bytes_reqd;
-
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
     serial_println!("genesis::kernel_prep() -> memory for node storage allocated: {} page(s) @ 0x{:08x}", node_storage_pages_reqd, new_node_storage_base.as_usize());
     
@@ -386,55 +351,15 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     serial_println!("genesis::kernel_init() -> allocating memory for memory info structs");
 
     // allocate memory for the page info structs
-    let mut new_page_info_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys();
-    let mut is_new_page_info_base_free = false;
-    
-    while !is_new_page_info_base_free {
-        let mut frame_idx = ZERO_USIZE;
-        let mut is_new_page_info_base_allocated = false;
-
-        while allocated_frame_array[frame_idx].size != ZERO_USIZE {
-            if new_page_info_base.as_usize() >= allocated_frame_array[frame_idx].base_addr.as_usize() &&
-               new_page_info_base.as_usize() < allocated_frame_array[frame_idx].base_addr.as_usize() + allocated_frame_array[frame_idx].size {
-                
-                    is_new_page_info_base_allocated = true;
-                    break;
-            } else {
-                frame_idx += 1;
-            }
-        }
-
-        if !is_new_page_info_base_allocated {
-            for e in mm_scratch.iter_mut() {
-                if new_page_info_base.as_usize() >= e.phys_start.as_usize() &&
-                   new_page_info_base.as_usize() < e.phys_start.as_usize() + pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM) {
-
-                    // found a match but it's not conventional memory
-                    if e.ty != MemoryType::CONVENTIONAL {
-                        break;
-                    }
-
-                    // make sure we are still within the frame size tolerances
-                    let total_frame_size = pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-                    if new_page_info_base.as_usize() + page_info_bytes_reqd > e.phys_start.as_usize() + total_frame_size {
-                        break;
-                    }
-
-                    is_new_page_info_base_free = true;
-                    break;
-                }
-            }
-        }
-        if !is_new_page_info_base_free { new_page_info_base = rng.ranged_rand_usize(USIZE_512K, usize::min(phys_boundary.as_usize(), USIZE_2G)).align_canon_default().as_phys(); }
-    }
+    let new_page_info_base = early_alloc
+        .alloc(page_info_bytes_reqd, MEMORY_DEFAULT_PAGE_USIZE, USIZE_2G, Some(&mut rng))
+        .unwrap_or_else(|| {
+            panic!("nebulae::kernel_init() -> no usable memory below 0x{:08x} for the page info structs ({} bytes)", USIZE_2G, page_info_bytes_reqd);
+        });
 
     // zero the memory for page info structs
     raw::memset_aligned(new_page_info_base, page_info_bytes_reqd, BytePattern::ZeroZero.as_usize_pattern());
     
-    // mark the page info struct storage as allocated in our scratch array
-    allocated_frame_array[6].base_addr = new_page_info_base;
-    allocated_frame_array[6].size = page_info_bytes_reqd;
-
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
     serial_println!("genesis::kernel_init() -> memory for memory info structs allocated: {} page(s) @ 0x{:08x}", page_info_pages_reqd, new_page_info_base.as_usize());
     
@@ -445,6 +370,28 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
 
     //-----------------------------------------------------------------------------------
 
+    // exceptions
+
+    // the firmware's idt is no longer ours to rely on once boot services are gone,
+    // so get our own exception handlers in place before doing anything risky
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("installing exception handlers");
+
+        x86_interrupts_init();
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("installing exception vectors");
+
+        aa64_exceptions_init();
+    }
+
+    //-----------------------------------------------------------------------------------
+
     // memory structures init
 
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
@@ -542,7 +489,7 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     serial_println!("beginning physical frame allocator bringup & init");
 
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
-    serial_println!("constructing new TreeAllocator for physical frame management");
+    serial_println!("constructing new {} for physical frame management", core::any::type_name::<KernelFrameAllocator>());
 
     // wire up the bitmap sub-allocator to the frame allocator and then
     // the node storage to the nebulae struct, then the nebulae struct back
@@ -553,7 +500,7 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     {    
         // set up & initialize the frame allocator
         let mut frame_alloc_result = iron().unwrap().frame_alloc_internal_04.lock_rw_spin();
-        (*frame_alloc_result) = Some(TreeAllocator::new(new_node_storage_base, total_pages));
+        (*frame_alloc_result) = Some(KernelFrameAllocator::new(new_node_storage_base, total_pages));
         let frame_alloc = (*frame_alloc_result).as_mut().unwrap_or_else(|| {
             panic!("failed to dereference pointer to frame allocator");
         });
@@ -579,7 +526,7 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
         // now that the allocator is set up with a bitmap, we can call init() on the physical frame allocator
         
         #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        serial_println!("calling init() on the physical frame allocator (TreeAllocator)");
+        serial_println!("calling init() on the physical frame allocator");
         
         frame_alloc.init();
         
@@ -587,94 +534,58 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
         serial_println!("node storage wired. adding memory frames to physical frame allocator");
 
         {
-            // now we need to go through the uefi memory map one final time and add all the regions
+            // now we need to go through the memory map one final time and add all the regions
             // to their respective trees
 
             // add_mem_frame() should never fail
             // there are enough slots pre-allocated for worst-case
-            for e in mm_scratch {
-                
-                // once we hit a zero size descriptor, we are done
-                if e.page_count.as_usize() == ZERO_USIZE {
-                    break;
-                }
-
-                // if this range contains our genesis block, treat it specially
-                if range_contains(
-                    e.phys_start.as_usize(), 
-                    pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM), 
-                    new_nebulae_base.as_usize()
-                ) {
-                    // get stats
-                    let parent_region_size = pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-                    let _gblock_idx: usize;
-                    let _parent_region_idx: usize;
-
-                    // we are either splitting the first page of this region
-                    // or the last page of this region.
-                    // either way, both regions are added to the frame allocator
-                    // in their respective trunks
-                    if new_nebulae_base.as_usize() == e.phys_start.as_usize() {
-                        
-                        // add the first page of this region
-                        _ = frame_alloc.add_mem_frame(
-                            e.phys_start.as_phys(),
-                            MEMORY_DEFAULT_PAGE_USIZE,
-                            false,
-                            0,
-                            Owner::Kernel,
-                        ).unwrap();
-
-                        // add the rest of this region
-                        _ = frame_alloc.add_mem_frame(
-                            PhysAddr(e.phys_start.as_usize() + MEMORY_DEFAULT_PAGE_USIZE),
-                            parent_region_size - MEMORY_DEFAULT_PAGE_USIZE,
-                            true,
-                            0,
-                            Owner::Nobody,
-                        ).unwrap();
-                    } else {
-                        // split off the last page of this region
-
-                        // the last page
-                        _ = frame_alloc.add_mem_frame(
-                            PhysAddr(e.phys_start.as_usize() + parent_region_size - MEMORY_DEFAULT_PAGE_USIZE),
-                            MEMORY_DEFAULT_PAGE_USIZE,
-                            false,
-                            0,
-                            Owner::Kernel,
-                        ).unwrap();
-
-                        // the rest of this region
-                        _ = frame_alloc.add_mem_frame(
-                            e.phys_start.as_phys(),
-                            parent_region_size - MEMORY_DEFAULT_PAGE_USIZE,
-                            true,
-                            0,
-                            Owner::Nobody,
-                        ).unwrap();
+            for e in regions.iter() {
+                if e.ty == MemRegionType::Usable {
+                    _ = frame_alloc.add_mem_frame(
+                        e.base,
+                        pages::pages_to_bytes(e.page_count, MEMORY_DEFAULT_PAGE_SIZE_ENUM),
+                        true,
+                        0,
+                        Owner::Nobody,
+                    );
+                } else if kernel_region_is_reclaimable(e.ty) {
+                    // the firmware's & loader's memory stays theirs until
+                    // kernel_reclaim_boot_memory(); the kernel image (which
+                    // the loader's memory may include) is ours for good, and
+                    // whatever's past mem= is never ours at all
+                    for (base, size, is_image) in kernel_reclaim_split(e, kernel_image_base, kernel_image_size) {
+                        for (base, size, is_below) in kernel_limit_split(base, size, kernel_mem_limit()) {
+                            _ = frame_alloc.add_mem_frame(
+                                base,
+                                size,
+                                false,
+                                0,
+                                if is_image { Owner::Kernel } else if is_below { Owner::Firmware } else { Owner::Reserved },
+                            );
+                        }
                     }
+                } else {
+                    _ = frame_alloc.add_mem_frame(
+                        e.base,
+                        pages::pages_to_bytes(e.page_count, MEMORY_DEFAULT_PAGE_SIZE_ENUM),
+                        false,
+                        0,
+                        Owner::Reserved,
+                    );
+                }
+            }
 
-                    // we are set with the genesis block now
+            // everything the early allocator handed out (the genesis frame, the frame
+            // allocator's own metadata and, on multiboot, the scratch pages) sits in
+            // usable memory that was just added as free, so take it back out. blocks
+            // that landed in loader memory are already spoken for and stay put.
+            for block in early_alloc.allocated() {
+                if frame_alloc.frame_owner(block.base_addr) != Some(Owner::Nobody) {
                     continue;
-                } else {
-                    if e.ty == MemoryType::CONVENTIONAL {
-                        _ = frame_alloc.add_mem_frame(
-                            e.phys_start.as_phys(),
-                            pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM),
-                            true,
-                            0,
-                            Owner::Nobody,
-                        );
-                    } else {
-                        _ = frame_alloc.add_mem_frame(
-                            e.phys_start.as_phys(),
-                            pages::pages_to_bytes(e.page_count.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM),
-                            false,
-                            0,
-                            Owner::Reserved,
-                        );
-                    }
+                }
+
+                if frame_alloc.reserve_frame_fixed(block.base_addr, block.size, Owner::Kernel).is_none() {
+                    panic!("failed to reserve bringup frame @ 0x{:08x}", block.base_addr);
                 }
             }
         }
@@ -689,6 +600,25 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     // blow the physical frame allocator fuse
     frame_alloc_fuse(false);
 
+    // carve physical memory up into zones (dma16 / dma32 / normal) for the
+    // allocations that care where they land
+    mem_zones_init(regions, kernel_mem_limit());
+
+    //-----------------------------------------------------------------------------------
+
+    // acpi
+
+    // find & checksum the firmware's tables while the frame allocator can still
+    // be told about them; the ones that aren't in acpi reclaim / nvs memory
+    // (which is already reserved) get pulled out of the free pool
+    if let Some(rsdp) = boot_info.rsdp {
+        acpi_set_rsdp(rsdp);
+    }
+
+    if acpi_init() {
+        acpi_reserve_regions();
+    }
+
     //-----------------------------------------------------------------------------------
 
     // paging init
@@ -702,6 +632,13 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
     // initialize the kernel's virtual address space - x86 & x64
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     {
+        // the tables we build carry NX bits, which are reserved (and fault)
+        // unless EFER.NXE is on
+        #[cfg(target_arch = "x86_64")]
+        if !x86_enable_nx() {
+            panic!("processor does not support no-execute pages");
+        }
+
         // set the base page table
         {
             let mut kernel_vas = iron().unwrap().base_vas_07.lock_rw_spin();
@@ -726,154 +663,193 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
         #[cfg(all(debug_assertions, feature = "serialdbg"))]
         serial_println!("base page table initialized.");
 
-        // #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        // serial_println!("programming system memory layout into new base page table...");
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("programming system memory layout into new base page table...");
+
+        // plain data: read/write, never executable
+        #[cfg(target_arch = "x86_64")]
+        let data_flags = PAGING_PRESENT | PAGING_WRITEABLE | PAGING_NX;
+        #[cfg(target_arch = "x86")]
+        let data_flags = PAGING_PRESENT | PAGING_WRITEABLE;
+
+        // 1. identity map every frame the frame allocator knows about, alloc'ed and free
+        //    alike. free memory has to be reachable after the switch since alloc_frame()
+        //    zeroes new frames through their physical address. we walk the frames by
+        //    address and re-lock each step, because map_page() takes the frame allocator
+        //    lock itself (and may carve new page tables out of the free frames we're walking)
+        for is_free in [false, true] {
+            let mut next_addr = ZERO_USIZE.as_phys();
+
+            loop {
+                let next_frame = iron().unwrap().frame_alloc_internal_04
+                    .lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
+                    .next_frame_by_addr(next_addr, is_free);
+
+                let (frame_base, frame_size) = match next_frame {
+                    Some(frame) => frame,
+                    None => break,
+                };
+
+                next_addr = PhysAddr(frame_base.as_usize() + frame_size);
+
+                // leave physical page zero unmapped so null derefs fault
+                let (map_base, map_size) = if frame_base.is_null() {
+                    (PhysAddr(MEMORY_DEFAULT_PAGE_USIZE), frame_size.saturating_sub(MEMORY_DEFAULT_PAGE_USIZE))
+                } else {
+                    (frame_base, frame_size)
+                };
 
-        // // this is where we map the kernel's memory layout into the new page directory
-        // // we've set up
-        // {
-        //     // go through the alloc regions and identity map them
+                if map_size == ZERO_USIZE {
+                    continue;
+                }
 
-        //     // obtain a ref to the frame allocator
-        //     // this lock MUST be released prior to mapping pages
-        //     let mut frame_alloc = iron().unwrap().frame_alloc_internal_04.lock_rw_spin();
+                if !kernel_identity_map(map_base, map_size, data_flags) {
+                    panic!("failed to identity map frame @ 0x{:08x}, size {}", map_base, map_size);
+                }
+            }
+        }
 
-        //     // obtain a reference to the alloc'ed frames' address trunk
-        //     let alloc_frames = (*frame_alloc).unwrap();
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("physical frames identity mapped.");
+
+        // 2. fix up the regions that aren't plain data. firmware runtime code images (uefi
+        //    runtime services) carry their own data sections and get relocated in place, so
+        //    they have to stay writeable as well as executable; runtime data (the system table
+        //    among it) has to stay reachable for us to call them at all. mmio must not be cached.
+        for e in regions.iter() {
+            let fixup_flags = match e.ty {
+                MemRegionType::FirmwareCode => PAGING_PRESENT | PAGING_WRITEABLE,
+                MemRegionType::FirmwareData => data_flags,
+                MemRegionType::Mmio => data_flags | PAGING_CACHE_DISABLE,
+                _ => continue,
+            };
 
-        //     // obtain a ref to our memory regions
-        //     let mem_frame_array = frame_alloc.as_mut().unwrap().as_mut().unwrap().get_mem_frame_descr_array_mut();
+            if e.base.as_usize() == ZERO_USIZE {
+                continue;
+            }
 
-        //     #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //     serial_println!("obtained reference to memory regions at 0x{:08x}", raw::ref_to_raw::<FrameDescr, PhysAddr>(&mem_frame_array[0]));
-            
-        //     // iterate through the alloc'ed regions
-
-        //     // if there is no root node, then there are no alloc'ed regions to identity map
-        //     if alloc_frames.root().is_none() {
-        //         #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //         serial_println!("no existing memory regions to program.");
-        //     } else {
-        //         // otherwise, we have some regions to identity map
-        //         #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //         serial_println!("found existing memory regions to program.");
-
-        //         // iterate through the node(s), if any;
-                
-        //         // set the current alloc'ed node to the maximum node
-        //         let mut current_alloc_node = alloc_frames.max_node();
-                
-        //         // iterate through the alloc'ed nodes by address, high to low
-        //         while !current_alloc_node.is_none() {
-        //             let region_base: u64;
-        //             let region_size: u64;
-        //             let region_idx: usize;
-                    
-        //             // get the size and base address of the region
-        //             region_base = hi64((*current_alloc_node.unwrap()).key());
-        //             region_size = lo64((*current_alloc_node.unwrap()).key());
-        //             region_idx = (*current_alloc_node.unwrap()).value();
-
-        //             #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //             serial_println!("-> mapping region id {} @ 0x{:08x} size {}.", region_idx, region_base, region_size);
-                    
-        //             #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //             unsafe {
-        //                 serial_println!("-> region id {}: size: {}, owner: {:?}, idx: {}", 
-        //                     region_idx, mem_frame_array[region_idx].mem_block.get().as_ref().unwrap().size,
-        //                     mem_frame_array[region_idx].owner.get().as_ref().unwrap().clone(),
-        //                     mem_frame_array[region_idx].mem_frame_idx.get().as_ref().unwrap().clone());
-        //             }
-
-        //             // calculate the number of pages in the region
-        //             let region_page_count = pages::bytes_to_pages(region_size.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-
-        //             #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //             serial_println!("-> region has {} page(s)", region_page_count);
-
-        //             // map each page in the region
-        //             let mut current_addr = region_base.as_phys();
-
-        //             #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //             serial_println!("-> identity mapping region page(s)");
-
-        //             for _i in 0..region_page_count {
-                        
-        //                 // lock the address space and map the pages for this region
-        //                 {
-        //                     let mut kernel_vas = iron().unwrap().base_vas_07.lock_rw_spin();
-                            
-        //                     // verify the lock
-        //                     if (*kernel_vas).is_none() {
-        //                         serial_println!("-> failure mapping page: could not locate kernel vas");
-        //                         continue;
-        //                     }
-
-        //                     // unfortunately these mappings in early boot are critical
-        //                     unsafe {
-        //                         (*kernel_vas).as_mut().unwrap_or_else(|| {
-        //                                 panic!("-> failure mapping page: could not locate kernel vas lock");
-        //                             })
-        //                             .base_page_table.as_mut().unwrap_or_else(|| {
-        //                                 panic!("-> failure mapping page: could not locate kernel vas base struct pointer");
-        //                             }).as_mut().unwrap()
-        //                             .map_page(
-        //                             current_addr,
-        //                             current_addr.as_usize().as_virt(),
-        //                             MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-        //                             0,                                
-        //                         );
-        //                     }
-        //                 }
-                        
-        //                 // increment the current address
-        //                 current_addr.inner_inc_by_page_size(MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-        //             }
-
-        //             // if we just did base zero, we are finished
-        //             if region_base == 0 {
-        //                 break;
-        //             }
-
-        //             // switch the current node the prev node
-        //             let key_prev = make128(region_base.as_usize() - 1, 0);
-        //             current_alloc_node = alloc_frames.floor_node(key_prev);
-        //         }
-        //     }
-        // }
-
-        // #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        // serial_println!("new address space programming complete.");
-
-        // // see how many free pages we have after bootstrapping the memory manager
-        // {
-        //     let free_pages = iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
-        //         .free_page_count();
+            if !kernel_identity_map(
+                e.base,
+                pages::pages_to_bytes(e.page_count, MEMORY_DEFAULT_PAGE_SIZE_ENUM),
+                fixup_flags) {
+                panic!("failed to identity map firmware region @ 0x{:08x}", e.base.as_usize());
+            }
+        }
+
+        //    the acpi tables are covered by the above, save for any that live in
+        //    memory the memory map didn't describe; map them all to be sure
+        acpi_for_each_region(|base, size| {
+            if !kernel_identity_map(base, size, data_flags) {
+                panic!("failed to identity map acpi tables @ 0x{:08x}", base);
+            }
+        });
+
+        // 3. the kernel image itself gets mapped section by section: code read-only &
+        //    executable, data writeable & non-executable, headers read-only
+        match PeImage::parse(kernel_image_base, kernel_image_size) {
+            Some(kernel_image) => {
+                let mut offset = ZERO_USIZE;
+
+                while offset < kernel_image.size() {
+                    let page_addr = PhysAddr(kernel_image.base().as_usize() + offset);
+
+                    if !kernel_identity_map(page_addr, MEMORY_DEFAULT_PAGE_USIZE, kernel_image.page_flags(offset)) {
+                        panic!("failed to identity map kernel image page @ 0x{:08x}", page_addr);
+                    }
+
+                    offset += MEMORY_DEFAULT_PAGE_USIZE;
+                }
+
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("kernel image mapped: {} section(s) @ 0x{:08x}", kernel_image.section_count(), kernel_image.base());
+            }
+            None => {
+                // we're still running out of this image, so if we can't read its
+                // layout the best we can do is leave all of it executable
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("warning: unable to parse kernel image headers, mapping image read/write/execute");
+
+                if !kernel_identity_map(kernel_image_base, kernel_image_size, PAGING_PRESENT | PAGING_WRITEABLE) {
+                    panic!("failed to identity map kernel image @ 0x{:08x}", kernel_image_base);
+                }
+            }
+        }
+
+        // 4. the genesis frame, the scratch pages and the frame allocator metadata are
+        //    already covered by pass 1, but these are what we absolutely cannot lose when
+        //    cr3 flips, so (re)map them explicitly
+        for block in early_alloc.allocated() {
+            if !kernel_identity_map(block.base_addr, block.size, data_flags) {
+                panic!("failed to identity map bringup frame @ 0x{:08x}", block.base_addr);
+            }
+        }
+
+        // the serial port is port i/o on x86, so there's nothing to map for it
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("new address space programming complete.");
+
+        // see how many free pages we have after bootstrapping the memory manager
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        {
+            let free_pages = iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
+                .free_page_count();
             
-        //     serial_println!(
-        //         "Free pages: {} / {} KB",
-        //         free_pages,
-        //         free_pages << UFACTOR_OF_4K
-        //     );
-        // }
-        
-        // #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        // serial_println!("initializing base register & switching to nebulae address space");
+            serial_println!(
+                "Free pages: {} / {} KB",
+                free_pages,
+                free_pages << 2
+            );
+        }
+
+        // enforce read-only pages in ring 0 as well
+        x86_enable_write_protect();
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("initializing base register & switching to nebulae address space");
+
+        // initialize the base address register
+        let new_cr3 = iron().unwrap().base_vas_07.lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
+            .init_cr3()
+            .unwrap_or_else(|| {
+                panic!("failed to initialize base address register");
+            });
 
-        // // initialize the base address register
-        // {            
-        //     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-        //     let new_cr3 = iron().unwrap().base_vas_07.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().init_cr3().unwrap();
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("base address register initialized: 0x{:08x}", new_cr3);
+
+        // switch to the nebulae address space
+        iron().unwrap().base_vas_07.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().switch_to();
 
-        //     #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        //     serial_println!("base address register initialized: 0x{:08x}", new_cr3);
-        // }
+        // verify the switch: cr3 has to point at our base table, and the genesis
+        // frame & the code we're running have to translate back to themselves
+        if x86_read_raw_cr3() & !PAGING_PCID_CR3_MASK != new_cr3.as_usize() & !PAGING_PCID_CR3_MASK {
+            panic!("address space switch failed: cr3 is 0x{:08x}, expected 0x{:08x}", x86_read_raw_cr3(), new_cr3);
+        }
 
-        // // switch to the nebulae address space
-        // {
-        //     // switch address spaces
-        //     iron().unwrap().base_vas_07.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().switch_to();
-        // }
+        {
+            let mut kernel_vas = iron().unwrap().base_vas_07.lock_rw_spin();
+            let base_page_table = kernel_vas.as_mut().unwrap().as_mut().unwrap()
+                .base_table()
+                .unwrap_or_else(|| {
+                    panic!("address space switch failed: could not locate kernel base page table");
+                });
+
+            let genesis_phys = base_page_table.virt_to_phys(new_nebulae_base.as_usize().as_virt());
+            if genesis_phys != new_nebulae_base.align_canon_default() {
+                panic!("address space switch failed: genesis frame translates to 0x{:08x}", genesis_phys);
+            }
+
+            let code_addr = kernel_init as usize;
+            let code_phys = base_page_table.virt_to_phys(code_addr.as_virt());
+            if code_phys != PhysAddr(code_addr).align_canon_default() {
+                panic!("address space switch failed: kernel code translates to 0x{:08x}", code_phys);
+            }
+        }
+
+        // blow the base vas fuse & record our progress
+        base_vas_fuse(false);
+        iron().unwrap().status.lock_rw_spin().as_mut().unwrap().virtual_mem = KernelServiceStatus::SysOnly;
 
         #[cfg(all(debug_assertions, feature = "serialdbg"))]
         serial_println!("welcome to nebulae.");
@@ -881,13 +857,405 @@ pub fn kernel_init(conv_page_count: usize, phys_boundary: PhysAddr, scratch_base
 
     //-----------------------------------------------------------------------------------
 
+    // kernel stack
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("allocating kernel stack");
+
+        let kernel_stack_base = kernel_alloc_stack(KERNEL_STACK_SIZE_SMALL);
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("kernel stack allocated @ 0x{:08x}, size {}", kernel_stack_base, KERNEL_STACK_SIZE_SMALL);
+
+        // hang onto it in the genesis struct
+        iron().unwrap().set_kernel_stack(kernel_stack_base, KERNEL_STACK_SIZE_SMALL);
+
+        // blow the kernel stack fuse
+        kernel_stack_fuse(false);
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("kernel stack initialized");
+    }
+
+    //-----------------------------------------------------------------------------------
+
+    // descriptor tables
+
+    // swap the firmware's gdt for our own, with a tss whose interrupt stack table
+    // gives #DF, NMI & #MC their own stacks; that way a blown kernel stack ends up
+    // as a double fault report instead of a triple fault
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("setting up gdt & tss");
+
+        let kernel_stack_top = iron().unwrap().get_kernel_stack_top().unwrap();
+
+        #[cfg(target_arch = "x86_64")]
+        let ist_stack_tops = {
+            let mut tops = [ZERO_USIZE.as_phys(); X86_IST_STACK_COUNT];
+
+            for i in 0..X86_IST_STACK_COUNT {
+                let ist_stack_base = kernel_alloc_stack(KERNEL_IST_STACK_SIZE);
+                tops[i] = PhysAddr(ist_stack_base.as_usize() + KERNEL_IST_STACK_SIZE);
+
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("ist stack {} allocated @ 0x{:08x}, size {}", i + 1, ist_stack_base, KERNEL_IST_STACK_SIZE);
+            }
+
+            tops
+        };
+
+        // no ist on x86
+        #[cfg(target_arch = "x86")]
+        let ist_stack_tops = [kernel_stack_top; X86_IST_STACK_COUNT];
+
+        x86_gdt_init(kernel_stack_top, &ist_stack_tops);
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("gdt & tss loaded");
+    }
+
+    //-----------------------------------------------------------------------------------
+    // external interrupts
+
+    // mask the legacy pics & bring up the local apic & i/o apic; both register
+    // windows are device memory, so they're (re)mapped uncached before we touch them.
+    // noapic on the command line leaves them (& with them external interrupts) be.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if params.noapic {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("noapic; external interrupts unavailable");
+    } else {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("setting up the apics");
+
+        #[cfg(target_arch = "x86_64")]
+        let mmio_flags = PAGING_PRESENT | PAGING_WRITEABLE | PAGING_CACHE_DISABLE | PAGING_WRITETHROUGH | PAGING_NX;
+        #[cfg(target_arch = "x86")]
+        let mmio_flags = PAGING_PRESENT | PAGING_WRITEABLE | PAGING_CACHE_DISABLE | PAGING_WRITETHROUGH;
+
+        // the madt says where the (first) i/o apic is & which gsis it handles;
+        // without one, take the usual spot
+        let (ioapic_base, ioapic_gsi_base) = acpi_madt()
+            .and_then(|madt| madt.io_apic(0))
+            .unwrap_or((PhysAddr(X86_IOAPIC_DEFAULT_BASE), 0));
+
+        for mmio_base in [x86_lapic_base(), ioapic_base] {
+            if !kernel_identity_map(mmio_base, MEMORY_DEFAULT_PAGE_USIZE, mmio_flags) {
+                panic!("failed to map apic registers @ 0x{:0x}", mmio_base);
+            }
+
+            x86_invalidate_page(mmio_base.as_usize());
+        }
+
+        if x86_apic_init(ioapic_base, ioapic_gsi_base) {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("apics up, legacy pics masked");
+        } else {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("no apic; external interrupts unavailable");
+        }
+    }
+
+    // the gic; the aarch64 side doesn't manage its own page tables yet, so the
+    // firmware's identity map of the device regions is what we're using
+    #[cfg(target_arch = "aarch64")]
+    {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("setting up the gic");
+
+        // the madt describes the gic; without one (or without acpi), assume
+        // qemu's virt machine. TODO: the device tree, for non-acpi boots
+        let gic_up = match acpi_madt().and_then(|madt| madt.gic_distributor().map(|(dist, _)| (madt, dist))) {
+            Some((madt, dist)) => aa64_gic_init(
+                dist.as_usize(),
+                madt.gic_cpu_interface().map_or(ZERO_USIZE, |base| base.as_usize()),
+                madt.gic_redistributor().map_or(ZERO_USIZE, |base| base.as_usize()),
+                madt.gic_msi_frame().map(|base| base.as_usize()),
+            ),
+            None => aa64_gic_init(
+                AA64_GIC_QEMU_VIRT_DIST_BASE,
+                AA64_GIC_QEMU_VIRT_CPU_BASE,
+                AA64_GIC_QEMU_VIRT_REDIST_BASE,
+                Some(AA64_GIC_QEMU_VIRT_V2M_BASE),
+            ),
+        };
+
+        if gic_up && aa64_timer_init(GenericTimer::Virtual) {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("gic & generic timer up");
+        } else {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("no usable gic; external interrupts unavailable");
+        }
+    }
+
+    //-----------------------------------------------------------------------------------
+
+    // timer
+
+    // bring up the hpet, if acpi knows of one; it's a better reference to
+    // calibrate against than the pit, and a clock of last resort
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if let Some(hpet_base) = acpi_hpet().and_then(|hpet| hpet.base()) {
+        #[cfg(target_arch = "x86_64")]
+        let mmio_flags = PAGING_PRESENT | PAGING_WRITEABLE | PAGING_CACHE_DISABLE | PAGING_WRITETHROUGH | PAGING_NX;
+        #[cfg(target_arch = "x86")]
+        let mmio_flags = PAGING_PRESENT | PAGING_WRITEABLE | PAGING_CACHE_DISABLE | PAGING_WRITETHROUGH;
+
+        if !kernel_identity_map(hpet_base, MEMORY_DEFAULT_PAGE_USIZE, mmio_flags) {
+            panic!("failed to map hpet registers @ 0x{:0x}", hpet_base);
+        }
+
+        x86_invalidate_page(hpet_base.as_usize());
+
+        _ = x86_hpet_init(hpet_base);
+    }
+
+    // work out how fast the tsc & the local apic timer run
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if x86_tsc_calibrate().is_none() {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("couldn't calibrate the tsc");
+    }
+
+    // start the tick; it doesn't fire until kernel_main() enables interrupts
+    let external_interrupts = iron().unwrap().status.lock_rw_spin().as_mut().unwrap().external_interrupts;
+
+    if external_interrupts != KernelServiceStatus::Uninit {
+        if timer_start_periodic(TIMER_DEFAULT_TICK_HZ) {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("timer ticking @ {} hz", timer_tick_hz());
+        } else {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("timer ticking at an uncalibrated rate");
+        }
+    }
+
+    // and the clock, which needs the above to pick a source
+    if clock_init() == ClockSource::None {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("no usable clock source; monotonic_ns() will read 0");
+    }
+
+    // and what time it is out in the world; the fadt says where the rtc keeps
+    // the century (0 if it doesn't)
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if let Some(fadt) = acpi_fadt() {
+        x86_rtc_set_century_register(fadt.century_reg);
+    }
+
+    if clock_realtime_init() == RealtimeSource::None {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("no wall-clock time available; realtime() will read None");
+    }
+
+    //-----------------------------------------------------------------------------------
+
     // fin.
 
-    // set up a new stack and jump to kernel_main()
     // one day Johnny, you'll go to -> kernel_main(); too
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        let kernel_stack_top = iron().unwrap().get_kernel_stack_top().unwrap();
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("switching to kernel stack @ 0x{:08x} and jumping to kernel_main()", kernel_stack_top);
+
+        // see you on the other side
+        x86_switch_stack_and_jump(kernel_stack_top.as_usize(), kernel_main);
+    }
 
     // halt
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("Fell through kernel_main(), halting back in kernel_init()");
+
+        // no kernel_main() on aarch64 yet, so take the tick from here
+        #[cfg(target_arch = "aarch64")]
+        if external_interrupts != KernelServiceStatus::Uninit {
+            crate::arch::aa64::asm::aarch_enable_interrupts();
+        }
+
+        wait_forever();
+    }
+}
+
+// stretches a single seed over the whole rng seed (splitmix64)
+fn kernel_expand_seed(seed: u64, rng_seed: &mut [u32]) {
+    let mut state = seed;
+
+    for pair in rng_seed.chunks_mut(2) {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        pair[0] = z as u32;
+
+        if pair.len() > 1 {
+            pair[1] = (z >> 32) as u32;
+        }
+    }
+}
+
+// memory that's only spoken for until the kernel stops leaning on the firmware
+// & the loader: uefi boot services code & data, and whatever the loader (or
+// the pre-init code) allocated, which includes the staged uefi memory map
+fn kernel_region_is_reclaimable(ty: MemRegionType) -> bool {
+    ty == MemRegionType::BootServices || ty == MemRegionType::Loader
+}
+
+// splits a region into the pieces before, inside & after the kernel image, as
+// (base, size, is_image); empty pieces are skipped
+fn kernel_reclaim_split(e: &MemRegion, image_base: PhysAddr, image_size: usize) -> impl Iterator<Item = (PhysAddr, usize, bool)> {
+    let start = e.base.as_usize();
+    let end = e.end();
+    let image_start = usize::min(usize::max(align_down(image_base.as_usize(), MEMORY_DEFAULT_PAGE_USIZE), start), end);
+    let image_end = usize::max(usize::min(align_up(image_base.as_usize() + image_size, MEMORY_DEFAULT_PAGE_USIZE), end), image_start);
+
+    [(start, image_start, false), (image_start, image_end, true), (image_end, end, false)]
+        .into_iter()
+        .filter(|(lo, hi, _)| lo < hi)
+        .map(|(lo, hi, is_image)| (PhysAddr(lo), hi - lo, is_image))
+}
+
+// where mem= cuts physical memory off, or usize::MAX if it doesn't
+fn kernel_mem_limit() -> usize {
+    kernel_params().mem_limit.map_or(usize::MAX, |limit| align_down(limit, MEMORY_DEFAULT_PAGE_USIZE))
+}
+
+// splits [base, base + size) into the pieces below & at or above limit, as
+// (base, size, is_below); empty pieces are skipped
+fn kernel_limit_split(base: PhysAddr, size: usize, limit: usize) -> impl Iterator<Item = (PhysAddr, usize, bool)> {
+    let start = base.as_usize();
+    let end = start + size;
+    let split = usize::min(usize::max(limit, start), end);
+
+    [(start, split, true), (split, end, false)]
+        .into_iter()
+        .filter(|(lo, hi, _)| lo < hi)
+        .map(|(lo, hi, is_below)| (PhysAddr(lo), hi - lo, is_below))
+}
+
+// hands the firmware's & the loader's memory (see kernel_region_is_reclaimable())
+// to the frame allocator, less the kernel image. only safe once nothing refers
+// to it anymore: boot services are gone, we're on our own page tables & our own
+// stack (the uefi stack is boot services data), and whatever we wanted out of
+// the loader's data (the command line) has been copied. acpi tables that were
+// sitting in it are reserved again, & anything past mem= stays put. returns the
+// number of pages reclaimed.
+pub fn kernel_reclaim_boot_memory() -> usize {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return ZERO_USIZE;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let boot_info = match boot_info() {
+        Some(boot_info) => boot_info,
+        None => return ZERO_USIZE,
+    };
+
+    let mut reclaimed_bytes = ZERO_USIZE;
+
+    {
+        let mut frame_alloc_lock = iron().unwrap().frame_alloc_internal_04.lock_rw_spin();
+        let frame_alloc = frame_alloc_lock.as_mut().unwrap().as_mut().unwrap();
+
+        for e in boot_info.regions().iter().filter(|e| kernel_region_is_reclaimable(e.ty)) {
+            for (base, size, is_image) in kernel_reclaim_split(e, boot_info.kernel_image_base, boot_info.kernel_image_size) {
+                if is_image {
+                    continue;
+                }
+
+                // kernel_init() split the pieces at the limit the same way
+                for (base, size, _) in kernel_limit_split(base, size, kernel_mem_limit()).filter(|&(_, _, is_below)| is_below) {
+                    if frame_alloc.dealloc_frame(base, Owner::Firmware) {
+                        reclaimed_bytes += size;
+                    } else {
+                        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                        serial_println!("kernel_reclaim_boot_memory() -> couldn't reclaim 0x{:08x}, size {}", base, size);
+                    }
+                }
+            }
+        }
+    }
+
+    // the command line pointed into what was just freed
+    boot_info_forget_cmdline();
+
+    // some firmware leaves acpi tables in boot services data
+    acpi_reserve_regions();
+
+    let reclaimed_pages = pages::bytes_to_pages(reclaimed_bytes, MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+    iron().unwrap().set_reclaimed_pages(reclaimed_pages);
+
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
-    serial_println!("Fell through kernel_main(), halting back in kernel_init()");
-    wait_forever();
-}
\ No newline at end of file
+    serial_println!("kernel_reclaim_boot_memory() -> reclaimed {} pages / {} KB", reclaimed_pages, reclaimed_bytes >> 10);
+
+    reclaimed_pages
+}
+
+// identity maps [base, base + size) into the kernel's base address space;
+// the vas lock is only held for the duration of the mapping
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn kernel_identity_map(base: PhysAddr, size: usize, flags: usize) -> bool {
+    let mut kernel_vas = iron().unwrap().base_vas_07.lock_rw_spin();
+
+    match kernel_vas.as_mut().unwrap().as_mut().unwrap().base_table_mut() {
+        Some(base_page_table) => base_page_table.identity_map_range(base, size, flags),
+        None => false,
+    }
+}
+
+// allocates, maps & zeroes a kernel stack of size bytes, with an unmapped guard
+// page right below it so that running off the end faults instead of silently
+// scribbling over whatever lives there; returns the base of the usable stack
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn kernel_alloc_stack(size: usize) -> PhysAddr {
+    // grab the frames for the stack & its guard page; the frame allocator lock
+    // has to be released before we map anything, since map_page() takes it too
+    let guard_base = {
+        iron().unwrap().frame_alloc_internal_04
+            .lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
+            .alloc_frame(size + MEMORY_DEFAULT_PAGE_USIZE, MEMORY_DEFAULT_PAGE_SIZE_ENUM, Owner::Kernel)
+            .unwrap_or_else(|| {
+                panic!("failed to allocate kernel stack of size {}", size);
+            })
+    };
+
+    let stack_base = PhysAddr(guard_base.as_usize() + MEMORY_DEFAULT_PAGE_USIZE);
+
+    // the stack is data, never code
+    #[cfg(target_arch = "x86_64")]
+    let stack_flags = PAGING_PRESENT | PAGING_WRITEABLE | PAGING_NX;
+    #[cfg(target_arch = "x86")]
+    let stack_flags = PAGING_PRESENT | PAGING_WRITEABLE;
+
+    // identity map the stack into the kernel's address space
+    if !kernel_identity_map(stack_base, size, stack_flags) {
+        panic!("failed to map kernel stack @ 0x{:08x}", stack_base);
+    }
+
+    // the guard page keeps its frame but loses its present bit
+    if !kernel_identity_map(guard_base, MEMORY_DEFAULT_PAGE_USIZE, ZERO_USIZE) {
+        panic!("failed to map kernel stack guard page @ 0x{:08x}", guard_base);
+    }
+
+    // start from a clean slate
+    raw::memset_aligned(stack_base, size, ZERO_USIZE);
+
+    stack_base
+}
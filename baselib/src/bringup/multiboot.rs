@@ -0,0 +1,371 @@
+// Rust
+use core::sync::atomic::{AtomicBool, Ordering};
+// Internal
+use crate::common::base::*;
+use crate::bringup::bootinfo::*;
+use crate::bringup::image::PeImage;
+
+// Constants
+
+// where multiboot2 loaders put the kernel image (see iron/build.rs, which
+// links the image here, & iron/src/multiboot.rs); x86_64 only
+pub const MULTIBOOT_LOAD_BASE: usize = USIZE_2M;
+// what the loader sets aside past the end of the image proper for .reloc
+pub const MULTIBOOT_RELOC_RESERVE: usize = USIZE_256K;
+
+// what a multiboot2 loader leaves in eax
+pub const MULTIBOOT2_BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+
+// boot information tag types
+const MULTIBOOT2_TAG_END: u32 = 0;
+const MULTIBOOT2_TAG_CMDLINE: u32 = 1;
+const MULTIBOOT2_TAG_MMAP: u32 = 6;
+const MULTIBOOT2_TAG_FRAMEBUFFER: u32 = 8;
+const MULTIBOOT2_TAG_ACPI_OLD: u32 = 14;
+const MULTIBOOT2_TAG_ACPI_NEW: u32 = 15;
+
+// the fixed part of the info struct (total size, reserved) & of each tag (type, size)
+const MULTIBOOT2_INFO_HEADER_SIZE: usize = 8;
+const MULTIBOOT2_TAG_HEADER_SIZE: usize = 8;
+const MULTIBOOT2_TAG_ALIGN: usize = 8;
+// the mmap tag adds entry size & entry version
+const MULTIBOOT2_MMAP_HEADER_SIZE: usize = 16;
+
+// memory map entry types
+const MULTIBOOT2_MEMORY_AVAILABLE: u32 = 1;
+const MULTIBOOT2_MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+const MULTIBOOT2_MEMORY_NVS: u32 = 4;
+const MULTIBOOT2_MEMORY_BADRAM: u32 = 5;
+
+// framebuffer types; direct rgb framebuffers describe their channels
+const MULTIBOOT2_FRAMEBUFFER_TYPE_RGB: u8 = 1;
+const MULTIBOOT2_FRAMEBUFFER_RED_POSITION: usize = 32;
+const MULTIBOOT2_FRAMEBUFFER_BLUE_POSITION: usize = 36;
+
+// we keep the scratch pages out of low memory
+const MULTIBOOT_SCRATCH_MIN_ADDR: usize = USIZE_1M;
+
+// Etc. ->
+
+// Multiboot2 loaders give us a memory map, a command line, maybe a framebuffer
+// & a copy of the acpi rsdp, in a list of tags. We turn them into a BootInfo
+// (carving our own image & the tags out of the available memory) & call
+// kernel_init().
+
+fn read_u8(addr: usize) -> u8 {
+    unsafe { core::ptr::read_unaligned(addr as *const u8) }
+}
+
+fn read_u32(addr: usize) -> u32 {
+    unsafe { core::ptr::read_unaligned(addr as *const u32) }
+}
+
+fn read_u64(addr: usize) -> u64 {
+    unsafe { core::ptr::read_unaligned(addr as *const u64) }
+}
+
+// the boot information tags, as (type, address, size); the end tag (or a
+// tag running off the end) ends the walk
+fn multiboot_tags(info: usize) -> impl Iterator<Item = (u32, usize, usize)> {
+    let end = info + read_u32(info) as usize;
+    let mut tag = info + MULTIBOOT2_INFO_HEADER_SIZE;
+
+    core::iter::from_fn(move || {
+        if tag + MULTIBOOT2_TAG_HEADER_SIZE > end {
+            return None;
+        }
+
+        let ty = read_u32(tag);
+        let size = read_u32(tag + 4) as usize;
+
+        if ty == MULTIBOOT2_TAG_END || size < MULTIBOOT2_TAG_HEADER_SIZE || tag + size > end {
+            return None;
+        }
+
+        let this = (ty, tag, size);
+        tag = align_up(tag + size, MULTIBOOT2_TAG_ALIGN);
+
+        Some(this)
+    })
+}
+
+// the memory map tag's entries, as (base, length, type)
+fn multiboot_mmap_entries(tag: usize, tag_size: usize) -> impl Iterator<Item = (usize, usize, u32)> {
+    let entry_size = read_u32(tag + 8) as usize;
+    let end = tag + tag_size;
+    let mut entry = tag + MULTIBOOT2_MMAP_HEADER_SIZE;
+
+    core::iter::from_fn(move || {
+        if entry_size < 24 || entry + entry_size > end {
+            return None;
+        }
+
+        let this = (read_u64(entry) as usize, read_u64(entry + 8) as usize, read_u32(entry + 16));
+        entry += entry_size;
+
+        Some(this)
+    })
+}
+
+fn multiboot_region_type(ty: u32) -> MemRegionType {
+    match ty {
+        MULTIBOOT2_MEMORY_AVAILABLE => MemRegionType::Usable,
+        MULTIBOOT2_MEMORY_ACPI_RECLAIMABLE => MemRegionType::AcpiReclaimable,
+        MULTIBOOT2_MEMORY_NVS => MemRegionType::AcpiNvs,
+        MULTIBOOT2_MEMORY_BADRAM => MemRegionType::Unusable,
+        _ => MemRegionType::Reserved,
+    }
+}
+
+// moves addr past any of the holes [start, end) that [addr, addr + size) runs into
+fn multiboot_skip_holes(mut addr: usize, size: usize, holes: &[(usize, usize, MemRegionType)]) -> usize {
+    loop {
+        let mut moved = false;
+
+        for &(start, end, _) in holes {
+            if addr < end && addr + size > start {
+                addr = end;
+                moved = true;
+            }
+        }
+
+        if !moved {
+            return addr;
+        }
+    }
+}
+
+// appends [start, end) to the boot info's memory map
+fn multiboot_push_region(boot_info: &mut BootInfo, start: usize, end: usize, ty: MemRegionType) {
+    if end > start {
+        boot_info.push_region(PhysAddr(start), pages::bytes_to_pages(end - start, MEMORY_DEFAULT_PAGE_SIZE_ENUM), ty);
+    }
+}
+
+// multiboot_pre_init() is called from multiboot_start() in iron/src/main.rs,
+// by way of the trampoline in iron/src/multiboot.rs.
+pub fn multiboot_pre_init(magic: u32, info: usize) {
+
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    //-----------------------------------------------------------------------------------
+
+    // multiboot pre-init begin
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("nebulae::multiboot_pre_init() -> beginning prep for kernel_init()");
+
+    if magic != MULTIBOOT2_BOOTLOADER_MAGIC {
+        panic!("nebulae::multiboot_pre_init() -> bad multiboot2 magic: 0x{:08x}", magic);
+    }
+
+    if info == ZERO_USIZE {
+        panic!("nebulae::multiboot_pre_init() -> no multiboot2 boot information");
+    }
+
+    //-----------------------------------------------------------------------------------
+
+    // tags
+
+    let mut mmap_tag: Option<(usize, usize)> = None;
+    let mut cmdline: Option<&'static str> = None;
+    let mut framebuffer: Option<BootFramebuffer> = None;
+    let mut rsdp: Option<PhysAddr> = None;
+
+    for (ty, tag, size) in multiboot_tags(info) {
+        match ty {
+            MULTIBOOT2_TAG_CMDLINE => {
+                let bytes = unsafe { core::slice::from_raw_parts((tag + MULTIBOOT2_TAG_HEADER_SIZE) as *const u8, size - MULTIBOOT2_TAG_HEADER_SIZE) };
+                let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+                cmdline = core::str::from_utf8(&bytes[..len]).ok();
+            },
+            MULTIBOOT2_TAG_MMAP => {
+                mmap_tag = Some((tag, size));
+            },
+            MULTIBOOT2_TAG_FRAMEBUFFER => {
+                let bpp = read_u8(tag + 28);
+                let height = read_u32(tag + 24);
+                let pitch = read_u32(tag + 16);
+
+                // 32 bit direct color with the channels where uefi's would be
+                let layout = match (read_u8(tag + 29), bpp, read_u8(tag + MULTIBOOT2_FRAMEBUFFER_RED_POSITION), read_u8(tag + MULTIBOOT2_FRAMEBUFFER_BLUE_POSITION)) {
+                    (MULTIBOOT2_FRAMEBUFFER_TYPE_RGB, 32, 0, 16) => PixelLayout::Rgb,
+                    (MULTIBOOT2_FRAMEBUFFER_TYPE_RGB, 32, 16, 0) => PixelLayout::Bgr,
+                    _ => PixelLayout::Other,
+                };
+
+                framebuffer = Some(BootFramebuffer {
+                    address: PhysAddr(read_u64(tag + 8) as usize),
+                    size: pitch as usize * height as usize,
+                    width: read_u32(tag + 20),
+                    height,
+                    pitch,
+                    bpp,
+                    layout,
+                });
+            },
+            // the tags carry a copy of the rsdp itself, which is as good as
+            // the original (it points at the same rsdt / xsdt); prefer the
+            // acpi 2.0+ copy, which gets us the xsdt
+            MULTIBOOT2_TAG_ACPI_NEW => {
+                rsdp = Some(PhysAddr(tag + MULTIBOOT2_TAG_HEADER_SIZE));
+            },
+            MULTIBOOT2_TAG_ACPI_OLD => {
+                rsdp = rsdp.or(Some(PhysAddr(tag + MULTIBOOT2_TAG_HEADER_SIZE)));
+            },
+            _ => {},
+        }
+    }
+
+    let (mmap_tag, mmap_tag_size) = mmap_tag.unwrap_or_else(|| {
+        panic!("nebulae::multiboot_pre_init() -> no memory map from the bootloader");
+    });
+
+    //-----------------------------------------------------------------------------------
+
+    // kernel image
+
+    let kernel_image_base = PhysAddr(MULTIBOOT_LOAD_BASE);
+    let kernel_image_size = PeImage::size_of_image(kernel_image_base).unwrap_or_else(|| {
+        panic!("nebulae::multiboot_pre_init() -> no pe image @ 0x{:08x}", kernel_image_base.as_usize());
+    });
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("kernel image loaded @ 0x{:08x}, size {}", kernel_image_base, kernel_image_size);
+
+    //-----------------------------------------------------------------------------------
+
+    // memory map
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("nebulae::multiboot_pre_init() -> parsing memory map");
+
+    // the loader reports the memory our image & the boot information occupy
+    // as available; they have to come out of it (& be kept). the boot
+    // information holds the command line & the rsdp copy.
+    let mut holes = [
+        (
+            MULTIBOOT_LOAD_BASE,
+            align_up(MULTIBOOT_LOAD_BASE + kernel_image_size, MEMORY_DEFAULT_PAGE_USIZE),
+            MemRegionType::KernelImage,
+        ),
+        (
+            align_down(info, MEMORY_DEFAULT_PAGE_USIZE),
+            align_up(info + read_u32(info) as usize, MEMORY_DEFAULT_PAGE_USIZE),
+            MemRegionType::Loader,
+        ),
+    ];
+    holes.sort_unstable_by_key(|h| h.0);
+
+    // the staged map needs room for every entry, plus two more per hole
+    // (splitting an available entry around a hole leaves up to three)
+    let region_count = multiboot_mmap_entries(mmap_tag, mmap_tag_size).count() + 2 * holes.len();
+
+    // the scratch pages, as in uefi_pre_init(): the first spot in available
+    // memory big enough for them
+    let scratch_size = pages::pages_to_bytes(BootInfo::scratch_pages_for(region_count), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+    let mut scratch_base_addr: PhysAddr = PhysAddr(NEBULAE_TEST_PATTERN);
+
+    for (base, length, ty) in multiboot_mmap_entries(mmap_tag, mmap_tag_size) {
+        if ty != MULTIBOOT2_MEMORY_AVAILABLE {
+            continue;
+        }
+
+        let start = usize::max(align_up(base, MEMORY_DEFAULT_PAGE_USIZE), MULTIBOOT_SCRATCH_MIN_ADDR);
+        let end = align_down(base + length, MEMORY_DEFAULT_PAGE_USIZE);
+        let candidate = multiboot_skip_holes(start, scratch_size, &holes);
+
+        if candidate + scratch_size <= end {
+            scratch_base_addr = PhysAddr(candidate);
+            break;
+        }
+    }
+
+    if scratch_base_addr.as_usize() == NEBULAE_TEST_PATTERN {
+        panic!("nebulae::multiboot_pre_init() -> failed to locate suitable block for initial scratch");
+    }
+
+    let mut boot_info = BootInfo::new(scratch_base_addr, region_count);
+
+    // convert: available memory shrinks to whole pages (less the holes), anything
+    // else grows to them
+    for (base, length, ty) in multiboot_mmap_entries(mmap_tag, mmap_tag_size) {
+        if ty != MULTIBOOT2_MEMORY_AVAILABLE {
+            multiboot_push_region(
+                &mut boot_info,
+                align_down(base, MEMORY_DEFAULT_PAGE_USIZE),
+                align_up(base + length, MEMORY_DEFAULT_PAGE_USIZE),
+                multiboot_region_type(ty));
+            continue;
+        }
+
+        let start = align_up(base, MEMORY_DEFAULT_PAGE_USIZE);
+        let end = align_down(base + length, MEMORY_DEFAULT_PAGE_USIZE);
+        let mut cursor = start;
+
+        for &(hole_start, hole_end, hole_ty) in holes.iter() {
+            let hole_start = usize::max(hole_start, cursor);
+            let hole_end = usize::min(hole_end, end);
+
+            if hole_start >= hole_end {
+                continue;
+            }
+
+            multiboot_push_region(&mut boot_info, cursor, hole_start, MemRegionType::Usable);
+            multiboot_push_region(&mut boot_info, hole_start, hole_end, hole_ty);
+            cursor = hole_end;
+        }
+
+        multiboot_push_region(&mut boot_info, cursor, end, MemRegionType::Usable);
+    }
+
+    // the loader's map needn't be sorted
+    boot_info.sort_regions();
+
+    // output the map entries in debug mode
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    for r in boot_info.regions() {
+        serial_println!("ty: {:?} ps: 0x{:08x} pc: {}", r.ty, r.base, r.page_count);
+    }
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("physical address boundary: 0x{:0x}", boot_info.phys_boundary());
+
+    //-----------------------------------------------------------------------------------
+
+    // the rest
+
+    boot_info.kernel_image_base = kernel_image_base;
+    boot_info.kernel_image_size = kernel_image_size;
+    boot_info.cmdline = cmdline;
+    boot_info.framebuffer = framebuffer;
+    boot_info.rsdp = rsdp;
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    {
+        if let Some(cmdline) = cmdline {
+            serial_println!("command line: \"{}\"", cmdline);
+        }
+
+        if let Some(fb) = framebuffer {
+            serial_println!("framebuffer @ 0x{:08x}: {}x{}x{}", fb.address, fb.width, fb.height, fb.bpp);
+        }
+
+        match rsdp {
+            Some(rsdp) => serial_println!("acpi rsdp (copy) @ 0x{:08x}", rsdp),
+            None => serial_println!("no acpi rsdp from the bootloader"),
+        }
+    }
+
+    kernel_init(&boot_info);
+}
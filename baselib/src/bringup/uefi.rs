@@ -4,11 +4,32 @@ use core::fmt::Write;
 // External
 use ::uefi::prelude::*;
 use ::uefi::table::boot::*;
+use ::uefi::proto::loaded_image::LoadedImage;
+use ::uefi::proto::console::gop::{GraphicsOutput, PixelFormat};
+use ::uefi::proto::rng::Rng;
+use ::uefi::table::Runtime;
+use ::uefi::table::cfg::{ACPI_GUID, ACPI2_GUID};
+use ::uefi::table::runtime::{Daylight, ResetType, RuntimeServices, Time, TimeParams};
+use ::uefi::{guid, Guid};
+use spin::Mutex;
 // Internal
 use crate::common::base::*;
+use crate::clock::{DateTime, NANOSECONDS_PER_SECOND};
+use crate::bringup::bootinfo::*;
 
 // Constants
-pub const PREBOOT_SCRATCH_PAGE_COUNT: usize = 3;
+// the configuration table entry for a flattened device tree (not in the uefi crate)
+const UEFI_DEVICE_TREE_GUID: Guid = guid!("b1b621d5-f19c-41a5-830b-d9152c69aae0");
+
+// gop framebuffers in the rgb / bgr formats are always 32 bits per pixel
+const UEFI_GOP_BYTES_PER_PIXEL: usize = 4;
+
+// the longest command line we'll take from the load options
+const UEFI_CMDLINE_MAX_BYTES: usize = 1024;
+
+// the command line, as ascii; the load options themselves are boot services
+// memory (& ucs-2), so they don't outlive exit_boot_services()
+static mut UEFI_CMDLINE: [u8; UEFI_CMDLINE_MAX_BYTES] = [0; UEFI_CMDLINE_MAX_BYTES];
 
 // Etc. ->
 
@@ -56,9 +77,6 @@ pub fn uefi_pre_init() {
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
     serial_println!("nebulae::uefi_pre_init() -> parsing memory map");
 
-    let mut conv_page_count: usize = ZERO_USIZE;
-    let mut phys_boundary: usize = ZERO_USIZE;
-
     // memory map var
     let mm: MemoryMap;
     
@@ -73,8 +91,9 @@ pub fn uefi_pre_init() {
     // we are going to do some allocations; uefi seems to report a smaller
     // map size than exit_boot_services() ultimately needs, so we are going
     // to allocate an extra page if there's not enough space for at least 
-    // 5 additional entries in the map
-    if pages::pages_to_bytes(mm_size_in_pages, MEMORY_DEFAULT_PAGE_SIZE_ENUM) - mm_size_struct.map_size < 5 * core::mem::size_of::<MemoryDescriptor>() {
+    // 5 additional entries in the map (descriptors are entry_size apart,
+    // which can be more than the size of the struct)
+    if pages::pages_to_bytes(mm_size_in_pages, MEMORY_DEFAULT_PAGE_SIZE_ENUM) - mm_size_struct.map_size < 5 * mm_size_struct.entry_size {
         mm_size_in_pages += 1;
     }
     
@@ -111,9 +130,18 @@ pub fn uefi_pre_init() {
         mm = uefi_result.unwrap();
     }
 
-    // we are going to comandeer the first 3 physical pages of the first 
-    // conventional frame large enough to hold them. we will use these pages
-    // as scratch space during the rest of the boot process
+    // the staged copy of the map needs room for every entry (merging only
+    // ever shrinks it); big machines easily have more than fit in a page
+    let mm_entry_count = mm.entries().len();
+    let scratch_page_count = BootInfo::scratch_pages_for(mm_entry_count);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("{} memory map entries -> {} scratch page(s)", mm_entry_count, scratch_page_count);
+
+    // we are going to comandeer the first physical pages of the first 
+    // conventional frame large enough to hold them (3 on most systems).
+    // we will use these pages as scratch space during the rest of the
+    // boot process
     let mut scratch_base_addr: PhysAddr = PhysAddr(NEBULAE_TEST_PATTERN);
 
     // we have the memory map, so first find some space for our scratch pages
@@ -121,7 +149,7 @@ pub fn uefi_pre_init() {
         
         // see if this block is suitable for our scratch pages
         if e.ty == MemoryType::CONVENTIONAL && 
-           e.page_count >= PREBOOT_SCRATCH_PAGE_COUNT as u64 {
+           e.page_count >= scratch_page_count as u64 {
 
             scratch_base_addr = PhysAddr(e.phys_start.as_usize());
             break;
@@ -130,18 +158,13 @@ pub fn uefi_pre_init() {
 
     // make sure we found a suitable block for our scratch pages
     // if not, panic -> it's only 12k worth of memory on most systems.
-    if scratch_base_addr.is_null() {
-        panic!("nebulae::uefi_pre_init() -> failed to locate suitable block for initial scratch");
+    if scratch_base_addr.as_usize() == NEBULAE_TEST_PATTERN {
+        panic!(
+            "nebulae::uefi_pre_init() -> failed to locate suitable block for initial scratch ({} pages for {} memory map entries)",
+            scratch_page_count,
+            mm_entry_count);
     }
 
-    // zero the scratch pages
-    raw::memset_aligned(
-        scratch_base_addr, 
-        pages::pages_to_bytes(
-            PREBOOT_SCRATCH_PAGE_COUNT, 
-            MEMORY_DEFAULT_PAGE_SIZE_ENUM), 
-        BytePattern::ZeroZero.as_usize_pattern());
-
     // the first page of scratch is where we will store info on the memory
     // frames we allocate during bringup; it's nothing fancy, we're
     // just recording the base address and size of the allocated frames
@@ -149,35 +172,14 @@ pub fn uefi_pre_init() {
 
     // the second page of scratch will be used for the rng
 
-    // the third page of scratch will be used as a temporary memory map
-
-    let allocated_frame_array: &mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES] = unsafe { core::mem::transmute::<PhysAddr, &mut [MemBlock<PhysAddr>; MAX_PREBOOT_PAGES]>(scratch_base_addr) };
-    
-    // mark the scratch pages as allocated by storing them in the
-    // array located on the first scratch page 
-    // (max preboot pages: 512 pages 32-bit, 256 pages 64-bit)
-    allocated_frame_array[0].base_addr = scratch_base_addr;
-    allocated_frame_array[0].size = MEMORY_DEFAULT_PAGE_USIZE;
-    allocated_frame_array[1].base_addr = scratch_base_addr.clone().inner_inc_by_page_size(MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-    allocated_frame_array[1].size = MEMORY_DEFAULT_PAGE_USIZE;
-    allocated_frame_array[2].base_addr = allocated_frame_array[1].base_addr.clone().inner_inc_by_page_size(MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-    allocated_frame_array[2].size = MEMORY_DEFAULT_PAGE_USIZE;
-    
-    // obtain a mutable reference to the 3rd scratch page
-    // as an array of MemoryDescriptor structs
-    let mm_scratch: &mut [MemoryDescriptor; MEMORY_DEFAULT_PAGE_USIZE / core::mem::size_of::<MemoryDescriptor>()]
-            = unsafe { core::mem::transmute::<PhysAddr, &mut [MemoryDescriptor; MEMORY_DEFAULT_PAGE_USIZE / core::mem::size_of::<MemoryDescriptor>()]>(allocated_frame_array[2].base_addr) };
+    // the third page of scratch on will be used as a temporary memory map
 
-    // go back through the memory map to get some stats
-    // -> print the map entries to the serial console
-    // in debug mode
-    let mut i = ZERO_USIZE;
+    // (BootInfo::new() zeroes the scratch pages & marks them allocated)
+    let mut boot_info = BootInfo::new(scratch_base_addr, mm_entry_count);
 
+    // go back through the memory map, translating it into the kernel's
+    // terms -> print the map entries to the serial console in debug mode
     for e in mm.entries() {
-        
-        // write the descriptor to our scratch page
-        mm_scratch[i] = e.clone();
-        i += 1;
 
         // output the map entries in debug mode
         #[cfg(all(debug_assertions, feature = "serialdbg"))]
@@ -189,17 +191,185 @@ pub fn uefi_pre_init() {
             e.att
         );
 
-        // we are only interested in conventional memory for stats
-        if e.ty == MemoryType::CONVENTIONAL {
-            phys_boundary = e.phys_start as usize + pages::pages_to_bytes(e.page_count as usize, MEMORY_DEFAULT_PAGE_SIZE_ENUM);
-            conv_page_count += e.page_count.as_usize();        
+        boot_info.push_region(PhysAddr(e.phys_start.as_usize()), e.page_count.as_usize(), uefi_region_type(e.ty));
+    }
+
+    // the firmware's map is usually, but not necessarily, sorted
+    boot_info.sort_regions();
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("{} memory map entries staged as {} regions", mm_entry_count, boot_info.regions().len());
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("physical address boundary: 0x{:0x}", boot_info.phys_boundary());
+
+    //-----------------------------------------------------------------------------------
+
+    // kernel image
+
+    // find out where the firmware loaded us, so kernel_init() can map
+    // our own sections properly; the protocol is scoped so that it's
+    // closed again before we exit boot services
+    {
+        let loaded_image = st
+            .boot_services()
+            .open_protocol_exclusive::<LoadedImage>(st.boot_services().image_handle())
+            .unwrap_or_else(|_| {
+                panic!("nebulae::uefi_pre_init() -> failed to open loaded image protocol");
+            });
+
+        let (image_base, image_size) = loaded_image.info();
+        boot_info.kernel_image_base = PhysAddr(image_base as usize);
+        boot_info.kernel_image_size = image_size as usize;
+
+        // & what we were started with: the shell passes its command line,
+        // a boot option its optional data
+        boot_info.cmdline = loaded_image.load_options_as_bytes().and_then(uefi_load_options_to_cmdline);
+    }
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("kernel image loaded @ 0x{:08x}, size {}", boot_info.kernel_image_base, boot_info.kernel_image_size);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    if let Some(cmdline) = boot_info.cmdline {
+        serial_println!("command line: \"{}\"", cmdline);
+    }
+
+    //-----------------------------------------------------------------------------------
+
+    // acpi & device tree
+
+    // the configuration table (& with it the rsdp) is only ours to read while
+    // boot services are up; prefer the acpi 2.0+ entry, which gets us the xsdt
+    boot_info.rsdp = st.config_table().iter()
+        .find(|e| e.guid == ACPI2_GUID)
+        .or_else(|| st.config_table().iter().find(|e| e.guid == ACPI_GUID))
+        .map(|e| PhysAddr(e.address as usize));
+
+    boot_info.dtb = st.config_table().iter()
+        .find(|e| e.guid == UEFI_DEVICE_TREE_GUID)
+        .map(|e| PhysAddr(e.address as usize));
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    match boot_info.rsdp {
+        Some(rsdp) => serial_println!("acpi rsdp @ 0x{:08x}", rsdp),
+        None => serial_println!("no acpi rsdp in the uefi configuration table"),
+    }
+
+    //-----------------------------------------------------------------------------------
+
+    // framebuffer
+
+    // the gop's current mode, if there's a gop & it has a linear framebuffer
+    if let Ok(gop_handle) = st.boot_services().get_handle_for_protocol::<GraphicsOutput>() {
+        if let Ok(mut gop) = st.boot_services().open_protocol_exclusive::<GraphicsOutput>(gop_handle) {
+            let mode_info = gop.current_mode_info();
+
+            let layout = match mode_info.pixel_format() {
+                PixelFormat::Rgb => Some(PixelLayout::Rgb),
+                PixelFormat::Bgr => Some(PixelLayout::Bgr),
+                PixelFormat::Bitmask => Some(PixelLayout::Other),
+                PixelFormat::BltOnly => None,
+            };
+
+            if let Some(layout) = layout {
+                let (width, height) = mode_info.resolution();
+                let mut fb = gop.frame_buffer();
+
+                boot_info.framebuffer = Some(BootFramebuffer {
+                    address: PhysAddr(fb.as_mut_ptr() as usize),
+                    size: fb.size(),
+                    width: width as u32,
+                    height: height as u32,
+                    pitch: (mode_info.stride() * UEFI_GOP_BYTES_PER_PIXEL) as u32,
+                    bpp: (UEFI_GOP_BYTES_PER_PIXEL * 8) as u8,
+                    layout,
+                });
+            }
         }
     }
 
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
-    serial_println!("physical address boundary: 0x{:0x}", phys_boundary);
+    if let Some(fb) = boot_info.framebuffer {
+        serial_println!("framebuffer @ 0x{:08x}: {}x{}x{}", fb.address, fb.width, fb.height, fb.bpp);
+    }
+
+    //-----------------------------------------------------------------------------------
+
+    // entropy
+
+    // the firmware's rng, if it has one; kernel_init() mixes it into the seed
+    if let Ok(rng_handle) = st.boot_services().get_handle_for_protocol::<Rng>() {
+        if let Ok(mut rng) = st.boot_services().open_protocol_exclusive::<Rng>(rng_handle) {
+            let mut entropy = [ZERO_U8; BOOT_INFO_ENTROPY_BYTES];
 
-    kernel_init(conv_page_count, phys_boundary.as_phys(), scratch_base_addr, i);
+            if rng.get_rng(None, &mut entropy).is_ok() {
+                boot_info.entropy = Some(entropy);
+            }
+        }
+    }
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("firmware entropy: {}", if boot_info.entropy.is_some() { "yes" } else { "no" });
+
+    kernel_init(&boot_info);
+}
+
+// copies ucs-2 load options into UEFI_CMDLINE, less the image's own path if the
+// shell put it up front. load options that aren't printable text (a boot
+// option's binary optional data) aren't a command line.
+fn uefi_load_options_to_cmdline(load_options: &[u8]) -> Option<&'static str> {
+    let cmdline_buf = unsafe { &mut *core::ptr::addr_of_mut!(UEFI_CMDLINE) };
+    let mut len = ZERO_USIZE;
+
+    for unit in load_options.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])) {
+        let c = match unit {
+            0 => break,
+            0x20..=0x7E => unit as u8,
+            0x09 | 0x0A | 0x0D => b' ',
+            _ => return None,
+        };
+
+        if len == UEFI_CMDLINE_MAX_BYTES {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("uefi: command line truncated to {} bytes", UEFI_CMDLINE_MAX_BYTES);
+            break;
+        }
+
+        cmdline_buf[len] = c;
+        len += 1;
+    }
+
+    // ascii, so this can't fail
+    let cmdline = core::str::from_utf8(&cmdline_buf[..len]).ok()?.trim();
+
+    let is_image_path = |s: &str| s.len() >= 4 && s.as_bytes()[s.len() - 4..].eq_ignore_ascii_case(b".efi");
+
+    let cmdline = match cmdline.split_once(' ') {
+        Some((first, rest)) if is_image_path(first) => rest.trim_start(),
+        None if is_image_path(cmdline) => "",
+        _ => cmdline,
+    };
+
+    if cmdline.is_empty() { None } else { Some(cmdline) }
+}
+
+// the kernel's name for a uefi memory type
+fn uefi_region_type(ty: MemoryType) -> MemRegionType {
+    match ty {
+        MemoryType::CONVENTIONAL => MemRegionType::Usable,
+        MemoryType::LOADER_CODE | MemoryType::LOADER_DATA => MemRegionType::Loader,
+        MemoryType::BOOT_SERVICES_CODE | MemoryType::BOOT_SERVICES_DATA => MemRegionType::BootServices,
+        MemoryType::RUNTIME_SERVICES_CODE => MemRegionType::FirmwareCode,
+        MemoryType::RUNTIME_SERVICES_DATA => MemRegionType::FirmwareData,
+        MemoryType::ACPI_RECLAIM => MemRegionType::AcpiReclaimable,
+        MemoryType::ACPI_NON_VOLATILE => MemRegionType::AcpiNvs,
+        MemoryType::UNUSABLE => MemRegionType::Unusable,
+        MemoryType::MMIO | MemoryType::MMIO_PORT_SPACE => MemRegionType::Mmio,
+        // our own allocation (the memory map buffer) is a loader allocation
+        t if t == MemoryType::custom(MEMORY_TYPE_UEFI_MEM_MAP) => MemRegionType::Loader,
+        _ => MemRegionType::Reserved,
+    }
 }
 
 pub fn uefi_exit_boot_services() {
@@ -217,7 +387,7 @@ pub fn uefi_exit_boot_services() {
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
     serial_println!("terminating uefi boot services");
 
-    let (_, _mm) = 
+    let (rt_table, _mm) = 
         unsafe { 
             uefi_system_table(
                 None, 
@@ -228,10 +398,119 @@ pub fn uefi_exit_boot_services() {
             MemoryType::custom(MEMORY_TYPE_UEFI_MEM_MAP)
         );
 
+    // hang on to the runtime half of the system table; the firmware's
+    // clock (& later on, variables & reset) live there
+    unsafe {
+        UEFI_RUNTIME_TABLE = Some(rt_table);
+    }
+
     #[cfg(all(debug_assertions, feature = "serialdbg"))]
     serial_println!("uefi boot services terminated");
 }
 
+//-----------------------------------------------------------------------------------
+
+// uefi runtime services
+
+// the system table as it stands after exit_boot_services(); we never call
+// SetVirtualAddressMap(), so runtime services keep running in physical mode
+// through the identity map (which covers the runtime code & data regions)
+static mut UEFI_RUNTIME_TABLE: Option<SystemTable<Runtime>> = None;
+
+// runtime services aren't reentrant; one caller at a time
+static UEFI_RUNTIME_LOCK: Mutex<()> = Mutex::new(());
+
+// returns the runtime system table, if we booted via uefi & boot
+// services have been exited
+pub fn uefi_runtime_table() -> Option<&'static SystemTable<Runtime>> {
+    unsafe { (*core::ptr::addr_of!(UEFI_RUNTIME_TABLE)).as_ref() }
+}
+
+// the firmware's idea of the current time, in utc. uefi times carry their
+// offset from utc in minutes (local = utc + time_zone); an unspecified zone
+// is taken to be utc already.
+pub fn uefi_get_time() -> Option<DateTime> {
+    let rt_table = uefi_runtime_table()?;
+
+    let time = {
+        let _guard = UEFI_RUNTIME_LOCK.lock();
+        unsafe { rt_table.runtime_services() }.get_time().ok()?
+    };
+
+    if !time.is_valid() {
+        return None;
+    }
+
+    let local = DateTime {
+        year: time.year(),
+        month: time.month(),
+        day: time.day(),
+        hour: time.hour(),
+        minute: time.minute(),
+        second: time.second(),
+        nanosecond: time.nanosecond(),
+    };
+
+    match time.time_zone() {
+        Some(tz) => {
+            let utc_ns = local.to_unix_ns()? as i128 - tz as i128 * 60 * NANOSECONDS_PER_SECOND as i128;
+
+            if utc_ns < 0 {
+                return None;
+            }
+
+            Some(DateTime::from_unix_ns(utc_ns as u64))
+        },
+        None => Some(local),
+    }
+}
+
+// sets the firmware's clock to dt (utc); returns false if there's no
+// firmware clock or it refused
+pub fn uefi_set_time(dt: &DateTime) -> bool {
+    let rt_table = match uefi_runtime_table() {
+        Some(rt_table) => rt_table,
+        None => return false,
+    };
+
+    let time = Time::new(TimeParams {
+        year: dt.year,
+        month: dt.month,
+        day: dt.day,
+        hour: dt.hour,
+        minute: dt.minute,
+        second: dt.second,
+        nanosecond: dt.nanosecond,
+        time_zone: Some(0),
+        daylight: Daylight::empty(),
+    });
+
+    let time = match time {
+        Ok(time) => time,
+        Err(_) => return false,
+    };
+
+    let _guard = UEFI_RUNTIME_LOCK.lock();
+
+    // set_time() wants &mut, though nothing of ours is written; the lock
+    // above is the synchronization it's asking for
+    let rt = unsafe { rt_table.runtime_services() } as *const RuntimeServices as *mut RuntimeServices;
+    unsafe { (*rt).set_time(&time) }.is_ok()
+}
+
+// asks the firmware to reset or power off the machine; only returns if
+// there's no firmware to ask
+pub fn uefi_reset(reset_type: ResetType) {
+    let rt_table = match uefi_runtime_table() {
+        Some(rt_table) => rt_table,
+        None => return,
+    };
+
+    let _guard = UEFI_RUNTIME_LOCK.lock();
+
+    unsafe { rt_table.runtime_services() }.reset(reset_type, Status::SUCCESS, None);
+}
+
 // returns the uefi system table pointer
 // once set, the pointer cannot be changed;
 // once purged, the pointer cannot be reset
@@ -0,0 +1,366 @@
+// Purpose: the kernel's clocks. The monotonic clock reads the timer's
+// free-running counter when that counter is calibrated & runs at a constant
+// rate; if not, it drops to the arch's fallback counter (the hpet on x86), and
+// failing that to counting timer ticks. Whatever the source, time never goes
+// backwards. Wall-clock (real) time is read once from the firmware or the rtc
+// & carried forward on the monotonic clock from there.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+use crate::common::base::*;
+use crate::timer::*;
+use crate::bringup::uefi::{uefi_get_time, uefi_set_time};
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::rtc::{x86_rtc_read, x86_rtc_write};
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::timer::*;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aa64::timer::*;
+
+pub const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockSource {
+    // clock_init() hasn't run, or found nothing to count with
+    None = 0,
+    // the timer counter (invariant tsc / cntvct)
+    Counter = 1,
+    // the arch's fallback counter (hpet)
+    Fallback = 2,
+    // timer ticks; only as fine grained as the tick
+    Ticks = 3,
+}
+
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(ClockSource::None as u8);
+// the source's reading at clock_init() & its rate
+static CLOCK_BASE: AtomicU64 = AtomicU64::new(0);
+static CLOCK_HZ: AtomicU64 = AtomicU64::new(0);
+// the latest time handed out, so no caller ever sees the clock step back
+static CLOCK_LAST_NS: AtomicU64 = AtomicU64::new(0);
+
+#[inline(always)]
+fn clock_read(source: ClockSource) -> u64 {
+    match source {
+        ClockSource::None => ZERO_U64,
+        ClockSource::Counter => timer_counter(),
+        ClockSource::Fallback => arch_timer_fallback_counter(),
+        ClockSource::Ticks => timer_ticks(),
+    }
+}
+
+// picks the best source available right now & starts the clock at 0. run it
+// after the timer is calibrated & ticking; returns the source it settled on.
+pub fn clock_init() -> ClockSource {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return clock_source();
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let (source, hz) = if timer_counter_hz() != ZERO_U64 && arch_timer_counter_invariant() {
+        (ClockSource::Counter, timer_counter_hz())
+    } else if arch_timer_fallback_counter_hz() != ZERO_U64 {
+        (ClockSource::Fallback, arch_timer_fallback_counter_hz())
+    } else if timer_tick_hz() != ZERO_U64 {
+        (ClockSource::Ticks, timer_tick_hz())
+    } else {
+        (ClockSource::None, ZERO_U64)
+    };
+
+    CLOCK_HZ.store(hz, Ordering::SeqCst);
+    CLOCK_BASE.store(clock_read(source), Ordering::SeqCst);
+    CLOCK_SOURCE.store(source as u8, Ordering::SeqCst);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("clock_init() -> {:?} @ {} hz", source, hz);
+
+    source
+}
+
+pub fn clock_source() -> ClockSource {
+    match CLOCK_SOURCE.load(Ordering::Relaxed) {
+        1 => ClockSource::Counter,
+        2 => ClockSource::Fallback,
+        3 => ClockSource::Ticks,
+        _ => ClockSource::None,
+    }
+}
+
+// how far apart two distinct readings of the clock can be, at best
+pub fn clock_resolution_ns() -> u64 {
+    match CLOCK_HZ.load(Ordering::Relaxed) {
+        ZERO_U64 => ZERO_U64,
+        hz => (NANOSECONDS_PER_SECOND / hz).max(1),
+    }
+}
+
+// nanoseconds since clock_init(); 0 until then. never decreases, even
+// across cpus whose counters are slightly out of step.
+pub fn monotonic_ns() -> u64 {
+    let source = clock_source();
+    let hz = CLOCK_HZ.load(Ordering::Relaxed);
+
+    if source == ClockSource::None || hz == ZERO_U64 {
+        return ZERO_U64;
+    }
+
+    let elapsed = clock_read(source).wrapping_sub(CLOCK_BASE.load(Ordering::Relaxed));
+    let now = (elapsed as u128 * NANOSECONDS_PER_SECOND as u128 / hz as u128) as u64;
+
+    // fetch_max hands back what was there before; if somebody already
+    // got a later time, that's the time
+    now.max(CLOCK_LAST_NS.fetch_max(now, Ordering::Relaxed))
+}
+
+// monotonic_ns() in coarser units, for convenience
+pub fn monotonic_us() -> u64 {
+    monotonic_ns() / 1_000
+}
+
+pub fn monotonic_ms() -> u64 {
+    monotonic_ns() / 1_000_000
+}
+
+//-----------------------------------------------------------------------------------
+
+// wall-clock time
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// a utc date & time
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    pub const UNIX_EPOCH: DateTime = DateTime {
+        year: 1970,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        nanosecond: 0,
+    };
+
+    fn is_leap_year(year: u16) -> bool {
+        (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    }
+
+    fn days_in_month(year: u16, month: u8) -> u8 {
+        match month {
+            2 if Self::is_leap_year(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.year >= 1970 &&
+        (1..=12).contains(&self.month) &&
+        self.day >= 1 && self.day <= Self::days_in_month(self.year, self.month) &&
+        self.hour < 24 &&
+        self.minute < 60 &&
+        self.second < 60 &&
+        (self.nanosecond as u64) < NANOSECONDS_PER_SECOND
+    }
+
+    // nanoseconds since the unix epoch; None if the date's not valid
+    pub fn to_unix_ns(&self) -> Option<u64> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        // days from 1970-01-01 to the civil date, shifting the year to start
+        // in march so the leap day falls at the end (hinnant's days_from_civil)
+        let (year, month) = if self.month <= 2 {
+            (self.year as u64 - 1, self.month as u64 + 9)
+        } else {
+            (self.year as u64, self.month as u64 - 3)
+        };
+
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * month + 2) / 5 + self.day as u64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        let seconds = days * SECONDS_PER_DAY + self.hour as u64 * 3_600 + self.minute as u64 * 60 + self.second as u64;
+
+        // u64 nanoseconds run out in 2554
+        seconds.checked_mul(NANOSECONDS_PER_SECOND)?.checked_add(self.nanosecond as u64)
+    }
+
+    // the date & time ns nanoseconds after the unix epoch
+    pub fn from_unix_ns(ns: u64) -> DateTime {
+        let seconds = ns / NANOSECONDS_PER_SECOND;
+        let days = seconds / SECONDS_PER_DAY;
+        let second_of_day = seconds % SECONDS_PER_DAY;
+
+        // and back again (hinnant's civil_from_days)
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (second_of_day / 3_600) as u8,
+            minute: ((second_of_day / 60) % 60) as u8,
+            second: (second_of_day % 60) as u8,
+            nanosecond: (ns % NANOSECONDS_PER_SECOND) as u32,
+        }
+    }
+}
+
+impl core::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} utc",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.nanosecond / 1_000_000
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RealtimeSource {
+    // nobody could tell us the time
+    None = 0,
+    // uefi runtime services GetTime() / SetTime()
+    Uefi = 1,
+    // the cmos rtc
+    Rtc = 2,
+}
+
+static REALTIME_SOURCE: AtomicU8 = AtomicU8::new(RealtimeSource::None as u8);
+// unix time minus monotonic time; realtime is the monotonic clock plus this
+static REALTIME_OFFSET_NS: AtomicU64 = AtomicU64::new(0);
+
+fn realtime_read(source: RealtimeSource) -> Option<DateTime> {
+    match source {
+        RealtimeSource::None => None,
+        RealtimeSource::Uefi => uefi_get_time(),
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        RealtimeSource::Rtc => x86_rtc_read(),
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        RealtimeSource::Rtc => None,
+    }
+}
+
+fn realtime_write(source: RealtimeSource, dt: &DateTime) -> bool {
+    match source {
+        RealtimeSource::None => false,
+        RealtimeSource::Uefi => uefi_set_time(dt),
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        RealtimeSource::Rtc => x86_rtc_write(dt),
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        RealtimeSource::Rtc => false,
+    }
+}
+
+// reads the wall-clock time once, from the firmware if it's around & the
+// rtc if not. run it after clock_init(); returns the source it settled on.
+// only the one source is ever touched afterwards, since on a pc the
+// firmware's clock *is* the rtc & the two mustn't race.
+pub fn clock_realtime_init() -> RealtimeSource {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return realtime_source();
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    for source in [RealtimeSource::Uefi, RealtimeSource::Rtc] {
+        let unix_ns = match realtime_read(source).and_then(|dt| dt.to_unix_ns()) {
+            Some(unix_ns) => unix_ns,
+            None => continue,
+        };
+
+        REALTIME_OFFSET_NS.store(unix_ns.saturating_sub(monotonic_ns()), Ordering::SeqCst);
+        REALTIME_SOURCE.store(source as u8, Ordering::SeqCst);
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("clock_realtime_init() -> {} via {:?}", DateTime::from_unix_ns(unix_ns), source);
+
+        return source;
+    }
+
+    RealtimeSource::None
+}
+
+pub fn realtime_source() -> RealtimeSource {
+    match REALTIME_SOURCE.load(Ordering::Relaxed) {
+        1 => RealtimeSource::Uefi,
+        2 => RealtimeSource::Rtc,
+        _ => RealtimeSource::None,
+    }
+}
+
+// nanoseconds since the unix epoch; 0 if we don't know the time
+pub fn realtime_ns() -> u64 {
+    if realtime_source() == RealtimeSource::None {
+        return ZERO_U64;
+    }
+
+    monotonic_ns() + REALTIME_OFFSET_NS.load(Ordering::Relaxed)
+}
+
+// the current utc date & time, if we know it
+pub fn realtime() -> Option<DateTime> {
+    match realtime_ns() {
+        ZERO_U64 => None,
+        ns => Some(DateTime::from_unix_ns(ns)),
+    }
+}
+
+// sets the wall clock, both ours & the hardware's. returns false (& leaves
+// ours alone) if dt isn't valid or the hardware wouldn't take it.
+pub fn set_realtime(dt: &DateTime) -> bool {
+    let unix_ns = match dt.to_unix_ns() {
+        Some(unix_ns) => unix_ns,
+        None => return false,
+    };
+
+    if !realtime_write(realtime_source(), dt) {
+        return false;
+    }
+
+    REALTIME_OFFSET_NS.store(unix_ns.saturating_sub(monotonic_ns()), Ordering::SeqCst);
+
+    true
+}
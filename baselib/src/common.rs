@@ -19,6 +19,7 @@ pub mod debug_assert {
 }
 
 pub mod priority {
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
     pub enum Priority {
         Lowest,
         Anonymous,
@@ -622,6 +623,7 @@ pub mod platform_constants {
     pub const KERNEL_STACK_SIZE_SMALL: usize = USIZE_512K;
     pub const KERNEL_STACK_SIZE_MED: usize = USIZE_4M;
     pub const KERNEL_STACK_SIZE_LARGE: usize = USIZE_8M;
+    pub const KERNEL_IST_STACK_SIZE: usize = USIZE_16K;
 
     pub const FACTOR_OF_USIZE_BYTES: usize = UFACTOR_OF_8;
     pub const FACTOR_OF_USIZE_BITS: usize = UFACTOR_OF_64;
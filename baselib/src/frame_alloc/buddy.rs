@@ -0,0 +1,758 @@
+// Purpose: a binary buddy physical frame allocator, the alternative to
+// TreeAllocator (pick it at build time with the buddyalloc feature). Memory is
+// kept as naturally aligned blocks of 2^order pages. Every page has a record in
+// the node storage; the first page of a block holds the block's order & owner,
+// & the records of free blocks double as the links of a free list per order.
+// The frame allocator bitmap has a bit set for every page that starts a free
+// block, so checking whether a block's buddy is free (& pulling it off its free
+// list) is O(1), as is every split & merge. Allocations of any page count come
+// out of the smallest block that fits, with the unused tail handed right back.
+
+use core::ops::Range;
+
+use crate::structures::bitmap::*;
+use crate::common::base::*;
+use crate::frame_alloc::zone::*;
+
+use crate::vmem::*;
+
+// the largest block is 2^18 pages (1g with 4k pages); nothing bigger can be
+// allocated in one go
+pub const BUDDY_MAX_ORDER: usize = 18;
+pub const BUDDY_ORDER_COUNT: usize = BUDDY_MAX_ORDER + 1;
+
+// the end of a free list
+const BUDDY_NONE: usize = usize::MAX;
+
+// the record for one page; only the first page of a block means anything
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct BuddyPage {
+    // free list links (free blocks)
+    next: usize,
+    prev: usize,
+    // the first page of the allocation the block is part of (allocated blocks)
+    extent: usize,
+    owner: Owner,
+    order: u8,
+    allocated: bool,
+}
+
+impl BuddyPage {
+    const fn new() -> Self {
+        BuddyPage {
+            next: BUDDY_NONE,
+            prev: BUDDY_NONE,
+            extent: BUDDY_NONE,
+            owner: Owner::Nobody,
+            order: 0,
+            allocated: false,
+        }
+    }
+}
+
+// like the tree allocator, not thread safe; wrap it in a lock
+pub struct BuddyAllocator<'n> {
+    pages: &'n mut [BuddyPage],
+    free_heads: [usize; BUDDY_ORDER_COUNT],
+    free_pages: usize,
+
+    // one bit per page, set when the page starts a free block. it goes by the
+    // tree allocator's name so the same bringup code can wire up either one.
+    pub frame_node_slot_bitmap: Option<Bitmap>,
+}
+
+impl<'n> BuddyAllocator<'n> {
+    // the node storage needed per page of physical memory
+    pub const FRAME_NODE_SIZE: usize = core::mem::size_of::<BuddyPage>();
+
+    fn bitmap(&self) -> &Bitmap {
+        self.frame_node_slot_bitmap.as_ref().unwrap()
+    }
+
+    // the largest block order starting at page that fits in count (> 0) pages
+    fn range_order(page: usize, count: usize) -> usize {
+        let align_order = if page == ZERO_USIZE { BUDDY_MAX_ORDER } else { page.trailing_zeros() as usize };
+        let size_order = (usize::BITS - 1 - count.leading_zeros()) as usize;
+
+        usize::min(usize::min(align_order, size_order), BUDDY_MAX_ORDER)
+    }
+
+    // the smallest block order holding count (> 0) pages
+    fn fit_order(count: usize) -> usize {
+        count.next_power_of_two().trailing_zeros() as usize
+    }
+
+    fn is_free_head(&self, page: usize, order: usize) -> bool {
+        page < self.pages.len() && self.bitmap().is_set(page) && self.pages[page].order as usize == order
+    }
+
+    fn is_alloc_head(&self, page: usize, order: usize) -> bool {
+        page < self.pages.len() && self.pages[page].allocated && self.pages[page].order as usize == order
+    }
+
+    // the (first page, order) of the free block containing page, if it's free
+    fn free_block_containing(&self, page: usize) -> Option<(usize, usize)> {
+        (0..BUDDY_ORDER_COUNT)
+            .map(|order| (page & !((1 << order) - 1), order))
+            .find(|&(head, order)| self.is_free_head(head, order))
+    }
+
+    // the (first page, order) of the allocated block containing page, if it's allocated
+    fn alloc_block_containing(&self, page: usize) -> Option<(usize, usize)> {
+        (0..BUDDY_ORDER_COUNT)
+            .map(|order| (page & !((1 << order) - 1), order))
+            .find(|&(head, order)| self.is_alloc_head(head, order))
+    }
+
+    fn push_free(&mut self, page: usize, order: usize) {
+        let head = self.free_heads[order];
+
+        self.pages[page] = BuddyPage { next: head, order: order as u8, ..BuddyPage::new() };
+
+        if head != BUDDY_NONE {
+            self.pages[head].prev = page;
+        }
+
+        self.free_heads[order] = page;
+        self.bitmap().set(page);
+        self.free_pages += 1 << order;
+    }
+
+    fn unlink_free(&mut self, page: usize) {
+        let BuddyPage { next, prev, order, .. } = self.pages[page];
+
+        if prev != BUDDY_NONE {
+            self.pages[prev].next = next;
+        } else {
+            self.free_heads[order as usize] = next;
+        }
+
+        if next != BUDDY_NONE {
+            self.pages[next].prev = prev;
+        }
+
+        self.bitmap().clear(page);
+        self.free_pages -= 1 << order;
+    }
+
+    // frees the block of order at page, merging it with its buddy for as long as
+    // the buddy is free too
+    fn free_block(&mut self, page: usize, order: usize) {
+        let mut page = page;
+        let mut order = order;
+
+        self.pages[page].allocated = false;
+
+        while order < BUDDY_MAX_ORDER {
+            let buddy = page ^ (1 << order);
+
+            if !self.is_free_head(buddy, order) {
+                break;
+            }
+
+            self.unlink_free(buddy);
+
+            // the upper half's first page is just another page in the block now
+            self.pages[page | (1 << order)] = BuddyPage::new();
+
+            page &= !(1 << order);
+            order += 1;
+        }
+
+        self.push_free(page, order);
+    }
+
+    // frees the pages [first, first + count) as the largest aligned blocks that fit
+    fn free_range(&mut self, first: usize, count: usize) {
+        let end = first + count;
+        let mut page = first;
+
+        while page < end {
+            let order = Self::range_order(page, end - page);
+            self.free_block(page, order);
+            page += 1 << order;
+        }
+    }
+
+    // records the (not free) pages [first, first + count) as one allocation for owner
+    fn mark_allocated(&mut self, first: usize, count: usize, owner: Owner) {
+        let end = first + count;
+        let mut page = first;
+
+        while page < end {
+            let order = Self::range_order(page, end - page);
+
+            self.pages[page] = BuddyPage { extent: first, owner, order: order as u8, allocated: true, ..BuddyPage::new() };
+            page += 1 << order;
+        }
+    }
+
+    // takes a free block of order off the free lists, splitting a bigger one if
+    // need be; returns its first page
+    fn take_block(&mut self, order: usize) -> Option<usize> {
+        let mut block_order = (order..BUDDY_ORDER_COUNT).find(|&o| self.free_heads[o] != BUDDY_NONE)?;
+        let page = self.free_heads[block_order];
+
+        self.unlink_free(page);
+
+        // hand the upper halves back until the block is the right size
+        while block_order > order {
+            block_order -= 1;
+            self.push_free(page + (1 << block_order), block_order);
+        }
+
+        Some(page)
+    }
+
+    // allocates count pages starting on a multiple of align pages
+    fn alloc_pages(&mut self, count: usize, align: usize, owner: Owner) -> Option<usize> {
+        let order = usize::max(Self::fit_order(count), Self::fit_order(align));
+
+        if order > BUDDY_MAX_ORDER {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("BuddyAllocator::alloc_pages(): -> request too large: {} pages, align {}", count, align);
+            return None;
+        }
+
+        let page = self.take_block(order)?;
+
+        self.mark_allocated(page, count, owner);
+        self.free_range(page + count, (1 << order) - count);
+
+        Some(page)
+    }
+
+    // claims the free pages [first, first + count) for owner; all or nothing
+    fn claim_pages(&mut self, first: usize, count: usize, owner: Owner) -> Option<usize> {
+        let end = first + count;
+
+        // make sure it's all free before touching anything
+        let mut page = first;
+
+        while page < end {
+            let (head, order) = self.free_block_containing(page)?;
+            page = head + (1 << order);
+        }
+
+        // take each block the range touches, giving back what's outside the range
+        page = first;
+
+        while page < end {
+            let (head, order) = self.free_block_containing(page)?;
+            let block_end = head + (1 << order);
+
+            self.unlink_free(head);
+            self.free_range(head, page - head);
+
+            if block_end > end {
+                self.free_range(end, block_end - end);
+            }
+
+            page = block_end;
+        }
+
+        self.mark_allocated(first, count, owner);
+
+        Some(first)
+    }
+
+    // the first page of the lowest run of count free pages starting on a multiple
+    // of align pages that lies within [first, end); walks the free runs by address
+    // starting at first, so pages outside the range are never looked at
+    fn find_pages_in(&self, first: usize, end: usize, count: usize, align: usize) -> Option<usize> {
+        // start with the free block straddling first (if any), then move up
+        let mut page = self.free_block_containing(first).map_or(first, |(head, _)| head);
+
+        while page < end {
+            let (run_base, run_size) = self.next_frame_by_addr(Self::page_addr(page), true)?;
+            let run_first = pages::addr_to_page_index(run_base);
+            let run_end = run_first + pages::bytes_to_pages(run_size, MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+
+            let lo = usize::max(run_first, first).next_multiple_of(align);
+            let hi = usize::min(run_end, end);
+
+            if lo < hi && hi - lo >= count {
+                return Some(lo);
+            }
+
+            page = run_end;
+        }
+
+        None
+    }
+
+    // the page count & page index span of [addr, addr + size)
+    fn page_span(addr: PhysAddr, size: usize) -> (usize, usize) {
+        (pages::addr_to_page_index(addr), pages::bytes_to_pages(size, MEMORY_DEFAULT_PAGE_SIZE_ENUM))
+    }
+
+    fn page_addr(page: usize) -> PhysAddr {
+        PhysAddr(pages::pages_to_bytes(page, MEMORY_DEFAULT_PAGE_SIZE_ENUM))
+    }
+
+    // hands the allocator a region of physical memory, free or already spoken for
+    pub fn add_mem_frame(
+        &mut self,
+        base_addr: PhysAddr,
+        size: usize,
+        is_free: bool,
+        _flags: usize,
+        owner: Owner,
+    ) -> Option<usize> {
+        let (first, count) = Self::page_span(base_addr, size);
+
+        if count == ZERO_USIZE || first + count > self.pages.len() {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("BuddyAllocator::add_mem_frame(): -> region out of range: base_addr = 0x{:08x}, size = {}", base_addr, size);
+            return None;
+        }
+
+        if is_free {
+            self.free_range(first, count);
+        } else {
+            self.mark_allocated(first, count, owner);
+        }
+
+        Some(first)
+    }
+
+    // returns the (base, size) of the first run of free (or alloc'ed) blocks
+    // starting at or above addr, or None if there are no more
+    pub fn next_frame_by_addr(&self, addr: PhysAddr, is_free: bool) -> Option<(PhysAddr, usize)> {
+        let mut page = pages::addr_to_page_index(addr);
+
+        while page < self.pages.len() {
+            let page_is_free = self.bitmap().is_set(page);
+
+            // not the start of a block (a hole in the memory map, or we were
+            // handed an address in the middle of one)
+            if !page_is_free && !self.pages[page].allocated {
+                page += 1;
+                continue;
+            }
+
+            if page_is_free != is_free {
+                page += 1 << self.pages[page].order;
+                continue;
+            }
+
+            let run_start = page;
+
+            while page < self.pages.len() && (self.bitmap().is_set(page) == is_free) && (is_free || self.pages[page].allocated) {
+                page += 1 << self.pages[page].order;
+            }
+
+            return Some((Self::page_addr(run_start), pages::pages_to_bytes(page - run_start, MEMORY_DEFAULT_PAGE_SIZE_ENUM)));
+        }
+
+        None
+    }
+
+    // returns the owner of the block containing addr, or None if the
+    // allocator doesn't track that address at all
+    pub fn frame_owner(&self, addr: PhysAddr) -> Option<Owner> {
+        let page = pages::addr_to_page_index(addr);
+
+        if self.free_block_containing(page).is_some() {
+            return Some(Owner::Nobody);
+        }
+
+        self.alloc_block_containing(page).map(|(head, _)| self.pages[head].owner)
+    }
+
+    // claims the free memory @ phys_addr for owner without touching its contents
+    pub fn reserve_frame_fixed(&mut self, phys_addr: PhysAddr, size: usize, owner: Owner) -> Option<PhysAddr> {
+        let (first, count) = Self::page_span(phys_addr, size);
+
+        self.claim_pages(first, count, owner).map(Self::page_addr)
+    }
+
+    // allocates (& zeroes) size bytes whose base is aligned to align (a power of
+    // two, at least a page; blocks are naturally aligned, so this costs nothing
+    // beyond a bigger block)
+    pub fn alloc_frame_aligned(&mut self, size: usize, align: usize, owner: Owner) -> Option<PhysAddr> {
+        let count = pages::bytes_to_pages(size, MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+        let align = usize::max(align, MEMORY_DEFAULT_PAGE_USIZE);
+
+        if count == ZERO_USIZE || !align.is_power_of_two() {
+            return None;
+        }
+
+        let base = Self::page_addr(self.alloc_pages(count, align / MEMORY_DEFAULT_PAGE_USIZE, owner)?);
+
+        raw::memset_aligned(base, pages::pages_to_bytes(count, MEMORY_DEFAULT_PAGE_SIZE_ENUM), ZERO_USIZE);
+
+        Some(base)
+    }
+
+    // allocates (& zeroes) size bytes aligned to align (a power of two, at least
+    // a page) lying entirely within range, lowest address first
+    pub fn alloc_frame_in(&mut self, range: Range<PhysAddr>, size: usize, align: usize, owner: Owner) -> Option<PhysAddr> {
+        let count = pages::bytes_to_pages(size, MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+        let align = usize::max(align, MEMORY_DEFAULT_PAGE_USIZE);
+
+        if count == ZERO_USIZE || !align.is_power_of_two() || range.start >= range.end {
+            return None;
+        }
+
+        // only whole pages inside the range count
+        let first = pages::bytes_to_pages(range.start.as_usize(), MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+        let end = pages::addr_to_page_index(range.end);
+
+        let page = self.find_pages_in(first, end, count, align / MEMORY_DEFAULT_PAGE_USIZE)?;
+        let base = Self::page_addr(self.claim_pages(page, count, owner)?);
+
+        raw::memset_aligned(base, pages::pages_to_bytes(count, MEMORY_DEFAULT_PAGE_SIZE_ENUM), ZERO_USIZE);
+
+        Some(base)
+    }
+
+    // allocates (& zeroes) size bytes aligned to align from zone, falling back to
+    // the zones below it when it's out of room
+    pub fn alloc_frame_in_zone(&mut self, zone: MemZone, size: usize, align: usize, owner: Owner) -> Option<PhysAddr> {
+        zone.fallbacks()
+            .filter_map(mem_zone)
+            .filter(|info| !info.is_empty())
+            .find_map(|info| self.alloc_frame_in(info.range(), size, align, owner))
+    }
+}
+
+impl<'n> FrameAllocator for BuddyAllocator<'n> {
+    fn new(mem_nodes_base: PhysAddr, node_count: usize) -> Self {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("BuddyAllocator::new(): -> allocating a new BuddyAllocator");
+
+        BuddyAllocator {
+            pages: unsafe {
+                core::slice::from_raw_parts_mut::<'n, BuddyPage>(
+                    raw::abracadabra_ptr_mut::<BuddyPage, PhysAddr>(mem_nodes_base, false),
+                    node_count,
+                )
+            },
+            free_heads: [BUDDY_NONE; BUDDY_ORDER_COUNT],
+            free_pages: ZERO_USIZE,
+
+            frame_node_slot_bitmap: Some(Bitmap::new(Owner::Memory)),
+        }
+    }
+
+    // ok to panic in frame allocator init
+    fn init(&mut self) {
+        // nothing is free until add_mem_frame() says so
+        self.bitmap().clear_all();
+        self.pages.fill(BuddyPage::new());
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("BuddyAllocator::init(): -> {} pages, max order {}; init complete", self.pages.len(), BUDDY_MAX_ORDER);
+    }
+
+    fn alloc_frame_fixed(&mut self, phys_addr: PhysAddr, size: usize, page_size: PageSize, owner: Owner) -> Option<PhysAddr> {
+        debug_assert!(phys_addr.is_aligned(page_size.as_usize()));
+
+        let size = align_up(size, page_size.as_usize());
+        let base = self.reserve_frame_fixed(phys_addr, size, owner)?;
+
+        raw::memset_aligned(base, size, ZERO_USIZE);
+
+        Some(base)
+    }
+
+    fn alloc_frame(&mut self, size: usize, page_size: PageSize, owner: Owner) -> Option<PhysAddr> {
+        debug_assert!(size.is_aligned(page_size.as_usize()));
+
+        self.alloc_frame_aligned(align_up(size, page_size.as_usize()), page_size.as_usize(), owner)
+    }
+
+    // page_base has to be the base of an allocation; the whole allocation is freed
+    fn dealloc_frame(&mut self, page_base: PhysAddr, owner: Owner) -> bool {
+        let first = pages::addr_to_page_index(page_base);
+
+        if first >= self.pages.len() || !self.pages[first].allocated || self.pages[first].extent != first {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("BuddyAllocator::dealloc_frame(): -> 0x{:08x} isn't the base of an allocation", page_base);
+            return false;
+        }
+
+        if self.pages[first].owner != owner {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("BuddyAllocator::dealloc_frame(): -> owner mismatch: {:?} tried to deallocate physical memory owned by {:?}", owner, self.pages[first].owner);
+            return false;
+        }
+
+        // the allocation's blocks are back to back, each pointing at the first
+        let mut page = first;
+
+        while page < self.pages.len() && self.pages[page].allocated && self.pages[page].extent == first {
+            let order = self.pages[page].order as usize;
+
+            self.free_block(page, order);
+            page += 1 << order;
+        }
+
+        true
+    }
+
+    fn free_page_count(&mut self) -> usize {
+        self.free_pages
+    }
+
+    fn free_mem_count(&mut self) -> usize {
+        pages::pages_to_bytes(self.free_pages, MEMORY_DEFAULT_PAGE_SIZE_ENUM)
+    }
+
+    fn total_page_count(&self) -> usize {
+        iron().unwrap().get_total_pages()
+    }
+
+    fn total_mem_count(&self) -> usize {
+        iron().unwrap().get_phys_mem_boundary().as_usize()
+    }
+
+    fn is_memory_frame_free(&self, page_base: PhysAddr) -> bool {
+        self.free_block_containing(pages::addr_to_page_index(page_base)).is_some()
+    }
+
+    fn is_frame_index_free(&self, page_idx: usize) -> bool {
+        self.free_block_containing(page_idx).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4m worth of pages, from physical zero up. nothing here allocates through
+    // a path that zeroes memory, so none of it is ever touched.
+    const TEST_PAGES: usize = 1024;
+
+    static mut PAGES: [BuddyPage; TEST_PAGES] = [BuddyPage::new(); TEST_PAGES];
+    static mut BITMAP: [usize; TEST_PAGES / MACHINE_UBITS] = [ZERO_USIZE; TEST_PAGES / MACHINE_UBITS];
+
+    // an allocator over PAGES & BITMAP with nothing added yet; init() wipes
+    // whatever the last test left in them
+    fn buddy() -> BuddyAllocator<'static> {
+        let mut buddy = BuddyAllocator {
+            pages: unsafe { &mut *core::ptr::addr_of_mut!(PAGES) },
+            free_heads: [BUDDY_NONE; BUDDY_ORDER_COUNT],
+            free_pages: ZERO_USIZE,
+
+            frame_node_slot_bitmap: Some(Bitmap::new(Owner::Memory)),
+        };
+
+        buddy.bitmap().init_phys_fixed(TEST_PAGES, PhysAddr(unsafe { core::ptr::addr_of!(BITMAP) } as usize));
+        buddy.init();
+        buddy
+    }
+
+    fn addr(page: usize) -> PhysAddr {
+        BuddyAllocator::page_addr(page)
+    }
+
+    // walks every free list, checking each block is marked free & as big as its
+    // list says, and that no block's buddy was left unmerged; returns the number
+    // of free pages on the lists
+    fn check_free_lists(buddy: &BuddyAllocator) -> usize {
+        let mut free = ZERO_USIZE;
+
+        for order in 0..BUDDY_ORDER_COUNT {
+            let mut page = buddy.free_heads[order];
+
+            while page != BUDDY_NONE {
+                assert!(buddy.is_free_head(page, order));
+                assert!(order == BUDDY_MAX_ORDER || !buddy.is_free_head(page ^ (1 << order), order));
+
+                free += 1 << order;
+                page = buddy.pages[page].next;
+            }
+        }
+
+        assert_eq!(free, buddy.free_pages);
+        free
+    }
+
+    // the orders that have anything on their free list
+    fn free_orders(buddy: &BuddyAllocator) -> usize {
+        (0..BUDDY_ORDER_COUNT)
+            .filter(|&order| buddy.free_heads[order] != BUDDY_NONE)
+            .fold(ZERO_USIZE, |orders, order| orders | (1 << order))
+    }
+
+    #[test_case]
+    fn buddy_split_merge_round_trip() {
+        let mut buddy = buddy();
+        buddy.add_mem_frame(addr(0), pages::pages_to_bytes(TEST_PAGES, MEMORY_DEFAULT_PAGE_SIZE_ENUM), true, 0, Owner::Nobody);
+
+        // one block of 1024 pages
+        assert_eq!(free_orders(&buddy), 1 << 10);
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES);
+
+        // a single page splits it all the way down, one block of each order left over
+        assert_eq!(buddy.alloc_pages(1, 1, Owner::Kernel), Some(0));
+        assert_eq!(free_orders(&buddy), (1 << 10) - 1);
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 1);
+
+        let a = buddy.alloc_pages(16, 1, Owner::Kernel).unwrap();
+        let b = buddy.alloc_pages(7, 1, Owner::Kernel).unwrap();
+        let c = buddy.alloc_pages(1, 1, Owner::Kernel).unwrap();
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 25);
+
+        // give it all back, out of order; it merges back into the one block
+        assert!(buddy.dealloc_frame(addr(b), Owner::Kernel));
+        assert!(buddy.dealloc_frame(addr(0), Owner::Kernel));
+        assert!(buddy.dealloc_frame(addr(c), Owner::Kernel));
+        assert!(buddy.dealloc_frame(addr(a), Owner::Kernel));
+
+        assert_eq!(free_orders(&buddy), 1 << 10);
+        assert_eq!(buddy.free_heads[10], 0);
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES);
+    }
+
+    #[test_case]
+    fn buddy_claim_straddles_blocks() {
+        let mut buddy = buddy();
+        buddy.add_mem_frame(addr(0), pages::pages_to_bytes(TEST_PAGES, MEMORY_DEFAULT_PAGE_SIZE_ENUM), true, 0, Owner::Nobody);
+
+        // page 0 taken leaves free blocks [1, 2) [2, 4) [4, 8) [8, 16) ...
+        assert_eq!(buddy.alloc_pages(1, 1, Owner::Kernel), Some(0));
+
+        // [3, 9) cuts across three of them
+        assert_eq!(buddy.reserve_frame_fixed(addr(3), pages::pages_to_bytes(6, MEMORY_DEFAULT_PAGE_SIZE_ENUM), Owner::Firmware), Some(addr(3)));
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 7);
+
+        assert_eq!(buddy.frame_owner(addr(2)), Some(Owner::Nobody));
+        assert_eq!(buddy.frame_owner(addr(3)), Some(Owner::Firmware));
+        assert_eq!(buddy.frame_owner(addr(8)), Some(Owner::Firmware));
+        assert_eq!(buddy.frame_owner(addr(9)), Some(Owner::Nobody));
+        assert!(buddy.is_frame_index_free(2));
+        assert!(!buddy.is_frame_index_free(5));
+
+        // all or nothing: a claim running into allocated pages changes nothing
+        assert_eq!(buddy.claim_pages(1, 4, Owner::Firmware), None);
+        assert_eq!(buddy.claim_pages(8, 4, Owner::Firmware), None);
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 7);
+
+        // the claim frees as one allocation
+        assert!(buddy.dealloc_frame(addr(3), Owner::Firmware));
+        assert!(buddy.dealloc_frame(addr(0), Owner::Kernel));
+        assert_eq!(free_orders(&buddy), 1 << 10);
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES);
+    }
+
+    #[test_case]
+    fn buddy_aligned_allocations() {
+        let mut buddy = buddy();
+
+        // memory that starts on an odd page
+        buddy.add_mem_frame(addr(3), pages::pages_to_bytes(TEST_PAGES - 3, MEMORY_DEFAULT_PAGE_SIZE_ENUM), true, 0, Owner::Nobody);
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 3);
+
+        for (count, align) in [(1, 1), (3, 1), (1, 8), (5, 16), (4, 64), (1, 256), (2, 2)] {
+            let page = buddy.alloc_pages(count, align, Owner::Kernel).unwrap();
+
+            assert_eq!(page % align, 0);
+            assert!(page >= 3 && page + count <= TEST_PAGES);
+        }
+
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 3 - 17);
+
+        // the only 1024 aligned page (0) was never ours, & nothing at all is bigger
+        // than the max order
+        assert_eq!(buddy.alloc_pages(1, TEST_PAGES, Owner::Kernel), None);
+        assert_eq!(buddy.alloc_pages(1, 1 << (BUDDY_MAX_ORDER + 1), Owner::Kernel), None);
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 3 - 17);
+    }
+
+    #[test_case]
+    fn buddy_dealloc_multi_block_extent() {
+        let mut buddy = buddy();
+        buddy.add_mem_frame(addr(0), pages::pages_to_bytes(TEST_PAGES, MEMORY_DEFAULT_PAGE_SIZE_ENUM), true, 0, Owner::Nobody);
+
+        // 13 pages is three blocks (8 + 4 + 1), the 3 page tail of the 16 page block goes back
+        let first = buddy.alloc_pages(13, 1, Owner::Kernel).unwrap();
+        assert_eq!(buddy.pages[first].order, 3);
+        assert_eq!(buddy.pages[first + 8].order, 2);
+        assert_eq!(buddy.pages[first + 12].order, 0);
+        assert!([first, first + 8, first + 12].iter().all(|&page| buddy.pages[page].extent == first));
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 13);
+
+        // only the base of the allocation, & only by its owner
+        assert!(!buddy.dealloc_frame(addr(first + 8), Owner::Kernel));
+        assert!(!buddy.dealloc_frame(addr(first), Owner::Firmware));
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 13);
+
+        // a neighbouring allocation isn't part of it
+        let next = buddy.alloc_pages(1, 1, Owner::Kernel).unwrap();
+        assert_eq!(next, first + 13);
+
+        assert!(buddy.dealloc_frame(addr(first), Owner::Kernel));
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 1);
+        assert_eq!(buddy.frame_owner(addr(next)), Some(Owner::Kernel));
+
+        assert!(!buddy.dealloc_frame(addr(first), Owner::Kernel));
+        assert!(buddy.dealloc_frame(addr(next), Owner::Kernel));
+        assert_eq!(free_orders(&buddy), 1 << 10);
+    }
+
+    #[test_case]
+    fn buddy_free_page_accounting() {
+        let mut buddy = buddy();
+        assert_eq!(buddy.free_page_count(), ZERO_USIZE);
+
+        // a hole at [64, 128) & firmware memory at [128, 192)
+        buddy.add_mem_frame(addr(0), pages::pages_to_bytes(64, MEMORY_DEFAULT_PAGE_SIZE_ENUM), true, 0, Owner::Nobody);
+        buddy.add_mem_frame(addr(128), pages::pages_to_bytes(64, MEMORY_DEFAULT_PAGE_SIZE_ENUM), false, 0, Owner::Firmware);
+        buddy.add_mem_frame(addr(192), pages::pages_to_bytes(TEST_PAGES - 192, MEMORY_DEFAULT_PAGE_SIZE_ENUM), true, 0, Owner::Nobody);
+
+        let mut free = TEST_PAGES - 128;
+        assert_eq!(buddy.free_page_count(), free);
+        assert_eq!(buddy.free_mem_count(), pages::pages_to_bytes(free, MEMORY_DEFAULT_PAGE_SIZE_ENUM));
+        assert_eq!(buddy.frame_owner(addr(64)), None);
+
+        let mut taken = [ZERO_USIZE; 8];
+
+        for (i, count) in [1, 2, 3, 64, 100, 7, 32, 1].into_iter().enumerate() {
+            taken[i] = buddy.alloc_pages(count, 1, Owner::Kernel).unwrap();
+            free -= count;
+            assert_eq!(buddy.free_page_count(), free);
+        }
+
+        assert_eq!(check_free_lists(&buddy), free);
+
+        // handing the firmware memory over frees it, & it merges with what's around it
+        assert!(buddy.dealloc_frame(addr(128), Owner::Firmware));
+
+        for page in taken {
+            assert!(buddy.dealloc_frame(addr(page), Owner::Kernel));
+        }
+
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 64);
+        assert_eq!(buddy.free_page_count(), TEST_PAGES - 64);
+        assert_eq!(buddy.next_frame_by_addr(addr(0), true), Some((addr(0), pages::pages_to_bytes(64, MEMORY_DEFAULT_PAGE_SIZE_ENUM))));
+        assert_eq!(buddy.next_frame_by_addr(addr(64), true), Some((addr(128), pages::pages_to_bytes(TEST_PAGES - 128, MEMORY_DEFAULT_PAGE_SIZE_ENUM))));
+    }
+
+    #[test_case]
+    fn buddy_find_pages_in_range() {
+        let mut buddy = buddy();
+        buddy.add_mem_frame(addr(0), pages::pages_to_bytes(TEST_PAGES, MEMORY_DEFAULT_PAGE_SIZE_ENUM), true, 0, Owner::Nobody);
+        assert_eq!(buddy.alloc_pages(1, 1, Owner::Kernel), Some(0));
+
+        // free runs span blocks: [1, 4) is two of them
+        assert_eq!(buddy.find_pages_in(0, TEST_PAGES, 3, 1), Some(1));
+        assert_eq!(buddy.find_pages_in(0, TEST_PAGES, 3, 4), Some(4));
+        assert_eq!(buddy.find_pages_in(100, 200, 8, 32), Some(128));
+
+        // starting in the middle of a free block
+        assert_eq!(buddy.find_pages_in(600, TEST_PAGES, 1, 1), Some(600));
+
+        // too small a range, or running off the end
+        assert_eq!(buddy.find_pages_in(1, 3, 3, 1), None);
+        assert_eq!(buddy.find_pages_in(1000, TEST_PAGES, 32, 1), None);
+
+        // a claim of the find crosses the block boundary fine
+        assert_eq!(buddy.claim_pages(1, 3, Owner::Kernel), Some(1));
+        assert_eq!(buddy.find_pages_in(0, 8, 1, 1), Some(4));
+        assert_eq!(check_free_lists(&buddy), TEST_PAGES - 4);
+    }
+}
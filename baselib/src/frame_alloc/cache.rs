@@ -0,0 +1,239 @@
+// Purpose: per-cpu caches ("magazines") of free default size frames in front of
+// the frame allocator, so grabbing or dropping a single page (page tables,
+// mostly) doesn't mean taking the global frame allocator lock every time. A
+// cpu's magazine is refilled from the frame allocator in batches when it runs
+// low & drained back in batches when it runs high; either way that's one trip
+// through the global lock per batch.
+//
+// Cached frames stay allocated to Owner::Memory as far as the frame allocator
+// is concerned, so only the memory subsystem's own single pages go through
+// here, & anything that needs the frame allocator's books to be exact (fixed
+// allocations, free memory stats, running out of memory) calls
+// frame_cache_drain_all() first. Lock order: a magazine, then the frame
+// allocator, never the other way around.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use crate::common::base::*;
+
+use crate::vmem::*;
+
+// one magazine per cpu; only the boot cpu runs kernel code until the aps are
+// brought up, so for now that's one. once there are more, frame_cache_cpu()
+// picks them by lapic / mpidr id.
+pub const FRAME_CACHE_MAX_CPUS: usize = 1;
+pub const FRAME_CACHE_CAPACITY: usize = 64;
+
+// frames moved per refill / drain
+pub const FRAME_CACHE_BATCH: usize = 16;
+// an alloc refills the magazine when it finds fewer than this many frames
+pub const FRAME_CACHE_LOW_WATERMARK: usize = 4;
+// a free drains the magazine when it leaves more than this many frames
+pub const FRAME_CACHE_HIGH_WATERMARK: usize = 48;
+
+// who the frame allocator thinks owns the cached frames
+const FRAME_CACHE_OWNER: Owner = Owner::Memory;
+
+pub struct FrameMagazine {
+    frames: [PhysAddr; FRAME_CACHE_CAPACITY],
+    // set for the frames that came back through frame_cache_free() & may hold
+    // anything; the ones from refill() were zeroed by the frame allocator
+    dirty: [bool; FRAME_CACHE_CAPACITY],
+    count: usize,
+}
+
+impl FrameMagazine {
+    const fn new() -> Self {
+        FrameMagazine {
+            frames: [PhysAddr(ZERO_USIZE); FRAME_CACHE_CAPACITY],
+            dirty: [false; FRAME_CACHE_CAPACITY],
+            count: ZERO_USIZE,
+        }
+    }
+
+    fn push(&mut self, frame: PhysAddr, dirty: bool) -> bool {
+        if self.count == FRAME_CACHE_CAPACITY {
+            return false;
+        }
+
+        self.frames[self.count] = frame;
+        self.dirty[self.count] = dirty;
+        self.count += 1;
+        true
+    }
+
+    // the frame & whether it needs zeroing
+    fn pop(&mut self) -> Option<(PhysAddr, bool)> {
+        if self.count == ZERO_USIZE {
+            return None;
+        }
+
+        self.count -= 1;
+        Some((self.frames[self.count], self.dirty[self.count]))
+    }
+
+    // tops the magazine up with as many as count frames from the frame allocator;
+    // returns how many it got
+    fn refill(&mut self, count: usize) -> usize {
+        let mut frame_alloc_lock = iron().unwrap().frame_alloc_internal_04.lock_rw_spin();
+        let frame_alloc = frame_alloc_lock.as_mut().unwrap().as_mut().unwrap();
+
+        let mut added = ZERO_USIZE;
+
+        while added < count && self.count < FRAME_CACHE_CAPACITY {
+            match frame_alloc.alloc_frame(MEMORY_DEFAULT_PAGE_USIZE, MEMORY_DEFAULT_PAGE_SIZE_ENUM, FRAME_CACHE_OWNER) {
+                Some(frame) => {
+                    self.push(frame, false);
+                    added += 1;
+                }
+                None => break,
+            }
+        }
+
+        added
+    }
+
+    // hands as many as count frames back to the frame allocator; returns how
+    // many went back
+    fn drain(&mut self, count: usize) -> usize {
+        if self.count == ZERO_USIZE {
+            return ZERO_USIZE;
+        }
+
+        let mut frame_alloc_lock = iron().unwrap().frame_alloc_internal_04.lock_rw_spin();
+        let frame_alloc = frame_alloc_lock.as_mut().unwrap().as_mut().unwrap();
+
+        let mut drained = ZERO_USIZE;
+
+        while drained < count {
+            let frame = match self.pop() {
+                Some((frame, _)) => frame,
+                None => break,
+            };
+
+            if !frame_alloc.dealloc_frame(frame, FRAME_CACHE_OWNER) {
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("FrameMagazine::drain(): -> frame allocator refused cached frame 0x{:08x}", frame);
+            }
+
+            drained += 1;
+        }
+
+        drained
+    }
+}
+
+const FRAME_MAGAZINE_EMPTY: Mutex<FrameMagazine> = Mutex::new(FrameMagazine::new());
+static FRAME_CACHES: [Mutex<FrameMagazine>; FRAME_CACHE_MAX_CPUS] = [FRAME_MAGAZINE_EMPTY; FRAME_CACHE_MAX_CPUS];
+
+// until the kernel runs on its own page tables, frames go straight to & from the
+// frame allocator: the identity map is built by walking the frame allocator's
+// frames, & a batch sitting in a magazine mid-walk could be missed
+static FRAME_CACHE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+// the magazine of the cpu we're running on; there's only the boot cpu's for now
+fn frame_cache_cpu() -> usize {
+    ZERO_USIZE
+}
+
+// turns the magazines on; call once every frame the frame allocator knows
+// about is mapped
+pub fn frame_cache_init() {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    FRAME_CACHE_ENABLED.store(true, Ordering::SeqCst);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!(
+        "frame_cache_init() -> {} frame magazine(s) of {} (batch {}, watermarks {} / {})",
+        FRAME_CACHE_MAX_CPUS,
+        FRAME_CACHE_CAPACITY,
+        FRAME_CACHE_BATCH,
+        FRAME_CACHE_LOW_WATERMARK,
+        FRAME_CACHE_HIGH_WATERMARK
+    );
+}
+
+// a zeroed default size frame for the memory subsystem, from this cpu's magazine
+// when there is one; give it back with frame_cache_free()
+pub fn frame_cache_alloc() -> Option<PhysAddr> {
+    let cached = if FRAME_CACHE_ENABLED.load(Ordering::SeqCst) {
+        let mut magazine = FRAME_CACHES[frame_cache_cpu()].lock();
+
+        if magazine.count < FRAME_CACHE_LOW_WATERMARK {
+            magazine.refill(FRAME_CACHE_BATCH);
+        }
+
+        magazine.pop()
+    } else {
+        None
+    };
+
+    let frame = match cached {
+        Some((frame, dirty)) => {
+            if dirty {
+                raw::memset_aligned(frame, MEMORY_DEFAULT_PAGE_USIZE, ZERO_USIZE);
+            }
+
+            frame
+        }
+        None => {
+            // the frame allocator is out as far as this cpu can tell; whatever the
+            // other magazines are sitting on goes back before we give up
+            if FRAME_CACHE_ENABLED.load(Ordering::SeqCst) {
+                frame_cache_drain_all();
+            }
+
+            iron().unwrap().frame_alloc_internal_04
+                .lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
+                .alloc_frame(MEMORY_DEFAULT_PAGE_USIZE, MEMORY_DEFAULT_PAGE_SIZE_ENUM, FRAME_CACHE_OWNER)?
+        }
+    };
+
+    Some(frame)
+}
+
+// gives back a frame from frame_cache_alloc() (or any single default size
+// Owner::Memory frame). it's taken at its word until it's drained back to the
+// frame allocator, so the one thing it checks for is an empty page table entry.
+pub fn frame_cache_free(frame: PhysAddr) -> bool {
+    debug_assert!(frame.is_aligned(MEMORY_DEFAULT_PAGE_USIZE));
+
+    // page zero is never handed out
+    if frame.is_null() {
+        return false;
+    }
+
+    if FRAME_CACHE_ENABLED.load(Ordering::SeqCst) {
+        let mut magazine = FRAME_CACHES[frame_cache_cpu()].lock();
+
+        if magazine.push(frame, true) {
+            if magazine.count > FRAME_CACHE_HIGH_WATERMARK {
+                magazine.drain(FRAME_CACHE_BATCH);
+            }
+
+            return true;
+        }
+    }
+
+    iron().unwrap().frame_alloc_internal_04
+        .lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
+        .dealloc_frame(frame, FRAME_CACHE_OWNER)
+}
+
+// hands every cached frame on every cpu back to the frame allocator; returns how
+// many went back. don't call it holding the frame allocator lock.
+pub fn frame_cache_drain_all() -> usize {
+    FRAME_CACHES.iter()
+        .map(|magazine| magazine.lock().drain(FRAME_CACHE_CAPACITY))
+        .sum()
+}
@@ -1,9 +1,16 @@
+pub mod zone;
+pub mod cache;
+#[cfg(feature = "buddyalloc")]
+pub mod buddy;
+
 use core::cell::UnsafeCell;
+use core::ops::Range;
 
 use crate::nebulae::*;
 use crate::structures::bitmap::*;
 use crate::common::base::*;
 use crate::structures::tree::red_black::*;
+use crate::frame_alloc::zone::*;
 
 use crate::vmem::*;
 
@@ -180,6 +187,13 @@ impl<'n> FrameDescr<'n> {
     }
 }
 
+// the physical frame allocator the kernel runs on; the buddyalloc feature
+// swaps the tree allocator out for the buddy allocator
+#[cfg(not(feature = "buddyalloc"))]
+pub type KernelFrameAllocator<'n> = TreeAllocator<'n>;
+#[cfg(feature = "buddyalloc")]
+pub type KernelFrameAllocator<'n> = buddy::BuddyAllocator<'n>;
+
 // the tree allocator is not thread safe, so it's instance
 // needs to be wrapped in a lock
 #[allow(dead_code)]
@@ -196,13 +210,14 @@ pub struct TreeAllocator<'n> {
     rb_size_alloc: UnsafeCell<RBTree<'n, MemNode<'n>>>,
     rb_addr_alloc: UnsafeCell<RBTree<'n, MemNode<'n>>>,
 
-    dealloc_since_last_coalesce_free_count: UnsafeCell<usize>,
-    pub merge_free_dealloc_interval: UnsafeCell<usize>,
-
     pub frame_node_slot_bitmap: Option<Bitmap>,
 }
 
 impl<'n> TreeAllocator<'n> {
+    // the node storage needed per page of physical memory (worst case, every
+    // page its own frame)
+    pub const FRAME_NODE_SIZE: usize = core::mem::size_of::<FrameDescr>();
+
     // Update a range of frames (free/alloc, owner) in our frame info structs
     fn update_frame_info_structs (
         &self,
@@ -249,99 +264,49 @@ impl<'n> TreeAllocator<'n> {
         true
     }
 
-    // Merges the free frames in the tree
-    // returns the number of merges that were performed
-    fn coalesce_free_frames(&mut self) -> Option<usize> {
-        let mut merge_count: usize = 0;
-
-        // get a reference to the memory frame array
-        let mem_frames = unsafe { self.mem_frame_nodes.get().as_mut().unwrap() };
-
-        // get root node from the free by address tree
-        let free_addr_root = unsafe { self.rb_addr_free.get().as_ref().unwrap().root() };
-        
-        // if there's no root, then there's no memory to coalesce
-        if free_addr_root.is_none() {
-            return None;
-        }
-
-        // get the min node (lowest address) from the free address tree
-        let free_addr_min_result = unsafe { self.rb_addr_free.get().as_ref().unwrap().min_node() };
-        // if there is no min node, but there was a root node, we have corruption
-        if free_addr_min_result.is_none() {
-            #[cfg(all(debug_assertions, feature = "serialdbg"))]
-            serial_println!("TreeAllocator::coalesce_free_space(): -> memory subsystem error: reference to min node was None from free address root");
-            return None;
-        }
-        
-        // set the current node = to the min node (safety checked above)
-        let mut free_addr_node_cur = free_addr_min_result.unwrap();
-
-        // iterate & merge
-        loop {
-            // get the current node's key
-            let node_key = free_addr_node_cur.key();
-
-            // get the current node's address
-            let node_addr = hi64(node_key);
-
-            // get the current node's size
-            let node_size = lo64(node_key);
-
-            // get the current node's memory frame index
-            let node_mem_frame_idx = free_addr_node_cur.value();
-
-            // see if there's a next node
-            if free_addr_node_cur.right().is_none() {
-                // if there is no next node, we're done
-                break;
-            }
-
-            // get the next node - safe unwrap
-            let next_node = free_addr_node_cur.right().unwrap();
-            
-            // get the next node's address
-            let next_node_addr = hi64(next_node.key().clone());
+    // merges free frame frame_idx with the free frames ending right where it
+    // starts & starting right where it ends (found by floor / ceiling in the free
+    // address trunk), so no two free frames are ever adjacent; returns the index
+    // of the frame now holding frame_idx's memory
+    fn merge_free_neighbors(&mut self, frame_idx: usize) -> usize {
+        let mem_frame_array = unsafe { self.mem_frame_nodes.get().as_mut().unwrap() };
+        let addr_free_trunk = unsafe { self.rb_addr_free.get().as_ref().unwrap() };
 
-            // get the next node's index
-            let next_node_mem_idx = next_node.value().clone();
+        let mut frame_idx = frame_idx;
 
-            // make sure the current node and the next node are adjacent
-            if node_addr.as_usize() + node_size.as_usize() == next_node_addr.as_usize() {
-                // they are adjacent, so merge them
-                let merged_frame_result = self.merge_free_frames(node_mem_frame_idx, next_node_mem_idx);
+        // the free frame below
+        let frame_base = mem_frame_array[frame_idx].mem_block.get_mut().base_addr.as_usize();
 
-                // make sure the merge was successful
-                if merged_frame_result.is_none() {
-                    #[cfg(all(debug_assertions, feature = "serialdbg"))]
-                    serial_println!("TreeAllocator::coalesce_free_frames(): -> error in memory subsystem: failed to merge free frames: {} & {}", node_mem_frame_idx, next_node_mem_idx);
-                    return Some(merge_count);
-                }
-
-                // update the current node (safe unwrap)
-                let free_addr_node_cur_result = unsafe { mem_frames[merged_frame_result.unwrap()].addr_node.get().as_ref().unwrap() }.left();
-                if free_addr_node_cur_result.is_none() {
-                    return Some(merge_count);
+        if frame_base != ZERO_USIZE {
+            if let Some(prev_node) = addr_free_trunk.floor_node(make128(frame_base - 1, usize::MAX)) {
+                if hi64(prev_node.key()) as usize + lo64(prev_node.key()) as usize == frame_base {
+                    match self.merge_free_frames(prev_node.value(), frame_idx) {
+                        Some(merged_idx) => frame_idx = merged_idx,
+                        None => {
+                            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                            serial_println!("TreeAllocator::merge_free_neighbors(): -> error in memory subsystem: failed to merge free frames: {} & {}", prev_node.value(), frame_idx);
+                        }
+                    }
                 }
-                free_addr_node_cur = free_addr_node_cur_result.unwrap();
+            }
+        }
 
-                // increment our merge count
-                merge_count += 1;
+        // the free frame above
+        let frame_end = mem_frame_array[frame_idx].mem_block.get_mut().base_addr.as_usize() + mem_frame_array[frame_idx].mem_block.get_mut().size;
 
-            } else {
-                // they are not adjacent, so move to the next node if there is one
-                if free_addr_node_cur.right().is_none() {
-                    break;
+        if let Some(next_node) = addr_free_trunk.ceiling_node(make128(frame_end, ZERO_USIZE)) {
+            if hi64(next_node.key()) as usize == frame_end {
+                match self.merge_free_frames(frame_idx, next_node.value()) {
+                    Some(merged_idx) => frame_idx = merged_idx,
+                    None => {
+                        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                        serial_println!("TreeAllocator::merge_free_neighbors(): -> error in memory subsystem: failed to merge free frames: {} & {}", frame_idx, next_node.value());
+                    }
                 }
-                free_addr_node_cur = free_addr_node_cur.right().unwrap();
             }
         }
-        
-        // reset the "deallocs since last coalesce" count
-        let dslcc = unsafe { self.dealloc_since_last_coalesce_free_count.get().as_mut().unwrap() };
-        *dslcc = ZERO_USIZE;
 
-        Some(merge_count)
+        frame_idx
     }
 
     // Add a new mem frame to the tree
@@ -402,6 +367,11 @@ impl<'n> TreeAllocator<'n> {
                 // a new frame to the tree
                 self.update_frame_info_structs(new_frame_idx, is_free);
 
+                // free memory joins whatever free memory it touches
+                if is_free {
+                    return Some(self.merge_free_neighbors(new_frame_idx));
+                }
+
                 Some(new_frame_idx)
             }
             None => None,
@@ -418,6 +388,11 @@ impl<'n> TreeAllocator<'n> {
             self.remove_frame_from_alloc_trunks(frame_idx);
         }
 
+        self.release_frame(frame_idx);
+    }
+
+    // gives up the slot of a frame that's already out of the trunks
+    fn release_frame(&mut self, frame_idx: usize) {
         // deallocate the frame slot we were using via the bitmap
         self.dealloc_internal_frame_slot(frame_idx);
 
@@ -429,6 +404,45 @@ impl<'n> TreeAllocator<'n> {
         }
     }
 
+    // returns the (base, size) of the first free (or alloc'ed) frame whose base
+    // address is >= addr, or None if there are no more; this lets callers walk
+    // the frames in address order without holding on to any tree nodes
+    pub fn next_frame_by_addr(&self, addr: PhysAddr, is_free: bool) -> Option<(PhysAddr, usize)> {
+        let addr_trunk = if is_free {
+            unsafe { self.rb_addr_free.get().as_ref().unwrap() }
+        } else {
+            unsafe { self.rb_addr_alloc.get().as_ref().unwrap() }
+        };
+
+        match addr_trunk.ceiling_node(make128(addr.as_usize(), ZERO_USIZE)) {
+            Some(node) => {
+                let key = node.key();
+                Some((PhysAddr(hi64(key) as usize), lo64(key) as usize))
+            }
+            None => None,
+        }
+    }
+
+    // returns the owner of the frame containing addr, or None if the
+    // allocator doesn't track that address at all
+    pub fn frame_owner(&self, addr: PhysAddr) -> Option<Owner> {
+        let mem_frame_array = unsafe { self.mem_frame_nodes.get().as_ref().unwrap() };
+
+        for addr_trunk in [unsafe { self.rb_addr_alloc.get().as_ref().unwrap() }, unsafe { self.rb_addr_free.get().as_ref().unwrap() }] {
+            // the closest frame starting at or below addr
+            if let Some(node) = addr_trunk.floor_node(make128(addr.as_usize(), usize::MAX)) {
+                let frame_base = hi64(node.key()) as usize;
+                let frame_size = lo64(node.key()) as usize;
+
+                if range_contains(frame_base, frame_size, addr.as_usize()) {
+                    return Some(unsafe { mem_frame_array[node.value()].owner.get().as_ref().unwrap().clone() });
+                }
+            }
+        }
+
+        None
+    }
+
     // alloc before doing anything else
     fn alloc_internal_frame_slot(&mut self) -> Option<usize> {
         
@@ -787,8 +801,8 @@ impl<'n> TreeAllocator<'n> {
         // add the left frame back into the free trunks
         self.put_frame_into_free_trunks(left_frame_idx);
 
-        // remove the right frame entirely
-        self.remove_frame(right_frame_idx);
+        // the right frame is out of the trunks already; give up its slot
+        self.release_frame(right_frame_idx);
 
         // return the left frame's index
         Some(left_frame_idx)
@@ -902,67 +916,16 @@ impl<'n> TreeAllocator<'n> {
         // update the page info structs
         self.update_frame_info_structs(frame_idx, true);
 
-        // add the frame to the free trunks
+        // add the frame to the free trunks, merged with its free neighbors
         self.put_frame_into_free_trunks(frame_idx);
+        self.merge_free_neighbors(frame_idx);
 
         true
     }
-}
-
-impl<'n> FrameAllocator for TreeAllocator<'n> {
-    fn new(mem_nodes_base: PhysAddr, node_count: usize) -> Self {
-        #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        serial_println!("TreeAllocator::new(): -> allocating a new TreeAllocator");
 
-        let ret = TreeAllocator {
-            mem_frame_nodes: UnsafeCell::new(
-                unsafe {
-                    core::slice::from_raw_parts_mut::<'n, FrameDescr>(
-                        raw::abracadabra_ptr_mut::<FrameDescr, PhysAddr>(mem_nodes_base, false),
-                        node_count,
-                    )
-                }
-            ),
-            count: UnsafeCell::new(ZERO_USIZE),
-            capacity: UnsafeCell::new(ZERO_USIZE),
-
-            rb_size_free: UnsafeCell::new(RBTree::<MemNode>::new()),
-            rb_addr_free: UnsafeCell::new(RBTree::<MemNode>::new()),
-            rb_size_alloc: UnsafeCell::new(RBTree::<MemNode>::new()),
-            rb_addr_alloc: UnsafeCell::new(RBTree::<MemNode>::new()),
-
-            dealloc_since_last_coalesce_free_count: UnsafeCell::new(0usize),
-            merge_free_dealloc_interval: UnsafeCell::new(FRAME_ALLOCATOR_COALESCE_THRESHOLD_DEALLOC),
-
-            frame_node_slot_bitmap: Some(Bitmap::new(Owner::Memory)),            
-        };
-
-        #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        serial_println!("TreeAllocator::new(): -> allocation complete");
-
-        ret
-    }
-
-    // ok to panic in frame allocator init
-    fn init(&mut self) {
-
-        let neb = iron().unwrap();
-
-        #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        serial_println!("TreeAllocator::init(): -> iron: nebulae struct @ {:#x}", neb as *const Nebulae as usize);
-
-        let total_frames = neb.get_total_pages();
-        {
-            let cap_ref = unsafe { self.capacity.get().as_mut().unwrap() };
-            (*cap_ref) = total_frames;
-        }
-
-        #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        serial_println!("TreeAllocator::init(): -> allocator capacity set to {} frames; init complete", total_frames);
-    }
-
-    // Allocates memory by physical address & size
-    fn alloc_frame_fixed(&mut self, phys_addr: PhysAddr, size: usize, page_size: PageSize, owner: Owner) -> Option<PhysAddr> {
+    // claims the free memory @ phys_addr for owner, splitting frames as needed;
+    // alloc_frame_fixed() zeroes the block, reserve_frame_fixed() leaves it be
+    fn claim_frame_fixed(&mut self, phys_addr: PhysAddr, size: usize, page_size: PageSize, owner: Owner, zero: bool) -> Option<PhysAddr> {
         
         debug_assert!(phys_addr.is_aligned(page_size.as_usize()));
 
@@ -973,9 +936,6 @@ impl<'n> FrameAllocator for TreeAllocator<'n> {
         let aligned_size_in_pages = pages::bytes_to_pages(aligned_size, page_size);
         let aligned_size_in_bytes = pages::pages_to_bytes(aligned_size_in_pages, page_size);
 
-        // on a fixed allocation, we need to coalesce the free blocks
-        self.coalesce_free_frames();
-        
         // We need to see if the frame containing the desired address is free
 
         // get a reference to the root node of the address tree's free trunk
@@ -1057,32 +1017,28 @@ impl<'n> FrameAllocator for TreeAllocator<'n> {
                     self.mark_frame_allocated(left_node_idx, owner);
 
                     // zero the new block
-                    raw::memset_aligned(
-                        mem_frame_array[left_node_idx].mem_block.get_mut().base_addr,
-                        aligned_size,
-                        0usize,
-                    );
+                    if zero {
+                        raw::memset_aligned(
+                            mem_frame_array[left_node_idx].mem_block.get_mut().base_addr,
+                            aligned_size,
+                            0usize,
+                        );
+                    }
                 } else {
                     // the frame and the allocation request are the same size;
                     // mark the frame as allocated
                     self.mark_frame_allocated(parent_node_frame_idx, owner);
 
                     // zero the new block
-                    raw::memset_aligned(
-                        mem_frame_array[parent_node_frame_idx].mem_block.get_mut().base_addr,
-                        aligned_size,
-                        0usize,
-                    );
+                    if zero {
+                        raw::memset_aligned(
+                            mem_frame_array[parent_node_frame_idx].mem_block.get_mut().base_addr,
+                            aligned_size,
+                            0usize,
+                        );
+                    }
                 }
             } else {
-                // get a new frame slot
-                let new_frame_slot_result = self.alloc_internal_frame_slot();
-
-                // make sure we obtained a new frame slot
-                if new_frame_slot_result.is_none() {
-                    return None;
-                }
-
                 // split @ the offset creating a new frame (the offset frame will be left, allocated frame will be right)
                 let try_split_result =
                     self.split_free_frame(parent_node_frame_idx, addr_split_offset);
@@ -1119,54 +1075,112 @@ impl<'n> FrameAllocator for TreeAllocator<'n> {
                     self.mark_frame_allocated(left_node_idx_2, owner);
 
                     // zero the new block
-                    raw::memset_aligned(
-                        mem_frame_array[left_node_idx_2].mem_block.get_mut().base_addr,
-                        aligned_size,
-                        0usize,
-                    );
+                    if zero {
+                        raw::memset_aligned(
+                            mem_frame_array[left_node_idx_2].mem_block.get_mut().base_addr,
+                            aligned_size,
+                            0usize,
+                        );
+                    }
                 } else {
+                    // the right side is exactly the size requested;
                     // mark the frame as allocated
-                    self.mark_frame_allocated(parent_node_frame_idx, owner);
+                    self.mark_frame_allocated(right_node_idx, owner);
 
                     // zero the new block
-                    raw::memset_aligned(
-                        mem_frame_array[parent_node_frame_idx].mem_block.get_mut().base_addr,
-                        aligned_size,
-                        0usize,
-                    );
+                    if zero {
+                        raw::memset_aligned(
+                            mem_frame_array[right_node_idx].mem_block.get_mut().base_addr,
+                            aligned_size,
+                            0usize,
+                        );
+                    }
                 }
             }            
         }
         Some(phys_addr)
     }
 
-    // general purpose frame allocation
-    fn alloc_frame(
-        &mut self,
-        size: usize,
-        page_size: PageSize,
-        owner: Owner,
-    ) -> Option<PhysAddr> {
-        
-        debug_assert!(size.is_aligned(page_size.as_usize()));
+    // claims the free memory @ phys_addr for owner without touching its
+    // contents; for memory that's already spoken for by the firmware
+    // (acpi tables, etc.) but was handed to us as conventional
+    pub fn reserve_frame_fixed(&mut self, phys_addr: PhysAddr, size: usize, owner: Owner) -> Option<PhysAddr> {
+        self.claim_frame_fixed(phys_addr, size, PageSize::Small, owner, false)
+    }
+
+    // allocates (& zeroes) size bytes aligned to align (a power of two, at least
+    // a page) lying entirely within range, lowest address first. walks the free
+    // frames by address starting at range.start, so memory outside the range is
+    // never looked at.
+    pub fn alloc_frame_in(&mut self, range: Range<PhysAddr>, size: usize, align: usize, owner: Owner) -> Option<PhysAddr> {
+        let size = size.align_up(MEMORY_DEFAULT_PAGE_USIZE);
+        let align = usize::max(align, MEMORY_DEFAULT_PAGE_USIZE);
+
+        if size == ZERO_USIZE || !align.is_power_of_two() || range.start >= range.end {
+            return None;
+        }
+
+        let addr_free_trunk = unsafe { self.rb_addr_free.get().as_ref().unwrap() };
+
+        // start with the frame straddling range.start (if any), then move up
+        let mut node_result = addr_free_trunk
+            .floor_node(make128(range.start.as_usize(), usize::MAX))
+            .or_else(|| addr_free_trunk.ceiling_node(make128(range.start.as_usize(), ZERO_USIZE)));
+
+        while let Some(node) = node_result {
+            let frame_base = hi64(node.key()) as usize;
+            let frame_end = frame_base + lo64(node.key()) as usize;
+
+            if frame_base >= range.end.as_usize() {
+                break;
+            }
+
+            let lo = usize::max(frame_base, range.start.as_usize()).align_up(align);
+            let hi = usize::min(frame_end, range.end.as_usize());
+
+            if lo < hi && hi - lo >= size {
+                return self.claim_frame_fixed(PhysAddr(lo), size, PageSize::Small, owner, true);
+            }
 
-        // if the page_size is greater than the default page size, then we need to coalesce
-        if page_size.as_usize() > MEMORY_DEFAULT_PAGE_USIZE {
-            self.coalesce_free_frames();
+            node_result = addr_free_trunk.ceiling_node(node.key() + 1);
+        }
+
+        None
+    }
+
+    // allocates (& zeroes) size bytes aligned to align from zone, falling back to
+    // the zones below it when it's out of room
+    pub fn alloc_frame_in_zone(&mut self, zone: MemZone, size: usize, align: usize, owner: Owner) -> Option<PhysAddr> {
+        zone.fallbacks()
+            .filter_map(mem_zone)
+            .filter(|info| !info.is_empty())
+            .find_map(|info| self.alloc_frame_in(info.range(), size, align, owner))
+    }
+
+    // allocates (& zeroes) size bytes whose base is aligned to align (any power of
+    // two, at least a page); best fit, among the free frames that have an aligned
+    // run of size bytes. the misaligned head & the leftover tail stay free as
+    // frames of their own. returns None rather than a misaligned base.
+    pub fn alloc_frame_aligned(&mut self, size: usize, align: usize, owner: Owner) -> Option<PhysAddr> {
+        let aligned_size = align_up(size, MEMORY_DEFAULT_PAGE_USIZE);
+        let align = usize::max(align, MEMORY_DEFAULT_PAGE_USIZE);
+
+        if aligned_size == ZERO_USIZE || !align.is_power_of_two() {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("TreeAllocator::alloc_frame_aligned(): -> bad request: size = {}, align = 0x{:0x}", size, align);
+            return None;
         }
 
         let mem_frames = unsafe { self.mem_frame_nodes.get().as_mut().unwrap() };
-        let aligned_size = align_up(size, page_size.as_usize());
 
         // best fit
         let size_key = make128(aligned_size, 0);
-        let mut block_idx: usize;
-        let mut comp_node = unsafe { self.rb_size_free.get().as_mut().unwrap().ceiling_node(size_key) };
+        let mut comp_node = unsafe { self.rb_size_free.get().as_ref().unwrap().ceiling_node(size_key) };
 
         if comp_node.is_none() {
             // no free blocks large enough to satisfy the request
             #[cfg(all(debug_assertions, feature = "serialdbg"))]
-            serial_println!("TreeAllocator::alloc_frame(): no free blocks large enough to satisfy the request -> size = {}, size_key = 0x{:0x}", aligned_size, size_key);
+            serial_println!("TreeAllocator::alloc_frame_aligned(): no free blocks large enough to satisfy the request -> size = {}, size_key = 0x{:0x}", aligned_size, size_key);
             
             #[cfg(all(debug_assertions, feature = "serialdbg"))]
             unsafe { self.rb_size_free.get().as_ref().unwrap().print_tree() };
@@ -1174,138 +1188,147 @@ impl<'n> FrameAllocator for TreeAllocator<'n> {
             return None;
         }
 
-        #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        serial_println!("TreeAllocator::alloc_frame(): size = {}, size_key = 0x{:0x}, comp_node = 0x{:0x}", 
-            aligned_size, size_key, comp_node.unwrap() as *const MemNode as usize);
+        while let Some(node) = comp_node {
+            let addr = lo64(node.key()) as usize;
+            let sz = hi64(node.key()) as usize;
 
-        while comp_node.is_some() {
-            // unwraps are safe; comp_node is not none
-            let addr = lo64(comp_node.unwrap().key()) as usize;
-            let sz = hi64(comp_node.unwrap().key()) as usize;
-            block_idx = comp_node.unwrap().value();
+            // how far in the first aligned address is (None if it's past the end of memory)
+            let head = addr.checked_next_multiple_of(align).map(|aligned_addr| aligned_addr - addr);
 
-            if addr.is_aligned(page_size.as_usize()) && sz >= aligned_size {
-                
-                if sz == aligned_size {
-                    // if the page size is exact, then we don't need to split
+            // the size tree hands out frames of at least aligned_size, so sz - aligned_size can't wrap
+            if let Some(head) = head.filter(|&head| head <= sz - aligned_size) {
+                let frame_idx = match self.carve_free_frame(node.value(), head, aligned_size) {
+                    Some(frame_idx) => frame_idx,
+                    None => {
+                        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                        serial_println!("TreeAllocator::alloc_frame_aligned(): -> could not split memory frame");
+                        return None;
+                    }
+                };
 
-                    // mark the block as allocated
-                    self.mark_frame_allocated(block_idx, owner);
+                let base = mem_frames[frame_idx].mem_block.get_mut().base_addr;
 
-                    // zero the new block
-                    raw::memset_aligned(
-                        mem_frames[block_idx].mem_block.get_mut().base_addr,
-                        aligned_size,
-                        ZERO_USIZE,
-                    );
+                if !base.is_aligned(align) || mem_frames[frame_idx].mem_block.get_mut().size != aligned_size {
+                    // never hand out the wrong thing; leave the frame free & glue
+                    // the head & tail back onto it
+                    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                    serial_println!("TreeAllocator::alloc_frame_aligned(): -> carved frame is misaligned: base = 0x{:0x}, align = 0x{:0x}", base, align);
+                    self.merge_free_neighbors(frame_idx);
+                    return None;
+                }
 
-                    return Some(mem_frames[block_idx].mem_block.get_mut().base_addr);
+                self.mark_frame_allocated(frame_idx, owner);
 
-                } else {
+                // zero the new block
+                raw::memset_aligned(base, aligned_size, ZERO_USIZE);
 
-                    // split the block
-                    let nodes_opt =
-                        self.split_free_frame(block_idx, aligned_size);
-
-                    match nodes_opt {
-                        Some((left_node_idx, _right_node_idx)) => {
-                            self.mark_frame_allocated(left_node_idx, owner);
-
-                            // zero the new block
-                            raw::memset_aligned(
-                                mem_frames[left_node_idx].mem_block.get_mut().base_addr,
-                                aligned_size,
-                                0usize,
-                            );
-                            return Some(mem_frames[left_node_idx].mem_block.get_mut().base_addr);
-                        }
-                        None => {
-                            #[cfg(all(debug_assertions, feature = "serialdbg"))]
-                            serial_println!("TreeAllocator::alloc_frame(): -> could not split memory frame (left alloc)");
-                            return None;
-                        }
-                    }
-                }              
-            } else {
-                // see what it would take to align this frame
-                let aligned_addr = align_up(addr, page_size.as_usize());
-
-                if aligned_addr + aligned_size <= addr + sz {
-                    // this frame can be aligned
-                    
-                    // split the block
-                    // the first split will take off the lower addresses to bring the base address of
-                    // the frame up to the alignment boundary
-                    let nodes_opt =
-                        self.split_free_frame(block_idx, aligned_addr - addr);
-
-                    match nodes_opt {
-
-                        // the left frame will be what we trimmed to meet alignment requirements
-                        // so we're only interested in the right frame
-                        Some((_, right_node_idx)) => {
-                            
-                            // see if we need to split the block again
-                            if mem_frames[right_node_idx].mem_block.get_mut().size - aligned_size >= MEMORY_DEFAULT_PAGE_USIZE {
-                                
-                                let nodes_opt2 =
-                                    self.split_free_frame(right_node_idx, aligned_size);
-
-                                match nodes_opt2 {
-                                    Some((left_node_idx2, _)) => {
-                                        self.mark_frame_allocated(left_node_idx2, owner);
-
-                                        // zero the new block
-                                        raw::memset_aligned(
-                                            mem_frames[left_node_idx2].mem_block.get_mut().base_addr,
-                                            aligned_size,
-                                            ZERO_USIZE,
-                                        );
-                                        
-                                        return Some(mem_frames[left_node_idx2].mem_block.get_mut().base_addr);
-                                    }
-                                    None => {
-                                        #[cfg(all(debug_assertions, feature = "serialdbg"))]
-                                        serial_println!("TreeAllocator::alloc_frame(): -> could not re-split memory frame (old alloc)");
-                                        return None;
-                                    }
-                                }
-                            } else {
-                                // the block is too small to split, but it will work
-                                // mark the frame as allocated
-                                self.mark_frame_allocated(right_node_idx, owner);
-
-                                // zero the new block
-                                raw::memset_aligned(
-                                    mem_frames[right_node_idx].mem_block.get_mut().base_addr,
-                                    aligned_size,
-                                    0usize,
-                                );
-
-                                return Some(mem_frames[right_node_idx].mem_block.get_mut().base_addr);
-                            }
-                        }
-                        None => {
-                            #[cfg(all(debug_assertions, feature = "serialdbg"))]
-                            serial_println!("TreeAllocator::alloc_frame(): -> could not split memory frame (new alloc)");
-                            return None;
-                        }
-                    }
-                }
+                return Some(base);
             }
 
             // move to the next comparison node
-            // unwrap is safe with '?' operator
-            unsafe {
-                comp_node = self
-                    .rb_size_free
-                    .get().as_ref().unwrap()
-                    .ceiling_node(comp_node.unwrap().key() + 1);
-            }
+            comp_node = unsafe { self.rb_size_free.get().as_ref().unwrap().ceiling_node(node.key() + 1) };
         }
+
         None
     }
 
+    // cuts the size bytes starting offset bytes into free frame frame_idx out into
+    // a free frame of their own (the head & tail, if any, remain free frames);
+    // returns the index of the new frame, or None if a split failed
+    fn carve_free_frame(&mut self, frame_idx: usize, offset: usize, size: usize) -> Option<usize> {
+        let mut frame_idx = frame_idx;
+
+        if offset != ZERO_USIZE {
+            let (_head_idx, right_idx) = self.split_free_frame(frame_idx, offset)?;
+            frame_idx = right_idx;
+        }
+
+        let frame_size = unsafe { self.mem_frame_nodes.get().as_mut().unwrap()[frame_idx].mem_block.get_mut().size };
+
+        if frame_size > size {
+            match self.split_free_frame(frame_idx, size) {
+                Some((left_idx, _tail_idx)) => frame_idx = left_idx,
+                None => {
+                    // put the head back the way it was
+                    self.merge_free_neighbors(frame_idx);
+                    return None;
+                }
+            }
+        }
+
+        Some(frame_idx)
+    }
+}
+
+impl<'n> FrameAllocator for TreeAllocator<'n> {
+    fn new(mem_nodes_base: PhysAddr, node_count: usize) -> Self {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("TreeAllocator::new(): -> allocating a new TreeAllocator");
+
+        let ret = TreeAllocator {
+            mem_frame_nodes: UnsafeCell::new(
+                unsafe {
+                    core::slice::from_raw_parts_mut::<'n, FrameDescr>(
+                        raw::abracadabra_ptr_mut::<FrameDescr, PhysAddr>(mem_nodes_base, false),
+                        node_count,
+                    )
+                }
+            ),
+            count: UnsafeCell::new(ZERO_USIZE),
+            capacity: UnsafeCell::new(ZERO_USIZE),
+
+            rb_size_free: UnsafeCell::new(RBTree::<MemNode>::new()),
+            rb_addr_free: UnsafeCell::new(RBTree::<MemNode>::new()),
+            rb_size_alloc: UnsafeCell::new(RBTree::<MemNode>::new()),
+            rb_addr_alloc: UnsafeCell::new(RBTree::<MemNode>::new()),
+
+
+            frame_node_slot_bitmap: Some(Bitmap::new(Owner::Memory)),            
+        };
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("TreeAllocator::new(): -> allocation complete");
+
+        ret
+    }
+
+    // ok to panic in frame allocator init
+    fn init(&mut self) {
+
+        let neb = iron().unwrap();
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("TreeAllocator::init(): -> iron: nebulae struct @ {:#x}", neb as *const Nebulae as usize);
+
+        let total_frames = neb.get_total_pages();
+        {
+            let cap_ref = unsafe { self.capacity.get().as_mut().unwrap() };
+            (*cap_ref) = total_frames;
+        }
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("TreeAllocator::init(): -> allocator capacity set to {} frames; init complete", total_frames);
+    }
+
+    // Allocates memory by physical address & size
+    fn alloc_frame_fixed(&mut self, phys_addr: PhysAddr, size: usize, page_size: PageSize, owner: Owner) -> Option<PhysAddr> {
+        self.claim_frame_fixed(phys_addr, size, page_size, owner, true)
+    }
+
+    // general purpose frame allocation
+    fn alloc_frame(
+        &mut self,
+        size: usize,
+        page_size: PageSize,
+        owner: Owner,
+    ) -> Option<PhysAddr> {
+        
+        debug_assert!(size.is_aligned(page_size.as_usize()));
+
+        // a frame for page_size pages has to be aligned to (& a multiple of) page_size
+        self.alloc_frame_aligned(align_up(size, page_size.as_usize()), page_size.as_usize(), owner)
+    }
+
     // Deallocates a single page of memory of the specified size
     // page_base is the base address of the page to deallocate, not
     // the base address of the frame that contains the page
@@ -1364,27 +1387,9 @@ impl<'n> FrameAllocator for TreeAllocator<'n> {
             return false;
         }
 
-        // mark the frame as free
-        self.mark_frame_free(frame_to_dealloc_idx, owner);
-
-        // see if we need to coalesce the free space
-        if unsafe { self.dealloc_since_last_coalesce_free_count.get().as_ref().unwrap() }.clone() > unsafe { self.merge_free_dealloc_interval.get().as_ref().unwrap() }.clone() {
-            self.coalesce_free_frames();
-            {
-                let count_ref = unsafe { self.dealloc_since_last_coalesce_free_count.get().as_mut().unwrap() };
-                (*count_ref) = ZERO_USIZE;
-            }
-        } else {
-            let new_count = unsafe { self.dealloc_since_last_coalesce_free_count.get().as_ref().unwrap().clone() + 1 };
-            {
-                let count_ref = unsafe { self.dealloc_since_last_coalesce_free_count.get().as_mut().unwrap() };
-                (*count_ref) = new_count;
-            }
-        }
-
-        // update the page info structs
-        self.update_frame_info_structs(frame_to_dealloc_idx, true);
-        true
+        // mark the frame as free (which updates the page info structs & merges
+        // it with its free neighbors)
+        self.mark_frame_free(frame_to_dealloc_idx, owner)
     }
 
     fn free_page_count(&mut self) -> usize {
@@ -0,0 +1,134 @@
+// Purpose: physical memory zones. Some memory is only good for some things:
+// isa dma can only reach the first 16m, 32 bit pci devices (& the ap startup
+// trampoline) the first 4g. The zones carve physical memory up along those
+// lines; what's actually in each comes from the boot memory map, and
+// the frame allocator's alloc_frame_in_zone() allocates from them.
+
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::common::base::*;
+use crate::bringup::bootinfo::*;
+use crate::vmem::*;
+
+// the zone boundaries
+pub const MEM_ZONE_DMA16_END: usize = USIZE_16M;
+#[cfg(target_pointer_width = "64")]
+pub const MEM_ZONE_DMA32_END: usize = USIZE_4G;
+// a 32 bit kernel can't see past 4g anyhow
+#[cfg(target_pointer_width = "32")]
+pub const MEM_ZONE_DMA32_END: usize = usize::MAX;
+
+pub const MEM_ZONE_COUNT: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemZone {
+    // below 16m: legacy isa dma
+    Dma16,
+    // 16m to 4g: 32 bit dma
+    Dma32,
+    // everything above 4g
+    Normal,
+}
+
+impl MemZone {
+    pub const ALL: [MemZone; MEM_ZONE_COUNT] = [MemZone::Dma16, MemZone::Dma32, MemZone::Normal];
+
+    // the addresses the zone covers (whether or not there's memory there)
+    pub const fn span(self) -> Range<usize> {
+        match self {
+            MemZone::Dma16 => ZERO_USIZE..MEM_ZONE_DMA16_END,
+            MemZone::Dma32 => MEM_ZONE_DMA16_END..MEM_ZONE_DMA32_END,
+            MemZone::Normal => MEM_ZONE_DMA32_END..usize::MAX,
+        }
+    }
+
+    // the zones an allocation for this zone may come out of, best first: its
+    // own, then the ones below it (anything that fits under 4g fits under 16m,
+    // but dma16 memory is scarce, so it's the last resort)
+    pub fn fallbacks(self) -> impl Iterator<Item = MemZone> {
+        MemZone::ALL.into_iter().rev().skip_while(move |&z| z != self)
+    }
+
+    pub const fn as_index(self) -> usize {
+        self as usize
+    }
+}
+
+// what the boot memory map put in a zone
+#[derive(Copy, Clone, Debug)]
+pub struct MemZoneInfo {
+    pub zone: MemZone,
+    // the lowest & highest usable addresses in the zone (base == end if there's none);
+    // boot services & loader memory count, it's usable once it's been reclaimed
+    pub base: PhysAddr,
+    pub end: PhysAddr,
+    pub usable_pages: usize,
+}
+
+impl MemZoneInfo {
+    pub fn is_empty(&self) -> bool {
+        self.usable_pages == ZERO_USIZE
+    }
+
+    // where in the zone an allocation can actually land
+    pub fn range(&self) -> Range<PhysAddr> {
+        self.base..self.end
+    }
+}
+
+static mut MEM_ZONES: Option<[MemZoneInfo; MEM_ZONE_COUNT]> = None;
+
+// memory that is (or will be) the frame allocator's to hand out
+fn mem_zone_region_is_usable(ty: MemRegionType) -> bool {
+    ty == MemRegionType::Usable || ty == MemRegionType::BootServices || ty == MemRegionType::Loader
+}
+
+// builds the zones from the usable regions in the boot memory map, leaving out
+// everything at & above limit (mem=)
+pub fn mem_zones_init(regions: &[MemRegion], limit: usize) {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { FUSE.load(Ordering::SeqCst) } {
+        return;
+    } else {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let zones = MemZone::ALL.map(|zone| {
+        let span = zone.span();
+        let mut info = MemZoneInfo { zone, base: PhysAddr(ZERO_USIZE), end: PhysAddr(ZERO_USIZE), usable_pages: ZERO_USIZE };
+
+        for r in regions.iter().filter(|r| mem_zone_region_is_usable(r.ty)) {
+            let lo = usize::max(r.base.as_usize(), span.start);
+            let hi = usize::min(usize::min(r.end(), span.end), limit);
+
+            if lo >= hi {
+                continue;
+            }
+
+            if info.usable_pages == ZERO_USIZE || lo < info.base.as_usize() {
+                info.base = PhysAddr(lo);
+            }
+
+            info.end = PhysAddr(usize::max(info.end.as_usize(), hi));
+            info.usable_pages += pages::bytes_to_pages(hi - lo, MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+        }
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("zone {:?}: 0x{:08x} - 0x{:08x}, {} usable pages", zone, info.base, info.end, info.usable_pages);
+
+        info
+    });
+
+    unsafe {
+        MEM_ZONES = Some(zones);
+    }
+}
+
+// the zone's extent, once mem_zones_init() has run
+pub fn mem_zone(zone: MemZone) -> Option<&'static MemZoneInfo> {
+    unsafe { (*core::ptr::addr_of!(MEM_ZONES)).as_ref() }.map(|zones| &zones[zone.as_index()])
+}
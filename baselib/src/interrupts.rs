@@ -1,5 +1,316 @@
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub use crate::arch::x86::interrupts::*;
 
 #[cfg(target_arch = "aarch64")]
 pub use crate::arch::aa64::exception::*;
+
+// Purpose: the driver facing interrupt api. Drivers hook a vector with a
+// handler + context, optionally getting the vector (or an msi block) from
+// the allocator first; the arch backend takes care of the gates / controller
+// & eoi, so nothing in here knows whether it's sitting on an apic or a gic.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+
+use crate::common::base::*;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::irq::*;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aa64::irq::*;
+
+// an interrupt vector as the arch sees it (idt vector on x86, interrupt id on the gic)
+pub type InterruptVector = u32;
+
+// handlers get the vector that fired & the context they registered with
+pub type InterruptHandler = fn(InterruptVector, usize) -> InterruptResult;
+
+// how many handlers can share one vector
+pub const INTERRUPT_CHAIN_DEPTH: usize = 4;
+
+// the most vectors a single msi block can span
+pub const INTERRUPT_MSI_MAX_VECTORS: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterruptResult {
+    // the interrupt was for this handler
+    Handled,
+    // not ours; somebody else on the chain may want it
+    NotMine,
+}
+
+// the address / data pair a device writes to raise an msi
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MsiMessage {
+    pub address: u64,
+    pub data: u32,
+}
+
+// identifies a registration, for unregister_handler()
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InterruptHandle {
+    pub vector: InterruptVector,
+    id: u32,
+}
+
+//-----------------------------------------------------------------------------------
+
+#[derive(Copy, Clone)]
+struct InterruptChainEntry {
+    handler: Option<InterruptHandler>,
+    context: usize,
+    priority: Priority,
+    id: u32,
+}
+
+impl InterruptChainEntry {
+    const fn empty() -> Self {
+        InterruptChainEntry {
+            handler: None,
+            context: ZERO_USIZE,
+            priority: Priority::Lowest,
+            id: ZERO_U32,
+        }
+    }
+}
+
+struct InterruptTable {
+    // handlers per vector, highest priority first
+    chains: [[InterruptChainEntry; INTERRUPT_CHAIN_DEPTH]; ARCH_INTERRUPT_VECTOR_COUNT],
+    // vectors handed out by the allocator
+    reserved: [bool; ARCH_INTERRUPT_VECTOR_COUNT],
+    next_id: u32,
+}
+
+impl InterruptTable {
+    const fn new() -> Self {
+        InterruptTable {
+            chains: [[InterruptChainEntry::empty(); INTERRUPT_CHAIN_DEPTH]; ARCH_INTERRUPT_VECTOR_COUNT],
+            reserved: [false; ARCH_INTERRUPT_VECTOR_COUNT],
+            next_id: 1,
+        }
+    }
+
+    fn chain_len(&self, vector: InterruptVector) -> usize {
+        self.chains[vector as usize].iter().take_while(|e| e.handler.is_some()).count()
+    }
+
+    // a vector is free if nobody allocated it & nobody hooked it directly
+    fn vector_is_free(&self, vector: InterruptVector) -> bool {
+        !self.reserved[vector as usize] && self.chain_len(vector) == 0
+    }
+}
+
+// the table is only ever locked with interrupts off on the locking cpu,
+// so the dispatcher can't deadlock against a registration in progress
+static INTERRUPT_TABLE: Mutex<InterruptTable> = Mutex::new(InterruptTable::new());
+
+// hits per vector, and the hits nobody on the chain claimed
+const ZERO_HITS: AtomicU64 = AtomicU64::new(0);
+static INTERRUPT_HITS: [AtomicU64; ARCH_INTERRUPT_VECTOR_COUNT] = [ZERO_HITS; ARCH_INTERRUPT_VECTOR_COUNT];
+static INTERRUPT_UNCLAIMED: [AtomicU64; ARCH_INTERRUPT_VECTOR_COUNT] = [ZERO_HITS; ARCH_INTERRUPT_VECTOR_COUNT];
+
+// runs f against the table with interrupts disabled on this cpu
+fn with_interrupt_table<R>(f: impl FnOnce(&mut InterruptTable) -> R) -> R {
+    let were_enabled = arch_interrupt_save_disable();
+    let result = f(&mut INTERRUPT_TABLE.lock());
+    arch_interrupt_restore(were_enabled);
+
+    result
+}
+
+//-----------------------------------------------------------------------------------
+
+// hooks handler onto vector. vectors are shared: every handler on the chain gets a
+// look at each interrupt, highest priority first. returns None if the vector isn't
+// one drivers may use or its chain is full.
+pub fn register_handler(vector: InterruptVector, priority: Priority, handler: InterruptHandler, context: usize) -> Option<InterruptHandle> {
+    if !arch_interrupt_vector_is_dynamic(vector) {
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("interrupts::register_handler() -> vector 0x{:0x} is not available to drivers", vector);
+        return None;
+    }
+
+    register_handler_internal(vector, priority, handler, context)
+}
+
+// like register_handler(), but for the kernel's own per-cpu sources (the timer,
+// ipis) that live outside the driver range
+pub fn register_system_handler(vector: InterruptVector, priority: Priority, handler: InterruptHandler, context: usize) -> Option<InterruptHandle> {
+    if vector as usize >= ARCH_INTERRUPT_VECTOR_COUNT {
+        return None;
+    }
+
+    register_handler_internal(vector, priority, handler, context)
+}
+
+fn register_handler_internal(vector: InterruptVector, priority: Priority, handler: InterruptHandler, context: usize) -> Option<InterruptHandle> {
+    with_interrupt_table(|table| {
+        let len = table.chain_len(vector);
+
+        if len == INTERRUPT_CHAIN_DEPTH {
+            #[cfg(all(debug_assertions, feature = "serialdbg"))]
+            serial_println!("interrupts::register_handler() -> handler chain for vector 0x{:0x} is full", vector);
+            return None;
+        }
+
+        let id = table.next_id;
+        table.next_id = table.next_id.wrapping_add(1).max(1);
+
+        // equal priorities keep registration order
+        let chain = &mut table.chains[vector as usize];
+        let pos = chain[..len].iter().position(|e| e.priority < priority).unwrap_or(len);
+
+        chain.copy_within(pos..len, pos + 1);
+        chain[pos] = InterruptChainEntry {
+            handler: Some(handler),
+            context,
+            priority,
+            id,
+        };
+
+        // the head of the chain decides the priority the controller sees
+        arch_interrupt_install(vector, chain[0].priority);
+
+        Some(InterruptHandle { vector, id })
+    })
+}
+
+// takes a registration off its chain; the vector's gate goes away with the
+// last handler. returns false if the handle wasn't registered.
+pub fn unregister_handler(handle: InterruptHandle) -> bool {
+    if handle.vector as usize >= ARCH_INTERRUPT_VECTOR_COUNT {
+        return false;
+    }
+
+    with_interrupt_table(|table| {
+        let len = table.chain_len(handle.vector);
+        let chain = &mut table.chains[handle.vector as usize];
+
+        let pos = match chain[..len].iter().position(|e| e.id == handle.id) {
+            Some(pos) => pos,
+            None => return false,
+        };
+
+        chain.copy_within(pos + 1..len, pos);
+        chain[len - 1] = InterruptChainEntry::empty();
+
+        if len == 1 {
+            arch_interrupt_remove(handle.vector);
+        } else {
+            arch_interrupt_install(handle.vector, chain[0].priority);
+        }
+
+        true
+    })
+}
+
+// reserves a vector nobody else is using, in the range the arch
+// associates with priority
+pub fn allocate_vector(priority: Priority) -> Option<InterruptVector> {
+    allocate_vectors(1, priority)
+}
+
+// reserves count contiguous vectors, aligned to count, as multi-message msi
+// requires; count must be a power of two. returns the first vector.
+pub fn allocate_vectors(count: usize, priority: Priority) -> Option<InterruptVector> {
+    if count == ZERO_USIZE || !count.is_power_of_two() || count > INTERRUPT_MSI_MAX_VECTORS {
+        return None;
+    }
+
+    let (first, last) = arch_interrupt_priority_range(priority);
+    let count = count as InterruptVector;
+
+    with_interrupt_table(|table| {
+        let mut base = align_up(first as usize, count as usize) as InterruptVector;
+
+        while base + count - 1 <= last {
+            if (base..base + count).all(|v| table.vector_is_free(v)) {
+                for v in base..base + count {
+                    table.reserved[v as usize] = true;
+                }
+
+                return Some(base);
+            }
+
+            base += count;
+        }
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("interrupts::allocate_vectors() -> no block of {} free vectors at {:?} priority", count, priority);
+
+        None
+    })
+}
+
+// hands vectors from allocate_vector(s) back; handlers still hooked on them
+// stay put until they're unregistered
+pub fn free_vectors(first: InterruptVector, count: usize) {
+    with_interrupt_table(|table| {
+        for v in first..first + count as InterruptVector {
+            if arch_interrupt_vector_is_dynamic(v) {
+                table.reserved[v as usize] = false;
+            }
+        }
+    });
+}
+
+// the msi address / data that raises vector on the given cpu
+pub fn msi_message(vector: InterruptVector, target_cpu: u32) -> Option<MsiMessage> {
+    if !arch_interrupt_vector_is_dynamic(vector) {
+        return None;
+    }
+
+    arch_msi_message(vector, target_cpu)
+}
+
+// how many times vector has fired
+pub fn interrupt_hits(vector: InterruptVector) -> u64 {
+    match INTERRUPT_HITS.get(vector as usize) {
+        Some(hits) => hits.load(Ordering::Relaxed),
+        None => ZERO_U64,
+    }
+}
+
+// how many times vector fired without any handler claiming it
+pub fn interrupt_unclaimed(vector: InterruptVector) -> u64 {
+    match INTERRUPT_UNCLAIMED.get(vector as usize) {
+        Some(hits) => hits.load(Ordering::Relaxed),
+        None => ZERO_U64,
+    }
+}
+
+//-----------------------------------------------------------------------------------
+
+// called by the arch backend, with interrupts disabled, for every device vector
+pub fn interrupt_dispatch(vector: InterruptVector) {
+    let idx = vector as usize;
+
+    if idx >= ARCH_INTERRUPT_VECTOR_COUNT {
+        return;
+    }
+
+    INTERRUPT_HITS[idx].fetch_add(1, Ordering::Relaxed);
+
+    // work on a copy so handlers are free to (un)register
+    let chain = INTERRUPT_TABLE.lock().chains[idx];
+    let mut claimed = false;
+
+    for entry in chain.iter() {
+        let handler = match entry.handler {
+            Some(handler) => handler,
+            None => break,
+        };
+
+        if handler(vector, entry.context) == InterruptResult::Handled {
+            claimed = true;
+        }
+    }
+
+    if !claimed {
+        INTERRUPT_UNCLAIMED[idx].fetch_add(1, Ordering::Relaxed);
+    }
+
+    arch_interrupt_eoi(vector);
+}
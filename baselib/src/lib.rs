@@ -10,10 +10,13 @@
 #![feature(slice_ptr_get)]
 #![feature(const_for)]
 #![feature(custom_test_frameworks)]
-#![test_runner(crate::test_runner)]
+#![test_runner(crate::tests::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![cfg_attr(test, no_main)]
 #![feature(strict_provenance)]
 
 // baselib mods
+pub mod acpi;
 pub mod bringup;
 pub mod permissions;
 pub mod common;
@@ -21,13 +24,18 @@ pub mod cpu;
 pub mod rng;
 pub mod sync;
 pub mod frame_alloc;
+pub mod interrupts;
 pub mod nebulae;
 pub mod vmem;
 pub mod kalloc;
 pub mod memory;
 pub mod panic;
+pub mod params;
+pub mod power;
 pub mod status;
 pub mod structures;
+pub mod timer;
+pub mod clock;
 
 // baselib::arch mods
 pub mod arch {
@@ -35,16 +43,33 @@ pub mod arch {
     pub mod aa64 {
         pub mod asm;
         pub mod cpu;
+        pub mod exception;
+        pub mod gic;
+        pub mod irq;
+        pub mod power;
         pub mod serial;
+        pub mod timer;
     }
 
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     pub mod x86 {
+        pub mod apic;
         pub mod asm;
         pub mod cache_descriptor;
         pub mod cpu;
+        pub mod gdt;
+        pub mod hpet;
+        pub mod interrupts;
+        pub mod irq;
+        pub mod page_fault;
+        pub mod pic;
+        pub mod pit;
+        pub mod power;
         pub mod random;
-        pub mod serial;        
+        pub mod rtc;
+        pub mod serial;
+        pub mod timer;
+        pub mod tsc;
     }
 }
 
@@ -52,10 +77,22 @@ pub mod arch {
 mod tests {
     use super::*;
 
+    // the test build is its own uefi image; all it does is run the tests
+    #[uefi::entry]
+    fn test_start(_image_handle: uefi::Handle, _system_table: uefi::table::SystemTable<uefi::table::Boot>) -> uefi::Status {
+        crate::test_main();
+        uefi::Status::SUCCESS
+    }
+
+    // one at a time, in order. fixtures that need real memory (node storage,
+    // bitmaps & the like) live in statics each test resets before use, which
+    // only works because nothing here runs two tests at once.
     pub fn test_runner(tests: &[&dyn Fn()]) {
         serial_println!("running {} tests", tests.len());
         for test in tests {
             test();
         }
+
+        crate::power::exit_with_code(crate::power::POWER_EXIT_SUCCESS);
     }
 }
\ No newline at end of file
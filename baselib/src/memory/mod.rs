@@ -8,6 +8,8 @@ use crate::common::base::*;
 // Re-exports
 pub use crate::memory::address::*;
 pub use crate::frame_alloc::*;
+pub use crate::frame_alloc::zone::*;
+pub use crate::frame_alloc::cache::*;
 pub use crate::vmem::*;
 
 // CONSTANTS
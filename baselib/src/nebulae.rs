@@ -4,9 +4,12 @@ use crate::serial_println;
 // External Items
 // Internal Items
 use crate::common::base::*;
+use crate::cpu::wait_forever;
 use crate::rng::isaac64::Isaac64Rng;
 use crate::frame_alloc::*;
 use crate::vmem::*;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::asm::x86_enable_interrupts;
 
 #[repr(C)]
 pub struct Nebulae<'n> {
@@ -15,12 +18,16 @@ pub struct Nebulae<'n> {
     pub status: HybridLock<KernelStatus>,
     
     conv_pages: usize,
+    reclaimed_pages: usize,
     total_pages: usize,
     phys_mem_boundary: PhysAddr,
+
+    kernel_stack_base: Option<PhysAddr>,
+    kernel_stack_size: usize,
     
     pub page_info_structs_01: HybridLock<Option<&'n mut [pages::PageInfoStruct]>>,
     pub krng_03: HybridLock<Option<Isaac64Rng<'n>>>,
-    pub frame_alloc_internal_04: HybridLock<Option<TreeAllocator<'n>>>,
+    pub frame_alloc_internal_04: HybridLock<Option<KernelFrameAllocator<'n>>>,
     pub frame_alloc_05: HybridLock<bool>,
     pub base_vas_internal_06: HybridLock<bool>,
     pub base_vas_07: HybridLock<Option<Vas>>,
@@ -40,9 +47,12 @@ impl<'n> Nebulae<'n> {
         neb.internal_id = neb_fn(neb_fn_seed);
         neb.status = HybridLock::new(LockType::ExclusiveReadWrite, KernelStatus::new());
         neb.conv_pages = conv_pages;
+        neb.reclaimed_pages = ZERO_USIZE;
         neb.total_pages = total_pages;
         neb.phys_mem_boundary = phys_mem_boundary;
         neb.orig_mem_map_addr = Some(orig_mem_map_addr);
+        neb.kernel_stack_base = None;
+        neb.kernel_stack_size = ZERO_USIZE;
         neb.page_info_structs_01 = HybridLock::new(LockType::ExclusiveReadWrite, None);
         neb.krng_03 = HybridLock::new(LockType::ExclusiveReadWrite, None);
         neb.frame_alloc_internal_04 = HybridLock::new(LockType::ExclusiveReadWrite, None);
@@ -63,6 +73,15 @@ impl<'n> Nebulae<'n> {
         self.conv_pages
     }
 
+    // the firmware & loader memory handed back by kernel_reclaim_boot_memory()
+    pub fn get_reclaimed_pages(&self) -> usize {
+        self.reclaimed_pages
+    }
+
+    pub fn set_reclaimed_pages(&mut self, reclaimed_pages: usize) {
+        self.reclaimed_pages = reclaimed_pages;
+    }
+
     pub fn get_total_pages(&self) -> usize {
         self.total_pages
     }
@@ -70,6 +89,30 @@ impl<'n> Nebulae<'n> {
     pub fn get_phys_mem_boundary(&self) -> PhysAddr {
         self.phys_mem_boundary
     }
+
+    // the kernel stack can only be set once
+    pub fn set_kernel_stack(&mut self, base: PhysAddr, size: usize) {
+        debug_assert!(self.kernel_stack_base.is_none());
+
+        self.kernel_stack_base = Some(base);
+        self.kernel_stack_size = size;
+    }
+
+    pub fn get_kernel_stack_base(&self) -> Option<PhysAddr> {
+        self.kernel_stack_base
+    }
+
+    pub fn get_kernel_stack_size(&self) -> usize {
+        self.kernel_stack_size
+    }
+
+    // stacks grow down, so this is where rsp/esp starts out
+    pub fn get_kernel_stack_top(&self) -> Option<PhysAddr> {
+        match self.kernel_stack_base {
+            None => None,
+            Some(base) => Some((base.as_usize() + self.kernel_stack_size).as_phys()),
+        }
+    }
 }
 
 // This is our genesis block function. For children,
@@ -80,6 +123,49 @@ pub fn nebulae(let_the_magic_in: usize) -> usize {
 }
 
 // Here. Goes. Nothing.
-pub fn kernel_main() {
+// kernel_init() jumps here on the freshly minted kernel stack; there is
+// nowhere to return to
+pub extern "C" fn kernel_main() -> ! {
+    // make sure we actually came in through the front door
+    debug_assert!(kernel_stack_fuse(true));
+
     serial_println!("kernel_main() called with new stack");
+
+    // nothing refers to the firmware's & the loader's memory anymore
+    kernel_reclaim_boot_memory();
+
+    // we're on our own page tables now, single pages can go through the per-cpu
+    // frame magazines
+    frame_cache_init();
+
+    // memory stats
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    if crate::params::kernel_params().verbosity >= crate::params::KERNEL_VERBOSITY_NORMAL {
+        // cached frames count as allocated until they're handed back
+        frame_cache_drain_all();
+
+        let free_pages = iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
+            .free_page_count();
+
+        serial_println!(
+            "Free pages: {} / {} KB (reclaimed from boot: {} / {} KB)",
+            free_pages,
+            free_pages << 2,
+            iron().unwrap().get_reclaimed_pages(),
+            iron().unwrap().get_reclaimed_pages() << 2
+        );
+    }
+
+    // the interrupt controller is set up & everything routed through it is
+    // masked until a driver asks for it, so it's safe to take interrupts now
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        let external_interrupts = iron().unwrap().status.lock_rw_spin().as_mut().unwrap().external_interrupts;
+
+        if external_interrupts != KernelServiceStatus::Uninit {
+            x86_enable_interrupts();
+        }
+    }
+
+    wait_forever();
 }
\ No newline at end of file
@@ -4,6 +4,7 @@
 
 //! A panic handler that infinitely waits.
 
+#[cfg(not(feature = "qemuexit"))]
 use crate::cpu;
 use crate::serial_println;
 
@@ -19,14 +20,14 @@ use core::panic::PanicInfo;
 #[linkage = "weak"]
 #[no_mangle]
 fn _panic_exit() -> ! {
-    #[cfg(not(feature = "test_build"))]
+    #[cfg(not(feature = "qemuexit"))]
     {
         cpu::wait_forever()
     }
 
-    #[cfg(feature = "test_build")]
+    #[cfg(feature = "qemuexit")]
     {
-        cpu::qemu_exit_failure()
+        crate::power::exit_with_code(crate::power::POWER_EXIT_FAILURE)
     }
 }
 
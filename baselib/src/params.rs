@@ -0,0 +1,202 @@
+// Purpose: the kernel's command line parameters. The boot method hands over a
+// command line (uefi load options, the multiboot2 command line tag) in the
+// BootInfo; kernel_init() parses it once, early, into a KernelParams, and
+// everything else reads the typed values from there. Parameters are
+// whitespace separated, either key=value or a bare flag:
+//
+//   verbosity=<n>           how chatty the kernel is (0 quiet, 1 normal, 2+ debug)
+//   rngseed=<n>             seed the kernel rng with n instead of the hw / firmware
+//                           (reproducible runs; NOT for anything that matters)
+//   mem=<n>[k|m|g]          ignore usable memory at & above n bytes
+//   noapic                  leave the apics alone (no external interrupts)
+//
+// Numbers are decimal or 0x hex. Anything unknown or malformed is ignored (&
+// said so in debug builds); a bad command line shouldn't keep us from booting.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::common::base::*;
+
+pub const KERNEL_VERBOSITY_QUIET: usize = 0;
+pub const KERNEL_VERBOSITY_NORMAL: usize = 1;
+pub const KERNEL_VERBOSITY_DEBUG: usize = 2;
+
+#[derive(Copy, Clone, Debug)]
+pub struct KernelParams {
+    pub verbosity: usize,
+    pub rng_seed: Option<u64>,
+    pub mem_limit: Option<usize>,
+    pub noapic: bool,
+}
+
+impl KernelParams {
+    // what we run with when the command line doesn't say otherwise
+    pub const fn defaults() -> KernelParams {
+        KernelParams {
+            verbosity: KERNEL_VERBOSITY_NORMAL,
+            rng_seed: None,
+            mem_limit: None,
+            noapic: false,
+        }
+    }
+
+    // applies each parameter on the command line over the defaults
+    pub fn parse(cmdline: &str) -> KernelParams {
+        let mut params = KernelParams::defaults();
+
+        for token in cmdline.split_ascii_whitespace() {
+            let (key, value) = match token.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (token, None),
+            };
+
+            let applied = match (key, value) {
+                ("verbosity", Some(v)) => params_parse_usize(v).map(|n| params.verbosity = n).is_some(),
+                ("rngseed", Some(v)) => params_parse_usize(v).map(|n| params.rng_seed = Some(n as u64)).is_some(),
+                ("mem", Some(v)) => params_parse_size(v).filter(|&n| n != ZERO_USIZE).map(|n| params.mem_limit = Some(n)).is_some(),
+                ("noapic", None) => {
+                    params.noapic = true;
+                    true
+                }
+                _ => false,
+            };
+
+            if !applied {
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("params: ignoring \"{}\"", token);
+            }
+        }
+
+        params
+    }
+}
+
+// a decimal or 0x hex number
+fn params_parse_usize(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse::<usize>().ok(),
+    }
+}
+
+// a number of bytes, optionally in k / m / g
+fn params_parse_size(s: &str) -> Option<usize> {
+    let (digits, shift) = match s.as_bytes().last()? {
+        b'k' | b'K' => (&s[..s.len() - 1], 10),
+        b'm' | b'M' => (&s[..s.len() - 1], 20),
+        b'g' | b'G' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+
+    params_parse_usize(digits)?.checked_mul(1 << shift)
+}
+
+static mut KERNEL_PARAMS: Option<KernelParams> = None;
+
+// parses the command line (if any) into the kernel's parameters; only the first
+// call counts
+pub fn kernel_params_init(cmdline: Option<&str>) -> &'static KernelParams {
+    // Function guard - atomic fuse
+    static mut FUSE: AtomicBool = AtomicBool::new(false);
+
+    if unsafe { !FUSE.load(Ordering::SeqCst) } {
+        unsafe {
+            FUSE.store(true, Ordering::SeqCst);
+        }
+
+        let params = cmdline.map_or(KernelParams::defaults(), KernelParams::parse);
+
+        #[cfg(all(debug_assertions, feature = "serialdbg"))]
+        serial_println!("params: {:?}", params);
+
+        unsafe {
+            KERNEL_PARAMS = Some(params);
+        }
+    }
+
+    kernel_params()
+}
+
+// the kernel's parameters; the defaults until kernel_params_init() has run
+pub fn kernel_params() -> &'static KernelParams {
+    static DEFAULTS: KernelParams = KernelParams::defaults();
+
+    unsafe { (*core::ptr::addr_of!(KERNEL_PARAMS)).as_ref() }.unwrap_or(&DEFAULTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn params_parse_numbers() {
+        assert_eq!(params_parse_usize("42"), Some(42));
+        assert_eq!(params_parse_usize("0x2a"), Some(42));
+        assert_eq!(params_parse_usize("0X2A"), Some(42));
+        assert_eq!(params_parse_usize("0x"), None);
+        assert_eq!(params_parse_usize("-1"), None);
+        assert_eq!(params_parse_usize("4z"), None);
+    }
+
+    #[test_case]
+    fn params_parse_sizes() {
+        assert_eq!(params_parse_size("4096"), Some(4096));
+        assert_eq!(params_parse_size("4k"), Some(USIZE_4K));
+        assert_eq!(params_parse_size("2M"), Some(USIZE_2M));
+        assert_eq!(params_parse_size("1g"), Some(USIZE_1G));
+        assert_eq!(params_parse_size("0x10m"), Some(USIZE_16M));
+        assert_eq!(params_parse_size(""), None);
+        assert_eq!(params_parse_size("m"), None);
+        assert_eq!(params_parse_size("4t"), None);
+
+        // too big to be a size
+        assert_eq!(params_parse_size("18446744073709551616"), None);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(params_parse_size("0x400000000g"), None);
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(params_parse_size("4g"), None);
+    }
+
+    #[test_case]
+    fn params_defaults() {
+        let params = KernelParams::parse("");
+
+        assert_eq!(params.verbosity, KERNEL_VERBOSITY_NORMAL);
+        assert_eq!(params.rng_seed, None);
+        assert_eq!(params.mem_limit, None);
+        assert!(!params.noapic);
+    }
+
+    #[test_case]
+    fn params_parse_cmdline() {
+        let params = KernelParams::parse("  verbosity=2\trngseed=0xdeadbeef mem=128M noapic ");
+
+        assert_eq!(params.verbosity, KERNEL_VERBOSITY_DEBUG);
+        assert_eq!(params.rng_seed, Some(0xDEAD_BEEF));
+        assert_eq!(params.mem_limit, Some(128 * USIZE_1M));
+        assert!(params.noapic);
+    }
+
+    #[test_case]
+    fn params_ignore_bad_tokens() {
+        // unknown keys, bad values, flags with values & keys without them
+        let params = KernelParams::parse("quiet verbosity=loud rngseed= mem=lots noapic=1 verbosity foo=bar =3");
+
+        assert_eq!(params.verbosity, KERNEL_VERBOSITY_NORMAL);
+        assert_eq!(params.rng_seed, None);
+        assert_eq!(params.mem_limit, None);
+        assert!(!params.noapic);
+
+        // a bad token doesn't spoil the good ones around it
+        let params = KernelParams::parse("mem=lots verbosity=0 bogus");
+        assert_eq!(params.verbosity, KERNEL_VERBOSITY_QUIET);
+    }
+
+    #[test_case]
+    fn params_reject_zero_mem() {
+        assert_eq!(KernelParams::parse("mem=0").mem_limit, None);
+        assert_eq!(KernelParams::parse("mem=0k").mem_limit, None);
+
+        // a later good one still counts
+        assert_eq!(KernelParams::parse("mem=0 mem=1g").mem_limit, Some(USIZE_1G));
+    }
+}
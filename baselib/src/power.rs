@@ -0,0 +1,117 @@
+// Purpose: the arch-neutral power interface; powering off, rebooting, and
+// (for test runs & panics under qemu) leaving the vm with an exit code. Each
+// goes down a list of mechanisms, best first, giving every one that claims
+// to have worked a moment to take before trying the next.
+
+use crate::common::base::*;
+use crate::cpu::wait_forever;
+use crate::acpi::*;
+use crate::timer::*;
+use crate::bringup::uefi::uefi_reset;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::power::*;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aa64::power::*;
+
+use ::uefi::table::runtime::ResetType;
+
+pub const POWER_EXIT_SUCCESS: u32 = 0;
+pub const POWER_EXIT_FAILURE: u32 = 1;
+
+// how long a mechanism gets to take effect
+const POWER_SETTLE_MS: u64 = 500;
+// the same, when the counter's rate is unknown
+const POWER_SETTLE_SPINS: usize = 100_000_000;
+
+// qemu's acpi tables all carry its oem id
+const POWER_QEMU_OEM_ID: [u8; 6] = *b"BOCHS ";
+
+// waits on the free-running counter; interrupts are off, so the tick (and
+// with it maybe the monotonic clock) isn't moving
+fn power_settle() {
+    let hz = timer_counter_hz();
+
+    if hz == ZERO_U64 {
+        for _ in 0..POWER_SETTLE_SPINS {
+            core::hint::spin_loop();
+        }
+
+        return;
+    }
+
+    let start = timer_counter();
+    let ticks = hz / 1_000 * POWER_SETTLE_MS;
+
+    while timer_counter().wrapping_sub(start) < ticks {
+        core::hint::spin_loop();
+    }
+}
+
+// true if we're a qemu guest
+pub fn power_running_under_qemu() -> bool {
+    arch_hypervisor_is_qemu() || acpi_tables().iter().any(|t| t.oem_id() == POWER_QEMU_OEM_ID)
+}
+
+// turns the machine off: acpi S5, then the arch's way (psci), then the firmware.
+// if none of them take, halts.
+pub fn shutdown() -> ! {
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("shutdown() -> powering off");
+
+    arch_power_disable_interrupts();
+
+    if acpi_enter_sleep_state(ACPI_S5) {
+        power_settle();
+    }
+
+    if arch_power_off() {
+        power_settle();
+    }
+
+    uefi_reset(ResetType::SHUTDOWN);
+
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("shutdown() -> nothing would power us off; halting");
+
+    wait_forever()
+}
+
+// resets the machine: the fadt's reset register, then the arch's way (the 8042
+// on x86, psci on arm), then the firmware, then whatever can't fail (a triple
+// fault on x86)
+pub fn reboot() -> ! {
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("reboot() -> resetting");
+
+    arch_power_disable_interrupts();
+
+    if acpi_reset() {
+        power_settle();
+    }
+
+    if arch_reset() {
+        power_settle();
+    }
+
+    uefi_reset(ResetType::COLD);
+
+    arch_reset_last_resort()
+}
+
+// ends the run with code. under qemu (& built with the qemuexit feature, which
+// needs qemu started with the isa-debug-exit device / -semihosting) qemu itself
+// exits; otherwise the machine is powered off & the code is lost.
+#[cfg_attr(not(feature = "qemuexit"), allow(unused_variables))]
+pub fn exit_with_code(code: u32) -> ! {
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("exit_with_code() -> {}", code);
+
+    #[cfg(feature = "qemuexit")]
+    if power_running_under_qemu() {
+        arch_power_disable_interrupts();
+        arch_qemu_exit(code);
+    }
+
+    shutdown()
+}
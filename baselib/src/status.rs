@@ -11,6 +11,7 @@ pub enum MemSubsystemAvailability {
     VirtualPool,
     Full,
 }
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum KernelServiceStatus {
     Uninit,
     SysOnly,
@@ -5,7 +5,7 @@ use core::cell::UnsafeCell;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 use crate::arch::x86::asm::x86_noop as noop;
 #[cfg(any(target_arch = "aarch64"))]
-use crate::arch::a64::asm::aarch_noop as noop;
+use crate::arch::aa64::asm::aarch_noop as noop;
 
 use crate::common::base::*;
 
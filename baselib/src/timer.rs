@@ -0,0 +1,81 @@
+// Purpose: the arch-neutral timer interface. Every arch provides a free-running
+// counter & a periodic tick (the local apic timer on x86, the generic timer
+// on aarch64); this is what the rest of the kernel talks to.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::common::base::*;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::arch::x86::timer::*;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aa64::timer::*;
+
+// the tick rate the kernel asks for at boot
+pub const TIMER_DEFAULT_TICK_HZ: u64 = 100;
+
+// called from the tick interrupt with the new tick count
+pub type TimerTickHook = fn(u64);
+
+static TIMER_TICKS: AtomicU64 = AtomicU64::new(0);
+
+// raw fn pointer (0 = none), so the tick path never takes a lock
+static TIMER_TICK_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+// ticks since the timer was started
+pub fn timer_ticks() -> u64 {
+    TIMER_TICKS.load(Ordering::Relaxed)
+}
+
+// the rate the tick is running at; 0 if it isn't running at a known rate
+pub fn timer_tick_hz() -> u64 {
+    arch_timer_tick_hz()
+}
+
+// the free-running counter (tsc / cntvct)
+#[inline(always)]
+pub fn timer_counter() -> u64 {
+    arch_timer_counter()
+}
+
+// the counter's frequency; 0 if we don't know it (yet)
+pub fn timer_counter_hz() -> u64 {
+    arch_timer_counter_hz()
+}
+
+// (re)starts the periodic tick at hz. returns false if the rate couldn't be
+// honored; the tick may still be running at some fallback rate in that case.
+pub fn timer_start_periodic(hz: u64) -> bool {
+    if hz == ZERO_U64 {
+        return false;
+    }
+
+    arch_timer_start_periodic(hz)
+}
+
+pub fn timer_stop() {
+    arch_timer_stop();
+}
+
+// installs (or with None, removes) the tick hook; returns the previous one
+pub fn set_timer_tick_hook(hook: Option<TimerTickHook>) -> Option<TimerTickHook> {
+    let new_raw = match hook {
+        Some(f) => f as usize,
+        None => ZERO_USIZE,
+    };
+
+    match TIMER_TICK_HOOK.swap(new_raw, Ordering::SeqCst) {
+        ZERO_USIZE => None,
+        old_raw => Some(unsafe { core::mem::transmute::<usize, TimerTickHook>(old_raw) }),
+    }
+}
+
+// called by the arch backend from the tick interrupt
+pub(crate) fn timer_tick() {
+    let ticks = TIMER_TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    if let raw @ 1.. = TIMER_TICK_HOOK.load(Ordering::SeqCst) {
+        let hook = unsafe { core::mem::transmute::<usize, TimerTickHook>(raw) };
+        hook(ticks);
+    }
+}
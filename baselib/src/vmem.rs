@@ -149,6 +149,12 @@ impl Vas {
 // x86 just has levels 2 (@4MB pages) & 1 (4KB pages)
 pub type Pte = PhysAddr;
 
+// the number of paging structure levels translating a virtual address
+#[cfg(target_arch = "x86_64")]
+pub const PAGING_LEVELS: usize = 4;
+#[cfg(target_arch = "x86")]
+pub const PAGING_LEVELS: usize = 2;
+
 #[cfg(target_arch = "x86")]
 #[repr(usize)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -215,181 +221,173 @@ impl const AsUsize for PageSize {
     }
 }
 
-#[repr(C)]
+// a page table lives in place at its frame; a reference to a PageTable
+// *is* a reference to the hardware table
+#[repr(C, align(4096))]
 #[derive(Debug)]
 pub struct PageTable {
-    pub entries: *mut [Pte; PAGE_TABLE_MAX_ENTRIES],
+    pub entries: [Pte; PAGE_TABLE_MAX_ENTRIES],
 }
 impl PageTable {
-    pub fn new(base_addr: PhysAddr) -> Self {
+    // zeroes the frame at base_addr and hands it back as a page table
+    pub fn new_at(base_addr: PhysAddr) -> &'static mut Self {
         debug_assert!(base_addr.is_default_page_aligned());
 
-        raw::memset_aligned(base_addr, MEMORY_DEFAULT_PAGE_USIZE, ZERO_USIZE);
-
-        PageTable {
-            entries: {
-                unsafe { core::mem::transmute::<PhysAddr, *mut [Pte; PAGE_TABLE_MAX_ENTRIES]>(base_addr) }
-            }
-        }        
-    }
-
-    pub fn rebase(&mut self, base_addr: PhysAddr) {
-        debug_assert!(base_addr.is_default_page_aligned());
-        self.entries = unsafe { core::mem::transmute::<PhysAddr, *mut [Pte; PAGE_TABLE_MAX_ENTRIES]>(base_addr) };        
+        raw::abracadabra_static_ref_mut::<PageTable>(base_addr, true)
     }
 
     #[inline(always)]
     pub fn get_entries(&self) -> &[Pte; PAGE_TABLE_MAX_ENTRIES] {
-        unsafe { &*self.entries }
+        &self.entries
     }
 
     #[inline(always)]
     pub fn get_entries_mut(&mut self) -> &mut [Pte; PAGE_TABLE_MAX_ENTRIES] {
-        unsafe { &mut *self.entries }
+        &mut self.entries
     }
 
     #[inline(always)]
     pub fn set_entry(&mut self, idx: usize, entry: Pte) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx] = entry };
+        self.entries[idx] = entry;
     }
 
     #[inline(always)]
     pub fn get_entry(&self, idx: usize) -> Pte {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx] }
+        self.entries[idx]
     }
 
     #[inline(always)]
     pub fn clear_entry(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx] = ZERO_USIZE.as_phys() };
+        self.entries[idx] = ZERO_USIZE.as_phys();
     }
 
     #[inline(always)]
     pub fn mark_entry_present(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_PRESENT) };
+        self.entries[idx].inner_or(PAGING_PRESENT);
     }
 
     #[inline(always)]
     pub fn mark_entry_not_present(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_PRESENT) };
+        self.entries[idx].inner_and(!PAGING_PRESENT);
     }
 
     #[inline(always)]
     pub fn mark_entry_rw(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_WRITEABLE) };
+        self.entries[idx].inner_or(PAGING_WRITEABLE);
     }
 
     #[inline(always)]
     pub fn mark_entry_readonly(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_WRITEABLE) };
+        self.entries[idx].inner_and(!PAGING_WRITEABLE);
     }
 
     #[inline(always)]
     pub fn mark_entry_user(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_USERMODE) };
+        self.entries[idx].inner_or(PAGING_USERMODE);
     }
 
     #[inline(always)]
     pub fn mark_entry_supervisor(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_USERMODE) };
+        self.entries[idx].inner_and(!PAGING_USERMODE);
     }
 
     #[inline(always)]
     pub fn mark_entry_write_through(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_WRITETHROUGH) };
+        self.entries[idx].inner_or(PAGING_WRITETHROUGH);
     }
 
     #[inline(always)]
     pub fn mark_entry_not_write_through(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_WRITETHROUGH) };
+        self.entries[idx].inner_and(!PAGING_WRITETHROUGH);
     }
 
     #[inline(always)]
     pub fn mark_entry_cache_disabled(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_CACHE_DISABLE) };
+        self.entries[idx].inner_or(PAGING_CACHE_DISABLE);
     }
 
     #[inline(always)]
     pub fn mark_entry_cache_enabled(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_CACHE_DISABLE) };
+        self.entries[idx].inner_and(!PAGING_CACHE_DISABLE);
     }
 
     #[inline(always)]
     pub fn mark_entry_accessed(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_ACCESSED) };
+        self.entries[idx].inner_or(PAGING_ACCESSED);
     }
 
     #[inline(always)]
     pub fn mark_entry_not_accessed(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_ACCESSED) };
+        self.entries[idx].inner_and(!PAGING_ACCESSED);
     }
 
     #[inline(always)]
     pub fn mark_entry_dirty(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_DIRTY) };
+        self.entries[idx].inner_or(PAGING_DIRTY);
     }
 
     #[inline(always)]
     pub fn mark_entry_clean(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_DIRTY) };
+        self.entries[idx].inner_and(!PAGING_DIRTY);
     }
 
     #[inline(always)]
     pub fn mark_entry_global(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_GLOBAL) };
+        self.entries[idx].inner_or(PAGING_GLOBAL);
     }
 
     #[inline(always)]
     pub fn clear_entry_global(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_GLOBAL) };
+        self.entries[idx].inner_and(!PAGING_GLOBAL);
     }
 
     #[inline(always)]
     pub fn mark_entry_page_frame(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_IS_PAGE_FRAME) };
+        self.entries[idx].inner_or(PAGING_IS_PAGE_FRAME);
     }
 
     #[inline(always)]
     pub fn mark_entry_page_directory(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_IS_PAGE_FRAME) };
+        self.entries[idx].inner_and(!PAGING_IS_PAGE_FRAME);
     }
 
     #[inline(always)]
     pub fn is_entry_page_frame(&self, idx: usize) -> bool {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { ubit::is_bit_set(self.entries.as_mut().unwrap()[idx].as_usize(), PAGING_IS_PAGE_FRAME_BIT) }
+        ubit::is_bit_set(self.entries[idx].as_usize(), PAGING_IS_PAGE_FRAME_BIT)
     }
 
     #[inline(always)]
     pub fn mark_entry_no_execute(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_or(PAGING_NX) };
+        self.entries[idx].inner_or(PAGING_NX);
     }
 
     #[inline(always)]
     pub fn mark_entry_executable(&mut self, idx: usize) {
         debug_assert!(idx < PAGE_TABLE_MAX_ENTRIES);
-        unsafe { self.entries.as_mut().unwrap()[idx].inner_and(!PAGING_NX) };
+        self.entries[idx].inner_and(!PAGING_NX);
     }
 }
 
@@ -553,23 +551,21 @@ impl PageDir for BasePageTable {
         let pt: &mut PageTable;
 
         #[cfg(all(debug_assertions, feature = "serialdbg"))]
-        serial_println!("BasePageTable::map_page() -> self.entries[] == 0x{:0x}", self.entries as usize);
+        serial_println!("BasePageTable::map_page() -> self.entries[] == 0x{:0x}", &self.entries as *const [Pte; PAGE_TABLE_MAX_ENTRIES] as usize);
 
         #[cfg(all(debug_assertions, feature = "serialdbg"))]
         serial_println!("BasePageTable::map_page() -> page map level 4 index == {}", pml4_idx);
 
-        let my_entries = unsafe { self.entries.as_mut().unwrap() };
+        // the tables get re-entered while we map their own frames, hence the raw pointer
+        let my_entries = unsafe { &mut *(&mut self.entries as *mut [Pte; PAGE_TABLE_MAX_ENTRIES]) };
 
         // check our entry in the pml4 table, which maps 512GB chunks
         // create a new pdpt if one does not exist
         if my_entries[pml4_idx] == ZERO_USIZE.as_phys() {
             #[cfg(all(debug_assertions, feature = "serialdbg"))]
             serial_println!("BasePageTable::map_page() -> allocating frame for new pdpt");
-            
-            let new_pdpt_base = 
-                iron().unwrap().frame_alloc_internal_04.as_mut()
-                .lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
-                .alloc_frame(MEMORY_DEFAULT_PAGE_USIZE, MEMORY_DEFAULT_PAGE_SIZE_ENUM, Owner::Memory);
+            // page tables come out of this cpu's frame magazine
+            let new_pdpt_base = frame_cache_alloc();
 
             match new_pdpt_base {
                 None => return None,
@@ -579,14 +575,18 @@ impl PageDir for BasePageTable {
 
                     #[cfg(all(debug_assertions, feature = "serialdbg"))]
                     serial_println!("BasePageTable::map_page() -> identity mapping new pdpt @ 0x{:0x}", np);
-                   
+
+                    // hook the pdpt in before identity mapping it; the identity mapping
+                    // walks through this very pml4 slot, and if it's still empty we
+                    // end up allocating pdpts all the way down
+                    my_entries[pml4_idx] = np;
+
                     self.identity_map_page (
                         np,
                         MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                        PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH,
+                        PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH | PAGING_NX,
                     );
                     
-                    my_entries[pml4_idx] = np;
                     my_entries[pml4_idx]
                         .inner_or(PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH);
                     pdpt_is_new = true;
@@ -605,7 +605,7 @@ impl PageDir for BasePageTable {
         // check our entry in the pdpt table, which maps 1GB chunks.
         // create a reference to our pdpt
         pdpt = raw::abracadabra_static_ref_mut::<PageTable>(my_entries[pml4_idx].align_canon_default(), false);
-        let pdpt_entries = unsafe { pdpt.entries.as_mut().unwrap() };
+        let pdpt_entries = &mut pdpt.entries;
         
         // see if we're doing a 1GB page. if so, mark it as a page and clean up if necessary
         if page_size == PageSize::Huge {
@@ -625,7 +625,7 @@ impl PageDir for BasePageTable {
                     let local_pd = raw::abracadabra_static_ref_mut::<PageTable>(
                         pdpt_entries[pdpt_idx].align_canon_default(), false,
                     );
-                    let local_pd_entries = unsafe { local_pd.entries.as_mut().unwrap() };
+                    let local_pd_entries = &mut local_pd.entries;
 
                     // this pdpt entry is either a pointer to a 2MB page frame, or a pointer to
                     // another page directory whose entries point to 4KB pages.
@@ -640,29 +640,14 @@ impl PageDir for BasePageTable {
                             local_pd_entries[i].as_usize(),
                             PAGING_IS_PAGE_FRAME_BIT,
                         ) {
-                            iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                                local_pd_entries[i].align_canon_default(),
-                                Owner::Memory,
-                            );
-
-                            self.unmap_page(
-                                local_pd_entries[i].align_canon_default().as_usize().as_virt(),
-                                Owner::Memory,
-                                MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                            );
+                            // page tables go back through this cpu's frame magazine; they keep
+                            // their identity mapping, whoever gets them next zeroes them through it
+                            frame_cache_free(local_pd_entries[i].align_canon_default());
                         }
                     }
 
                     // now de-allocate the page directory itself
-                    iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                        pdpt_entries[pdpt_idx].align_canon_default(),
-                        Owner::Memory);
-
-                    self.unmap_page(
-                        pdpt_entries[pdpt_idx].align_canon_default().as_usize().as_virt(),
-                        Owner::Memory,
-                        MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                    );
+                    frame_cache_free(pdpt_entries[pdpt_idx].align_canon_default());
                 }
             }
 
@@ -686,10 +671,8 @@ impl PageDir for BasePageTable {
 
         // create a new pd if one does not exist
         if pdpt_entries[pdpt_idx] == ZERO_USIZE.as_phys() {
-            let new_pd_base = 
-                iron().unwrap().frame_alloc_internal_04
-                    .lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
-                    .alloc_frame(MEMORY_DEFAULT_PAGE_USIZE, MEMORY_DEFAULT_PAGE_SIZE_ENUM, Owner::Memory);
+            // page tables come out of this cpu's frame magazine
+            let new_pd_base = frame_cache_alloc();
 
             match new_pd_base {
                 None => return None,
@@ -698,7 +681,7 @@ impl PageDir for BasePageTable {
                     self.identity_map_page(
                         np,
                         MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                        PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH,
+                        PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH | PAGING_NX,
                     );
                     pdpt_entries[pdpt_idx]
                         .inner_or(PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH);
@@ -711,7 +694,7 @@ impl PageDir for BasePageTable {
 
         // create a reference to our pd
         pd = raw::abracadabra_static_ref_mut::<PageTable>(pdpt_entries[pdpt_idx].align_canon_default(), false);
-        let pd_entries = unsafe { pd.entries.as_mut().unwrap() };
+        let pd_entries = &mut pd.entries;
 
         // see if we're doing a 2MB page. if so, mark it as a page and clean up if necessary
         if page_size == PageSize::Medium {
@@ -730,30 +713,12 @@ impl PageDir for BasePageTable {
                             PAGING_IS_PAGE_FRAME_BIT,
                         ) {
                             // de-allocate every page under this page table
-                            iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                                pd_entries[i].align_canon_default(),
-                                Owner::Memory,
-                            );
-
-                            self.unmap_page(
-                                pd_entries[i].align_canon_default().as_usize().as_virt(),
-                                Owner::Memory,
-                                MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                            );
+                            frame_cache_free(pd_entries[i].align_canon_default());
                         }
                     }
 
                     // now de-allocate the page table itself
-                    iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                        pd_entries[pd_idx].align_canon_default(),
-                        Owner::Memory,
-                    );
-
-                    self.unmap_page(
-                        pd_entries[pd_idx].align_canon_default().as_usize().as_virt(),
-                        Owner::Memory,
-                        MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                    );
+                    frame_cache_free(pd_entries[pd_idx].align_canon_default());
                 }
             }
 
@@ -772,10 +737,8 @@ impl PageDir for BasePageTable {
 
         // create a new pt if one does not exist
         if pd_entries[pd_idx] == ZERO_USIZE.as_phys() {
-            let new_pt_base = 
-                iron().unwrap().frame_alloc_internal_04
-                    .lock_rw_spin().as_mut().unwrap().as_mut().unwrap()
-                    .alloc_frame(MEMORY_DEFAULT_PAGE_USIZE, MEMORY_DEFAULT_PAGE_SIZE_ENUM, Owner::Memory);
+            // page tables come out of this cpu's frame magazine
+            let new_pt_base = frame_cache_alloc();
 
             match new_pt_base {
                 None => return None,
@@ -784,7 +747,7 @@ impl PageDir for BasePageTable {
                     self.identity_map_page(
                         np,
                         MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                        PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH,
+                        PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH | PAGING_NX,
                     );
                     pd_entries[pd_idx]
                         .inner_or(PAGING_PRESENT | PAGING_WRITEABLE | PAGING_WRITETHROUGH);
@@ -794,7 +757,7 @@ impl PageDir for BasePageTable {
 
         // create a reference to our pt
         pt = raw::abracadabra_static_ref_mut::<PageTable>(pd_entries[pd_idx].align_canon_default(), false);
-        let pt_entries = unsafe { pt.entries.as_mut().unwrap() };
+        let pt_entries = &mut pt.entries;
 
         // Map our small page
         // no page frame flag for 4KB pages
@@ -852,7 +815,7 @@ impl PageDir for BasePageTable {
     }
 
     #[cfg(target_arch = "x86_64")]
-    fn unmap_page(&mut self, v: VirtAddr, owner: Owner, page_size: PageSize) -> bool {
+    fn unmap_page(&mut self, v: VirtAddr, _owner: Owner, page_size: PageSize) -> bool {
         let (pml4_idx, pdpt_idx, pd_idx, pt_idx) = v.get_page_table_indexes();
 
         let pdpt: &mut PageTable;
@@ -900,7 +863,7 @@ impl PageDir for BasePageTable {
             }
         };
 
-        let my_entries = unsafe { self.entries.as_mut().unwrap() };
+        let my_entries = &mut self.entries;
 
         // check our entry in the pml4 table, which maps 512GB chunks
         if my_entries[pml4_idx] == ZERO_USIZE.as_phys() {
@@ -911,7 +874,7 @@ impl PageDir for BasePageTable {
         // check our entry in the pdpt table, which maps 1GB chunks
         // create a reference to our pdpt
         pdpt = raw::abracadabra_static_ref_mut::<PageTable>(my_entries[pml4_idx].align_canon_default(), false);
-        let pdpt_entries = unsafe { pdpt.entries.as_mut().unwrap() };
+        let pdpt_entries = &mut pdpt.entries;
 
         // see if we're unmapping a 1GB page. if so, mark it as zero and clean up if necessary
         if page_size == PageSize::Huge {
@@ -924,36 +887,20 @@ impl PageDir for BasePageTable {
                 let local_pd = raw::abracadabra_static_ref_mut::<PageTable>(
                     pdpt_entries[pdpt_idx].align_canon_default(), false,
                 );
-                let local_pd_entries = unsafe { local_pd.entries.as_mut().unwrap() };
+                let local_pd_entries = &mut local_pd.entries;
 
                 // if they're not 2MB page entries, then we need to
                 // de-allocate every page table under this page directory
                 for i in 0..PAGE_TABLE_MAX_ENTRIES {
                     if !ubit::is_bit_set(local_pd_entries[i].as_usize(), PAGING_IS_PAGE_FRAME_BIT) {
-                        iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                            local_pd_entries[i].align_canon_default(),
-                            Owner::Memory,
-                        );
-
-                        self.unmap_page(
-                            local_pd_entries[i].align_canon_default().as_usize().as_virt(),
-                            Owner::Memory,
-                            MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                        );
+                        // page tables go back through this cpu's frame magazine; they keep
+                        // their identity mapping, whoever gets them next zeroes them through it
+                        frame_cache_free(local_pd_entries[i].align_canon_default());
                     }
                 }
 
                 // now de-allocate the page directory itself
-                iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                    pdpt_entries[pdpt_idx].align_canon_default(),
-                    Owner::Kernel,
-                );
-
-                self.unmap_page(
-                    pdpt_entries[pdpt_idx].align_canon_default().as_usize().as_virt(),
-                    owner,
-                    MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                );
+                frame_cache_free(pdpt_entries[pdpt_idx].align_canon_default());
             }
 
             // Clear our owner information
@@ -971,7 +918,7 @@ impl PageDir for BasePageTable {
 
         // create a reference to our pd
         pd = raw::abracadabra_static_ref_mut::<PageTable>(pdpt_entries[pdpt_idx].align_canon_default(), false);
-        let pd_entries = unsafe { pd.entries.as_mut().unwrap() };
+        let pd_entries = &mut pd.entries;
 
         // see if we're unmapping a 2MB page. if so, mark it as a 0 and clean up if necessary
         if page_size == PageSize::Medium {
@@ -985,30 +932,12 @@ impl PageDir for BasePageTable {
                     // don't do anything if this was already a 2MB page, since there is no page table in that case
                     if !ubit::is_bit_set(pd_entries[pd_idx].as_usize(), PAGING_IS_PAGE_FRAME_BIT) {
                         // de-allocate every page under this page table
-                        iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                            pd_entries[i].align_canon_default(),
-                            Owner::Memory,
-                        );
-
-                        self.unmap_page(
-                            pd_entries[i].align_canon_default().as_usize().as_virt(),
-                            Owner::Memory,
-                            MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                        );
+                        frame_cache_free(pd_entries[i].align_canon_default());
                     }
                 }
 
                 // now de-allocate the page directory itself
-                iron().unwrap().frame_alloc_internal_04.lock_rw_spin().as_mut().unwrap().as_mut().unwrap().dealloc_frame(
-                    pdpt_entries[pdpt_idx].align_canon_default(),
-                    Owner::Memory,
-                );
-
-                self.unmap_page(
-                    pdpt_entries[pdpt_idx].align_canon_default().as_usize().as_virt(),
-                    Owner::Memory,
-                    MEMORY_DEFAULT_PAGE_SIZE_ENUM,
-                );
+                frame_cache_free(pdpt_entries[pdpt_idx].align_canon_default());
             }
 
             // Clear our owner information
@@ -1028,7 +957,7 @@ impl PageDir for BasePageTable {
 
         // create a reference to our pt
         pt = raw::abracadabra_static_ref_mut::<PageTable>(pd_entries[pd_idx].align_canon_default(), false);
-        let pt_entries = unsafe { pt.entries.as_mut().unwrap() };
+        let pt_entries = &mut pt.entries;
 
         // Clear our owner information
         fn_unmap_page_info(pt_entries[pt_idx].align_canon_default(), page_size);
@@ -1072,7 +1001,7 @@ impl PageDir for BasePageTable {
         let pd: &mut PageTable;
         let pt: &mut PageTable;
 
-        let my_entries = unsafe { self.entries.as_mut().unwrap() };
+        let my_entries = &self.entries;
 
         // check our entry in the pml4 table, which maps 512GB chunks
         if my_entries[pml4_idx] == ZERO_USIZE.as_phys() {
@@ -1083,7 +1012,7 @@ impl PageDir for BasePageTable {
         // check our entry in the pdpt table, which maps 1GB chunks
         // create a reference to our pdpt
         pdpt = raw::abracadabra_static_ref_mut::<PageTable>(my_entries[pml4_idx].align_canon_default(), false);
-        let pdpt_entries = unsafe { pdpt.entries.as_mut().unwrap() };
+        let pdpt_entries = &mut pdpt.entries;
 
         // see if we're getting a 1GB page. if so, return it
         if ubit::is_bit_set(pdpt_entries[pdpt_idx].as_usize(), PAGING_IS_PAGE_FRAME_BIT) {
@@ -1097,7 +1026,7 @@ impl PageDir for BasePageTable {
 
         // create a reference to our pd
         pd = raw::abracadabra_static_ref_mut::<PageTable>(pdpt_entries[pdpt_idx].align_canon_default(), false);
-        let pd_entries = unsafe { pd.entries.as_mut().unwrap() };
+        let pd_entries = &mut pd.entries;
 
         // see if we're getting a 2MB page. if so, return it
         if ubit::is_bit_set(pd_entries[pd_idx].as_usize(), PAGING_IS_PAGE_FRAME_BIT) {
@@ -1113,7 +1042,7 @@ impl PageDir for BasePageTable {
 
         // create a reference to our pt
         pt = raw::abracadabra_static_ref_mut::<PageTable>(pd_entries[pd_idx].align_canon_default(), false);
-        let pt_entries = unsafe { pt.entries.as_mut().unwrap() };
+        let pt_entries = &mut pt.entries;
 
         // return our small page
         return pt_entries[pt_idx].align_canon_default();
@@ -1282,4 +1211,90 @@ impl PageDir for BasePageTable {
 
         Some(v)
     }
-}
\ No newline at end of file
+}
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+impl BasePageTable {
+    // identity maps every default sized page touched by [base, base + size);
+    // returns false as soon as a page can't be mapped
+    pub fn identity_map_range(&mut self, base: PhysAddr, size: usize, flags: usize) -> bool {
+        let end_addr = align_up(base.as_usize() + size, MEMORY_DEFAULT_PAGE_USIZE);
+        let mut current_addr = base.align_down(MEMORY_DEFAULT_PAGE_USIZE);
+
+        while current_addr.as_usize() < end_addr {
+            #[cfg(target_arch = "x86_64")]
+            let map_result = self.map_page(
+                current_addr,
+                current_addr.as_usize().as_virt(),
+                MEMORY_DEFAULT_PAGE_SIZE_ENUM,
+                flags,
+            );
+
+            #[cfg(target_arch = "x86")]
+            let map_result = self.map_page(
+                current_addr,
+                current_addr.as_usize().as_virt(),
+                Owner::Kernel,
+                MEMORY_DEFAULT_PAGE_SIZE_ENUM,
+                flags,
+            );
+
+            if map_result.is_none() {
+                #[cfg(all(debug_assertions, feature = "serialdbg"))]
+                serial_println!("BasePageTable::identity_map_range() -> failed to map page @ 0x{:0x}", current_addr);
+                return false;
+            }
+
+            current_addr.inner_inc_by_page_size(MEMORY_DEFAULT_PAGE_SIZE_ENUM);
+        }
+
+        true
+    }
+
+    // returns the raw entries passed through while translating v, top level first,
+    // along with the number of levels visited; the walk stops early at the first
+    // non-present entry or at a large page
+    #[cfg(target_arch = "x86_64")]
+    pub fn walk_entries(&self, v: VirtAddr) -> ([Pte; PAGING_LEVELS], usize) {
+        let (pml4_idx, pdpt_idx, pd_idx, pt_idx) = v.get_page_table_indexes();
+        let indexes = [pml4_idx, pdpt_idx, pd_idx, pt_idx];
+
+        let mut chain = [ZERO_USIZE.as_phys(); PAGING_LEVELS];
+        let mut table: &PageTable = self;
+
+        for level in 0..PAGING_LEVELS {
+            let entry = table.entries[indexes[level]];
+            chain[level] = entry;
+
+            // stop at the leaf, at a non-present entry, or at a 1GB / 2MB page
+            let is_leaf = level == PAGING_LEVELS - 1;
+            let is_present = entry.as_usize() & PAGING_PRESENT != 0;
+            let is_large_page = level > 0 && ubit::is_bit_set(entry.as_usize(), PAGING_IS_PAGE_FRAME_BIT);
+
+            if is_leaf || !is_present || is_large_page {
+                return (chain, level + 1);
+            }
+
+            table = raw::abracadabra_static_ref_mut::<PageTable>(entry.align_canon_default(), false);
+        }
+
+        (chain, PAGING_LEVELS)
+    }
+
+    #[cfg(target_arch = "x86")]
+    pub fn walk_entries(&self, v: VirtAddr) -> ([Pte; PAGING_LEVELS], usize) {
+        let (pd_idx, pt_idx) = v.get_page_table_indexes();
+
+        let mut chain = [ZERO_USIZE.as_phys(); PAGING_LEVELS];
+        chain[0] = self.entries[pd_idx];
+
+        // not present, or a 4MB page
+        if chain[0].as_usize() & PAGING_PRESENT == 0 || ubit::is_bit_set(chain[0].as_usize(), PAGING_IS_PAGE_FRAME_BIT) {
+            return (chain, 1);
+        }
+
+        let pt = raw::abracadabra_static_ref_mut::<PageTable>(chain[0].align_canon_default(), false);
+        chain[1] = pt.entries[pt_idx];
+
+        (chain, PAGING_LEVELS)
+    }
+}
@@ -212,7 +212,21 @@ def run_build(rust_flags, linker_flags, *flags):
 
   sp.run(cmd).check_returncode()
 
-def build_command(rust_flags, linker_flags):
+def write_startup_script(kernel_cmdline):
+  global current_arch_id
+
+  "Writes a startup script to make UEFI Shell load into iron automatically, passing it the kernel command line (if any)"
+
+  startup_cmd = str("\EFI\BOOT\\" + UEFI_BOOT_FILE[current_arch_id])
+
+  if kernel_cmdline:
+    startup_cmd = startup_cmd + " " + kernel_cmdline
+
+  startup_file = open(BUILD_DIR / "startup.nsh", "w")
+  startup_file.write(startup_cmd)
+  startup_file.close()
+
+def build_command(rust_flags, linker_flags, kernel_cmdline):
   global current_arch_id, cargo_build_dir
   
   "Builds iron"
@@ -226,20 +240,19 @@ def build_command(rust_flags, linker_flags):
   # Copy the build EFI application to the build directory
   built_file = cargo_build_dir / "iron.efi"
   output_file = boot_dir / UEFI_BOOT_FILE[current_arch_id]
-  output_full_path = str("\EFI\BOOT\\" + UEFI_BOOT_FILE[current_arch_id])
   shutil.copy2(built_file, output_file)
 
-  # Write a startup script to make UEFI Shell load into
-  # the application automatically
-  startup_file = open(BUILD_DIR / "startup.nsh", "w")
-  startup_file.write(output_full_path)
-  startup_file.close()
+  write_startup_script(kernel_cmdline)
 
-def run_command():
+def run_command(kernel_cmdline):
   global qemu_cmd, qemu_flags
   
   "Runs iron in QEMU"
 
+  # a command line given on the run overrides the one from the build
+  if kernel_cmdline != None:
+    write_startup_script(kernel_cmdline)
+
   sp.run([qemu_cmd] + qemu_flags).check_returncode()
 
 def main(args):
@@ -255,6 +268,7 @@ def main(args):
   options = [
     "-lf, --linkerflags for specifying linker flags",
     "-rf, --rustflags for specifying rust compiler flags",
+    "-a, --append for specifying the kernel command line (e.g. \"verbosity=2 mem=128M noapic\")",
     #"-af, --asmflags for specifying assembler flags",
   ]
 
@@ -272,6 +286,7 @@ def main(args):
   # Add the optional arguments
   parser.add_argument("-lf", "--linkerflags", help="The linker flags to use")
   parser.add_argument("-rf", "--rustflags", help="The compiler flags to use")
+  parser.add_argument("-a", "--append", help="The kernel command line to boot with")
   #parser.add_argument("-af", "--asmflags", help="The assembler flags to use")
 
   opts = parser.parse_args()
@@ -301,9 +316,9 @@ def main(args):
   init_arch(opts.arch, opts.config)
 
   if opts.action == "build":
-    build_command(opts.rustflags, opts.linkerflags)
+    build_command(opts.rustflags, opts.linkerflags, opts.append)
   elif opts.action == "run":
-    run_command()
+    run_command(opts.append)
   else:
     print(f"Unknown action '{opts.action}'")
 
@@ -0,0 +1,21 @@
+// Purpose: link settings for the kernel image. build_iron.py sets RUSTFLAGS,
+// which overrides any rustflags in .cargo/config.toml, so these have to come
+// from here.
+
+use std::env;
+
+fn main() {
+    // on x86_64 the image doubles as a multiboot2 kernel. multiboot2 loaders
+    // put the file down as-is, at a fixed address, & jump to 32 bit code that
+    // can't relocate itself; so we link at that address (uefi is happy to load
+    // us there, or relocate us elsewhere) & page align the file, so the .text
+    // section (& the header in it) sits at the same offset in the file as in
+    // the image. the base has to match MULTIBOOT_LOAD_BASE in
+    // baselib/src/bringup/multiboot.rs.
+    if env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("x86_64") {
+        println!("cargo:rustc-link-arg-bins=/BASE:0x200000");
+        println!("cargo:rustc-link-arg-bins=/FILEALIGN:0x1000");
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
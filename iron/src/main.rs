@@ -2,6 +2,7 @@
 #![no_std]
 #![no_main]
 #![feature(naked_functions)]
+#![feature(asm_const)]
 #![feature(const_trait_impl)]
 #![feature(panic_info_message)]
 
@@ -11,10 +12,17 @@ use crate::arch::x86::random::*;
 #[cfg(all(feature = "hwrngseed", target_arch = "x86_64"))]
 use crate::arch::x86::random::*;
 
+// multiboot2 header & trampoline
+#[cfg(target_arch = "x86_64")]
+mod multiboot;
+
 // common includes
 use baselib::common::base::*;
 // uefi bringup includes
 use baselib::bringup::uefi::*;
+// multiboot bringup includes
+#[cfg(target_arch = "x86_64")]
+use baselib::bringup::multiboot::*;
 use ::uefi::prelude::*;
 
 // uefi bringup entry point
@@ -67,15 +75,18 @@ fn uefi_start(_image_handler: ::uefi::Handle, mut system_table: SystemTable<Boot
     Status::SUCCESS
 }
 
-// multiboot bringup entry point
-#[allow(dead_code)]
-fn multiboot_start() -> ! {
+// multiboot bringup entry point; called (in long mode, on the boot stack)
+// by the trampoline in multiboot.rs with what the loader left in eax & ebx
+#[cfg(target_arch = "x86_64")]
+extern "sysv64" fn multiboot_start(magic: u32, info: usize) -> ! {
     // set the boot method
     unsafe { KERNEL_BOOT_METHOD = KernelBootMethod::Multiboot; }
 
     // enjoy the rest of your trip!
-    //multiboot_init();
+    multiboot_pre_init(magic, info);
 
     // should never return
+    #[cfg(all(debug_assertions, feature = "serialdbg"))]
+    serial_println!("fell back through to multiboot_start() -> halting system");
     loop {}
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+// Purpose: the multiboot2 side of the kernel image: the header a multiboot2
+// loader (grub, etc.) looks for in the first 32k of the file, and the 32 bit
+// trampoline it jumps to. The loader copies the pe file to MULTIBOOT_LOAD_BASE
+// verbatim, so before anything else the trampoline spreads the sections out to
+// their virtual addresses (zeroing .bss along the way). Then it identity maps
+// the first 4g with 2m pages, turns on long mode & calls multiboot_start() in
+// main.rs with the loader's magic & info pointer.
+//
+// The header has to be near the front of the file; it's the first thing in
+// iron's .text, which the linker puts first. .text itself is never moved (the
+// file is page aligned, so .text is at the same offset in the file & image),
+// which is why the trampoline keeps its variables there.
+
+use core::arch::global_asm;
+use baselib::bringup::multiboot::{MULTIBOOT_LOAD_BASE, MULTIBOOT_RELOC_RESERVE};
+
+global_asm!(
+    ".section .text,\"xr\"",
+
+    // header
+    ".p2align 3",
+    "multiboot2_header:",
+    ".long 0xE85250D6",                                     // magic
+    ".long 0",                                              // architecture: i386 (protected mode)
+    ".long multiboot2_header_end - multiboot2_header",      // header length
+    ".long 0x100000000 - 0xE85250D6 - (multiboot2_header_end - multiboot2_header)",
+
+    // address tag: the whole file goes at the load base; everything from
+    // there to the end of the image (plus room for .reloc, which comes after
+    // our end marker) is ours
+    ".p2align 3",
+    ".short 2, 0",
+    ".long 24",
+    ".long multiboot2_header@IMGREL + {base}",              // header_addr
+    ".long {base}",                                         // load_addr
+    ".long 0",                                              // load_end_addr: the whole file
+    "multiboot2_bss_end:",
+    ".long multiboot2_image_end@IMGREL + {base} + {reloc_reserve}",
+
+    // entry address tag
+    ".p2align 3",
+    ".short 3, 0",
+    ".long 12",
+    ".long multiboot2_entry32@IMGREL + {base}",
+
+    // end tag
+    ".p2align 3",
+    ".short 0, 0",
+    ".long 8",
+    "multiboot2_header_end:",
+
+    // what the loader handed us, in eax & ebx
+    ".p2align 2",
+    "multiboot2_boot_magic: .long 0",
+    "multiboot2_boot_info: .long 0",
+
+    // flat 64 bit code & data segments
+    ".p2align 3",
+    "multiboot2_gdt:",
+    ".quad 0",
+    ".quad 0x00AF9A000000FFFF",
+    ".quad 0x00CF92000000FFFF",
+    "multiboot2_gdt_ptr:",
+    ".short 23",
+    ".long multiboot2_gdt@IMGREL + {base}",
+
+    // 32 bit protected mode, paging off, no stack, interrupts off
+    ".code32",
+    "multiboot2_entry32:",
+    "cli",
+    "cld",
+    "mov dword ptr [multiboot2_boot_magic@IMGREL + {base}], eax",
+    "mov dword ptr [multiboot2_boot_info@IMGREL + {base}], ebx",
+
+    // the image has to fit in what we had the loader set aside; if it
+    // doesn't, the loader may have put its info or modules in our way
+    "mov eax, dword ptr [{base} + 0x3C]",                   // e_lfanew
+    "lea ebx, [eax + {base}]",                              // nt headers
+    "mov eax, dword ptr [ebx + 0x50]",                      // size of image
+    "add eax, {base}",
+    "cmp eax, dword ptr [multiboot2_bss_end@IMGREL + {base}]",
+    "ja multiboot2_halt32",
+
+    // spread the sections out, last first, as each moves up in memory.
+    // per section: n = the initialized bytes (0 if there's no raw data),
+    // zero [va + n, va + virtual size), then copy n bytes from the file
+    // offset to the va, backwards
+    "movzx edx, word ptr [ebx + 6]",                        // number of sections
+    "movzx eax, word ptr [ebx + 20]",                       // size of optional header
+    "lea ebx, [ebx + eax + 24]",                            // section table
+    "2:",
+    "test edx, edx",
+    "jz 4f",
+    "dec edx",
+    "imul esi, edx, 40",
+    "add esi, ebx",
+    "xor ebp, ebp",
+    "cmp dword ptr [esi + 20], 0",                          // pointer to raw data
+    "je 3f",
+    "mov ebp, dword ptr [esi + 16]",                        // size of raw data
+    "cmp ebp, dword ptr [esi + 8]",                         // virtual size
+    "jbe 3f",
+    "mov ebp, dword ptr [esi + 8]",
+    "3:",
+    "mov edi, dword ptr [esi + 12]",                        // virtual address
+    "lea edi, [edi + ebp + {base}]",
+    "mov ecx, dword ptr [esi + 8]",
+    "sub ecx, ebp",
+    "xor eax, eax",
+    "rep stosb",
+    "mov ecx, ebp",
+    "mov edi, dword ptr [esi + 12]",
+    "lea edi, [edi + ecx + {base} - 1]",
+    "mov esi, dword ptr [esi + 20]",
+    "lea esi, [esi + ecx + {base} - 1]",
+    "std",
+    "rep movsb",
+    "cld",
+    "jmp 2b",
+    "4:",
+
+    // identity map the first 4g: pml4[0] -> pdpt, pdpt[0..4] -> 4 page
+    // directories of 2m pages (the tables are in .bss, so already zeroed)
+    "lea eax, [multiboot2_pdpt@IMGREL + {base} + 0x3]",
+    "mov dword ptr [multiboot2_pml4@IMGREL + {base}], eax",
+    "lea eax, [multiboot2_pd@IMGREL + {base} + 0x3]",
+    "xor ecx, ecx",
+    "5:",
+    "mov dword ptr [multiboot2_pdpt@IMGREL + {base} + ecx * 8], eax",
+    "add eax, 0x1000",
+    "inc ecx",
+    "cmp ecx, 4",
+    "jne 5b",
+    "mov eax, 0x83",                                        // present | writeable | 2m
+    "xor ecx, ecx",
+    "6:",
+    "mov dword ptr [multiboot2_pd@IMGREL + {base} + ecx * 8], eax",
+    "add eax, 0x200000",
+    "inc ecx",
+    "cmp ecx, 2048",
+    "jne 6b",
+
+    // pae & sse (uefi hands over with sse on, & the compiler counts on it)
+    "mov eax, cr4",
+    "or eax, 0x620",                                        // pae | osfxsr | osxmmexcpt
+    "mov cr4, eax",
+    "lea eax, [multiboot2_pml4@IMGREL + {base}]",
+    "mov cr3, eax",
+
+    // efer.lme
+    "mov ecx, 0xC0000080",
+    "rdmsr",
+    "or eax, 0x100",
+    "wrmsr",
+
+    // paging on (& with it long mode), x87 emulation off
+    "mov eax, cr0",
+    "and eax, 0xFFFFFFFB",                                  // ~em
+    "or eax, 0x80000003",                                   // pg | mp | pe
+    "mov cr0, eax",
+
+    "lgdt [multiboot2_gdt_ptr@IMGREL + {base}]",
+    ".byte 0xEA",                                           // jmp far 0x08:multiboot2_entry64
+    ".long multiboot2_entry64@IMGREL + {base}",
+    ".short 0x08",
+
+    "multiboot2_halt32:",
+    "hlt",
+    "jmp multiboot2_halt32",
+
+    // long mode
+    ".code64",
+    "multiboot2_entry64:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "lea rsp, [rip + multiboot2_stack_top]",
+    "xor ebp, ebp",
+    "mov edi, dword ptr [rip + multiboot2_boot_magic]",
+    "mov esi, dword ptr [rip + multiboot2_boot_info]",
+    "call {start}",
+    "7:",
+    "hlt",
+    "jmp 7b",
+
+    // the trampoline's page tables & the boot stack
+    ".section .bss,\"bw\"",
+    ".p2align 12",
+    "multiboot2_pml4: .zero 0x1000",
+    "multiboot2_pdpt: .zero 0x1000",
+    "multiboot2_pd: .zero 0x4000",
+    "multiboot2_stack: .zero 0x10000",
+    "multiboot2_stack_top:",
+
+    // the end of the image, short of .reloc; lld puts sections it doesn't
+    // know about after its own (.text, .rdata, .data / .bss, ...), but
+    // before .reloc
+    ".section .mbend,\"r\"",
+    "multiboot2_image_end: .long 0",
+
+    base = const MULTIBOOT_LOAD_BASE,
+    reloc_reserve = const MULTIBOOT_RELOC_RESERVE,
+    start = sym crate::multiboot_start,
+);